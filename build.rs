@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    {
+        // Use the bundled protoc unless the environment names one
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform"));
+        }
+        tonic_prost_build::compile_protos("proto/nebuladb.proto").expect("Failed to compile proto/nebuladb.proto");
+    }
+}
@@ -3,7 +3,6 @@
 //! This module will handle archiving of old/cold data.
 //! For now, it's just a placeholder.
 
-use nebuladb_core::{Error, Result};
 
 /// Archive configuration
 #[derive(Debug, Clone)]
@@ -1,10 +1,26 @@
 //! Core functionality for NebulaDB
 use serde::{Serialize, Deserialize};
 
+pub mod metrics;
+
 /// Represents a database error
 #[derive(Debug)]
 pub enum Error {
     IoError(std::io::Error),
+    /// Stored checksum does not match the checksum computed from the data
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// A write would give a uniquely indexed field a value another document
+    /// already has
+    DuplicateKey { field: String, value: String },
+    /// No document has the given ID
+    NotFound { id: String },
+    /// A collection with the given name already exists
+    AlreadyExists { name: String },
+    /// A field holds a value of another type than the operation needs
+    TypeMismatch { field: String, expected: String },
+    /// Transactions waited on each other in a cycle; the given one was
+    /// aborted to break it
+    Deadlock { aborted_tx_id: u64 },
     Other(String),
 }
 
@@ -0,0 +1,94 @@
+//! Process-wide Prometheus metrics
+//!
+//! Each metric is registered with [`REGISTRY`] the first time it is used, so
+//! any crate can record into it. [`render`] produces the Prometheus text
+//! exposition format served at `/metrics`.
+
+use std::sync::LazyLock;
+
+use prometheus::core::Collector;
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+/// Registry holding every NebulaDB metric
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+/// Documents inserted, by collection
+pub static DOCUMENTS_INSERTED: LazyLock<IntCounterVec> = LazyLock::new(|| register(IntCounterVec::new(
+    Opts::new("nebuladb_documents_inserted_total", "Documents inserted"),
+    &["collection"],
+)));
+
+/// Documents deleted, by collection
+pub static DOCUMENTS_DELETED: LazyLock<IntCounterVec> = LazyLock::new(|| register(IntCounterVec::new(
+    Opts::new("nebuladb_documents_deleted_total", "Documents deleted"),
+    &["collection"],
+)));
+
+/// Time spent answering queries, by collection and operation
+pub static QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| register(HistogramVec::new(
+    HistogramOpts::new("nebuladb_query_duration_seconds", "Time spent answering queries"),
+    &["collection", "operation"],
+)));
+
+/// Requests being handled by the HTTP interface
+pub static ACTIVE_CONNECTIONS: LazyLock<IntGauge> = LazyLock::new(|| register(IntGauge::new(
+    "nebuladb_active_connections", "Requests being handled by the HTTP interface",
+)));
+
+/// Bytes appended to write-ahead logs
+pub static WAL_BYTES_WRITTEN: LazyLock<IntCounter> = LazyLock::new(|| register(IntCounter::new(
+    "nebuladb_wal_bytes_written_total", "Bytes appended to write-ahead logs",
+)));
+
+/// Block lookups served from a block cache
+pub static BLOCK_CACHE_HITS: LazyLock<IntCounter> = LazyLock::new(|| register(IntCounter::new(
+    "nebuladb_block_cache_hits_total", "Block lookups served from a block cache",
+)));
+
+/// Block lookups that had to read the disk
+pub static BLOCK_CACHE_MISSES: LazyLock<IntCounter> = LazyLock::new(|| register(IntCounter::new(
+    "nebuladb_block_cache_misses_total", "Block lookups that had to read the disk",
+)));
+
+/// Register a metric built from constant options, which cannot fail
+fn register<M: Collector + Clone + 'static>(metric: prometheus::Result<M>) -> M {
+    let metric = metric.expect("invalid metric definition");
+    REGISTRY.register(Box::new(metric.clone())).expect("metric registered twice");
+    metric
+}
+
+/// Every metric in the Prometheus text format
+///
+/// Metrics without labels are listed even before anything was recorded.
+pub fn render() -> String {
+    LazyLock::force(&DOCUMENTS_INSERTED);
+    LazyLock::force(&DOCUMENTS_DELETED);
+    LazyLock::force(&QUERY_DURATION);
+    LazyLock::force(&ACTIVE_CONNECTIONS);
+    LazyLock::force(&WAL_BYTES_WRITTEN);
+    LazyLock::force(&BLOCK_CACHE_HITS);
+    LazyLock::force(&BLOCK_CACHE_MISSES);
+
+    TextEncoder::new()
+        .encode_to_string(&REGISTRY.gather())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_lists_recorded_metrics() {
+        let before = render();
+        assert!(before.contains("nebuladb_wal_bytes_written_total "));
+        assert!(!before.contains(r#"collection="metrics_test""#));
+
+        DOCUMENTS_INSERTED.with_label_values(&["metrics_test"]).inc_by(3);
+        QUERY_DURATION.with_label_values(&["metrics_test", "find"]).observe(0.002);
+
+        let after = render();
+        assert!(after.contains(r#"nebuladb_documents_inserted_total{collection="metrics_test"} 3"#));
+        assert!(after.contains(r#"nebuladb_query_duration_seconds_count{collection="metrics_test",operation="find"} 1"#));
+    }
+}
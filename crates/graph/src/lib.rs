@@ -3,7 +3,6 @@
 //! This module will handle graph database functionality.
 //! For now, it's just a placeholder.
 
-use nebuladb_core::{Error, Result};
 
 /// Graph configuration
 #[derive(Debug, Clone)]
@@ -0,0 +1,278 @@
+//! B-tree backed field index
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::{Index, ScanDirection};
+
+/// A field value used as an index key, ordered by
+/// [`nebuladb_query::compare_values`]
+///
+/// That order compares numbers by their exact values, so keys stay distinct
+/// and totally ordered past the integers an `f64` holds.
+#[derive(Debug, Clone)]
+pub struct IndexKey(pub JsonValue);
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        nebuladb_query::compare_values(&self.0, &other.0)
+    }
+}
+
+/// On-disk form of an index
+///
+/// Keys are stored as JSON text since bincode cannot decode a `JsonValue`.
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    field: String,
+    /// Whether the index rejects duplicate values
+    unique: bool,
+    /// Whether `entries` is up to date; an index left incomplete by a crash
+    /// is rebuilt from the documents
+    complete: bool,
+    entries: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+/// Index over one field, keeping document IDs sorted by field value
+#[derive(Debug, Clone)]
+pub struct BTreeIndex {
+    field: String,
+    entries: BTreeMap<IndexKey, Vec<Vec<u8>>>,
+}
+
+impl BTreeIndex {
+    /// Create an empty index over `field`
+    pub fn new(field: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Number of distinct indexed values
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no document is indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Save the index to `path`
+    ///
+    /// With `complete` unset only the field is recorded, marking the saved
+    /// entries as untrustworthy until the next complete save.
+    pub fn save(&self, path: &Path, complete: bool) -> Result<()> {
+        self.save_as(path, complete, false)
+    }
+
+    /// Save the index, recording whether it enforces uniqueness
+    pub(crate) fn save_as(&self, path: &Path, complete: bool, unique: bool) -> Result<()> {
+        let entries = if complete {
+            self.entries.iter()
+                .map(|(key, ids)| (key.0.to_string(), ids.clone()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let file = IndexFile { field: self.field.clone(), unique, complete, entries };
+
+        let bytes = bincode::serialize(&file)
+            .map_err(|e| Error::Other(format!("Failed to encode index: {}", e)))?;
+        let tmp_path = path.with_extension("idx.tmp");
+        fs::write(&tmp_path, bytes).map_err(Error::IoError)?;
+        fs::rename(&tmp_path, path).map_err(Error::IoError)
+    }
+
+    /// Load an index saved with [`save`](Self::save)
+    ///
+    /// Returns the index and whether its entries are complete; an incomplete
+    /// index comes back empty and must be rebuilt.
+    pub fn load(path: &Path) -> Result<(Self, bool)> {
+        let (index, complete, _) = Self::load_as(path)?;
+        Ok((index, complete))
+    }
+
+    /// Load an index along with whether its entries are complete and whether
+    /// it enforces uniqueness
+    pub(crate) fn load_as(path: &Path) -> Result<(Self, bool, bool)> {
+        let bytes = fs::read(path).map_err(Error::IoError)?;
+        let file: IndexFile = bincode::deserialize(&bytes)
+            .map_err(|e| Error::Other(format!("Failed to decode index {:?}: {}", path, e)))?;
+
+        let mut index = Self::new(&file.field);
+        if file.complete {
+            for (key, ids) in file.entries {
+                let key = serde_json::from_str(&key)
+                    .map_err(|e| Error::Other(format!("Invalid key in index {:?}: {}", path, e)))?;
+                index.entries.insert(IndexKey(key), ids);
+            }
+        }
+
+        Ok((index, file.complete, file.unique))
+    }
+
+    /// Iterate over the IDs whose field lies between `low` and `high`,
+    /// inclusive, in `direction`
+    ///
+    /// A reverse scan walks the tree from `high` down, so taking the first few
+    /// IDs does not visit the rest of the range.
+    pub fn iter_range<'a>(&'a self, low: &JsonValue, high: &JsonValue, direction: ScanDirection)
+        -> Box<dyn Iterator<Item = &'a [u8]> + 'a> {
+        let (low, high) = (IndexKey(low.clone()), IndexKey(high.clone()));
+        if low > high {
+            return Box::new(std::iter::empty());
+        }
+
+        let range = self.entries.range(low..=high);
+        match direction {
+            ScanDirection::Forward => Box::new(range.flat_map(|(_, ids)| ids.iter().map(Vec::as_slice))),
+            ScanDirection::Reverse => Box::new(range.rev().flat_map(|(_, ids)| ids.iter().rev().map(Vec::as_slice))),
+        }
+    }
+
+    /// IDs recorded under `value`, without copying them
+    pub(crate) fn ids_for(&self, value: &JsonValue) -> &[Vec<u8>] {
+        self.entries.get(&IndexKey(value.clone())).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+impl Index for BTreeIndex {
+    fn field(&self) -> &str {
+        &self.field
+    }
+
+    fn insert(&mut self, id: &[u8], doc: &JsonValue) -> Result<()> {
+        if let Some(value) = nebuladb_query::field_value(doc, &self.field) {
+            let ids = self.entries.entry(IndexKey(value.clone())).or_default();
+            if !ids.iter().any(|existing| existing == id) {
+                ids.push(id.to_vec());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &[u8], doc: &JsonValue) {
+        if let Some(value) = nebuladb_query::field_value(doc, &self.field) {
+            let key = IndexKey(value.clone());
+            if let Some(ids) = self.entries.get_mut(&key) {
+                ids.retain(|existing| existing != id);
+                if ids.is_empty() {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn lookup_eq(&self, value: &JsonValue) -> Result<Vec<Vec<u8>>> {
+        Ok(self.entries.get(&IndexKey(value.clone())).cloned().unwrap_or_default())
+    }
+
+    fn scan_range(&self, low: &JsonValue, high: &JsonValue, direction: ScanDirection,
+        limit: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        Ok(self.iter_range(low, high, direction)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(<[u8]>::to_vec)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ids(list: &[&str]) -> Vec<Vec<u8>> {
+        list.iter().map(|id| id.as_bytes().to_vec()).collect()
+    }
+
+    fn people() -> BTreeIndex {
+        let mut index = BTreeIndex::new("age");
+        index.insert(b"ada", &json!({"age": 36})).unwrap();
+        index.insert(b"alan", &json!({"age": 41})).unwrap();
+        index.insert(b"grace", &json!({"age": 36.0})).unwrap();
+        index.insert(b"nobody", &json!({"name": "x"})).unwrap();
+        index
+    }
+
+    #[test]
+    fn test_lookup_eq() {
+        let index = people();
+        assert_eq!(index.lookup_eq(&json!(36)).unwrap(), ids(&["ada", "grace"]));
+        assert_eq!(index.lookup_eq(&json!(41)).unwrap(), ids(&["alan"]));
+        assert!(index.lookup_eq(&json!("36")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lookup_range_is_inclusive_and_ordered() {
+        let index = people();
+        assert_eq!(index.lookup_range(&json!(30), &json!(50)).unwrap(), ids(&["ada", "grace", "alan"]));
+        assert_eq!(index.lookup_range(&json!(37), &json!(41)).unwrap(), ids(&["alan"]));
+        assert!(index.lookup_range(&json!(50), &json!(30)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_range_directions() {
+        let mut index = BTreeIndex::new("ts");
+        for ts in [5, 1, 4, 2, 3] {
+            index.insert(format!("event{}", ts).as_bytes(), &json!({"ts": ts})).unwrap();
+        }
+
+        let scan = |direction, limit| index.scan_range(&json!(1), &json!(5), direction, limit).unwrap();
+        assert_eq!(scan(ScanDirection::Forward, None), ids(&["event1", "event2", "event3", "event4", "event5"]));
+        assert_eq!(scan(ScanDirection::Reverse, None), ids(&["event5", "event4", "event3", "event2", "event1"]));
+
+        // Newest two
+        assert_eq!(scan(ScanDirection::Reverse, Some(2)), ids(&["event5", "event4"]));
+        assert_eq!(scan(ScanDirection::Forward, Some(2)), ids(&["event1", "event2"]));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = people();
+        index.remove(b"ada", &json!({"age": 36}));
+        index.remove(b"alan", &json!({"age": 41}));
+        assert_eq!(index.lookup_eq(&json!(36)).unwrap(), ids(&["grace"]));
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("people_age.idx");
+        let index = people();
+
+        index.save(&path, true).unwrap();
+        let (loaded, complete) = BTreeIndex::load(&path).unwrap();
+        assert!(complete);
+        assert_eq!(loaded.field(), "age");
+        assert_eq!(loaded.lookup_eq(&json!(36)).unwrap(), ids(&["ada", "grace"]));
+
+        index.save(&path, false).unwrap();
+        let (loaded, complete) = BTreeIndex::load(&path).unwrap();
+        assert!(!complete);
+        assert!(loaded.is_empty());
+    }
+}
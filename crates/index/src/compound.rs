@@ -0,0 +1,213 @@
+//! Index over several fields at once
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::IndexKey;
+
+/// On-disk form of a compound index; keys are JSON arrays stored as text
+#[derive(Serialize, Deserialize)]
+struct CompoundIndexFile {
+    fields: Vec<String>,
+    /// Whether `entries` is up to date; see `BTreeIndex::save`
+    complete: bool,
+    entries: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+/// Index keeping document IDs sorted by the values of several fields, in
+/// declaration order
+///
+/// A missing field is indexed as `null`, so every JSON document has a key.
+/// Lookups may give values for any leading run of the fields.
+#[derive(Debug, Clone)]
+pub struct CompoundIndex {
+    fields: Vec<String>,
+    entries: BTreeMap<Vec<IndexKey>, Vec<Vec<u8>>>,
+}
+
+impl CompoundIndex {
+    /// Create an empty index over `fields`
+    pub fn new(fields: &[&str]) -> Result<Self> {
+        if fields.is_empty() {
+            return Err(Error::Other("A compound index needs at least one field".to_string()));
+        }
+
+        Ok(Self {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            entries: BTreeMap::new(),
+        })
+    }
+
+    /// The indexed fields, in key order
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Number of distinct indexed keys
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no document is indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The key `doc` is indexed under
+    fn key(&self, doc: &JsonValue) -> Vec<IndexKey> {
+        self.fields.iter()
+            .map(|field| IndexKey(nebuladb_query::field_value(doc, field).cloned().unwrap_or(JsonValue::Null)))
+            .collect()
+    }
+
+    /// Record `doc` under its values of the indexed fields
+    pub fn insert(&mut self, id: &[u8], doc: &JsonValue) {
+        let ids = self.entries.entry(self.key(doc)).or_default();
+        if !ids.iter().any(|existing| existing == id) {
+            ids.push(id.to_vec());
+        }
+    }
+
+    /// Forget `doc`, previously recorded with [`insert`](Self::insert)
+    pub fn remove(&mut self, id: &[u8], doc: &JsonValue) {
+        let key = self.key(doc);
+        if let Some(ids) = self.entries.get_mut(&key) {
+            ids.retain(|existing| existing != id);
+            if ids.is_empty() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    /// IDs of the documents whose leading fields equal `prefix`, in key order
+    ///
+    /// A prefix as long as the field list is a full-key lookup; an empty
+    /// prefix lists every document. Use `null` to find documents missing a
+    /// field.
+    pub fn lookup_compound_prefix(&self, prefix: &[JsonValue]) -> Result<Vec<Vec<u8>>> {
+        if prefix.len() > self.fields.len() {
+            return Err(Error::Other(format!(
+                "Prefix has {} values but the index covers {} fields", prefix.len(), self.fields.len())));
+        }
+
+        // Keys sharing the prefix sort right after the prefix itself
+        let prefix: Vec<IndexKey> = prefix.iter().cloned().map(IndexKey).collect();
+        Ok(self.entries.range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect())
+    }
+
+    /// Save the index to `path`; see [`BTreeIndex::save`](crate::BTreeIndex::save)
+    pub fn save(&self, path: &Path, complete: bool) -> Result<()> {
+        let entries = if complete {
+            self.entries.iter()
+                .map(|(key, ids)| {
+                    let key = JsonValue::Array(key.iter().map(|value| value.0.clone()).collect());
+                    (key.to_string(), ids.clone())
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let file = CompoundIndexFile { fields: self.fields.clone(), complete, entries };
+
+        let bytes = bincode::serialize(&file)
+            .map_err(|e| Error::Other(format!("Failed to encode compound index: {}", e)))?;
+        let tmp_path = path.with_extension("cidx.tmp");
+        fs::write(&tmp_path, bytes).map_err(Error::IoError)?;
+        fs::rename(&tmp_path, path).map_err(Error::IoError)
+    }
+
+    /// Load an index saved with [`save`](Self::save), returning it with
+    /// whether its entries are complete
+    pub fn load(path: &Path) -> Result<(Self, bool)> {
+        let bytes = fs::read(path).map_err(Error::IoError)?;
+        let file: CompoundIndexFile = bincode::deserialize(&bytes)
+            .map_err(|e| Error::Other(format!("Failed to decode compound index {:?}: {}", path, e)))?;
+
+        let mut index = Self {
+            fields: file.fields,
+            entries: BTreeMap::new(),
+        };
+        for (key, ids) in file.entries {
+            let key = match serde_json::from_str(&key) {
+                Ok(JsonValue::Array(values)) => values.into_iter().map(IndexKey).collect(),
+                _ => return Err(Error::Other(format!("Invalid key in compound index {:?}: {}", path, key))),
+            };
+            index.entries.insert(key, ids);
+        }
+
+        Ok((index, file.complete))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ids(list: &[&str]) -> Vec<Vec<u8>> {
+        list.iter().map(|id| id.as_bytes().to_vec()).collect()
+    }
+
+    fn names() -> CompoundIndex {
+        let mut index = CompoundIndex::new(&["last_name", "first_name"]).unwrap();
+        index.insert(b"ada", &json!({"last_name": "Lovelace", "first_name": "Ada"}));
+        index.insert(b"byron", &json!({"last_name": "Lovelace"}));
+        index.insert(b"alan", &json!({"last_name": "Turing", "first_name": "Alan"}));
+        index.insert(b"annie", &json!({"last_name": "Lovelace", "first_name": "Annie"}));
+        index
+    }
+
+    #[test]
+    fn test_prefix_lookup() {
+        let index = names();
+        // Missing first names sort first, as null
+        assert_eq!(index.lookup_compound_prefix(&[json!("Lovelace")]).unwrap(), ids(&["byron", "ada", "annie"]));
+        assert_eq!(index.lookup_compound_prefix(&[json!("Turing")]).unwrap(), ids(&["alan"]));
+        assert!(index.lookup_compound_prefix(&[json!("Hopper")]).unwrap().is_empty());
+        assert_eq!(index.lookup_compound_prefix(&[]).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_full_key_lookup() {
+        let index = names();
+        assert_eq!(index.lookup_compound_prefix(&[json!("Lovelace"), json!("Ada")]).unwrap(), ids(&["ada"]));
+        assert!(index.lookup_compound_prefix(&[json!("Turing"), json!("Ada")]).unwrap().is_empty());
+        assert!(index.lookup_compound_prefix(&[json!("Turing"), json!("Alan"), json!(1)]).is_err());
+    }
+
+    #[test]
+    fn test_missing_field_indexed_as_null() {
+        let mut index = names();
+        assert_eq!(index.lookup_compound_prefix(&[json!("Lovelace"), JsonValue::Null]).unwrap(), ids(&["byron"]));
+
+        index.remove(b"byron", &json!({"last_name": "Lovelace"}));
+        assert!(index.lookup_compound_prefix(&[json!("Lovelace"), JsonValue::Null]).unwrap().is_empty());
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("people_last_name+first_name.cidx");
+        let index = names();
+
+        index.save(&path, true).unwrap();
+        let (loaded, complete) = CompoundIndex::load(&path).unwrap();
+        assert!(complete);
+        assert_eq!(loaded.fields(), ["last_name", "first_name"]);
+        assert_eq!(loaded.lookup_compound_prefix(&[json!("Lovelace")]).unwrap(), ids(&["byron", "ada", "annie"]));
+
+        index.save(&path, false).unwrap();
+        let (loaded, complete) = CompoundIndex::load(&path).unwrap();
+        assert!(!complete);
+        assert!(loaded.is_empty());
+    }
+}
@@ -1,9 +1,24 @@
 //! Index module for NebulaDB
 //!
-//! This module will handle the indexing of documents.
-//! For now, it's just a placeholder.
+//! Secondary indexes map the value of a document field to the IDs of the
+//! documents holding it, so equality and range lookups on that field do not
+//! need a full collection scan.
 
-use nebuladb_core::{Error, Result};
+pub mod btree;
+pub mod compound;
+pub mod text;
+pub mod unique;
+
+pub use btree::{BTreeIndex, IndexKey};
+pub use compound::CompoundIndex;
+pub use text::{SearchMode, TextIndex};
+pub use unique::UniqueIndex;
+
+use std::path::Path;
+
+use nebuladb_core::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 /// Index configuration
 #[derive(Debug, Clone)]
@@ -21,12 +36,150 @@ impl Default for IndexConfig {
     }
 }
 
-/// Index module initialization function (placeholder)
-pub fn init() -> &'static str {
-    "Index module initialized (not implemented yet)"
+/// Order in which scans produce their results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScanDirection {
+    /// Ascending: smallest key (or oldest write) first
+    #[default]
+    Forward,
+    /// Descending: largest key (or newest write) first
+    Reverse,
 }
 
-/// A placeholder for index functionality
-pub fn placeholder() -> &'static str {
-    "Index module is not yet implemented"
+/// Expiry rule removing documents a fixed time after the UNIX timestamp (in
+/// seconds) stored in one of their fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlIndex {
+    /// Field holding the timestamp (dotted paths allowed)
+    pub field: String,
+    /// Seconds a document lives after its timestamp
+    pub ttl_seconds: u64,
+}
+
+impl TtlIndex {
+    /// Expire documents `ttl_seconds` after the timestamp in `field`
+    pub fn new(field: &str, ttl_seconds: u64) -> Self {
+        Self { field: field.to_string(), ttl_seconds }
+    }
+
+    /// Whether `doc` has expired at UNIX time `now`
+    ///
+    /// Documents without an integer timestamp in the field never expire.
+    pub fn is_expired(&self, doc: &JsonValue, now: u64) -> bool {
+        match nebuladb_query::field_value(doc, &self.field).and_then(JsonValue::as_u64) {
+            Some(timestamp) => timestamp.saturating_add(self.ttl_seconds) < now,
+            None => false,
+        }
+    }
+}
+
+/// A secondary index over one document field
+pub trait Index {
+    /// The indexed field (dotted paths allowed)
+    fn field(&self) -> &str;
+
+    /// Check that `doc` may be stored under `id` without breaking a
+    /// constraint of the index
+    fn check(&self, _id: &[u8], _doc: &JsonValue) -> Result<()> {
+        Ok(())
+    }
+
+    /// Record `doc` under its value of the indexed field
+    ///
+    /// Documents without the field are not indexed.
+    fn insert(&mut self, id: &[u8], doc: &JsonValue) -> Result<()>;
+
+    /// Forget `doc`, previously recorded with [`insert`](Self::insert)
+    fn remove(&mut self, id: &[u8], doc: &JsonValue);
+
+    /// IDs of the documents whose field equals `value`
+    fn lookup_eq(&self, value: &JsonValue) -> Result<Vec<Vec<u8>>>;
+
+    /// IDs of the documents whose field lies between `low` and `high`,
+    /// inclusive, in field order
+    fn lookup_range(&self, low: &JsonValue, high: &JsonValue) -> Result<Vec<Vec<u8>>> {
+        self.scan_range(low, high, ScanDirection::Forward, None)
+    }
+
+    /// Like [`lookup_range`](Self::lookup_range), in the given direction and
+    /// stopping after `limit` IDs
+    fn scan_range(&self, low: &JsonValue, high: &JsonValue, direction: ScanDirection,
+        limit: Option<usize>) -> Result<Vec<Vec<u8>>>;
+}
+
+/// An index of either kind, as kept by a collection
+#[derive(Debug, Clone)]
+pub enum FieldIndex {
+    Plain(BTreeIndex),
+    Unique(UniqueIndex),
+}
+
+impl FieldIndex {
+    /// Whether the index rejects duplicate values
+    pub fn is_unique(&self) -> bool {
+        matches!(self, FieldIndex::Unique(_))
+    }
+
+    /// Save the index and its kind to `path`
+    ///
+    /// See [`BTreeIndex::save`] for the meaning of `complete`.
+    pub fn save(&self, path: &Path, complete: bool) -> Result<()> {
+        match self {
+            FieldIndex::Plain(index) => index.save_as(path, complete, false),
+            FieldIndex::Unique(index) => index.inner().save_as(path, complete, true),
+        }
+    }
+
+    /// Load an index saved with [`save`](Self::save), returning it with
+    /// whether its entries are complete
+    pub fn load(path: &Path) -> Result<(Self, bool)> {
+        let (index, complete, unique) = BTreeIndex::load_as(path)?;
+        let index = if unique {
+            FieldIndex::Unique(UniqueIndex::from_inner(index))
+        } else {
+            FieldIndex::Plain(index)
+        };
+        Ok((index, complete))
+    }
+
+    fn as_index(&self) -> &dyn Index {
+        match self {
+            FieldIndex::Plain(index) => index,
+            FieldIndex::Unique(index) => index,
+        }
+    }
+
+    fn as_index_mut(&mut self) -> &mut dyn Index {
+        match self {
+            FieldIndex::Plain(index) => index,
+            FieldIndex::Unique(index) => index,
+        }
+    }
+}
+
+impl Index for FieldIndex {
+    fn field(&self) -> &str {
+        self.as_index().field()
+    }
+
+    fn check(&self, id: &[u8], doc: &JsonValue) -> Result<()> {
+        self.as_index().check(id, doc)
+    }
+
+    fn insert(&mut self, id: &[u8], doc: &JsonValue) -> Result<()> {
+        self.as_index_mut().insert(id, doc)
+    }
+
+    fn remove(&mut self, id: &[u8], doc: &JsonValue) {
+        self.as_index_mut().remove(id, doc)
+    }
+
+    fn lookup_eq(&self, value: &JsonValue) -> Result<Vec<Vec<u8>>> {
+        self.as_index().lookup_eq(value)
+    }
+
+    fn scan_range(&self, low: &JsonValue, high: &JsonValue, direction: ScanDirection,
+        limit: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        self.as_index().scan_range(low, high, direction, limit)
+    }
 }
@@ -0,0 +1,259 @@
+//! Full-text index over a string field
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+use nebuladb_query::text::{tokenize, DEFAULT_STOP_WORDS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// How the terms of a text search combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Documents containing every term
+    All,
+    /// Documents containing at least one term
+    Any,
+}
+
+/// A document's tokens and how often each occurs
+type TokenCounts = Vec<(String, u32)>;
+
+/// On-disk form of a text index
+///
+/// Only per-document token counts are stored; postings are rebuilt from them.
+#[derive(Serialize, Deserialize)]
+struct TextIndexFile {
+    field: String,
+    stop_words: Vec<String>,
+    /// Whether `counts` is up to date; see `BTreeIndex::save`
+    complete: bool,
+    counts: Vec<(Vec<u8>, TokenCounts)>,
+}
+
+/// Index mapping the words of a string field to the documents using them
+#[derive(Debug, Clone)]
+pub struct TextIndex {
+    field: String,
+    stop_words: Vec<String>,
+    /// Token to the IDs of the documents containing it
+    postings: HashMap<String, Vec<Vec<u8>>>,
+    /// Document ID to how often each of its tokens occurs
+    counts: HashMap<Vec<u8>, HashMap<String, u32>>,
+}
+
+impl TextIndex {
+    /// Create an empty index over `field` using the default stop-words
+    pub fn new(field: &str) -> Self {
+        Self::with_stop_words(field, DEFAULT_STOP_WORDS.iter().map(|word| word.to_string()).collect())
+    }
+
+    /// Create an empty index over `field` leaving out `stop_words`
+    pub fn with_stop_words(field: &str, stop_words: Vec<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            stop_words: stop_words.into_iter().map(|word| word.to_lowercase()).collect(),
+            postings: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// The indexed field
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Number of distinct indexed tokens
+    pub fn len(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Whether no document is indexed
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+
+    /// Record the words of `doc`'s field under `id`, replacing any earlier
+    /// version
+    ///
+    /// Documents whose field is missing or not a string are not indexed.
+    pub fn insert(&mut self, id: &[u8], doc: &JsonValue) {
+        self.remove(id);
+
+        let text = match nebuladb_query::field_value(doc, &self.field).and_then(JsonValue::as_str) {
+            Some(text) => text,
+            None => return,
+        };
+
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for token in tokenize(text, &self.stop_words) {
+            *counts.entry(token).or_default() += 1;
+        }
+        if counts.is_empty() {
+            return;
+        }
+
+        for token in counts.keys() {
+            self.postings.entry(token.clone()).or_default().push(id.to_vec());
+        }
+        self.counts.insert(id.to_vec(), counts);
+    }
+
+    /// Forget the document stored under `id`
+    pub fn remove(&mut self, id: &[u8]) {
+        let Some(counts) = self.counts.remove(id) else { return };
+        for token in counts.keys() {
+            if let Some(ids) = self.postings.get_mut(token) {
+                ids.retain(|existing| existing != id);
+                if ids.is_empty() {
+                    self.postings.remove(token);
+                }
+            }
+        }
+    }
+
+    /// IDs of the documents matching `terms`, most relevant first
+    ///
+    /// Terms are tokenised like the indexed text, so `"Rust async"` is two
+    /// terms. Documents rank by how often the terms occur in them, ties by ID.
+    pub fn search(&self, terms: &[&str], mode: SearchMode) -> Vec<Vec<u8>> {
+        let mut tokens: Vec<String> = terms.iter()
+            .flat_map(|term| tokenize(term, &self.stop_words))
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let postings = tokens.iter().map(|token| self.postings.get(token).map(Vec::as_slice).unwrap_or_default());
+        let matches: HashSet<&[u8]> = match mode {
+            SearchMode::Any => postings.flatten().map(Vec::as_slice).collect(),
+            SearchMode::All => {
+                let mut postings = postings;
+                let first: HashSet<&[u8]> = postings.next().unwrap_or_default().iter().map(Vec::as_slice).collect();
+                postings.fold(first, |matches, ids| {
+                    ids.iter().map(Vec::as_slice).filter(|id| matches.contains(id)).collect()
+                })
+            }
+        };
+
+        let mut ranked: Vec<(u32, &[u8])> = matches.into_iter()
+            .map(|id| {
+                let counts = &self.counts[id];
+                (tokens.iter().filter_map(|token| counts.get(token)).sum(), id)
+            })
+            .collect();
+        ranked.sort_by(|(a_score, a_id), (b_score, b_id)| b_score.cmp(a_score).then(a_id.cmp(b_id)));
+
+        ranked.into_iter().map(|(_, id)| id.to_vec()).collect()
+    }
+
+    /// Save the index to `path`; see [`BTreeIndex::save`](crate::BTreeIndex::save)
+    pub fn save(&self, path: &Path, complete: bool) -> Result<()> {
+        let counts = if complete {
+            self.counts.iter()
+                .map(|(id, counts)| (id.clone(), counts.iter().map(|(token, n)| (token.clone(), *n)).collect()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let file = TextIndexFile {
+            field: self.field.clone(),
+            stop_words: self.stop_words.clone(),
+            complete,
+            counts,
+        };
+
+        let bytes = bincode::serialize(&file)
+            .map_err(|e| Error::Other(format!("Failed to encode text index: {}", e)))?;
+        let tmp_path = path.with_extension("idx.tmp");
+        fs::write(&tmp_path, bytes).map_err(Error::IoError)?;
+        fs::rename(&tmp_path, path).map_err(Error::IoError)
+    }
+
+    /// Load an index saved with [`save`](Self::save), returning it with
+    /// whether its entries are complete
+    pub fn load(path: &Path) -> Result<(Self, bool)> {
+        let bytes = fs::read(path).map_err(Error::IoError)?;
+        let file: TextIndexFile = bincode::deserialize(&bytes)
+            .map_err(|e| Error::Other(format!("Failed to decode text index {:?}: {}", path, e)))?;
+
+        let mut index = Self::with_stop_words(&file.field, file.stop_words);
+        for (id, counts) in file.counts {
+            for (token, _) in &counts {
+                index.postings.entry(token.clone()).or_default().push(id.clone());
+            }
+            index.counts.insert(id, counts.into_iter().collect());
+        }
+
+        Ok((index, file.complete))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn articles() -> TextIndex {
+        let mut index = TextIndex::new("body");
+        index.insert(b"a1", &json!({"body": "Async Rust: async all the way down"}));
+        index.insert(b"a2", &json!({"body": "Rust ownership explained"}));
+        index.insert(b"a3", &json!({"body": "Async JavaScript and the event loop"}));
+        index.insert(b"a4", &json!({"body": "Rust and async, an introduction to async rust"}));
+        index.insert(b"a5", &json!({"title": "no body"}));
+        index
+    }
+
+    #[test]
+    fn test_all_terms_must_match() {
+        let index = articles();
+        let found = index.search(&["rust async"], SearchMode::All);
+        // a4 mentions the terms four times, a1 three times
+        assert_eq!(found, vec![b"a4".to_vec(), b"a1".to_vec()]);
+        assert_eq!(index.search(&["RUST", "async"], SearchMode::All), found);
+        assert!(index.search(&["rust", "python"], SearchMode::All).is_empty());
+    }
+
+    #[test]
+    fn test_any_term_matches_ranked_by_frequency() {
+        let index = articles();
+        let found = index.search(&["rust", "javascript"], SearchMode::Any);
+        assert_eq!(found, vec![b"a4".to_vec(), b"a1".to_vec(), b"a2".to_vec(), b"a3".to_vec()]);
+        // Stop-words alone find nothing
+        assert!(index.search(&["the and"], SearchMode::Any).is_empty());
+    }
+
+    #[test]
+    fn test_reinsert_and_remove() {
+        let mut index = articles();
+        index.insert(b"a2", &json!({"body": "Garbage collection"}));
+        assert_eq!(index.search(&["rust"], SearchMode::Any), vec![b"a4".to_vec(), b"a1".to_vec()]);
+
+        index.remove(b"a4");
+        assert_eq!(index.search(&["rust"], SearchMode::Any), vec![b"a1".to_vec()]);
+        assert_eq!(index.search(&["garbage"], SearchMode::Any), vec![b"a2".to_vec()]);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("articles_body.txt.idx");
+        let mut index = TextIndex::with_stop_words("body", vec!["Rust".to_string()]);
+        index.insert(b"a1", &json!({"body": "Rust and async"}));
+
+        index.save(&path, true).unwrap();
+        let (loaded, complete) = TextIndex::load(&path).unwrap();
+        assert!(complete);
+        assert_eq!(loaded.search(&["and async"], SearchMode::All), vec![b"a1".to_vec()]);
+        assert!(loaded.search(&["rust"], SearchMode::Any).is_empty());
+
+        index.save(&path, false).unwrap();
+        let (loaded, complete) = TextIndex::load(&path).unwrap();
+        assert!(!complete);
+        assert!(loaded.is_empty());
+    }
+}
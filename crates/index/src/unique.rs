@@ -0,0 +1,109 @@
+//! Field index enforcing unique values
+
+use nebuladb_core::{Error, Result};
+use serde_json::Value as JsonValue;
+
+use crate::{BTreeIndex, Index, ScanDirection};
+
+/// A [`BTreeIndex`] that allows each field value on at most one document
+#[derive(Debug, Clone)]
+pub struct UniqueIndex {
+    inner: BTreeIndex,
+}
+
+impl UniqueIndex {
+    /// Create an empty unique index over `field`
+    pub fn new(field: &str) -> Self {
+        Self::from_inner(BTreeIndex::new(field))
+    }
+
+    /// Enforce uniqueness on an existing index
+    pub(crate) fn from_inner(inner: BTreeIndex) -> Self {
+        Self { inner }
+    }
+
+    /// The underlying index
+    pub fn inner(&self) -> &BTreeIndex {
+        &self.inner
+    }
+}
+
+impl Index for UniqueIndex {
+    fn field(&self) -> &str {
+        self.inner.field()
+    }
+
+    /// Fails with [`Error::DuplicateKey`] if another document already has
+    /// `doc`'s field value
+    fn check(&self, id: &[u8], doc: &JsonValue) -> Result<()> {
+        if let Some(value) = nebuladb_query::field_value(doc, self.field()) {
+            if self.inner.ids_for(value).iter().any(|existing| existing != id) {
+                return Err(Error::DuplicateKey {
+                    field: self.field().to_string(),
+                    value: value.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, id: &[u8], doc: &JsonValue) -> Result<()> {
+        self.check(id, doc)?;
+        self.inner.insert(id, doc)
+    }
+
+    fn remove(&mut self, id: &[u8], doc: &JsonValue) {
+        self.inner.remove(id, doc)
+    }
+
+    fn lookup_eq(&self, value: &JsonValue) -> Result<Vec<Vec<u8>>> {
+        self.inner.lookup_eq(value)
+    }
+
+    fn scan_range(&self, low: &JsonValue, high: &JsonValue, direction: ScanDirection,
+        limit: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        self.inner.scan_range(low, high, direction, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_duplicate_value_rejected() {
+        let mut index = UniqueIndex::new("email");
+        index.insert(b"a", &json!({"email": "ada@example.com"})).unwrap();
+
+        match index.insert(b"b", &json!({"email": "ada@example.com"})) {
+            Err(Error::DuplicateKey { field, value }) => {
+                assert_eq!(field, "email");
+                assert_eq!(value, r#""ada@example.com""#);
+            }
+            other => panic!("expected a duplicate key error, got {:?}", other),
+        }
+        assert_eq!(index.lookup_eq(&json!("ada@example.com")).unwrap(), vec![b"a".to_vec()]);
+
+        // The same document may keep its value, and a freed value is reusable
+        index.check(b"a", &json!({"email": "ada@example.com"})).unwrap();
+        index.remove(b"a", &json!({"email": "ada@example.com"}));
+        index.insert(b"b", &json!({"email": "ada@example.com"})).unwrap();
+    }
+
+    #[test]
+    fn test_distinct_large_integers_accepted() {
+        // 2^53 and 2^53 + 1 are the same f64 but different keys
+        let mut index = UniqueIndex::new("account");
+        index.insert(b"a", &json!({"account": 9007199254740992u64})).unwrap();
+        index.insert(b"b", &json!({"account": 9007199254740993u64})).unwrap();
+        index.insert(b"c", &json!({"account": u64::MAX})).unwrap();
+        index.insert(b"d", &json!({"account": u64::MAX - 1})).unwrap();
+
+        assert_eq!(index.lookup_eq(&json!(9007199254740993u64)).unwrap(), vec![b"b".to_vec()]);
+        assert_eq!(index.lookup_eq(&json!(u64::MAX)).unwrap(), vec![b"c".to_vec()]);
+        assert!(matches!(index.insert(b"e", &json!({"account": 9007199254740993u64})),
+            Err(Error::DuplicateKey { .. })));
+    }
+}
@@ -0,0 +1,69 @@
+//! Index lookups against a full scan
+
+use std::time::Instant;
+
+use nebuladb_index::{BTreeIndex, Index};
+use serde_json::{json, Value as JsonValue};
+
+const DOCUMENTS: usize = 50_000;
+
+fn documents() -> Vec<(Vec<u8>, JsonValue)> {
+    (0..DOCUMENTS)
+        .map(|i| (format!("user{}", i).into_bytes(), json!({"name": format!("User {}", i), "age": i % 100})))
+        .collect()
+}
+
+fn indexed(docs: &[(Vec<u8>, JsonValue)]) -> BTreeIndex {
+    let mut index = BTreeIndex::new("age");
+    for (id, doc) in docs {
+        index.insert(id, doc).unwrap();
+    }
+    index
+}
+
+/// What a query does without an index: test every document
+fn scan_eq(docs: &[(Vec<u8>, JsonValue)], value: &JsonValue) -> Vec<Vec<u8>> {
+    docs.iter()
+        .filter(|(_, doc)| doc.get("age") == Some(value))
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+#[test]
+fn test_lookup_faster_than_full_scan() {
+    let docs = documents();
+    let index = indexed(&docs);
+
+    let value = json!(42);
+    assert_eq!(index.lookup_eq(&value).unwrap(), scan_eq(&docs, &value));
+    assert_eq!(index.lookup_eq(&value).unwrap().len(), DOCUMENTS / 100);
+
+    let started = Instant::now();
+    for _ in 0..20 {
+        std::hint::black_box(scan_eq(&docs, &value));
+    }
+    let scan_time = started.elapsed();
+
+    let started = Instant::now();
+    for _ in 0..20 {
+        std::hint::black_box(index.lookup_eq(&value).unwrap());
+    }
+    let lookup_time = started.elapsed();
+
+    assert!(lookup_time * 5 < scan_time, "lookup {:?} vs scan {:?}", lookup_time, scan_time);
+}
+
+#[test]
+fn test_range_lookup_matches_full_scan() {
+    let docs = documents();
+    let index = indexed(&docs);
+
+    let mut expected: Vec<_> = docs.iter()
+        .filter(|(_, doc)| (10..=12).contains(&doc["age"].as_u64().unwrap()))
+        .map(|(id, _)| id.clone())
+        .collect();
+    let mut found = index.lookup_range(&json!(10), &json!(12)).unwrap();
+    expected.sort();
+    found.sort();
+    assert_eq!(found, expected);
+}
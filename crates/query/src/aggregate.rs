@@ -0,0 +1,323 @@
+//! Aggregation pipelines
+//!
+//! Documents flow through a list of stages that filter, group, count, sort
+//! and page them, as in
+//! `[{"$match": {"status": "paid"}}, {"$group": {"_id": "$region", "total": {"$sum": "$amount"}}}]`.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use nebuladb_core::{Error, Result};
+use serde_json::{Map, Number, Value as JsonValue};
+
+use crate::query::{self, execute_with, Query};
+
+/// Value computed over the documents of a group
+#[derive(Debug, Clone, PartialEq)]
+pub enum Accumulator {
+    /// Sum of the numeric values of a field (`$sum`)
+    Sum(String),
+    /// Mean of the numeric values of a field, or null if there are none (`$avg`)
+    Avg(String),
+    /// Smallest value of a field, or null if no document has it (`$min`)
+    Min(String),
+    /// Largest value of a field, or null if no document has it (`$max`)
+    Max(String),
+    /// Array of the values of a field, in document order (`$push`)
+    Collect(String),
+}
+
+/// One step of a [`Pipeline`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    /// Keep the documents matching the query (`$match`)
+    Match(Query),
+    /// One document per distinct value of `by`, holding that value as `_id`
+    /// and each named accumulator (`$group`); `None` puts every document in
+    /// a single group, as `"_id": null` does
+    Group { by: Option<String>, accumulators: Vec<(String, Accumulator)> },
+    /// A single document holding the number of documents under the given
+    /// name (`$count`)
+    Count(String),
+    /// Order by fields, each ascending when `true` (`$sort`); documents
+    /// without a field come after those with it
+    Sort(Vec<(String, bool)>),
+    /// Keep at most this many documents (`$limit`)
+    Limit(usize),
+    /// Drop this many leading documents (`$skip`)
+    Skip(usize),
+}
+
+/// Stages applied in order to a stream of documents
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Pipeline running `stages` in order
+    pub fn new(stages: Vec<Stage>) -> Self {
+        Self { stages }
+    }
+
+    /// Parse a pipeline from a JSON array of single-operator stages
+    pub fn from_json(json: &JsonValue) -> Result<Self> {
+        let stages = json.as_array()
+            .ok_or_else(|| Error::Other("Pipeline must be an array of stages".to_string()))?;
+        stages.iter().map(parse_stage).collect::<Result<_>>().map(Self::new)
+    }
+
+    /// Run the documents through every stage
+    pub fn execute(&self, docs: impl Iterator<Item = JsonValue>) -> Result<Vec<JsonValue>> {
+        let mut docs: Vec<JsonValue> = docs.collect();
+        for stage in &self.stages {
+            docs = match stage {
+                Stage::Match(query) => docs.into_iter().filter(|doc| execute_with(query, doc, false)).collect(),
+                Stage::Group { by, accumulators } => group(docs, by.as_deref(), accumulators),
+                Stage::Count(name) => vec![JsonValue::Object(Map::from_iter([(name.clone(), docs.len().into())]))],
+                Stage::Sort(keys) => {
+                    docs.sort_by(|a, b| compare_by(a, b, keys));
+                    docs
+                },
+                Stage::Limit(n) => docs.into_iter().take(*n).collect(),
+                Stage::Skip(n) => docs.into_iter().skip(*n).collect(),
+            };
+        }
+        Ok(docs)
+    }
+}
+
+/// Parse one `{"$operator": argument}` stage
+fn parse_stage(json: &JsonValue) -> Result<Stage> {
+    let (operator, argument) = match json.as_object() {
+        Some(stage) if stage.len() == 1 => stage.iter().next().unwrap(),
+        _ => return Err(Error::Other(format!("Stage must be an object with one operator: {}", json))),
+    };
+    let count = || argument.as_u64().map(|n| n as usize)
+        .ok_or_else(|| Error::Other(format!("{} needs a non-negative integer", operator)));
+
+    match operator.as_str() {
+        "$match" => Ok(Stage::Match(Query::from_json(argument)?)),
+        "$group" => parse_group(argument),
+        "$count" => match argument.as_str() {
+            Some(name) if !name.is_empty() && !name.starts_with('$') => Ok(Stage::Count(name.to_string())),
+            _ => Err(Error::Other("$count needs a field name".to_string())),
+        },
+        "$sort" => {
+            let fields = argument.as_object().filter(|fields| !fields.is_empty())
+                .ok_or_else(|| Error::Other("$sort needs an object of fields".to_string()))?;
+            fields.iter().map(|(field, direction)| match direction.as_i64() {
+                Some(1) => Ok((field.clone(), true)),
+                Some(-1) => Ok((field.clone(), false)),
+                _ => Err(Error::Other(format!("$sort direction of '{}' must be 1 or -1", field))),
+            }).collect::<Result<_>>().map(Stage::Sort)
+        },
+        "$limit" => Ok(Stage::Limit(count()?)),
+        "$skip" => Ok(Stage::Skip(count()?)),
+        _ => Err(Error::Other(format!("Unknown pipeline stage: {}", operator))),
+    }
+}
+
+/// Parse `{"_id": "$field", "name": {"$sum": "$field"}, ...}`
+fn parse_group(json: &JsonValue) -> Result<Stage> {
+    let spec = json.as_object()
+        .ok_or_else(|| Error::Other("$group needs an object".to_string()))?;
+    let by = match spec.get("_id") {
+        Some(JsonValue::Null) => None,
+        Some(id) => Some(field_ref(id, "_id")?),
+        None => return Err(Error::Other("$group needs an _id".to_string())),
+    };
+
+    let mut accumulators = Vec::new();
+    for (name, accumulator) in spec.iter().filter(|(name, _)| *name != "_id") {
+        let (operator, field) = match accumulator.as_object() {
+            Some(accumulator) if accumulator.len() == 1 => accumulator.iter().next().unwrap(),
+            _ => return Err(Error::Other(format!("Accumulator '{}' must be an object with one operator", name))),
+        };
+        let field = field_ref(field, operator)?;
+        accumulators.push((name.clone(), match operator.as_str() {
+            "$sum" => Accumulator::Sum(field),
+            "$avg" => Accumulator::Avg(field),
+            "$min" => Accumulator::Min(field),
+            "$max" => Accumulator::Max(field),
+            "$push" => Accumulator::Collect(field),
+            _ => return Err(Error::Other(format!("Unknown accumulator: {}", operator))),
+        }));
+    }
+
+    Ok(Stage::Group { by, accumulators })
+}
+
+/// Field path of a `"$field"` reference
+fn field_ref(json: &JsonValue, context: &str) -> Result<String> {
+    match json.as_str().and_then(|text| text.strip_prefix('$')) {
+        Some(field) if !field.is_empty() => Ok(field.to_string()),
+        _ => Err(Error::Other(format!("{} needs a field reference such as \"$amount\", got {}", context, json))),
+    }
+}
+
+/// Group `docs` by the value of `by`, in order of each group's first document
+fn group(docs: Vec<JsonValue>, by: Option<&str>, accumulators: &[(String, Accumulator)]) -> Vec<JsonValue> {
+    let mut groups: Vec<(JsonValue, Vec<JsonValue>)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for doc in docs {
+        let key = by.and_then(|field| query::lookup(&doc, field)).cloned().unwrap_or(JsonValue::Null);
+        let position = *positions.entry(key.to_string()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(doc);
+    }
+
+    groups.into_iter().map(|(key, members)| {
+        let mut result = Map::new();
+        result.insert("_id".to_string(), key);
+        for (name, accumulator) in accumulators {
+            result.insert(name.clone(), accumulate(accumulator, &members));
+        }
+        JsonValue::Object(result)
+    }).collect()
+}
+
+/// Value of `accumulator` over the documents of one group
+fn accumulate(accumulator: &Accumulator, docs: &[JsonValue]) -> JsonValue {
+    let values = |field: &str| docs.iter().filter_map(|doc| query::lookup(doc, field)).cloned().collect::<Vec<_>>();
+    let numbers = |field: &str| values(field).into_iter().filter(JsonValue::is_number).collect::<Vec<_>>();
+
+    match accumulator {
+        Accumulator::Sum(field) => sum(&numbers(field)),
+        Accumulator::Avg(field) => {
+            let numbers = numbers(field);
+            let total: f64 = numbers.iter().filter_map(JsonValue::as_f64).sum();
+            Number::from_f64(total / numbers.len() as f64).map_or(JsonValue::Null, JsonValue::Number)
+        },
+        Accumulator::Min(field) => values(field).into_iter().min_by(query_order).unwrap_or(JsonValue::Null),
+        Accumulator::Max(field) => values(field).into_iter().max_by(query_order).unwrap_or(JsonValue::Null),
+        Accumulator::Collect(field) => JsonValue::Array(values(field)),
+    }
+}
+
+/// Sum of numbers, kept as an integer while every number is one
+fn sum(numbers: &[JsonValue]) -> JsonValue {
+    let integers: Option<i64> = numbers.iter()
+        .try_fold(0i64, |total, number| total.checked_add(number.as_i64()?));
+    match integers {
+        Some(total) => total.into(),
+        None => {
+            let total: f64 = numbers.iter().filter_map(JsonValue::as_f64).sum();
+            Number::from_f64(total).map_or(JsonValue::Null, JsonValue::Number)
+        }
+    }
+}
+
+fn query_order(a: &JsonValue, b: &JsonValue) -> Ordering {
+    query::sort_order(Some(a), Some(b))
+}
+
+/// Compare two documents by the sort keys, in turn
+fn compare_by(a: &JsonValue, b: &JsonValue, keys: &[(String, bool)]) -> Ordering {
+    keys.iter().map(|(field, ascending)| match (query::lookup(a, field), query::lookup(b, field)) {
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (a, b) => {
+            let order = query::sort_order(a, b);
+            if *ascending { order } else { order.reverse() }
+        }
+    }).find(|order| order.is_ne()).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const REGIONS: [&str; 4] = ["north", "south", "east", "west"];
+
+    fn amount(i: usize) -> i64 {
+        (i * 7 % 100 + i % 4 * 3) as i64
+    }
+
+    /// 500 sales records: region cycles, amounts vary, some are refunds
+    fn sales() -> Vec<JsonValue> {
+        (0..500).map(|i| json!({
+            "region": REGIONS[i % 4],
+            "amount": amount(i),
+            "refund": i % 10 == 0,
+        })).collect()
+    }
+
+    fn pipeline(json: JsonValue) -> Pipeline {
+        Pipeline::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn test_group_by_region_with_sum_and_average() {
+        let results = pipeline(json!([
+            {"$group": {"_id": "$region", "total": {"$sum": "$amount"}, "average": {"$avg": "$amount"}}}
+        ])).execute(sales().into_iter()).unwrap();
+
+        assert_eq!(results.len(), 4);
+        for (result, region) in results.iter().zip(REGIONS) {
+            let amounts: Vec<i64> = (0..500).filter(|i| REGIONS[i % 4] == region).map(amount).collect();
+            let total: i64 = amounts.iter().sum();
+            assert_eq!(result["_id"], json!(region));
+            assert_eq!(result["total"], json!(total));
+            assert!((result["average"].as_f64().unwrap() - total as f64 / amounts.len() as f64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_match_group_sort_and_page() {
+        let paid = |i: &usize| !i.is_multiple_of(10);
+        let results = pipeline(json!([
+            {"$match": {"refund": false}},
+            {"$group": {"_id": "$region", "total": {"$sum": "$amount"}, "largest": {"$max": "$amount"}}},
+            {"$sort": {"total": -1}},
+            {"$skip": 1},
+            {"$limit": 2},
+        ])).execute(sales().into_iter()).unwrap();
+
+        let mut expected: Vec<JsonValue> = REGIONS.iter().map(|region| {
+            let amounts: Vec<i64> = (0..500).filter(paid).filter(|i| REGIONS[i % 4] == *region).map(amount).collect();
+            json!({"_id": region, "total": amounts.iter().sum::<i64>(), "largest": amounts.iter().max()})
+        }).collect();
+        expected.sort_by_key(|result| -result["total"].as_i64().unwrap());
+        assert_eq!(results, expected[1..3]);
+
+        let counted = pipeline(json!([{"$match": {"refund": true}}, {"$count": "refunds"}]))
+            .execute(sales().into_iter()).unwrap();
+        assert_eq!(counted, vec![json!({"refunds": 50})]);
+    }
+
+    #[test]
+    fn test_accumulators_over_missing_and_mixed_values() {
+        let docs = vec![json!({"n": 2}), json!({"n": 1.5}), json!({"n": "x"}), json!({})];
+        let results = pipeline(json!([{"$group": {
+            "_id": null,
+            "sum": {"$sum": "$n"},
+            "avg": {"$avg": "$missing"},
+            "min": {"$min": "$n"},
+            "max": {"$max": "$n"},
+            "all": {"$push": "$n"},
+        }}])).execute(docs.into_iter()).unwrap();
+        assert_eq!(results, vec![json!({
+            "_id": null, "sum": 3.5, "avg": null, "min": 1.5, "max": "x", "all": [2, 1.5, "x"],
+        })]);
+    }
+
+    #[test]
+    fn test_invalid_pipelines() {
+        for json in [
+            json!({"$match": {}}),
+            json!([{"$match": {}, "$limit": 1}]),
+            json!([{"$unwind": "$tags"}]),
+            json!([{"$group": {"total": {"$sum": "$amount"}}}]),
+            json!([{"$group": {"_id": "$region", "total": {"$sum": 1}}}]),
+            json!([{"$sort": {"total": 0}}]),
+            json!([{"$limit": -1}]),
+            json!([{"$count": ""}]),
+        ] {
+            assert!(Pipeline::from_json(&json).is_err(), "{}", json);
+        }
+    }
+}
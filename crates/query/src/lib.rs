@@ -1,10 +1,28 @@
 //! Query engine for NebulaDB
 
+pub mod aggregate;
+pub mod projection;
+pub mod query;
+pub mod text;
+
+pub use aggregate::{Accumulator, Pipeline, Stage};
+pub use projection::{Projection, ProjectionMode};
+pub use query::{execute, execute_with, Query};
+
+use std::cmp::Ordering;
+
+use nebuladb_core::{Error, Result};
+use serde_json::Value as JsonValue;
+
 /// Query engine configuration
 #[derive(Debug, Clone)]
 pub struct QueryConfig {
+    /// Maximum number of documents a query may return
     pub max_results: usize,
     pub timeout_ms: u64,
+    /// Let numbers match numeric strings (`30` matches `"30"`) when the types
+    /// differ; strict matching is the default
+    pub coerce_types: bool,
 }
 
 impl Default for QueryConfig {
@@ -12,11 +30,258 @@ impl Default for QueryConfig {
         Self {
             max_results: 1000,
             timeout_ms: 30000, // 30 seconds
+            coerce_types: false,
         }
     }
 }
 
-/// Query module initialization function (placeholder)
-pub fn init() -> &'static str {
-    "Query module initialized (not implemented yet)"
+/// Resolve a dotted field path such as `address.city` inside a document
+pub fn field_value<'a>(doc: &'a JsonValue, field: &str) -> Option<&'a JsonValue> {
+    query::lookup(doc, field)
+}
+
+/// Total order over JSON values, as used by `FindOptions` sorting
+///
+/// Values rank null < number < string < bool < array < object; numbers
+/// compare numerically, so `1` and `1.0` are equal, and integers compare
+/// exactly.
+pub fn compare_values(a: &JsonValue, b: &JsonValue) -> Ordering {
+    query::sort_order(Some(a), Some(b))
+}
+
+/// Ordering and paging applied to query results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FindOptions {
+    /// Field to sort by (dotted paths allowed); `None` keeps scan order.
+    /// Documents without the field come last in either direction.
+    pub sort_field: Option<String>,
+    /// Sort in ascending rather than descending order
+    pub sort_asc: bool,
+    /// Maximum number of results returned after skipping
+    pub limit: Option<usize>,
+    /// Number of leading results to drop
+    pub skip: usize,
+}
+
+impl Default for FindOptions {
+    fn default() -> Self {
+        Self {
+            sort_field: None,
+            sort_asc: true,
+            limit: None,
+            skip: 0,
+        }
+    }
+}
+
+impl FindOptions {
+    /// Sort `results` by the sort field, then apply `skip` and `limit`
+    ///
+    /// The sort is stable, so documents with equal sort values keep their
+    /// relative order.
+    pub fn apply<K>(&self, mut results: Vec<(K, JsonValue)>) -> Vec<(K, JsonValue)> {
+        if self.sort_field.is_some() {
+            results.sort_by(|(_, a), (_, b)| self.order(a, b));
+        }
+
+        results.into_iter()
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Order of two documents by the sort field; equal without one
+    fn order(&self, a: &JsonValue, b: &JsonValue) -> Ordering {
+        let Some(field) = &self.sort_field else {
+            return Ordering::Equal;
+        };
+        match (query::lookup(a, field), query::lookup(b, field)) {
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (a, b) => {
+                let order = query::sort_order(a, b);
+                if self.sort_asc { order } else { order.reverse() }
+            }
+        }
+    }
+}
+
+/// Keep the documents matching `query`, failing once more than
+/// `config.max_results` match
+///
+/// Each document is paired with a caller-defined key (typically its ID).
+pub fn find<K>(
+    query: &Query,
+    documents: impl IntoIterator<Item = (K, JsonValue)>,
+    config: &QueryConfig,
+) -> Result<Vec<(K, JsonValue)>> {
+    find_with_options(query, documents, &FindOptions::default(), config)
+}
+
+/// Keep the page of documents matching `query` selected by `options`,
+/// failing if the page would hold more than `config.max_results` documents
+///
+/// Only the matches that can still make it into the page are held: without
+/// a sort field the documents stop being read once the page is full, and
+/// with one the best `skip + limit` matches so far are kept.
+pub fn find_with_options<K>(
+    query: &Query,
+    documents: impl IntoIterator<Item = (K, JsonValue)>,
+    options: &FindOptions,
+    config: &QueryConfig,
+) -> Result<Vec<(K, JsonValue)>> {
+    // One match past the maximum is enough to know the page is too large
+    let page_size = options.limit.unwrap_or(usize::MAX).min(config.max_results.saturating_add(1));
+    let window = options.skip.saturating_add(page_size);
+    let mut kept: Vec<(K, JsonValue)> = Vec::new();
+
+    for (key, doc) in documents {
+        if !execute_with(query, &doc, config.coerce_types) {
+            continue;
+        }
+        if options.sort_field.is_none() {
+            if kept.len() == window {
+                break;
+            }
+            kept.push((key, doc));
+            continue;
+        }
+
+        // After every kept match it does not sort before, so the sort is stable
+        let position = kept.partition_point(|(_, kept)| options.order(kept, &doc) != Ordering::Greater);
+        if position < window {
+            kept.insert(position, (key, doc));
+            kept.truncate(window);
+        }
+    }
+
+    let page: Vec<_> = kept.into_iter().skip(options.skip).collect();
+    if page.len() > config.max_results {
+        return Err(Error::Other(format!(
+            "Query matched more than the maximum of {} results", config.max_results)));
+    }
+
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_enforces_max_results() {
+        let docs: Vec<_> = (0..5).map(|i| (i, json!({"n": i}))).collect();
+        let query = Query::from_json(&json!({"n": {"$gte": 2}})).unwrap();
+
+        let config = QueryConfig { max_results: 3, ..QueryConfig::default() };
+        let found = find(&query, docs.clone(), &config).unwrap();
+        assert_eq!(found.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        let config = QueryConfig { max_results: 2, ..QueryConfig::default() };
+        assert!(find(&query, docs, &config).is_err());
+    }
+
+    #[test]
+    fn test_find_with_options_caps_the_page_not_the_matches() {
+        let docs: Vec<_> = (0..50).map(|i| (i, json!({"n": i % 10}))).collect();
+        let all = Query::from_json(&json!({})).unwrap();
+        let config = QueryConfig { max_results: 5, ..QueryConfig::default() };
+
+        let options = FindOptions { limit: Some(3), skip: 40, ..FindOptions::default() };
+        let found = find_with_options(&all, docs.clone(), &options, &config).unwrap();
+        assert_eq!(found.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![40, 41, 42]);
+
+        // Equal sort values keep their scan order
+        let options = FindOptions { sort_field: Some("n".into()), sort_asc: false, limit: Some(4), skip: 3 };
+        let found = find_with_options(&all, docs.clone(), &options, &config).unwrap();
+        assert_eq!(found.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![39, 49, 8, 18]);
+        assert_eq!(found, options.apply(docs.clone()));
+
+        let options = FindOptions { limit: Some(6), ..FindOptions::default() };
+        assert!(find_with_options(&all, docs.clone(), &options, &config).is_err());
+        let options = FindOptions { skip: 45, ..FindOptions::default() };
+        assert_eq!(find_with_options(&all, docs.clone(), &options, &config).unwrap().len(), 5);
+        let options = FindOptions { sort_field: Some("n".into()), skip: 44, ..FindOptions::default() };
+        assert!(find_with_options(&all, docs, &options, &config).is_err());
+    }
+
+    fn ids<'a>(results: &[(&'a str, JsonValue)]) -> Vec<&'a str> {
+        results.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn test_sort_numeric_field() {
+        let docs = vec![
+            ("a", json!({"age": 9})),
+            ("b", json!({"age": 100})),
+            ("c", json!({"age": 25.5})),
+            ("d", json!({})),
+        ];
+
+        let options = FindOptions { sort_field: Some("age".into()), ..FindOptions::default() };
+        // Numeric, not lexicographic, order; the missing field sorts last
+        assert_eq!(ids(&options.apply(docs.clone())), vec!["a", "c", "b", "d"]);
+
+        let options = FindOptions { sort_asc: false, ..options };
+        assert_eq!(ids(&options.apply(docs)), vec!["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn test_sort_ties_and_missing_fields() {
+        let docs = vec![
+            ("a", json!({"address": {"zip": 20}})),
+            ("b", json!({"address": {}})),
+            ("c", json!({"address": {"zip": 10}})),
+            ("d", json!({"address": {"zip": 20}})),
+            ("e", json!({"address": {"zip": null}})),
+            ("f", json!({})),
+        ];
+
+        // Ties keep scan order; null is a value, unlike a missing field
+        let options = FindOptions { sort_field: Some("address.zip".into()), ..FindOptions::default() };
+        assert_eq!(ids(&options.apply(docs.clone())), vec!["e", "c", "a", "d", "b", "f"]);
+
+        let options = FindOptions { sort_asc: false, ..options };
+        assert_eq!(ids(&options.apply(docs)), vec!["a", "d", "c", "e", "b", "f"]);
+    }
+
+    #[test]
+    fn test_sort_string_field() {
+        let docs = vec![
+            ("a", json!({"name": "carol"})),
+            ("b", json!({"name": "alice"})),
+            ("c", json!({"name": "Bob"})),
+            ("d", json!({"name": "10"})),
+        ];
+
+        let options = FindOptions { sort_field: Some("name".into()), ..FindOptions::default() };
+        assert_eq!(ids(&options.apply(docs)), vec!["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_compare_values_is_total() {
+        assert_eq!(compare_values(&json!(1), &json!(1.0)), Ordering::Equal);
+        assert_eq!(compare_values(&json!(null), &json!(0)), Ordering::Less);
+        assert_eq!(compare_values(&json!("b"), &json!(true)), Ordering::Less);
+        assert_eq!(compare_values(&json!([1, 2]), &json!([1, 3])), Ordering::Less);
+        assert_eq!(compare_values(&json!([1, 2]), &json!([1])), Ordering::Greater);
+        assert_eq!(compare_values(&json!({"a": 1}), &json!({"a": 2})), Ordering::Less);
+    }
+
+    #[test]
+    fn test_skip_and_limit() {
+        let docs: Vec<_> = ["a", "b", "c", "d", "e"].into_iter().map(|id| (id, json!({"id": id}))).collect();
+
+        let options = FindOptions {
+            sort_field: Some("id".into()),
+            sort_asc: false,
+            limit: Some(2),
+            skip: 1,
+        };
+        assert_eq!(ids(&options.apply(docs.clone())), vec!["d", "c"]);
+
+        let options = FindOptions { skip: 10, ..FindOptions::default() };
+        assert!(options.apply(docs).is_empty());
+    }
 }
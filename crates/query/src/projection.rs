@@ -0,0 +1,185 @@
+//! Field projection for query results
+//!
+//! Projections follow MongoDB semantics: a projection either lists the fields
+//! to keep or the fields to drop, and `_id` is kept unless it is explicitly
+//! excluded.
+
+use std::collections::HashSet;
+
+use nebuladb_core::{Error, Result};
+use serde_json::{Map, Value as JsonValue};
+
+/// Name of the document ID field
+const ID_FIELD: &str = "_id";
+
+/// Whether a projection lists the fields to keep or the fields to drop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Include,
+    Exclude,
+}
+
+/// Selection of top-level document fields returned by a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    mode: ProjectionMode,
+    fields: HashSet<String>,
+    /// Whether `_id` is kept in include mode
+    include_id: bool,
+}
+
+impl Projection {
+    /// Keep only `fields` (plus `_id`)
+    pub fn include<I, S>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            mode: ProjectionMode::Include,
+            fields: fields.into_iter().map(Into::into).collect(),
+            include_id: true,
+        }
+    }
+
+    /// Keep every field except `fields`
+    pub fn exclude<I, S>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            mode: ProjectionMode::Exclude,
+            fields: fields.into_iter().map(Into::into).collect(),
+            include_id: true,
+        }
+    }
+
+    /// Drop `_id` from the projected documents
+    pub fn without_id(mut self) -> Self {
+        match self.mode {
+            ProjectionMode::Include => self.include_id = false,
+            ProjectionMode::Exclude => {
+                self.fields.insert(ID_FIELD.to_string());
+            }
+        }
+        self
+    }
+
+    /// Parse a projection document such as `{"name": 1, "age": 1}` or
+    /// `{"bio": 0}`
+    ///
+    /// Included and excluded fields cannot be mixed, except for excluding
+    /// `_id` from an include projection.
+    pub fn from_json(value: &JsonValue) -> Result<Self> {
+        let object = value.as_object().ok_or_else(|| Error::Other(
+            "Projection must be a JSON object".to_string()))?;
+
+        let mut included = Vec::new();
+        let mut excluded = Vec::new();
+        let mut exclude_id = false;
+
+        for (field, flag) in object {
+            let keep = match flag {
+                JsonValue::Bool(keep) => *keep,
+                JsonValue::Number(n) => n.as_f64() != Some(0.0),
+                _ => return Err(Error::Other(format!(
+                    "Projection value for '{}' must be 0, 1, true or false", field))),
+            };
+
+            if field == ID_FIELD && !keep {
+                exclude_id = true;
+            } else if keep {
+                included.push(field.clone());
+            } else {
+                excluded.push(field.clone());
+            }
+        }
+
+        if !included.is_empty() && !excluded.is_empty() {
+            return Err(Error::Other(
+                "Projection cannot both include and exclude fields".to_string()));
+        }
+
+        let projection = if included.is_empty() {
+            Self::exclude(excluded)
+        } else {
+            Self::include(included)
+        };
+
+        Ok(if exclude_id { projection.without_id() } else { projection })
+    }
+
+    /// Whether the projection lists kept or dropped fields
+    pub fn mode(&self) -> ProjectionMode {
+        self.mode
+    }
+
+    /// Whether `field` appears in the projected documents
+    pub fn keeps(&self, field: &str) -> bool {
+        match self.mode {
+            ProjectionMode::Include if field == ID_FIELD => self.include_id,
+            ProjectionMode::Include => self.fields.contains(field),
+            ProjectionMode::Exclude => !self.fields.contains(field),
+        }
+    }
+
+    /// Apply the projection to a document
+    ///
+    /// Documents that are not JSON objects are returned unchanged.
+    pub fn apply(&self, doc: &JsonValue) -> JsonValue {
+        match doc {
+            JsonValue::Object(fields) => {
+                let projected: Map<String, JsonValue> = fields.iter()
+                    .filter(|(field, _)| self.keeps(field))
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect();
+                JsonValue::Object(projected)
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn user() -> JsonValue {
+        json!({"_id": "u1", "name": "Ada", "age": 36, "bio": "...", "tags": ["math"]})
+    }
+
+    #[test]
+    fn test_include_keeps_listed_fields_and_id() {
+        let projection = Projection::include(["name", "age"]);
+        assert_eq!(projection.apply(&user()), json!({"_id": "u1", "name": "Ada", "age": 36}));
+
+        let projection = projection.without_id();
+        assert_eq!(projection.apply(&user()), json!({"name": "Ada", "age": 36}));
+    }
+
+    #[test]
+    fn test_exclude_drops_listed_fields() {
+        let projected = Projection::exclude(["bio", "tags"]).apply(&user());
+        assert_eq!(projected, json!({"_id": "u1", "name": "Ada", "age": 36}));
+        assert!(projected.get("bio").is_none());
+
+        let projected = Projection::exclude(["bio"]).without_id().apply(&user());
+        assert!(projected.get("_id").is_none());
+        assert!(projected.get("bio").is_none());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let projection = Projection::from_json(&json!({"name": 1, "_id": 0})).unwrap();
+        assert_eq!(projection, Projection::include(["name"]).without_id());
+
+        let projection = Projection::from_json(&json!({"bio": false})).unwrap();
+        assert_eq!(projection, Projection::exclude(["bio"]));
+
+        assert!(Projection::from_json(&json!({"name": 1, "bio": 0})).is_err());
+        assert!(Projection::from_json(&json!({"name": "yes"})).is_err());
+        assert!(Projection::from_json(&json!(["name"])).is_err());
+    }
+}
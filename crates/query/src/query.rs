@@ -0,0 +1,704 @@
+//! JSON query parsing and evaluation
+//!
+//! Queries use the MongoDB-style JSON syntax:
+//!
+//! - `{ "field": value }` matches documents whose field equals `value`
+//! - `{ "field": { "$ne": value } }` matches documents whose field is missing
+//!   or differs from `value`
+//! - `{ "field": { "$gt": value } }` (and `$gte`, `$lt`, `$lte`) compares
+//!   numbers or strings
+//! - `{ "field": { "$in": [v1, v2] } }` and `$nin` test membership in a list;
+//!   an array field is a member if any of its elements is
+//! - `{ "field": { "$exists": true } }` tests whether the field is present
+//! - `{ "field": { "$type": "string" } }` tests the JSON type of the field
+//! - `{ "field": { "$regex": "^A" } }` matches string fields against a pattern;
+//!   `"$options"` beside it sets flags: `i` ignores case, `m` makes `^`/`$`
+//!   match at line breaks, `s` lets `.` match newlines, `x` ignores whitespace
+//! - `{ "field": { "$text": "some words" } }` matches string fields containing
+//!   every word, ignoring case, punctuation and stop-words
+//! - `{ "$and": [q1, q2] }` and `{ "$or": [q1, q2] }` combine sub-queries
+//!
+//! Several keys in one object must all match. Field names may use dots to
+//! reach into nested objects (`"address.city"`).
+
+use std::cmp::Ordering;
+
+use nebuladb_core::{Error, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::text;
+use serde_json::{Number, Value as JsonValue};
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Field equals the value
+    Eq { field: String, value: JsonValue },
+    /// Field is missing or does not equal the value
+    Ne { field: String, value: JsonValue },
+    /// Field is greater than the value
+    Gt { field: String, value: JsonValue },
+    /// Field is greater than or equal to the value
+    Gte { field: String, value: JsonValue },
+    /// Field is less than the value
+    Lt { field: String, value: JsonValue },
+    /// Field is less than or equal to the value
+    Lte { field: String, value: JsonValue },
+    /// Field, or an element of an array field, equals one of the values (an
+    /// empty list matches nothing)
+    In { field: String, values: Vec<JsonValue> },
+    /// Field is missing or `In` does not match it
+    Nin { field: String, values: Vec<JsonValue> },
+    /// Field is present (`true`) or absent (`false`)
+    Exists { field: String, exists: bool },
+    /// Field is present and has the given JSON type
+    Type { field: String, json_type: JsonType },
+    /// Field is a string matching the pattern
+    Regex { field: String, pattern: Pattern },
+    /// Field is a string containing every token (an empty list matches any string)
+    Text { field: String, terms: Vec<String> },
+    /// Every sub-query matches (an empty list matches everything)
+    And(Vec<Query>),
+    /// At least one sub-query matches (an empty list matches nothing)
+    Or(Vec<Query>),
+}
+
+/// JSON value types accepted by `$type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonType {
+    String,
+    Number,
+    Bool,
+    Null,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    /// Parse a `$type` operand
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(JsonType::String),
+            "number" => Some(JsonType::Number),
+            "bool" => Some(JsonType::Bool),
+            "null" => Some(JsonType::Null),
+            "array" => Some(JsonType::Array),
+            "object" => Some(JsonType::Object),
+            _ => None,
+        }
+    }
+
+    /// The type of a JSON value
+    fn of(value: &JsonValue) -> Self {
+        match value {
+            JsonValue::String(_) => JsonType::String,
+            JsonValue::Number(_) => JsonType::Number,
+            JsonValue::Bool(_) => JsonType::Bool,
+            JsonValue::Null => JsonType::Null,
+            JsonValue::Array(_) => JsonType::Array,
+            JsonValue::Object(_) => JsonType::Object,
+        }
+    }
+}
+
+/// A compiled `$regex` pattern, compared by its source text and options
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+    options: String,
+}
+
+impl Pattern {
+    /// Compile a pattern
+    pub fn new(pattern: &str) -> Result<Self> {
+        Self::with_options(pattern, "")
+    }
+
+    /// Compile a pattern with `$options` flags, any of `i`, `m`, `s` and `x`
+    pub fn with_options(pattern: &str, options: &str) -> Result<Self> {
+        let mut builder = RegexBuilder::new(pattern);
+        for flag in options.chars() {
+            match flag {
+                'i' => builder.case_insensitive(true),
+                'm' => builder.multi_line(true),
+                's' => builder.dot_matches_new_line(true),
+                'x' => builder.ignore_whitespace(true),
+                _ => return Err(Error::Other(format!("Unknown $options flag '{}'", flag))),
+            };
+        }
+        let regex = builder.build()
+            .map_err(|e| Error::Other(format!("Invalid $regex pattern '{}': {}", pattern, e)))?;
+        Ok(Pattern { regex, options: options.to_string() })
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.options == other.options
+    }
+}
+
+impl Query {
+    /// Parse a query from its JSON form
+    pub fn from_json(json: &JsonValue) -> Result<Query> {
+        let object = json.as_object()
+            .ok_or_else(|| Error::Other(format!("Query must be a JSON object, got {}", json)))?;
+
+        let mut clauses = Vec::new();
+        for (key, value) in object {
+            match key.as_str() {
+                "$and" => clauses.push(Query::And(Self::parse_list(key, value)?)),
+                "$or" => clauses.push(Query::Or(Self::parse_list(key, value)?)),
+                op if op.starts_with('$') => {
+                    return Err(Error::Other(format!("Unknown top-level query operator '{}'", op)));
+                }
+                field => Self::parse_field(field, value, &mut clauses)?,
+            }
+        }
+
+        Ok(Self::all_of(clauses))
+    }
+
+    /// Parse the array operand of `$and`/`$or`
+    fn parse_list(op: &str, value: &JsonValue) -> Result<Vec<Query>> {
+        let items = value.as_array()
+            .ok_or_else(|| Error::Other(format!("'{}' expects an array of queries", op)))?;
+
+        items.iter().map(Self::from_json).collect()
+    }
+
+    /// Parse the condition on a single field
+    ///
+    /// An object whose keys all start with `$` holds operators; any other
+    /// value is matched by equality.
+    fn parse_field(field: &str, value: &JsonValue, clauses: &mut Vec<Query>) -> Result<()> {
+        let operators = match value.as_object() {
+            Some(object) if !object.is_empty() && object.keys().all(|key| key.starts_with('$')) => object,
+            _ => {
+                clauses.push(Query::Eq { field: field.to_string(), value: value.clone() });
+                return Ok(());
+            }
+        };
+
+        let regex_options = match operators.get("$options") {
+            None => "",
+            Some(_) if !operators.contains_key("$regex") => {
+                return Err(Error::Other(format!("'$options' on field '{}' needs a '$regex'", field)));
+            }
+            Some(options) => options.as_str()
+                .ok_or_else(|| Error::Other("'$options' expects a string of flags".to_string()))?,
+        };
+
+        for (op, operand) in operators {
+            if op == "$options" {
+                continue;
+            }
+            let field = field.to_string();
+            let value = operand.clone();
+            clauses.push(match op.as_str() {
+                "$eq" => Query::Eq { field, value },
+                "$ne" => Query::Ne { field, value },
+                "$gt" => Query::Gt { field, value },
+                "$gte" => Query::Gte { field, value },
+                "$lt" => Query::Lt { field, value },
+                "$lte" => Query::Lte { field, value },
+                "$in" => Query::In { field, values: Self::parse_values(op, operand)? },
+                "$nin" => Query::Nin { field, values: Self::parse_values(op, operand)? },
+                "$exists" => Query::Exists {
+                    field,
+                    exists: operand.as_bool()
+                        .ok_or_else(|| Error::Other("'$exists' expects true or false".to_string()))?,
+                },
+                "$type" => Query::Type {
+                    field,
+                    json_type: operand.as_str()
+                        .and_then(JsonType::from_name)
+                        .ok_or_else(|| Error::Other(format!(
+                            "'$type' expects one of string, number, bool, null, array, object; got {}", operand)))?,
+                },
+                "$regex" => Query::Regex {
+                    field,
+                    pattern: Pattern::with_options(operand.as_str()
+                        .ok_or_else(|| Error::Other("'$regex' expects a string pattern".to_string()))?,
+                        regex_options)?,
+                },
+                "$text" => Query::Text {
+                    field,
+                    terms: text::tokenize(operand.as_str()
+                        .ok_or_else(|| Error::Other("'$text' expects a string of words".to_string()))?,
+                        text::DEFAULT_STOP_WORDS),
+                },
+                _ => return Err(Error::Other(format!("Unknown operator '{}' on field '{}'", op, field))),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Parse the array operand of `$in`/`$nin`
+    fn parse_values(op: &str, value: &JsonValue) -> Result<Vec<JsonValue>> {
+        value.as_array()
+            .cloned()
+            .ok_or_else(|| Error::Other(format!("'{}' expects an array of values", op)))
+    }
+
+    /// Combine clauses that must all match, avoiding a wrapper for a single clause
+    fn all_of(mut clauses: Vec<Query>) -> Query {
+        if clauses.len() == 1 {
+            clauses.remove(0)
+        } else {
+            Query::And(clauses)
+        }
+    }
+}
+
+/// Whether a document matches a query, comparing values strictly by type
+pub fn execute(query: &Query, doc: &JsonValue) -> bool {
+    execute_with(query, doc, false)
+}
+
+/// Whether a document matches a query
+///
+/// With `coerce_types` set, a number and a string holding a number compare
+/// as numbers when their types differ.
+pub fn execute_with(query: &Query, doc: &JsonValue, coerce_types: bool) -> bool {
+    let equal = |actual: &JsonValue, value: &JsonValue| values_equal(actual, value, coerce_types);
+    let compare_field = |field: &str, value: &JsonValue, accept: fn(Ordering) -> bool| lookup(doc, field)
+        .and_then(|actual| compare(actual, value, coerce_types))
+        .is_some_and(accept);
+    
+    match query {
+        Query::Eq { field, value } => lookup(doc, field).is_some_and(|actual| equal(actual, value)),
+        Query::Ne { field, value } => !lookup(doc, field).is_some_and(|actual| equal(actual, value)),
+        Query::Gt { field, value } => compare_field(field, value, |o| o == Ordering::Greater),
+        Query::Gte { field, value } => compare_field(field, value, |o| o != Ordering::Less),
+        Query::Lt { field, value } => compare_field(field, value, |o| o == Ordering::Less),
+        Query::Lte { field, value } => compare_field(field, value, |o| o != Ordering::Greater),
+        Query::In { field, values } => lookup(doc, field)
+            .is_some_and(|actual| is_in(actual, values, equal)),
+        Query::Nin { field, values } => !lookup(doc, field)
+            .is_some_and(|actual| is_in(actual, values, equal)),
+        Query::Exists { field, exists } => lookup(doc, field).is_some() == *exists,
+        Query::Type { field, json_type } => lookup(doc, field).is_some_and(|actual| JsonType::of(actual) == *json_type),
+        // Non-string fields never match rather than being an error
+        Query::Regex { field, pattern } => lookup(doc, field)
+            .and_then(JsonValue::as_str)
+            .is_some_and(|text| pattern.is_match(text)),
+        Query::Text { field, terms } => lookup(doc, field)
+            .and_then(JsonValue::as_str)
+            .is_some_and(|value| {
+                let tokens = text::tokenize(value, text::DEFAULT_STOP_WORDS);
+                terms.iter().all(|term| tokens.contains(term))
+            }),
+        Query::And(queries) => queries.iter().all(|q| execute_with(q, doc, coerce_types)),
+        Query::Or(queries) => queries.iter().any(|q| execute_with(q, doc, coerce_types)),
+    }
+}
+
+/// Resolve a dotted field path inside a document
+pub(crate) fn lookup<'a>(doc: &'a JsonValue, field: &str) -> Option<&'a JsonValue> {
+    field.split('.').try_fold(doc, |value, key| value.as_object()?.get(key))
+}
+
+/// Whether `actual`, or any element of it if it is an array, is one of `values`
+fn is_in(actual: &JsonValue, values: &[JsonValue], equal: impl Fn(&JsonValue, &JsonValue) -> bool) -> bool {
+    let contains = |actual: &JsonValue| values.iter().any(|value| equal(actual, value));
+    contains(actual) || actual.as_array().is_some_and(|elements| elements.iter().any(contains))
+}
+
+/// Equality that treats numerically equal numbers (`1` and `1.0`) as equal
+fn values_equal(a: &JsonValue, b: &JsonValue, coerce_types: bool) -> bool {
+    a == b || compare(a, b, coerce_types) == Some(Ordering::Equal)
+}
+
+/// Order two numbers or two strings; values of different types are not
+/// ordered unless `coerce_types` lets a numeric string stand in for a number
+fn compare(a: &JsonValue, b: &JsonValue, coerce_types: bool) -> Option<Ordering> {
+    match (a, b) {
+        (JsonValue::Number(a), JsonValue::Number(b)) => compare_numbers(a, b),
+        (JsonValue::String(a), JsonValue::String(b)) => Some(a.cmp(b)),
+        (JsonValue::Number(a), JsonValue::String(b)) if coerce_types => compare_numbers(a, &parse_number(b)?),
+        (JsonValue::String(a), JsonValue::Number(b)) if coerce_types => compare_numbers(&parse_number(a)?, b),
+        _ => None,
+    }
+}
+
+/// Order two numbers by their exact values
+///
+/// Going through `f64` would make integers above 2^53 that differ compare
+/// equal, to each other and to a float between them, which breaks the total
+/// order index keys rely on.
+fn compare_numbers(a: &Number, b: &Number) -> Option<Ordering> {
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        (Some(a), None) => compare_integer_float(a, b.as_f64()?),
+        (None, Some(b)) => compare_integer_float(b, a.as_f64()?).map(Ordering::reverse),
+        (None, None) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+/// An integer number, signed or not
+fn integer(number: &Number) -> Option<i128> {
+    number.as_i64().map(i128::from).or_else(|| number.as_u64().map(i128::from))
+}
+
+/// Order an integer against a float exactly
+///
+/// Rounding the integer to a float keeps the order; if that ties, the
+/// float is a whole number and both compare as integers.
+fn compare_integer_float(integer: i128, float: f64) -> Option<Ordering> {
+    match (integer as f64).partial_cmp(&float)? {
+        Ordering::Equal => Some(integer.cmp(&(float as i128))),
+        order => Some(order),
+    }
+}
+
+/// A numeric string as a number, keeping integers exact
+fn parse_number(s: &str) -> Option<Number> {
+    let s = s.trim();
+    s.parse().ok().or_else(|| Number::from_f64(s.parse().ok()?))
+}
+
+/// Total order over field values used for sorting and indexing
+///
+/// Missing fields and nulls sort first, followed by numbers, strings,
+/// booleans, arrays and objects. Numbers and strings are ordered by value,
+/// arrays element by element and objects by their serialized text.
+pub(crate) fn sort_order(a: Option<&JsonValue>, b: Option<&JsonValue>) -> Ordering {
+    fn rank(value: Option<&JsonValue>) -> u8 {
+        match value.map(JsonType::of) {
+            None | Some(JsonType::Null) => 0,
+            Some(JsonType::Number) => 1,
+            Some(JsonType::String) => 2,
+            Some(JsonType::Bool) => 3,
+            Some(JsonType::Array) => 4,
+            Some(JsonType::Object) => 5,
+        }
+    }
+
+    rank(a).cmp(&rank(b)).then_with(|| match (a, b) {
+        (Some(JsonValue::Bool(a)), Some(JsonValue::Bool(b))) => a.cmp(b),
+        (Some(JsonValue::Array(a)), Some(JsonValue::Array(b))) => a.iter().zip(b)
+            .map(|(a, b)| sort_order(Some(a), Some(b)))
+            .find(|order| order.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Some(a @ JsonValue::Object(_)), Some(b @ JsonValue::Object(_))) => a.to_string().cmp(&b.to_string()),
+        (Some(a), Some(b)) => compare(a, b, false).unwrap_or(Ordering::Equal),
+        _ => Ordering::Equal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(query: JsonValue, doc: JsonValue) -> bool {
+        execute(&Query::from_json(&query).unwrap(), &doc)
+    }
+
+    #[test]
+    fn test_equality() {
+        let doc = json!({"name": "Ada", "age": 36, "address": {"city": "London"}});
+
+        assert!(matches(json!({}), doc.clone()));
+        assert!(matches(json!({"name": "Ada"}), doc.clone()));
+        assert!(matches(json!({"age": 36.0}), doc.clone()));
+        assert!(matches(json!({"address.city": "London"}), doc.clone()));
+        assert!(matches(json!({"address": {"city": "London"}}), doc.clone()));
+        assert!(!matches(json!({"name": "Grace"}), doc.clone()));
+        assert!(!matches(json!({"missing": null}), doc.clone()));
+        assert!(!matches(json!({"name": "Ada", "age": 37}), doc));
+    }
+
+    #[test]
+    fn test_dotted_paths() {
+        let doc = json!({
+            "address": {"city": "NYC", "geo": {"zip": "10001", "lat": 40.7}},
+            "tags": ["a", "b"],
+        });
+
+        assert!(matches(json!({"address.city": "NYC"}), doc.clone()));
+        assert!(matches(json!({"address.geo.zip": "10001"}), doc.clone()));
+        assert!(matches(json!({"address.geo.lat": {"$gt": 40}}), doc.clone()));
+        assert!(!matches(json!({"address.city": "LA"}), doc.clone()));
+        assert!(!matches(json!({"address.geo.zip": "10002"}), doc.clone()));
+
+        // Missing keys and non-object steps never match, and are not errors
+        assert!(!matches(json!({"contact.city": "NYC"}), doc.clone()));
+        assert!(!matches(json!({"address.region.zip": "10001"}), doc.clone()));
+        assert!(!matches(json!({"address.city.name": "NYC"}), doc.clone()));
+        assert!(!matches(json!({"tags.0": "a"}), doc.clone()));
+        assert!(!matches(json!({"contact.city": {"$gte": "A"}}), doc.clone()));
+        assert!(matches(json!({"contact.city": {"$ne": "NYC"}}), doc));
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let doc = json!({"age": 36, "name": "Ada"});
+
+        assert!(matches(json!({"age": {"$gt": 30}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$gt": 36}}), doc.clone()));
+        assert!(matches(json!({"age": {"$gte": 36}}), doc.clone()));
+        assert!(matches(json!({"age": {"$lt": 40.5}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$lte": 35}}), doc.clone()));
+        assert!(matches(json!({"age": {"$gt": 30, "$lt": 40}}), doc.clone()));
+        assert!(matches(json!({"name": {"$gt": "Abe"}}), doc.clone()));
+        // Values of different types never compare
+        assert!(!matches(json!({"age": {"$gt": "30"}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$lte": "40"}}), doc.clone()));
+        assert!(!matches(json!({"name": {"$gte": 0}}), doc.clone()));
+        assert!(!matches(json!({"missing": {"$lt": 100}}), doc));
+    }
+
+    #[test]
+    fn test_comparison_boundaries() {
+        let doc = json!({"age": 36, "name": "Ada"});
+
+        assert!(matches(json!({"age": {"$gte": 36.0}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$gte": 36.5}}), doc.clone()));
+        assert!(matches(json!({"age": {"$lte": 36}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$lte": 35.9}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$lt": 36}}), doc.clone()));
+        assert!(matches(json!({"name": {"$gte": "Ada"}}), doc.clone()));
+        assert!(matches(json!({"name": {"$lte": "Ada"}}), doc.clone()));
+        assert!(!matches(json!({"name": {"$lt": "Ada"}}), doc));
+    }
+
+    #[test]
+    fn test_large_integers_compare_exactly() {
+        // 2^53 + 1 and 2^53 are the same f64
+        let doc = json!({"id": 9007199254740993u64, "big": u64::MAX, "small": i64::MIN});
+
+        assert!(!matches(json!({"id": 9007199254740992u64}), doc.clone()));
+        assert!(matches(json!({"id": 9007199254740993u64}), doc.clone()));
+        assert!(matches(json!({"id": {"$gt": 9007199254740992u64}}), doc.clone()));
+        assert!(!matches(json!({"id": {"$lte": 9007199254740992u64}}), doc.clone()));
+        assert!(!matches(json!({"id": {"$in": [9007199254740992u64]}}), doc.clone()));
+        assert!(matches(json!({"big": {"$gt": u64::MAX - 1}}), doc.clone()));
+        assert!(matches(json!({"big": {"$gt": -1}}), doc.clone()));
+        assert!(matches(json!({"small": {"$lt": i64::MIN + 1}}), doc.clone()));
+        assert!(matches(json!({"small": {"$lt": u64::MAX}}), doc.clone()));
+        assert!(execute_with(&Query::from_json(&json!({"id": {"$gt": "9007199254740992"}})).unwrap(), &doc, true));
+        assert_eq!(sort_order(Some(&json!(9007199254740993u64)), Some(&json!(9007199254740992u64))), Ordering::Greater);
+        // A float between two such integers orders exactly against each
+        assert_eq!(sort_order(Some(&json!(9007199254740993u64)), Some(&json!(9007199254740992.0))), Ordering::Greater);
+        assert_eq!(sort_order(Some(&json!(9007199254740992.0)), Some(&json!(9007199254740992u64))), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_ne() {
+        let doc = json!({"age": 36, "name": "Ada"});
+
+        assert!(matches(json!({"age": {"$ne": 30}}), doc.clone()));
+        assert!(!matches(json!({"age": {"$ne": 36.0}}), doc.clone()));
+        assert!(!matches(json!({"name": {"$ne": "Ada"}}), doc.clone()));
+        // A value of another type, or a missing field, is never equal
+        assert!(matches(json!({"age": {"$ne": "36"}}), doc.clone()));
+        assert!(matches(json!({"missing": {"$ne": 1}}), doc.clone()));
+        assert!(matches(json!({"age": {"$gt": 30, "$ne": 40}}), doc));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let doc = json!({"name": "Ada", "age": 36});
+
+        assert!(matches(json!({"$and": [{"name": "Ada"}, {"age": {"$gt": 30}}]}), doc.clone()));
+        assert!(!matches(json!({"$and": [{"name": "Ada"}, {"age": {"$gt": 40}}]}), doc.clone()));
+        assert!(matches(json!({"$or": [{"name": "Grace"}, {"age": 36}]}), doc.clone()));
+        assert!(!matches(json!({"$or": [{"name": "Grace"}, {"age": 40}]}), doc.clone()));
+        assert!(!matches(json!({"$or": []}), doc.clone()));
+        assert!(matches(json!({"$and": []}), doc));
+    }
+
+    #[test]
+    fn test_or_with_top_level_equality() {
+        let query = json!({"status": "active", "$or": [{"role": "admin"}, {"age": {"$gte": 65}}]});
+
+        assert!(matches(query.clone(), json!({"status": "active", "role": "admin"})));
+        assert!(matches(query.clone(), json!({"status": "active", "role": "user", "age": 70})));
+        assert!(!matches(query.clone(), json!({"status": "active", "role": "user", "age": 30})));
+        assert!(!matches(query, json!({"status": "closed", "role": "admin"})));
+
+        // An empty $or fails the whole query, an empty $and leaves it to the rest
+        assert!(!matches(json!({"status": "active", "$or": []}), json!({"status": "active"})));
+        assert!(matches(json!({"status": "active", "$and": []}), json!({"status": "active"})));
+        assert!(!matches(json!({"status": "active", "$and": []}), json!({"status": "closed"})));
+    }
+
+    #[test]
+    fn test_nested_and_or() {
+        let query = json!({
+            "$or": [
+                {"$and": [{"role": "admin"}, {"active": true}]},
+                {"$and": [{"role": "user"}, {"$or": [{"age": {"$lt": 18}}, {"age": {"$gte": 65}}]}]}
+            ]
+        });
+
+        assert!(matches(query.clone(), json!({"role": "admin", "active": true})));
+        assert!(!matches(query.clone(), json!({"role": "admin", "active": false})));
+        assert!(matches(query.clone(), json!({"role": "user", "age": 70})));
+        assert!(matches(query.clone(), json!({"role": "user", "age": 12})));
+        assert!(!matches(query, json!({"role": "user", "age": 30})));
+    }
+
+    #[test]
+    fn test_exists() {
+        let doc = json!({"name": "Ada", "nickname": null});
+
+        assert!(matches(json!({"name": {"$exists": true}}), doc.clone()));
+        assert!(matches(json!({"nickname": {"$exists": true}}), doc.clone()));
+        assert!(matches(json!({"email": {"$exists": false}}), doc.clone()));
+        assert!(!matches(json!({"name": {"$exists": false}}), doc));
+    }
+
+    #[test]
+    fn test_type() {
+        let doc = json!({"s": "x", "n": 1.5, "b": false, "z": null, "a": [1], "o": {}});
+
+        for (field, json_type) in [("s", "string"), ("n", "number"), ("b", "bool"), ("z", "null"), ("a", "array"), ("o", "object")] {
+            assert!(matches(json!({field: {"$type": json_type}}), doc.clone()), "{} is {}", field, json_type);
+            assert!(!matches(json!({field: {"$type": if json_type == "string" { "number" } else { "string" }}}), doc.clone()));
+        }
+        assert!(!matches(json!({"missing": {"$type": "null"}}), doc));
+    }
+
+    #[test]
+    fn test_regex() {
+        let doc = json!({"name": "Ada Lovelace", "age": 36, "tags": ["math"]});
+
+        assert!(matches(json!({"name": {"$regex": "^Ada"}}), doc.clone()));
+        assert!(!matches(json!({"name": {"$regex": "love"}}), doc.clone()));
+        assert!(matches(json!({"name": {"$regex": "(?i)love"}}), doc.clone()));
+        // Non-string and missing fields do not match and are not errors
+        assert!(!matches(json!({"age": {"$regex": "36"}}), doc.clone()));
+        assert!(!matches(json!({"tags": {"$regex": "math"}}), doc.clone()));
+        assert!(!matches(json!({"missing": {"$regex": ".*"}}), doc));
+    }
+
+    #[test]
+    fn test_regex_options() {
+        let doc = json!({"name": "John Smith", "email": "JOHN@Example.com", "bio": "line one\nline two"});
+
+        assert!(!matches(json!({"name": {"$regex": "john"}}), doc.clone()));
+        assert!(matches(json!({"name": {"$regex": "john", "$options": "i"}}), doc.clone()));
+        // Anchors hold with flags too
+        assert!(matches(json!({"email": {"$regex": "@example\\.com$", "$options": "i"}}), doc.clone()));
+        assert!(!matches(json!({"email": {"$regex": "^example", "$options": "i"}}), doc.clone()));
+        assert!(!matches(json!({"bio": {"$regex": "^line two$"}}), doc.clone()));
+        assert!(matches(json!({"bio": {"$regex": "^line two$", "$options": "m"}}), doc.clone()));
+        assert!(matches(json!({"bio": {"$regex": "one.line", "$options": "s"}}), doc.clone()));
+        assert!(matches(json!({"name": {"$regex": "john \\s smith", "$options": "ix"}}), doc));
+
+        // Options are part of a pattern's identity
+        let plain = Query::from_json(&json!({"name": {"$regex": "john"}})).unwrap();
+        let insensitive = Query::from_json(&json!({"name": {"$regex": "john", "$options": "i"}})).unwrap();
+        assert_ne!(plain, insensitive);
+
+        for invalid in [
+            json!({"name": {"$regex": "john", "$options": "q"}}),
+            json!({"name": {"$regex": "john", "$options": 1}}),
+            json!({"name": {"$options": "i"}}),
+        ] {
+            assert!(Query::from_json(&invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_type_coercion() {
+        let doc = json!({"age": "30", "score": 7.5, "name": "Ada"});
+        let coerced = |query: JsonValue| execute_with(&Query::from_json(&query).unwrap(), &doc, true);
+
+        // Strict matching keeps numbers and strings apart
+        assert!(!matches(json!({"age": 30}), doc.clone()));
+        assert!(!matches(json!({"age": {"$gt": 20}}), doc.clone()));
+        assert!(!matches(json!({"score": "7.5"}), doc.clone()));
+
+        assert!(coerced(json!({"age": 30})));
+        assert!(coerced(json!({"age": {"$gt": 20, "$lte": 30.0}})));
+        assert!(coerced(json!({"age": {"$in": [29, 30]}})));
+        assert!(coerced(json!({"score": "7.5"})));
+        assert!(!coerced(json!({"age": 31})));
+        assert!(!coerced(json!({"name": 0})));
+    }
+
+    #[test]
+    fn test_text() {
+        let doc = json!({"body": "Writing async code in Rust: a tour", "views": 3});
+
+        assert!(matches(json!({"body": {"$text": "rust async"}}), doc.clone()));
+        assert!(matches(json!({"body": {"$text": "RUST, the Tour"}}), doc.clone()));
+        assert!(!matches(json!({"body": {"$text": "rust python"}}), doc.clone()));
+        assert!(!matches(json!({"views": {"$text": "3"}}), doc.clone()));
+        assert!(Query::from_json(&json!({"body": {"$text": 1}})).is_err());
+    }
+
+    #[test]
+    fn test_in_and_nin() {
+        let doc = json!({"status": "active", "level": 3});
+
+        assert!(matches(json!({"status": {"$in": ["active", "pending"]}}), doc.clone()));
+        assert!(!matches(json!({"status": {"$in": ["closed"]}}), doc.clone()));
+        assert!(matches(json!({"level": {"$in": [3.0]}}), doc.clone()));
+        assert!(!matches(json!({"status": {"$in": []}}), doc.clone()));
+        assert!(!matches(json!({"missing": {"$in": [null]}}), doc.clone()));
+
+        assert!(matches(json!({"status": {"$nin": ["closed"]}}), doc.clone()));
+        assert!(!matches(json!({"status": {"$nin": ["active"]}}), doc.clone()));
+        assert!(matches(json!({"status": {"$nin": []}}), doc.clone()));
+        assert!(matches(json!({"missing": {"$nin": ["x"]}}), doc));
+    }
+
+    #[test]
+    fn test_in_array_fields() {
+        let doc = json!({"tags": ["rust", "db"], "scores": [1, 2], "empty": []});
+
+        assert!(matches(json!({"tags": {"$in": ["db", "web"]}}), doc.clone()));
+        assert!(matches(json!({"scores": {"$in": [2.0]}}), doc.clone()));
+        assert!(!matches(json!({"tags": {"$in": ["web"]}}), doc.clone()));
+        assert!(!matches(json!({"tags": {"$in": []}}), doc.clone()));
+        assert!(!matches(json!({"empty": {"$in": ["rust"]}}), doc.clone()));
+        // The whole array is a candidate too
+        assert!(matches(json!({"tags": {"$in": [["rust", "db"]]}}), doc.clone()));
+
+        assert!(!matches(json!({"tags": {"$nin": ["db"]}}), doc.clone()));
+        assert!(matches(json!({"tags": {"$nin": ["web"]}}), doc.clone()));
+        assert!(matches(json!({"empty": {"$nin": ["rust"]}}), doc));
+
+        let error = Query::from_json(&json!({"tags": {"$in": {"a": 1}}})).unwrap_err();
+        assert!(matches!(error, Error::Other(message) if message.contains("$in")));
+    }
+
+    #[test]
+    fn test_new_operators_compose_with_and_or() {
+        let query = json!({
+            "$or": [
+                {"$and": [{"email": {"$exists": true}}, {"email": {"$regex": "@example\\.com$"}}]},
+                {"role": {"$in": ["admin", "owner"]}, "tags": {"$type": "array"}}
+            ]
+        });
+
+        assert!(matches(query.clone(), json!({"email": "ada@example.com"})));
+        assert!(!matches(query.clone(), json!({"email": "ada@example.org"})));
+        assert!(matches(query.clone(), json!({"role": "owner", "tags": []})));
+        assert!(!matches(query.clone(), json!({"role": "owner", "tags": "x"})));
+        assert!(!matches(query, json!({"role": "user", "tags": []})));
+    }
+
+    #[test]
+    fn test_invalid_queries_rejected() {
+        assert!(Query::from_json(&json!([1, 2])).is_err());
+        assert!(Query::from_json(&json!({"$nor": []})).is_err());
+        assert!(Query::from_json(&json!({"$and": {"a": 1}})).is_err());
+        assert!(Query::from_json(&json!({"age": {"$between": [1, 2]}})).is_err());
+        assert!(Query::from_json(&json!({"a": {"$in": "x"}})).is_err());
+        assert!(Query::from_json(&json!({"a": {"$exists": 1}})).is_err());
+        assert!(Query::from_json(&json!({"a": {"$type": "date"}})).is_err());
+        assert!(Query::from_json(&json!({"a": {"$regex": "("}})).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! Tokenisation for full-text search
+//!
+//! Text is split on whitespace and punctuation, lowercased, and common
+//! stop-words are dropped, so `$text` queries and text indexes agree on which
+//! words a string contains.
+
+/// Words too common to be worth indexing
+pub const DEFAULT_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is",
+    "it", "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there",
+    "these", "they", "this", "to", "was", "will", "with",
+];
+
+/// Split `text` into lowercase tokens, leaving out `stop_words`
+///
+/// Tokens are returned in order and may repeat.
+pub fn tokenize<S: AsRef<str>>(text: &str, stop_words: &[S]) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .filter(|word| !stop_words.iter().any(|stop| stop.as_ref() == word))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("The Rust book, in async/await!", DEFAULT_STOP_WORDS),
+            vec!["rust", "book", "async", "await"]);
+        assert_eq!(tokenize("The end", &["end"]), vec!["the"]);
+        assert!(tokenize("  ... ", DEFAULT_STOP_WORDS).is_empty());
+    }
+}
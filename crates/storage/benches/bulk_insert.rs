@@ -0,0 +1,60 @@
+//! Loading 10 000 documents with an `insert` loop versus `bulk_insert`
+//!
+//! Run with `cargo bench -p nebuladb-storage --bench bulk_insert`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::StorageConfig;
+
+const DOCS: usize = 10_000;
+
+fn documents() -> Vec<(Vec<u8>, Vec<u8>)> {
+    (0..DOCS)
+        .map(|i| {
+            let doc = format!(
+                r#"{{"_id":"user{}","name":"User {}","email":"user{}@example.com","age":{}}}"#,
+                i, i, i, 18 + i % 60
+            );
+            (format!("user{:05}", i).into_bytes(), doc.into_bytes())
+        })
+        .collect()
+}
+
+fn bench_load(c: &mut Criterion) {
+    let docs = documents();
+    let config = StorageConfig::default();
+
+    let mut group = c.benchmark_group("load_10k");
+    group.throughput(Throughput::Elements(DOCS as u64));
+    group.sample_size(10);
+
+    let setup = || {
+        let dir = tempfile::tempdir().unwrap();
+        let collection = Collection::open("users", dir.path(), &config).unwrap();
+        (dir, collection, docs.clone())
+    };
+    group.bench_function("insert_loop", |b| {
+        b.iter_batched(setup, |(_dir, mut collection, docs)| {
+            for (id, data) in &docs {
+                collection.insert(id, data).unwrap();
+            }
+            collection.close().unwrap();
+        }, BatchSize::PerIteration)
+    });
+    group.bench_function("bulk_insert", |b| {
+        b.iter_batched(setup, |(_dir, mut collection, docs)| {
+            collection.bulk_insert(docs).unwrap();
+            collection.close().unwrap();
+        }, BatchSize::PerIteration)
+    });
+    group.bench_function("bulk_insert_ordered", |b| {
+        b.iter_batched(setup, |(_dir, mut collection, docs)| {
+            collection.bulk_insert_ordered(docs).unwrap();
+            collection.close().unwrap();
+        }, BatchSize::PerIteration)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
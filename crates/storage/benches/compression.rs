@@ -0,0 +1,46 @@
+//! Compression throughput for the block codecs on synthetic JSON payloads
+//!
+//! Run with `cargo bench -p nebuladb-storage --bench compression`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nebuladb_storage::compression::{compress, decompress, DEFAULT_COMPRESSION_LEVEL};
+use nebuladb_storage::CompressionType;
+
+/// Roughly one block worth of JSON documents
+fn synthetic_json(docs: usize) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for i in 0..docs {
+        payload.extend_from_slice(format!(
+            r#"{{"_id":"user{}","name":"User {}","email":"user{}@example.com","age":{},"active":{},"tags":["alpha","beta","gamma"]}}"#,
+            i, i, i, 18 + i % 60, i % 2 == 0
+        ).as_bytes());
+    }
+    payload
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let payload = synthetic_json(10_000);
+    let algorithms = [("lz4", CompressionType::Lz4), ("zstd", CompressionType::Zstd)];
+
+    let mut group = c.benchmark_group("compress");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    for (name, algorithm) in algorithms {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &payload, |b, payload| {
+            b.iter(|| compress(black_box(payload), algorithm, DEFAULT_COMPRESSION_LEVEL).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("decompress");
+    group.throughput(Throughput::Bytes(payload.len() as u64));
+    for (name, algorithm) in algorithms {
+        let compressed = compress(&payload, algorithm, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &compressed, |b, compressed| {
+            b.iter(|| decompress(black_box(compressed), algorithm).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_codecs);
+criterion_main!(benches);
@@ -1,8 +1,125 @@
 //! Block-level operations for NebulaDB storage
 
-use crate::{Block, BlockHeader, BlockFooter, CompressionType};
+use crate::{compression, Block, BlockHeader, BlockFooter, CompressionType};
 use nebuladb_core::{Error, Result};
 
+/// Version and write times stored ahead of a document's data
+///
+/// Documents written before the metadata existed have none; they read as
+/// version 0 with zero timestamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentMeta {
+    /// Number of times the document was written, starting at 1 on insert
+    pub version: u32,
+    /// When the document was inserted (UNIX timestamp)
+    pub created_at: u64,
+    /// When the document was last written (UNIX timestamp)
+    pub updated_at: u64,
+}
+
+impl DocumentMeta {
+    /// Size of the serialized metadata in bytes
+    pub const SIZE: usize = 4 + 8 + 8;
+    
+    /// First byte of the metadata prefix in blocks written before entries
+    /// recorded their kind; it never starts UTF-8 text, so it told prefixed
+    /// data apart from JSON documents stored without one
+    pub const MARKER: u8 = 0xFF;
+    
+    /// Metadata of a document first written at `now`
+    pub fn new(now: u64) -> Self {
+        Self {
+            version: 1,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+    
+    /// Metadata of the next version of a document, written at `now`
+    pub fn next(&self, now: u64) -> Self {
+        Self {
+            version: self.version + 1,
+            created_at: self.created_at,
+            updated_at: now,
+        }
+    }
+    
+    /// Serialize the metadata
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.created_at.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.updated_at.to_le_bytes());
+        bytes
+    }
+    
+    /// Split metadata off the start of `bytes`, returning it and the rest
+    fn split(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        
+        let meta = Self {
+            version: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            created_at: u64::from_le_bytes(bytes[4..12].try_into().unwrap()),
+            updated_at: u64::from_le_bytes(bytes[12..20].try_into().unwrap()),
+        };
+        Some((meta, &bytes[Self::SIZE..]))
+    }
+    
+    /// Split stored document data from a block written before entries
+    /// recorded their kind into its metadata, if prefixed, and the document
+    ///
+    /// Such blocks cannot tell the prefix apart from a document that starts
+    /// with [`MARKER`](Self::MARKER), so this is only a guess.
+    fn split_legacy(stored: &[u8]) -> (Option<Self>, &[u8]) {
+        match stored.split_first() {
+            Some((&Self::MARKER, rest)) => match Self::split(rest) {
+                Some((meta, data)) => (Some(meta), data),
+                None => (None, stored),
+            },
+            _ => (None, stored),
+        }
+    }
+}
+
+/// Stored data of an entry that deletes its ID, in blocks written before
+/// entries recorded their kind; like [`DocumentMeta::MARKER`] its byte never
+/// starts UTF-8 text
+const LEGACY_DELETION: &[u8] = &[0xFE];
+
+/// Kind byte starting the stored data of an entry holding a document, from
+/// [`BlockHeader::ENTRY_KIND_VERSION`] on
+const KIND_DOCUMENT: u8 = 0;
+
+/// Kind byte starting the stored data of an entry deleting its ID
+const KIND_DELETION: u8 = 1;
+
+/// Kind byte starting the stored data of an entry holding a document whose
+/// metadata follows the kind
+const KIND_DOCUMENT_WITH_META: u8 = 2;
+
+/// Split the stored data of an entry in a block of format `version` into its
+/// metadata and document, or `None` if the entry is a deletion
+pub fn decode_stored(stored: &[u8], version: u8) -> Result<Option<(Option<DocumentMeta>, &[u8])>> {
+    if version < BlockHeader::ENTRY_KIND_VERSION {
+        if stored == LEGACY_DELETION {
+            return Ok(None);
+        }
+        return Ok(Some(DocumentMeta::split_legacy(stored)));
+    }
+    
+    match stored.split_first() {
+        Some((&KIND_DOCUMENT, data)) => Ok(Some((None, data))),
+        Some((&KIND_DELETION, [])) => Ok(None),
+        Some((&KIND_DOCUMENT_WITH_META, rest)) => match DocumentMeta::split(rest) {
+            Some((meta, data)) => Ok(Some((Some(meta), data))),
+            None => Err(Error::Other("Invalid document entry: too short for metadata".to_string())),
+        },
+        _ => Err(Error::Other("Invalid document entry: unknown kind".to_string())),
+    }
+}
+
 /// Document entry in a block
 #[derive(Debug, Clone)]
 pub struct DocumentEntry {
@@ -10,6 +127,10 @@ pub struct DocumentEntry {
     pub id: Vec<u8>,
     /// Document data
     pub data: Vec<u8>,
+    /// Version and write times, stored as a prefix of the data
+    pub meta: Option<DocumentMeta>,
+    /// Whether the entry deletes the document rather than storing a version
+    pub deleted: bool,
     /// Offset of this document within the block data
     pub offset: usize,
 }
@@ -20,34 +141,92 @@ impl DocumentEntry {
         Self {
             id,
             data,
+            meta: None,
+            deleted: false,
             offset: 0,
         }
     }
     
+    /// Create an entry deleting the document with this ID
+    pub fn deletion(id: Vec<u8>) -> Self {
+        Self {
+            deleted: true,
+            ..Self::new(id, Vec::new())
+        }
+    }
+    
+    /// Attach metadata to the entry
+    pub fn with_meta(mut self, meta: DocumentMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+    
+    /// Length of the stored data in a block of format `version`, including
+    /// the kind byte and metadata prefix
+    fn stored_len(&self, version: u8) -> usize {
+        if self.deleted {
+            // Just the deletion kind, or the legacy deletion byte
+            return 1;
+        }
+        // Older blocks mark the metadata prefix instead of recording the kind
+        let kind_or_marker = if version >= BlockHeader::ENTRY_KIND_VERSION || self.meta.is_some() { 1 } else { 0 };
+        kind_or_marker + self.data.len() + if self.meta.is_some() { DocumentMeta::SIZE } else { 0 }
+    }
+    
     /// Size of this document entry in bytes
     pub fn size(&self) -> usize {
-        // Format: [doc_id_len(2)][doc_id][doc_data_len(4)][doc_data]
-        2 + self.id.len() + 4 + self.data.len()
+        // Format: [doc_id_len(2)][doc_id][doc_data_len(4)][kind(1)][meta?][doc_data]
+        2 + self.id.len() + 4 + self.stored_len(BlockHeader::VERSION)
     }
     
-    /// Serialize this document entry to bytes
+    /// Serialize this document entry to bytes in the current block format
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(self.size());
+        self.encode(BlockHeader::VERSION)
+    }
+    
+    /// Serialize this document entry for a block of format `version`
+    fn encode(&self, version: u8) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.id.len() + 4 + self.stored_len(version));
         
         // Write doc_id length (2 bytes)
         bytes.extend_from_slice(&(self.id.len() as u16).to_le_bytes());
         // Write doc_id
         bytes.extend_from_slice(&self.id);
-        // Write doc_data length (4 bytes)
-        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        // Write doc_data length (4 bytes), counting the kind and metadata prefix
+        bytes.extend_from_slice(&(self.stored_len(version) as u32).to_le_bytes());
+        if version < BlockHeader::ENTRY_KIND_VERSION {
+            if self.deleted {
+                bytes.extend_from_slice(LEGACY_DELETION);
+                return bytes;
+            }
+            if self.meta.is_some() {
+                bytes.push(DocumentMeta::MARKER);
+            }
+        } else if self.deleted {
+            bytes.push(KIND_DELETION);
+            return bytes;
+        } else if self.meta.is_some() {
+            bytes.push(KIND_DOCUMENT_WITH_META);
+        } else {
+            bytes.push(KIND_DOCUMENT);
+        }
+        // Write metadata prefix
+        if let Some(meta) = &self.meta {
+            bytes.extend_from_slice(&meta.to_bytes());
+        }
         // Write doc_data
         bytes.extend_from_slice(&self.data);
         
         bytes
     }
     
-    /// Deserialize a document entry from bytes
+    /// Deserialize a document entry in the current block format from bytes
     pub fn from_bytes(bytes: &[u8], offset: usize) -> Result<Self> {
+        Self::decode(bytes, offset, BlockHeader::VERSION)
+    }
+    
+    /// Deserialize a document entry from a block of format `version`
+    pub fn decode(bytes: &[u8], offset: usize, version: u8) -> Result<Self> {
         if bytes.len() < 6 {
             return Err(Error::Other("Invalid document entry: too short".to_string()));
         }
@@ -75,12 +254,17 @@ impl DocumentEntry {
             return Err(Error::Other("Invalid document entry: too short for data".to_string()));
         }
         
-        // Read doc_data
-        let data = bytes[(data_start + 4)..(data_start + 4 + data_len)].to_vec();
+        // Read metadata prefix and doc_data
+        let stored = &bytes[(data_start + 4)..(data_start + 4 + data_len)];
+        let Some((meta, data)) = decode_stored(stored, version)? else {
+            return Ok(Self { offset, ..Self::deletion(id) });
+        };
         
         Ok(Self {
             id,
-            data,
+            data: data.to_vec(),
+            meta,
+            deleted: false,
             offset,
         })
     }
@@ -113,7 +297,7 @@ impl BlockOperations for Block {
         doc.offset = self.data.len();
         
         // Add the document to the block
-        let doc_bytes = doc.to_bytes();
+        let doc_bytes = doc.encode(self.header.version);
         self.data.extend_from_slice(&doc_bytes);
         
         // Update the header
@@ -131,29 +315,11 @@ impl BlockOperations for Block {
     }
     
     fn size(&self) -> usize {
-        BlockHeader::SIZE + self.data.len() + BlockFooter::SIZE
+        self.header.size() + self.data.len() + BlockFooter::SIZE
     }
     
     fn to_bytes(&self) -> Result<Vec<u8>> {
-        let mut bytes = Vec::with_capacity(self.size());
-        
-        // Write header
-        bytes.extend_from_slice(&self.header.magic);
-        bytes.push(self.header.version);
-        bytes.push(self.header.compression as u8);
-        bytes.extend_from_slice(&self.header.doc_count.to_le_bytes());
-        bytes.extend_from_slice(&self.header.uncompressed_size.to_le_bytes());
-        bytes.extend_from_slice(&self.header.compressed_size.to_le_bytes());
-        bytes.extend_from_slice(&self.header.created_at.to_le_bytes());
-        
-        // Write data
-        bytes.extend_from_slice(&self.data);
-        
-        // Write footer
-        bytes.extend_from_slice(&self.footer.checksum.to_le_bytes());
-        bytes.extend_from_slice(&self.footer.magic);
-        
-        Ok(bytes)
+        self.to_bytes_with_level(crate::compression::DEFAULT_COMPRESSION_LEVEL)
     }
     
     fn from_bytes(bytes: &[u8]) -> Result<Self> {
@@ -162,67 +328,56 @@ impl BlockOperations for Block {
         }
         
         // Read header
-        let mut magic = [0u8; 4];
-        magic.copy_from_slice(&bytes[0..4]);
-        
-        if magic != BlockHeader::MAGIC {
-            return Err(Error::Other("Invalid block: wrong magic number".to_string()));
-        }
-        
-        let version = bytes[4];
-        let compression = match bytes[5] {
-            0 => CompressionType::None,
-            1 => CompressionType::Snappy,
-            2 => CompressionType::Zstd,
-            3 => CompressionType::Lz4,
-            _ => return Err(Error::Other("Invalid compression type".to_string())),
-        };
+        let header = BlockHeader::from_bytes(bytes)?;
         
-        let doc_count = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        let footer_start = bytes.len().checked_sub(header.footer_size())
+            .filter(|&start| start >= header.size())
+            .ok_or_else(|| Error::Other("Invalid block: too short for footer".to_string()))?;
+        let fixed_start = bytes.len() - BlockFooter::SIZE;
         
-        let uncompressed_size = u64::from_le_bytes([
-            bytes[10], bytes[11], bytes[12], bytes[13],
-            bytes[14], bytes[15], bytes[16], bytes[17],
-        ]);
-        
-        let compressed_size = u64::from_le_bytes([
-            bytes[18], bytes[19], bytes[20], bytes[21],
-            bytes[22], bytes[23], bytes[24], bytes[25],
-        ]);
-        
-        let created_at = u64::from_le_bytes([
-            bytes[26], bytes[27], bytes[28], bytes[29],
-            bytes[30], bytes[31], bytes[32], bytes[33],
-        ]);
-        
-        // Read data
-        let data_start = BlockHeader::SIZE;
-        let data_end = bytes.len() - BlockFooter::SIZE;
-        let data = bytes[data_start..data_end].to_vec();
+        // Read the stored (possibly compressed) payload
+        let payload = &bytes[header.size()..footer_start];
+        let directory = &bytes[footer_start..fixed_start];
         
         // Read footer
         let checksum = u32::from_le_bytes([
-            bytes[data_end], bytes[data_end + 1], bytes[data_end + 2], bytes[data_end + 3],
+            bytes[fixed_start], bytes[fixed_start + 1], bytes[fixed_start + 2], bytes[fixed_start + 3],
         ]);
         
         let mut footer_magic = [0u8; 4];
-        footer_magic.copy_from_slice(&bytes[data_end + 4..data_end + 8]);
+        footer_magic.copy_from_slice(&bytes[fixed_start + 4..fixed_start + 8]);
         
         if footer_magic != BlockHeader::MAGIC {
             return Err(Error::Other("Invalid block: wrong footer magic number".to_string()));
         }
         
-        let header = BlockHeader {
-            magic,
-            version,
-            compression,
-            doc_count,
-            uncompressed_size,
-            compressed_size,
-            created_at,
+        // Verify the checksum over the bytes as they were stored
+        let actual = checksum_of(&[&bytes[..header.size()], payload, directory]);
+        if actual != checksum {
+            return Err(Error::ChecksumMismatch { expected: checksum, actual });
+        }
+        
+        // Blocks without a recorded compressed size were written verbatim
+        let data = if header.compressed_size == 0 {
+            payload.to_vec()
+        } else {
+            compression::decompress(payload, header.compression)?
+        };
+        
+        if data.len() as u64 != header.uncompressed_size {
+            return Err(Error::Other(format!(
+                "Invalid block: expected {} uncompressed bytes, got {}",
+                header.uncompressed_size, data.len())));
+        }
+        
+        let offsets = if header.has_directory() {
+            read_directory(directory, header.doc_count, data.len())?
+        } else {
+            Vec::new()
         };
         
         let footer = BlockFooter {
+            offsets,
             checksum,
             magic: footer_magic,
         };
@@ -235,25 +390,462 @@ impl BlockOperations for Block {
     }
     
     fn compute_checksum(&self) -> u32 {
-        // In a real implementation, we would use CRC32 or a better algorithm
-        // For simplicity, we'll just sum the bytes
-        let mut sum = 0u32;
-        
-        // Include header in checksum
-        sum = sum.wrapping_add(self.header.magic.iter().fold(0u32, |acc, &b| acc.wrapping_add(b as u32)));
-        sum = sum.wrapping_add(self.header.version as u32);
-        sum = sum.wrapping_add(self.header.compression as u32);
-        sum = sum.wrapping_add(self.header.doc_count);
-        sum = sum.wrapping_add((self.header.uncompressed_size & 0xFFFFFFFF) as u32);
-        sum = sum.wrapping_add((self.header.uncompressed_size >> 32) as u32);
-        sum = sum.wrapping_add((self.header.compressed_size & 0xFFFFFFFF) as u32);
-        sum = sum.wrapping_add((self.header.compressed_size >> 32) as u32);
-        sum = sum.wrapping_add((self.header.created_at & 0xFFFFFFFF) as u32);
-        sum = sum.wrapping_add((self.header.created_at >> 32) as u32);
-        
-        // Include data in checksum
-        sum = sum.wrapping_add(self.data.iter().fold(0u32, |acc, &b| acc.wrapping_add(b as u32)));
-        
-        sum
+        checksum_of(&[&self.header.to_bytes(), &self.data])
+    }
+}
+
+impl Block {
+    /// Append several documents, updating the checksum once rather than after
+    /// each document as [`add_document`](BlockOperations::add_document) does
+    pub fn add_documents(&mut self, docs: impl IntoIterator<Item = DocumentEntry>) {
+        for mut doc in docs {
+            doc.offset = self.data.len();
+            let doc_bytes = doc.encode(self.header.version);
+            self.data.extend_from_slice(&doc_bytes);
+            self.header.doc_count += 1;
+            self.header.uncompressed_size += doc_bytes.len() as u64;
+        }
+        
+        self.footer.checksum = self.compute_checksum();
+    }
+    
+    /// Copy of the block in the current block format, keeping its creation
+    /// time and commit version
+    pub fn upgraded(&self) -> Result<Block> {
+        let mut docs = Vec::with_capacity(self.header.doc_count as usize);
+        for offset in entry_offsets(&self.data) {
+            docs.push(DocumentEntry::decode(&self.data[offset as usize..], offset as usize, self.header.version)?);
+        }
+        
+        let mut block = Block::new(self.header.compression);
+        block.header.created_at = self.header.created_at;
+        block.header.commit_version = self.header.commit_version;
+        block.add_documents(docs);
+        Ok(block)
+    }
+    
+    /// Serialize the block to bytes, compressing the data at the given level
+    ///
+    /// The stored header records the compressed size and the footer checksum
+    /// covers the header, the data exactly as written and the offset
+    /// directory. Data that does not shrink when compressed (already
+    /// compressed or random payloads) is stored verbatim with the compression
+    /// set to `None`.
+    pub fn to_bytes_with_level(&self, level: i32) -> Result<Vec<u8>> {
+        let mut header = self.header.clone();
+        let mut payload = compression::compress(&self.data, header.compression, level)?;
+        
+        if header.compression != CompressionType::None && payload.len() >= self.data.len() {
+            header.compression = CompressionType::None;
+            payload = self.data.clone();
+        }
+        
+        header.compressed_size = payload.len() as u64;
+        let header_bytes = header.to_bytes();
+        
+        let mut directory = Vec::new();
+        if header.has_directory() {
+            let offsets = entry_offsets(&self.data);
+            directory.reserve(BlockFooter::directory_size(offsets.len()));
+            directory.extend_from_slice(&(offsets.len() as u32).to_le_bytes());
+            for offset in offsets {
+                directory.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        
+        let mut bytes = Vec::with_capacity(
+            header_bytes.len() + payload.len() + directory.len() + BlockFooter::SIZE);
+        
+        // Write header
+        bytes.extend_from_slice(&header_bytes);
+        
+        // Write data
+        bytes.extend_from_slice(&payload);
+        
+        // Write footer
+        bytes.extend_from_slice(&directory);
+        bytes.extend_from_slice(&checksum_of(&[&header_bytes, &payload, &directory]).to_le_bytes());
+        bytes.extend_from_slice(&self.footer.magic);
+        
+        Ok(bytes)
+    }
+    
+    /// Get the document at `index` in the block
+    ///
+    /// Uses the footer directory when the block was read with one, and
+    /// otherwise walks the entries from the start of the block.
+    pub fn document_at(&self, index: usize) -> Result<Option<DocumentEntry>> {
+        if index >= self.header.doc_count as usize {
+            return Ok(None);
+        }
+        
+        let offset = match self.footer.offsets.get(index) {
+            Some(&offset) => offset as usize,
+            None => match entry_offsets(&self.data).get(index) {
+                Some(&offset) => offset as usize,
+                None => return Ok(None),
+            },
+        };
+        
+        DocumentEntry::decode(&self.data[offset..], offset, self.header.version).map(Some)
+    }
+    
+    /// IDs of the entries in the block, in order, borrowed from its data
+    pub fn entry_ids(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.entry_locations().map(|(id, _, _)| id)
+    }
+    
+    /// ID and data offset of each entry in the block, in order, and whether
+    /// the entry is a deletion
+    pub fn entry_locations(&self) -> impl Iterator<Item = (&[u8], usize, bool)> + '_ {
+        entry_offsets(&self.data).into_iter().map(move |offset| {
+            let offset = offset as usize;
+            let id_len = u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) as usize;
+            let len_start = offset + 2 + id_len;
+            let data_len = u32::from_le_bytes([
+                self.data[len_start], self.data[len_start + 1], self.data[len_start + 2], self.data[len_start + 3],
+            ]) as usize;
+            let deleted = self.data.get(len_start + 4..len_start + 4 + data_len)
+                .is_some_and(|stored| matches!(decode_stored(stored, self.header.version), Ok(None)));
+            (&self.data[offset + 2..len_start], offset, deleted)
+        })
+    }
+    
+    /// ID and data of each entry in the block, in order, borrowed from its
+    /// data without any metadata prefix; deletions have no data and a
+    /// truncated last entry is left out
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = (&[u8], Option<&[u8]>)> + '_ {
+        entry_offsets(&self.data).into_iter().filter_map(move |offset| {
+            let offset = offset as usize;
+            let id_len = u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) as usize;
+            let len_start = offset + 2 + id_len;
+            let data_len = u32::from_le_bytes(self.data[len_start..len_start + 4].try_into().ok()?) as usize;
+            let stored = self.data.get(len_start + 4..len_start + 4 + data_len)?;
+            let data = decode_stored(stored, self.header.version).ok()?.map(|(_, data)| data);
+            Some((&self.data[offset + 2..len_start], data))
+        })
+    }
+}
+
+/// Offsets of each document entry in uncompressed block data
+fn entry_offsets(data: &[u8]) -> Vec<u32> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    
+    while offset + 6 <= data.len() {
+        let id_len = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+        let len_start = offset + 2 + id_len;
+        if len_start + 4 > data.len() {
+            break;
+        }
+        
+        let data_len = u32::from_le_bytes([
+            data[len_start], data[len_start + 1], data[len_start + 2], data[len_start + 3],
+        ]) as usize;
+        
+        offsets.push(offset as u32);
+        offset = len_start + 4 + data_len;
+    }
+    
+    offsets
+}
+
+/// Parse a footer directory of `doc_count` offsets into data of `data_len` bytes
+fn read_directory(directory: &[u8], doc_count: u32, data_len: usize) -> Result<Vec<u32>> {
+    let count = u32::from_le_bytes([directory[0], directory[1], directory[2], directory[3]]);
+    if count != doc_count {
+        return Err(Error::Other(format!(
+            "Invalid block: directory lists {} documents, header {}", count, doc_count)));
+    }
+    
+    let offsets: Vec<u32> = directory[4..]
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    
+    if offsets.iter().any(|&offset| offset as usize >= data_len) {
+        return Err(Error::Other("Invalid block: directory offset out of range".to_string()));
+    }
+    
+    Ok(offsets)
+}
+
+/// CRC32 over the serialized header followed by the stored data and directory
+fn checksum_of(parts: &[&[u8]]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [CompressionType; 4] = [
+        CompressionType::None,
+        CompressionType::Snappy,
+        CompressionType::Zstd,
+        CompressionType::Lz4,
+    ];
+
+    fn sample_block(compression: CompressionType) -> Block {
+        let mut block = Block::new(compression);
+        block.add_document(DocumentEntry::new(b"doc1".to_vec(), br#"{"a":1}"#.to_vec())).unwrap();
+        block.add_document(DocumentEntry::new(b"doc2".to_vec(), br#"{"b":2}"#.to_vec())).unwrap();
+        block
+    }
+
+    fn legacy_block(version: u8) -> Block {
+        let mut block = Block::new(CompressionType::None);
+        block.header.version = version;
+        block.add_document(DocumentEntry::new(b"doc1".to_vec(), br#"{"a":1}"#.to_vec())).unwrap();
+        block.add_document(DocumentEntry::new(b"doc2".to_vec(), br#"{"b":2}"#.to_vec())).unwrap();
+        block
+    }
+
+    fn stored_checksum(bytes: &[u8]) -> u32 {
+        let end = bytes.len() - BlockFooter::SIZE;
+        u32::from_le_bytes([bytes[end], bytes[end + 1], bytes[end + 2], bytes[end + 3]])
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        for compression in ALGORITHMS {
+            let block = sample_block(compression);
+            let bytes = block.to_bytes().unwrap();
+            let decoded = Block::from_bytes(&bytes).unwrap();
+
+            assert_eq!(decoded.header.doc_count, 2);
+            assert_eq!(decoded.data, block.data);
+            assert_eq!(decoded.footer.checksum, stored_checksum(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_document_meta_prefix() {
+        let meta = DocumentMeta { version: 3, created_at: 100, updated_at: 250 };
+        let mut block = Block::new(CompressionType::None);
+        block.add_document(DocumentEntry::new(b"new".to_vec(), br#"{"a":1}"#.to_vec()).with_meta(meta)).unwrap();
+        block.add_document(DocumentEntry::new(b"old".to_vec(), br#"{"b":2}"#.to_vec())).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        let entry = decoded.document_at(0).unwrap().unwrap();
+        assert_eq!((entry.meta, entry.data.as_slice()), (Some(meta), br#"{"a":1}"#.as_slice()));
+        let entry = decoded.document_at(1).unwrap().unwrap();
+        assert_eq!((entry.meta, entry.data.as_slice()), (None, br#"{"b":2}"#.as_slice()));
+
+        let entries: Vec<_> = decoded.entries().collect();
+        assert_eq!(entries, vec![(b"new".as_slice(), Some(br#"{"a":1}"#.as_slice())), (b"old".as_slice(), Some(br#"{"b":2}"#.as_slice()))]);
+    }
+
+    #[test]
+    fn test_deletion_entry() {
+        let mut block = Block::new(CompressionType::None);
+        block.add_document(DocumentEntry::new(b"doc".to_vec(), b"{}".to_vec())).unwrap();
+        block.add_document(DocumentEntry::deletion(b"doc".to_vec())).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        assert!(!decoded.document_at(0).unwrap().unwrap().deleted);
+        let entry = decoded.document_at(1).unwrap().unwrap();
+        assert!(entry.deleted && entry.data.is_empty());
+        assert_eq!(decoded.entries().collect::<Vec<_>>(), vec![(b"doc".as_slice(), Some(b"{}".as_slice())), (b"doc".as_slice(), None)]);
+    }
+
+    #[test]
+    fn test_document_data_is_not_mistaken_for_deletion() {
+        let mut block = Block::new(CompressionType::None);
+        block.add_document(DocumentEntry::new(b"raw".to_vec(), vec![0xFE])).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        let entry = decoded.document_at(0).unwrap().unwrap();
+        assert!(!entry.deleted);
+        assert_eq!(entry.data, vec![0xFE]);
+        assert_eq!(decoded.entries().collect::<Vec<_>>(), vec![(b"raw".as_slice(), Some([0xFE].as_slice()))]);
+        assert!(!decoded.entry_locations().next().unwrap().2);
+    }
+
+    #[test]
+    fn test_document_data_is_not_mistaken_for_meta() {
+        // Starts with the marker older blocks used for the metadata prefix
+        let raw: Vec<u8> = std::iter::once(DocumentMeta::MARKER).chain(0..40).collect();
+        let mut block = Block::new(CompressionType::None);
+        block.add_document(DocumentEntry::new(b"raw".to_vec(), raw.clone())).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        let entry = decoded.document_at(0).unwrap().unwrap();
+        assert_eq!((entry.meta, &entry.data), (None, &raw));
+        assert_eq!(decoded.entries().collect::<Vec<_>>(), vec![(b"raw".as_slice(), Some(raw.as_slice()))]);
+    }
+
+    #[test]
+    fn test_reads_and_upgrades_entries_without_kind() {
+        let meta = DocumentMeta { version: 2, created_at: 100, updated_at: 250 };
+        let mut block = legacy_block(BlockHeader::MVCC_VERSION);
+        block.header.commit_version = 7;
+        block.add_document(DocumentEntry::deletion(b"doc1".to_vec())).unwrap();
+        block.add_document(DocumentEntry::new(b"doc3".to_vec(), br#"{"c":3}"#.to_vec()).with_meta(meta)).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        assert!(decoded.document_at(2).unwrap().unwrap().deleted);
+        let entry = decoded.document_at(3).unwrap().unwrap();
+        assert_eq!((entry.meta, entry.data.as_slice()), (Some(meta), br#"{"c":3}"#.as_slice()));
+        assert_eq!(decoded.entries().map(|(_, data)| data.is_some()).collect::<Vec<_>>(), vec![true, true, false, true]);
+
+        let upgraded = Block::from_bytes(&decoded.upgraded().unwrap().to_bytes().unwrap()).unwrap();
+        assert_eq!(upgraded.header.version, BlockHeader::VERSION);
+        assert_eq!(upgraded.header.commit_version, 7);
+        assert!(upgraded.header.has_directory());
+        assert_eq!(upgraded.entries().collect::<Vec<_>>(), decoded.entries().collect::<Vec<_>>());
+        assert_eq!(upgraded.document_at(3).unwrap().unwrap().meta, Some(meta));
+    }
+
+    #[test]
+    fn test_compressed_block_round_trip() {
+        for compression in [CompressionType::Snappy, CompressionType::Zstd, CompressionType::Lz4] {
+            let mut block = Block::new(compression);
+            for i in 0..100 {
+                let doc = format!(r#"{{"name":"user{}","active":true}}"#, i);
+                block.add_document(DocumentEntry::new(format!("doc{}", i).into_bytes(), doc.into_bytes())).unwrap();
+            }
+
+            let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+
+            assert_eq!(decoded.data, block.data);
+            assert_eq!(decoded.header.compression, compression);
+            assert!(decoded.header.compressed_size < decoded.header.uncompressed_size, "{:?}", compression);
+            assert!(decoded.compression_ratio() > 1.0);
+        }
+    }
+
+    #[test]
+    fn test_to_bytes_records_compressed_size() {
+        for compression in ALGORITHMS {
+            let bytes = sample_block(compression).to_bytes().unwrap();
+            let header = BlockHeader::from_bytes(&bytes).unwrap();
+
+            assert_eq!(header.compressed_size as usize, bytes.len() - header.size() - header.footer_size());
+        }
+    }
+
+    #[test]
+    fn test_reads_uncompressed_block_without_compressed_size() {
+        // Blocks written before compression was applied stored the data
+        // verbatim and left compressed_size at 0
+        let block = legacy_block(BlockHeader::LEGACY_VERSION);
+        assert_eq!(block.header.compressed_size, 0);
+
+        let mut bytes = block.header.to_bytes();
+        bytes.extend_from_slice(&block.data);
+        bytes.extend_from_slice(&block.compute_checksum().to_le_bytes());
+        bytes.extend_from_slice(&BlockHeader::MAGIC);
+
+        let decoded = Block::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.data, block.data);
+        assert_eq!(decoded.header.doc_count, 2);
+        assert!(decoded.footer.offsets.is_empty());
+        assert_eq!(decoded.document_at(1).unwrap().unwrap().id, b"doc2");
+    }
+
+    #[test]
+    fn test_footer_directory_points_at_documents() {
+        for compression in ALGORITHMS {
+            let mut block = Block::new(compression);
+            for i in 0..20 {
+                let doc = format!(r#"{{"n":{}}}"#, i);
+                block.add_document(DocumentEntry::new(format!("doc{}", i).into_bytes(), doc.into_bytes())).unwrap();
+            }
+
+            let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded.footer.offsets.len(), 20);
+
+            for (i, &offset) in decoded.footer.offsets.iter().enumerate() {
+                let entry = DocumentEntry::from_bytes(&decoded.data[offset as usize..], offset as usize).unwrap();
+                assert_eq!(entry.id, format!("doc{}", i).into_bytes());
+                assert_eq!(entry.data, format!(r#"{{"n":{}}}"#, i).into_bytes());
+            }
+            assert_eq!(decoded.document_at(7).unwrap().unwrap().id, b"doc7");
+            assert!(decoded.document_at(20).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_legacy_version_writes_no_directory() {
+        let block = legacy_block(BlockHeader::LEGACY_VERSION);
+
+        let bytes = block.to_bytes().unwrap();
+        assert_eq!(bytes.len(), BlockHeader::SIZE + block.data.len() + BlockFooter::SIZE);
+
+        let decoded = Block::from_bytes(&bytes).unwrap();
+        assert!(decoded.footer.offsets.is_empty());
+        assert_eq!(decoded.document_at(0).unwrap().unwrap().id, b"doc1");
+    }
+
+    #[test]
+    fn test_incompressible_data_stored_uncompressed() {
+        // Pseudo-random bytes do not compress
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let noise: Vec<u8> = (0..4096).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect();
+
+        let mut block = Block::new(CompressionType::Snappy);
+        block.add_document(DocumentEntry::new(b"noise".to_vec(), noise)).unwrap();
+
+        let decoded = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.header.compression, CompressionType::None);
+        assert_eq!(decoded.header.compressed_size, decoded.header.uncompressed_size);
+        assert_eq!(decoded.data, block.data);
+    }
+
+    #[test]
+    fn test_empty_block_round_trip() {
+        for compression in ALGORITHMS {
+            let block = Block::new(compression);
+            assert!(Block::from_bytes(&block.to_bytes().unwrap()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_bit_flip_in_data_fails_checksum() {
+        for compression in ALGORITHMS {
+            let block = sample_block(compression);
+            let mut bytes = block.to_bytes().unwrap();
+            let checksum = stored_checksum(&bytes);
+            bytes[block.header.size() + 3] ^= 0x01;
+
+            match Block::from_bytes(&bytes) {
+                Err(Error::ChecksumMismatch { expected, actual }) => {
+                    assert_eq!(expected, checksum);
+                    assert_ne!(expected, actual);
+                }
+                other => panic!("expected checksum mismatch, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bit_flip_in_header_fails_checksum() {
+        for compression in ALGORITHMS {
+            let mut bytes = sample_block(compression).to_bytes().unwrap();
+            bytes[6] ^= 0x01; // doc_count
+
+            assert!(matches!(Block::from_bytes(&bytes), Err(Error::ChecksumMismatch { .. })));
+        }
+    }
+
+    #[test]
+    fn test_swapped_bytes_fail_checksum() {
+        // A byte sum is blind to reordering; CRC32 is not
+        let block = sample_block(CompressionType::None);
+        let mut bytes = block.to_bytes().unwrap();
+        let (a, b) = (block.header.size(), block.header.size() + 1);
+        assert_ne!(bytes[a], bytes[b]);
+        bytes.swap(a, b);
+
+        assert!(matches!(Block::from_bytes(&bytes), Err(Error::ChecksumMismatch { .. })));
     }
 }
@@ -0,0 +1,171 @@
+//! Bloom filter for NebulaDB collections
+//!
+//! Answers "definitely not present" for document IDs that were never
+//! inserted, so lookups for missing documents skip the block scan.
+
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+
+/// Magic number for serialized Bloom filters: "NBBF"
+const BLOOM_MAGIC: [u8; 4] = [0x4E, 0x42, 0x42, 0x46];
+
+/// Default filter size in bits (128KB)
+pub const DEFAULT_BLOOM_BITS: usize = 1 << 20;
+
+/// Bloom filter using two hash functions over a bit vector
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    /// Bit vector
+    bits: Vec<u64>,
+    /// Number of usable bits
+    num_bits: u64,
+}
+
+impl BloomFilter {
+    /// Create an empty filter with at least `num_bits` bits
+    pub fn new(num_bits: usize) -> Self {
+        let words = num_bits.max(64).div_ceil(64);
+        Self {
+            bits: vec![0; words],
+            num_bits: words as u64 * 64,
+        }
+    }
+
+    /// Add a key to the filter
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.bit_positions(key) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Whether the key may have been inserted (`false` is definitive)
+    pub fn might_contain(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .iter()
+            .all(|&bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Number of bits in the filter
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    /// Bit positions for a key, one per hash function
+    fn bit_positions(&self, key: &[u8]) -> [u64; 2] {
+        let first = fnv1a(key);
+        [first % self.num_bits, mix(first ^ djb2(key)) % self.num_bits]
+    }
+
+    /// Serialize the filter: [magic(4)][num_bits(8)][words(8 * n)][crc32(4)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 8 + self.bits.len() * 8 + 4);
+        bytes.extend_from_slice(&BLOOM_MAGIC);
+        bytes.extend_from_slice(&self.num_bits.to_le_bytes());
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        let checksum = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a filter written by [`BloomFilter::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 4 + 8 + 4 || bytes[0..4] != BLOOM_MAGIC {
+            return Err(Error::Other("Invalid Bloom filter".to_string()));
+        }
+
+        let body_end = bytes.len() - 4;
+        let expected = u32::from_le_bytes([
+            bytes[body_end], bytes[body_end + 1], bytes[body_end + 2], bytes[body_end + 3],
+        ]);
+        let actual = crc32fast::hash(&bytes[..body_end]);
+        if actual != expected {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+
+        let mut num_bits = [0u8; 8];
+        num_bits.copy_from_slice(&bytes[4..12]);
+        let num_bits = u64::from_le_bytes(num_bits);
+
+        let bits: Vec<u64> = bytes[12..body_end]
+            .chunks_exact(8)
+            .map(|chunk| {
+                let mut word = [0u8; 8];
+                word.copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+
+        if num_bits == 0 || bits.len() as u64 * 64 != num_bits {
+            return Err(Error::Other("Invalid Bloom filter: size mismatch".to_string()));
+        }
+
+        Ok(Self { bits, num_bits })
+    }
+
+    /// Load a filter from a file
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| Error::Other(format!("Failed to read Bloom filter: {}", e)))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Write the filter to a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| Error::Other(format!("Failed to write Bloom filter: {}", e)))
+    }
+}
+
+/// 64-bit FNV-1a hash
+fn fnv1a(key: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in key {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// 64-bit djb2 hash
+fn djb2(key: &[u8]) -> u64 {
+    let mut hash = 5381u64;
+    for &byte in key {
+        hash = hash.wrapping_mul(33) ^ byte as u64;
+    }
+    hash
+}
+
+/// SplitMix64 finalizer to spread the bits of a hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let mut filter = BloomFilter::new(1024);
+        filter.insert(b"doc1");
+
+        let decoded = BloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!(decoded.might_contain(b"doc1"));
+        assert_eq!(decoded.num_bits(), filter.num_bits());
+    }
+
+    #[test]
+    fn test_corrupt_filter_rejected() {
+        let mut filter = BloomFilter::new(1024);
+        filter.insert(b"doc1");
+        let mut bytes = filter.to_bytes();
+        bytes[20] ^= 0x01;
+
+        assert!(BloomFilter::from_bytes(&bytes).is_err());
+    }
+}
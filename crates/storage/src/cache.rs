@@ -0,0 +1,134 @@
+//! Block cache for NebulaDB storage
+//!
+//! Keeps recently used decompressed blocks in memory so repeated lookups
+//! skip the disk read and decompression.
+
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+use nebuladb_core::metrics;
+
+use crate::Block;
+
+/// Cache key: (collection name, block index)
+pub type BlockKey = (String, u32);
+
+/// Hit and miss counters for a block cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups served from the cache
+    pub hits: u64,
+    /// Lookups that had to go to disk
+    pub misses: u64,
+}
+
+/// LRU cache of decompressed blocks
+///
+/// Entries are kept in recency order, least recently used first, so eviction
+/// pops from the front.
+#[derive(Debug)]
+pub struct BlockCache {
+    /// Maximum number of blocks held (0 disables caching)
+    capacity: usize,
+    /// Cached blocks in recency order
+    entries: IndexMap<BlockKey, Arc<Block>>,
+    /// Hit and miss counters
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    /// Create a cache holding at most `capacity` blocks
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: IndexMap::with_capacity(capacity),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Look up a block, marking it as most recently used
+    pub fn get(&mut self, key: &BlockKey) -> Option<Arc<Block>> {
+        match self.entries.shift_remove(key) {
+            Some(block) => {
+                self.stats.hits += 1;
+                metrics::BLOCK_CACHE_HITS.inc();
+                self.entries.insert(key.clone(), Arc::clone(&block));
+                Some(block)
+            }
+            None => {
+                self.stats.misses += 1;
+                metrics::BLOCK_CACHE_MISSES.inc();
+                None
+            }
+        }
+    }
+
+    /// Insert a block, evicting the least recently used one if full
+    pub fn insert(&mut self, key: BlockKey, block: Arc<Block>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.entries.shift_remove(&key);
+        while self.entries.len() >= self.capacity {
+            self.entries.shift_remove_index(0);
+        }
+        self.entries.insert(key, block);
+    }
+
+    /// Drop every cached block, keeping the counters
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of cached blocks
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no blocks
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Hit and miss counters
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompressionType;
+
+    fn key(idx: u32) -> BlockKey {
+        ("test".to_string(), idx)
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = BlockCache::new(2);
+        cache.insert(key(0), Arc::new(Block::new(CompressionType::None)));
+        cache.insert(key(1), Arc::new(Block::new(CompressionType::None)));
+
+        // Touch block 0 so block 1 becomes the eviction candidate
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(2), Arc::new(Block::new(CompressionType::None)));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(2)).is_some());
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let mut cache = BlockCache::new(0);
+        cache.insert(key(0), Arc::new(Block::new(CompressionType::None)));
+
+        assert!(cache.is_empty());
+        assert!(cache.get(&key(0)).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+//! Change feed for NebulaDB collections
+//!
+//! Every write to a collection is published to its change feed. Subscribers
+//! receive the events that happen after they subscribe, in write order, over
+//! a bounded broadcast channel; dropping the subscription unsubscribes. A
+//! subscriber that falls more than [`FEED_CAPACITY`] events behind loses the
+//! oldest ones and is told how many with [`Lagged`].
+//!
+//! An event's sequence number is the position in the collection's WAL just
+//! past its write, so sequence numbers keep growing across restarts. A
+//! subscriber that reconnects compares the [resume
+//! token](Subscription::resume_token) of its new subscription with the last
+//! sequence number it received to tell whether it may have missed events in
+//! between. Collections without a WAL number their writes from 1 each time
+//! they are opened.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast::{self, error::{RecvError, TryRecvError}};
+
+/// Number of events a subscriber may fall behind before it loses the oldest
+pub const FEED_CAPACITY: usize = 1024;
+
+/// How often [`Subscription::recv_timeout`] looks for an event
+const RECV_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Kind of write recorded by a change event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A single write to a collection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// Position of the event in the collection's feed: the WAL position
+    /// past the write, or a count from 1 without a WAL
+    pub sequence: u64,
+    /// Kind of write
+    pub op: ChangeOp,
+    /// ID of the document written
+    pub id: Vec<u8>,
+    /// New document data (`None` for deletes)
+    pub data: Option<Vec<u8>>,
+    /// UNIX time of the write in milliseconds
+    pub timestamp: u64,
+}
+
+/// A subscriber fell behind and lost events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged {
+    /// Number of events lost; the next event received follows them
+    pub missed: u64,
+}
+
+/// Publisher side of a collection's change feed
+#[derive(Debug, Clone)]
+pub struct ChangeFeed {
+    inner: Arc<Mutex<FeedState>>,
+}
+
+#[derive(Debug)]
+struct FeedState {
+    /// Sequence number of the last write
+    sequence: u64,
+    /// Channel every subscription receives from
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangeFeed {
+    /// Create a feed without subscribers
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Self {
+            inner: Arc::new(Mutex::new(FeedState { sequence: 0, sender })),
+        }
+    }
+
+    /// Start receiving events published from now on
+    pub fn subscribe(&self) -> Subscription {
+        let state = self.lock();
+        Subscription {
+            resume_token: state.sequence,
+            receiver: state.sender.subscribe(),
+        }
+    }
+
+    /// Number of open subscriptions
+    pub fn subscriber_count(&self) -> usize {
+        self.lock().sender.receiver_count()
+    }
+
+    /// Count the writes up to WAL position `position` as published, so
+    /// subscriptions taken from now on resume after them
+    pub fn advance_to(&self, position: u64) {
+        let mut state = self.lock();
+        state.sequence = state.sequence.max(position);
+    }
+
+    /// Number the write and deliver it to every subscriber
+    ///
+    /// `position` is the WAL position just past the write, which becomes its
+    /// sequence number; without one the last sequence number is counted up.
+    pub fn publish(&self, op: ChangeOp, id: &[u8], data: Option<&[u8]>, position: Option<u64>) {
+        let mut state = self.lock();
+        state.sequence = position.unwrap_or(state.sequence + 1);
+        if state.sender.receiver_count() == 0 {
+            return;
+        }
+
+        let event = ChangeEvent {
+            sequence: state.sequence,
+            op,
+            id: id.to_vec(),
+            data: data.map(<[u8]>::to_vec),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        };
+
+        // Only fails when every subscription is gone
+        let _ = state.sender.send(event);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FeedState> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Receiving side of a change feed subscription, unsubscribed on drop
+#[derive(Debug)]
+pub struct Subscription {
+    resume_token: u64,
+    receiver: broadcast::Receiver<ChangeEvent>,
+}
+
+impl Subscription {
+    /// Sequence number of the last write before subscribing
+    ///
+    /// Events received have higher sequence numbers. A client that last saw
+    /// sequence number `m` may have missed writes if the token is above `m`.
+    pub fn resume_token(&self) -> u64 {
+        self.resume_token
+    }
+
+    /// Wait for the next event
+    ///
+    /// Returns `None` once the feed is gone.
+    pub async fn recv(&mut self) -> Option<Result<ChangeEvent, Lagged>> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(Ok(event)),
+            Err(RecvError::Lagged(missed)) => Some(Err(Lagged { missed })),
+            Err(RecvError::Closed) => None,
+        }
+    }
+
+    /// Wait up to `timeout` for the next event, blocking the thread
+    ///
+    /// Returns `None` if no event arrived in time.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Result<ChangeEvent, Lagged>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(received) = self.try_recv() {
+                return Some(received);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            std::thread::sleep(RECV_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Take the next event if one is already waiting
+    pub fn try_recv(&mut self) -> Option<Result<ChangeEvent, Lagged>> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(Ok(event)),
+            Err(TryRecvError::Lagged(missed)) => Some(Err(Lagged { missed })),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => None,
+        }
+    }
+
+    /// Stop receiving events
+    pub fn unsubscribe(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::Collection;
+    use crate::StorageConfig;
+
+    #[test]
+    fn test_writes_after_subscribing_arrive_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("users", dir.path(), &StorageConfig::default()).unwrap();
+        collection.insert(b"before", b"{}").unwrap();
+
+        let mut subscription = collection.subscribe();
+        assert_eq!(subscription.resume_token(), 1);
+        for i in 0..20 {
+            collection.insert(format!("user{}", i).as_bytes(), format!(r#"{{"n":{}}}"#, i).as_bytes()).unwrap();
+        }
+        collection.delete(b"user3").unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = subscription.recv_timeout(Duration::from_millis(100)) {
+            events.push(event.unwrap());
+        }
+
+        assert_eq!(events.len(), 21);
+        for (i, event) in events.iter().take(20).enumerate() {
+            assert_eq!(event.sequence, i as u64 + 2);
+            assert_eq!(event.op, ChangeOp::Insert);
+            assert_eq!(event.id, format!("user{}", i).into_bytes());
+            assert_eq!(event.data, Some(format!(r#"{{"n":{}}}"#, i).into_bytes()));
+        }
+        assert_eq!(events[20].op, ChangeOp::Delete);
+        assert_eq!(events[20].id, b"user3".to_vec());
+        assert_eq!(events[20].data, None);
+    }
+
+    #[test]
+    fn test_unsubscribe_removes_subscriber() {
+        let feed = ChangeFeed::new();
+        let first = feed.subscribe();
+        let mut second = feed.subscribe();
+        assert_eq!(feed.subscriber_count(), 2);
+
+        first.unsubscribe();
+        assert_eq!(feed.subscriber_count(), 1);
+
+        feed.publish(ChangeOp::Insert, b"doc", Some(b"{}"), None);
+        assert_eq!(second.try_recv().map(|event| event.unwrap().id), Some(b"doc".to_vec()));
+
+        drop(second);
+        assert_eq!(feed.subscriber_count(), 0);
+    }
+
+    #[test]
+    fn test_resume_token_reveals_missed_writes() {
+        let feed = ChangeFeed::new();
+        let mut first = feed.subscribe();
+        feed.publish(ChangeOp::Insert, b"a", Some(b"{}"), Some(40));
+        let last_seen = first.try_recv().unwrap().unwrap().sequence;
+        assert_eq!(last_seen, 40);
+        drop(first);
+
+        // Written while nobody was subscribed
+        feed.publish(ChangeOp::Insert, b"b", Some(b"{}"), Some(75));
+
+        let mut second = feed.subscribe();
+        assert_eq!(second.resume_token(), 75);
+        assert!(second.resume_token() > last_seen);
+        feed.publish(ChangeOp::Delete, b"a", None, Some(90));
+        assert_eq!(second.try_recv().unwrap().unwrap().sequence, 90);
+    }
+
+    #[test]
+    fn test_slow_subscriber_is_told_how_many_events_it_lost() {
+        let feed = ChangeFeed::new();
+        let mut subscription = feed.subscribe();
+        for i in 0..FEED_CAPACITY + 10 {
+            feed.publish(ChangeOp::Insert, format!("doc{}", i).as_bytes(), Some(b"{}"), None);
+        }
+
+        assert_eq!(subscription.try_recv(), Some(Err(Lagged { missed: 10 })));
+        // Delivery continues with the oldest event still held
+        let next = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(next.id, b"doc10".to_vec());
+        assert_eq!(next.sequence, 11);
+    }
+}
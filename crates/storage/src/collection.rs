@@ -1,12 +1,81 @@
 //! Collection management for NebulaDB storage
 
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::time::Duration;
 
-use nebuladb_core::{Result, Error};
+use nebuladb_core::{metrics, Result, Error};
+use nebuladb_index::{BTreeIndex, CompoundIndex, FieldIndex, Index, ScanDirection, TextIndex, TtlIndex, UniqueIndex};
+use nebuladb_query::{FindOptions, Pipeline, Query, QueryConfig};
+use nebuladb_wal::{EntryType, WalEntry};
+use nebuladb_wal::manager::{SharedWalManager, WalManager};
+use serde_json::Value as JsonValue;
 
-use crate::StorageConfig;
-use crate::manager::BlockManager;
+use crate::{CompressionType, StorageConfig};
+use crate::block::{DocumentEntry, DocumentMeta};
+use crate::bloom::{BloomFilter, DEFAULT_BLOOM_BITS};
+use crate::changefeed::{ChangeFeed, ChangeOp, Subscription};
+use crate::compaction::{CompactionLimiter, CompactionStats};
+use crate::manager::{BlockManager, DocumentIter, IdPage};
+use crate::meta::{CollectionMeta, META_FILE};
+use crate::mvcc::{self, Snapshot};
+use crate::plan::{self, QueryPlan};
+
+/// Size of a collection's blocks file before and after recompression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecompressStats {
+    /// Size of the blocks file before recompression, in bytes
+    pub size_before: u64,
+    /// Size of the blocks file after recompression, in bytes
+    pub size_after: u64,
+}
+
+/// Compression effectiveness across a collection's on-disk blocks
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompressionStats {
+    /// Number of blocks on disk
+    pub block_count: usize,
+    /// Total document bytes before compression
+    pub uncompressed_bytes: u64,
+    /// Total document bytes as stored
+    pub compressed_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Ratio of uncompressed to stored bytes (1.0 for an empty collection)
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 1.0;
+        }
+        
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Size and document counts of a collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectionStats {
+    /// Number of live documents
+    pub doc_count: u64,
+    /// Total size of the files in the collection directory, in bytes
+    pub size_bytes: u64,
+    /// Size of the index files among them, in bytes
+    pub index_size_bytes: u64,
+    /// Number of blocks on disk
+    pub block_count: u32,
+    /// Number of deleted documents not yet reclaimed by compaction
+    pub deleted_doc_count: u64,
+}
+
+/// What [`Collection::upsert`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertResult {
+    /// No document had the ID, so one was inserted
+    Inserted,
+    /// The existing document was replaced
+    Updated,
+}
 
 /// A collection in NebulaDB storage
 #[derive(Debug, Clone)]
@@ -17,6 +86,20 @@ pub struct Collection {
     pub path: PathBuf,
     /// Block manager for this collection
     pub block_manager: BlockManager,
+    /// Bloom filter over the IDs of inserted documents
+    bloom: BloomFilter,
+    /// Write-ahead log that writes are recorded in before being applied
+    wal: Option<SharedWalManager>,
+    /// Feed publishing every write to subscribers
+    changes: ChangeFeed,
+    /// Secondary indexes over document fields
+    indexes: Vec<FieldIndex>,
+    /// Full-text indexes over string fields
+    text_indexes: Vec<TextIndex>,
+    /// Indexes over several fields at once
+    compound_indexes: Vec<CompoundIndex>,
+    /// Document counters, mirrored to `meta.json` on every change
+    meta: CollectionMeta,
 }
 
 impl Collection {
@@ -26,21 +109,602 @@ impl Collection {
         
         // Create directory if it doesn't exist
         if !path.exists() {
-            fs::create_dir_all(&path).map_err(|e| Error::IoError(e))?;
+            fs::create_dir_all(&path).map_err(Error::IoError)?;
         }
         
-        let block_manager = BlockManager::new(name, path.clone(), config.clone());
+        let block_manager = BlockManager::open(name, path.clone(), config.clone())?;
+        let bloom = Self::load_bloom(&path, &block_manager)?;
+        let meta = Self::load_meta(&path, &block_manager)?;
         
-        Ok(Self {
+        let mut collection = Self {
             name: name.to_string(),
             path,
             block_manager,
-        })
+            bloom,
+            wal: None,
+            changes: ChangeFeed::new(),
+            indexes: Vec::new(),
+            text_indexes: Vec::new(),
+            compound_indexes: Vec::new(),
+            meta,
+        };
+        collection.load_indexes()?;
+        
+        Ok(collection)
+    }
+    
+    /// Open a collection whose writes are recorded in a write-ahead log
+    ///
+    /// Entries logged since the collection's last checkpoint are replayed
+    /// first, so writes that never reached a flushed block before a crash are
+    /// restored. Replay is idempotent: documents already stored with the
+    /// logged data are not written again.
+    pub fn open_with_wal(name: &str, base_path: &Path, config: &StorageConfig, wal: SharedWalManager) -> Result<Self> {
+        let mut collection = Self::open(name, base_path, config)?;
+        
+        // The WAL is attached after replay so replayed writes are not logged again
+        let replayed = wal.read()
+            .map_err(|_| Error::Other("Failed to lock WAL manager".into()))?
+            .replay(name, |entry| collection.apply_wal_entry(entry))?;
+        collection.wal = Some(wal);
+        
+        // Persist the replayed documents so the next open starts after them
+        if replayed > 0 {
+            collection.block_manager.flush()?;
+            collection.checkpoint()?;
+        }
+        
+        // Resume tokens continue from the WAL position of the last write
+        let position = collection.log(|wal| wal.position(name))?;
+        collection.changes.advance_to(position);
+        
+        Ok(collection)
+    }
+    
+    /// Redo a write recovered from the WAL
+    fn apply_wal_entry(&mut self, entry: &WalEntry) -> Result<()> {
+        let id = &entry.header.document_id;
+        match entry.header.entry_type {
+            // Skip documents already stored with the logged data; updates go
+            // through the update path so the version history and the change
+            // published match the original write
+            EntryType::Insert | EntryType::Update
+                if self.get(id)?.as_deref() != Some(entry.data.as_slice()) =>
+            {
+                let op = match entry.header.entry_type {
+                    EntryType::Update => ChangeOp::Update,
+                    _ => ChangeOp::Insert,
+                };
+                self.write(&[(id.clone(), entry.data.clone())], op)
+            }
+            EntryType::Delete => self.delete(id).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Record in the WAL that every write so far is stored in flushed blocks
+    fn checkpoint(&self) -> Result<()> {
+        if let Some(wal) = &self.wal {
+            wal.write()
+                .map_err(|_| Error::Other("Failed to lock WAL manager".into()))?
+                .checkpoint(&self.name)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Run `f` against the WAL manager, if this collection has one, and
+    /// return its result; without a WAL the default is returned
+    fn log<T: Default>(&self, f: impl FnOnce(&mut WalManager) -> Result<T>) -> Result<T> {
+        if let Some(wal) = &self.wal {
+            let (result, commit) = {
+                let mut wal = wal.write()
+                    .map_err(|_| Error::Other("Failed to lock WAL manager".into()))?;
+                let result = f(&mut wal)?;
+                (result, wal.take_commit())
+            };
+            
+            // Wait for a group commit without the lock so others can join it
+            if let Some(commit) = commit {
+                commit.wait()?;
+            }
+            return Ok(result);
+        }
+        
+        Ok(T::default())
+    }
+    
+    /// Load the Bloom filter saved by the last close, or rebuild it from the blocks
+    ///
+    /// The sidecar file is removed once loaded so that, after a crash, a
+    /// filter missing the latest inserts is never trusted; the next open
+    /// rebuilds it instead.
+    fn load_bloom(path: &Path, block_manager: &BlockManager) -> Result<BloomFilter> {
+        let bloom_path = path.join("bloom.bin");
+        
+        if bloom_path.exists() {
+            let loaded = BloomFilter::load(&bloom_path);
+            fs::remove_file(&bloom_path).map_err(Error::IoError)?;
+            if let Ok(bloom) = loaded {
+                return Ok(bloom);
+            }
+        }
+        
+        let ids = block_manager.scan_document_ids()?;
+        let mut bloom = BloomFilter::new(DEFAULT_BLOOM_BITS.max(ids.len() * 16));
+        for id in &ids {
+            bloom.insert(id);
+        }
+        
+        Ok(bloom)
+    }
+    
+    /// Load the document counters saved by the last close, recounting them
+    /// after a crash
+    ///
+    /// Like the Bloom filter, the counters are marked unclean once loaded and
+    /// only marked clean again by the next close.
+    fn load_meta(path: &Path, block_manager: &BlockManager) -> Result<CollectionMeta> {
+        let meta_path = path.join(META_FILE);
+        let meta = match CollectionMeta::load(&meta_path) {
+            Ok(meta) if meta.clean => CollectionMeta { clean: false, ..meta },
+            _ => {
+                let (live, deleted) = block_manager.document_counts()?;
+                CollectionMeta { doc_count: live as u64, deleted_doc_count: deleted as u64, clean: false }
+            }
+        };
+        meta.save(&meta_path)?;
+        
+        Ok(meta)
+    }
+    
+    /// Apply `f` to the document counters and save them if they changed
+    fn update_meta(&mut self, f: impl FnOnce(&mut CollectionMeta)) -> Result<()> {
+        let before = self.meta;
+        f(&mut self.meta);
+        if self.meta != before {
+            self.meta.save(&self.path.join(META_FILE))?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Path of the file holding the index over `field`
+    fn index_path(&self, field: &str) -> PathBuf {
+        self.path.join(format!("{}_{}.idx", self.name, field))
+    }
+    
+    /// Path of the file holding the text index over `field`
+    fn text_index_path(&self, field: &str) -> PathBuf {
+        self.path.join(format!("{}_{}.txt.idx", self.name, field))
+    }
+    
+    /// Path of the file holding the compound index over `fields`
+    fn compound_index_path(&self, fields: &[String]) -> PathBuf {
+        self.path.join(format!("{}_{}.cidx", self.name, fields.join("+")))
+    }
+    
+    /// Load the indexes saved by the last close, rebuilding any left
+    /// incomplete by a crash
+    ///
+    /// Like the Bloom filter, each index file is marked incomplete once
+    /// loaded and only marked complete again by the next close.
+    fn load_indexes(&mut self) -> Result<()> {
+        let mut index_paths = Vec::new();
+        for entry in fs::read_dir(&self.path).map_err(Error::IoError)? {
+            let path = entry.map_err(Error::IoError)?.path();
+            if path.extension().is_some_and(|ext| ext == "idx" || ext == "cidx") {
+                index_paths.push(path);
+            }
+        }
+        index_paths.sort();
+        
+        for path in index_paths {
+            if path.extension().is_some_and(|ext| ext == "cidx") {
+                let (mut index, complete) = CompoundIndex::load(&path)?;
+                if !complete {
+                    self.fill_compound_index(&mut index)?;
+                }
+                index.save(&path, false)?;
+                self.compound_indexes.push(index);
+                continue;
+            }
+            
+            if path.to_string_lossy().ends_with(".txt.idx") {
+                let (mut index, complete) = TextIndex::load(&path)?;
+                if !complete {
+                    self.fill_text_index(&mut index)?;
+                }
+                index.save(&path, false)?;
+                self.text_indexes.push(index);
+                continue;
+            }
+            
+            let (mut index, complete) = FieldIndex::load(&path)?;
+            if !complete {
+                self.fill_index(&mut index)?;
+            }
+            index.save(&path, false)?;
+            self.indexes.push(index);
+        }
+        
+        Ok(())
+    }
+    
+    /// Index every stored JSON document
+    fn fill_index(&self, index: &mut FieldIndex) -> Result<()> {
+        for id in self.scan()? {
+            if let Ok(Some(doc)) = self.get_json(&id) {
+                index.insert(&id, &doc)?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Add every stored JSON document to a text index
+    fn fill_text_index(&self, index: &mut TextIndex) -> Result<()> {
+        for id in self.scan()? {
+            if let Ok(Some(doc)) = self.get_json(&id) {
+                index.insert(&id, &doc);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Add every stored JSON document to a compound index
+    fn fill_compound_index(&self, index: &mut CompoundIndex) -> Result<()> {
+        for id in self.scan()? {
+            if let Ok(Some(doc)) = self.get_json(&id) {
+                index.insert(&id, &doc);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Index the words of the string field `field` for full-text search
+    ///
+    /// Creating a text index that already exists does nothing.
+    pub fn create_text_index(&mut self, field: &str) -> Result<()> {
+        if self.text_index(field).is_some() {
+            return Ok(());
+        }
+        
+        let mut index = TextIndex::new(field);
+        self.fill_text_index(&mut index)?;
+        index.save(&self.text_index_path(field), false)?;
+        self.text_indexes.push(index);
+        
+        Ok(())
+    }
+    
+    /// The text index over `field`, if one was created
+    pub fn text_index(&self, field: &str) -> Option<&TextIndex> {
+        self.text_indexes.iter().find(|index| index.field() == field)
+    }
+    
+    /// Index the documents of this collection by `field`
+    ///
+    /// Existing documents are indexed right away and later writes keep the
+    /// index up to date. Creating an index that already exists does nothing.
+    /// Use [`Collection::add_unique_index`] to also reject duplicate values.
+    pub fn create_index(&mut self, field: &str) -> Result<()> {
+        if self.index(field).is_some() {
+            return Ok(());
+        }
+        
+        self.add_index(FieldIndex::Plain(BTreeIndex::new(field)))
+    }
+    
+    /// Index the documents of this collection by `field`, rejecting writes
+    /// that would give two documents the same value
+    ///
+    /// Fails with [`Error::DuplicateKey`] if existing documents already share
+    /// a value. An existing non-unique index over `field` is an error too.
+    pub fn add_unique_index(&mut self, field: &str) -> Result<()> {
+        if let Some(index) = self.index(field) {
+            if index.is_unique() {
+                return Ok(());
+            }
+            return Err(Error::Other(format!(
+                "Field '{}' already has a non-unique index", field)));
+        }
+        
+        self.add_index(FieldIndex::Unique(UniqueIndex::new(field)))
+    }
+    
+    /// Fill a new index from the stored documents and start maintaining it
+    fn add_index(&mut self, mut index: FieldIndex) -> Result<()> {
+        self.fill_index(&mut index)?;
+        index.save(&self.index_path(index.field()), false)?;
+        self.indexes.push(index);
+        
+        Ok(())
+    }
+    
+    /// The index over `field`, if one was created
+    pub fn index(&self, field: &str) -> Option<&FieldIndex> {
+        self.indexes.iter().find(|index| index.field() == field)
+    }
+    
+    /// Remove the index over `field`, unique or not, and its file
+    ///
+    /// Queries on the field go back to scanning. Returns whether there was
+    /// an index to drop.
+    pub fn drop_index(&mut self, field: &str) -> Result<bool> {
+        let Some(position) = self.indexes.iter().position(|index| index.field() == field) else {
+            return Ok(false);
+        };
+        
+        self.indexes.remove(position);
+        match std::fs::remove_file(self.index_path(field)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+            Err(e) => Err(Error::IoError(e)),
+        }
+    }
+    
+    /// Index the documents of this collection by the tuple of `fields`, in
+    /// the order given
+    ///
+    /// The index answers equality queries on any leading run of the fields.
+    /// Creating a compound index that already exists does nothing.
+    pub fn add_compound_index(&mut self, fields: &[&str]) -> Result<()> {
+        if self.compound_index(fields).is_some() {
+            return Ok(());
+        }
+        
+        let mut index = CompoundIndex::new(fields)?;
+        self.fill_compound_index(&mut index)?;
+        index.save(&self.compound_index_path(index.fields()), false)?;
+        self.compound_indexes.push(index);
+        
+        Ok(())
+    }
+    
+    /// The compound index over exactly `fields`, in that order, if one was
+    /// created
+    pub fn compound_index(&self, fields: &[&str]) -> Option<&CompoundIndex> {
+        self.compound_indexes.iter().find(|index| index.fields().iter().eq(fields.iter()))
+    }
+    
+    /// Whether writes have any index to maintain
+    fn has_indexes(&self) -> bool {
+        !self.indexes.is_empty() || !self.text_indexes.is_empty() || !self.compound_indexes.is_empty()
+    }
+    
+    /// Move a document from its `old` to its `new` indexed values
+    fn update_indexes(&mut self, id: &[u8], old: Option<&JsonValue>, new: Option<&JsonValue>) -> Result<()> {
+        for index in &mut self.indexes {
+            if let Some(old) = old {
+                index.remove(id, old);
+            }
+            if let Some(new) = new {
+                index.insert(id, new)?;
+            }
+        }
+        for index in &mut self.compound_indexes {
+            if let Some(old) = old {
+                index.remove(id, old);
+            }
+            if let Some(new) = new {
+                index.insert(id, new);
+            }
+        }
+        for index in &mut self.text_indexes {
+            match new {
+                Some(new) => index.insert(id, new),
+                None => index.remove(id),
+            }
+        }
+        
+        Ok(())
     }
     
     /// Insert a document into the collection
     pub fn insert(&mut self, id: &[u8], data: &[u8]) -> Result<()> {
-        self.block_manager.insert(id, data)
+        self.write(&[(id.to_vec(), data.to_vec())], ChangeOp::Insert)
+    }
+    
+    /// Insert several documents at once
+    ///
+    /// Index constraints are checked as if the documents were inserted one
+    /// after another, and nothing is written if any of them fails. All the
+    /// documents go into the active block, which is flushed at most once, at
+    /// the end.
+    pub fn insert_batch(&mut self, docs: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        self.write(docs, ChangeOp::Insert)
+    }
+    
+    /// Insert documents from an iterator, one block's worth at a time
+    ///
+    /// Documents are grouped into batches that fill the active block up to
+    /// the flush threshold, so it is flushed once per block boundary and the
+    /// document counters are saved once per batch. Returns the number of
+    /// documents written. Each batch is all-or-nothing as in
+    /// [`insert_batch`](Self::insert_batch); batches written before a failing
+    /// one are kept.
+    pub fn bulk_insert(&mut self, docs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<u64> {
+        self.bulk_write(docs, false)
+    }
+    
+    /// Like [`bulk_insert`](Self::bulk_insert) for documents whose IDs are
+    /// known to be distinct and not yet stored, such as a sorted dump loaded
+    /// into an empty collection
+    ///
+    /// Skips looking up existing versions of each ID, so documents breaking
+    /// that promise leave the counters from [`stats`](Self::stats) and any
+    /// index holding stale entries.
+    pub fn bulk_insert_ordered(&mut self, docs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<u64> {
+        self.bulk_write(docs, true)
+    }
+    
+    /// Write `docs` in batches ending at block boundaries
+    fn bulk_write(&mut self, docs: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>, known_new: bool) -> Result<u64> {
+        let threshold = self.block_manager.config().flush_threshold.max(1);
+        let mut written = 0;
+        let mut batch = Vec::with_capacity(threshold);
+        let mut docs = docs.into_iter().peekable();
+        
+        while docs.peek().is_some() {
+            let room = threshold.saturating_sub(self.block_manager.active_doc_count()).max(1);
+            batch.extend(docs.by_ref().take(room));
+            self.write_docs(&batch, ChangeOp::Insert, known_new)?;
+            written += batch.len() as u64;
+            batch.clear();
+        }
+        
+        Ok(written)
+    }
+    
+    /// Replace the data of an existing document
+    ///
+    /// Returns `false` if the document does not exist. The new version is
+    /// appended and shadows the old one, which compaction later reclaims.
+    pub fn update(&mut self, id: &[u8], data: &[u8]) -> Result<bool> {
+        if self.get(id)?.is_none() {
+            return Ok(false);
+        }
+        
+        self.write(&[(id.to_vec(), data.to_vec())], ChangeOp::Update)?;
+        Ok(true)
+    }
+    
+    /// Insert the document, or replace it if one with `id` already exists
+    ///
+    /// A replaced document's new version supersedes the old one, which stays
+    /// on disk until [`compact`](Self::compact) reclaims it.
+    pub fn upsert(&mut self, id: &[u8], data: &[u8]) -> Result<UpsertResult> {
+        if self.get(id)?.is_some() {
+            self.write(&[(id.to_vec(), data.to_vec())], ChangeOp::Update)?;
+            Ok(UpsertResult::Updated)
+        } else {
+            self.write(&[(id.to_vec(), data.to_vec())], ChangeOp::Insert)?;
+            Ok(UpsertResult::Inserted)
+        }
+    }
+    
+    /// Store a new version of each document, logged as `op`
+    fn write(&mut self, docs: &[(Vec<u8>, Vec<u8>)], op: ChangeOp) -> Result<()> {
+        self.write_docs(docs, op, false)
+    }
+    
+    /// Store a new version of each document, logged as `op`; with
+    /// `known_new` the IDs are trusted to be distinct and not yet stored
+    fn write_docs(&mut self, docs: &[(Vec<u8>, Vec<u8>)], op: ChangeOp, known_new: bool) -> Result<()> {
+        // IDs never stored before, or whose newest entry is a deletion,
+        // become new documents
+        let mut added = 0;
+        let mut previous: HashMap<&[u8], DocumentMeta> = HashMap::new();
+        if known_new {
+            added = docs.len() as u64;
+        } else {
+            let mut seen = HashSet::new();
+            for (id, _) in docs {
+                if !seen.insert(id.as_slice()) {
+                    continue;
+                }
+                let stored = if self.bloom.might_contain(id) {
+                    self.block_manager.find_document_with_meta(id)?
+                } else {
+                    None
+                };
+                match stored {
+                    Some((meta, _)) => { previous.insert(id, meta.unwrap_or_default()); },
+                    None => added += 1,
+                }
+            }
+        }
+        
+        // Updates continue the version history; inserts start a new one
+        let now = unix_now();
+        let entries: Vec<DocumentEntry> = docs.iter().map(|(id, data)| {
+            let meta = match (op, previous.get(id.as_slice())) {
+                (ChangeOp::Update, Some(meta)) => meta.next(now),
+                _ => DocumentMeta::new(now),
+            };
+            previous.insert(id, meta);
+            DocumentEntry::new(id.clone(), data.clone()).with_meta(meta)
+        }).collect();
+        
+        // Index constraints are enforced before anything is written
+        let versions = if self.has_indexes() {
+            self.index_batch(docs, known_new)?
+        } else {
+            Vec::new()
+        };
+        
+        // The WAL position after each document orders the changes published
+        let stored = self.log(|wal| docs.iter().map(|(id, data)| {
+            match op {
+                ChangeOp::Update => wal.update(&self.name, id, data)?,
+                _ => wal.insert(&self.name, id, data)?,
+            }
+            wal.position(&self.name)
+        }).collect::<Result<Vec<u64>>>())
+            .and_then(|positions| self.block_manager.insert_entries(entries).map(|_| positions));
+        let positions = match stored {
+            Ok(positions) => positions,
+            Err(e) => {
+                self.unindex_batch(docs, &versions);
+                return Err(e);
+            }
+        };
+        
+        for (i, (id, data)) in docs.iter().enumerate() {
+            self.bloom.insert(id);
+            self.changes.publish(op, id, Some(data), positions.get(i).copied());
+        }
+        if op == ChangeOp::Insert {
+            metrics::DOCUMENTS_INSERTED.with_label_values(&[&self.name]).inc_by(docs.len() as u64);
+        }
+        self.update_meta(|meta| meta.doc_count += added)
+    }
+    
+    /// Move each document of a batch to its new indexed values, in order
+    ///
+    /// Returns the old and new version of every document. Documents that are
+    /// not JSON are stored but not indexed. If a document breaks an index
+    /// constraint the indexes are left as they were.
+    fn index_batch(&mut self, docs: &[(Vec<u8>, Vec<u8>)], known_new: bool) -> Result<Vec<(Option<JsonValue>, Option<JsonValue>)>> {
+        let mut versions: Vec<(Option<JsonValue>, Option<JsonValue>)> = Vec::with_capacity(docs.len());
+        let mut latest: HashMap<&[u8], usize> = HashMap::new();
+        for (i, (id, data)) in docs.iter().enumerate() {
+            // A document written earlier in the batch is not stored yet
+            let old = match latest.insert(id, i) {
+                Some(earlier) => versions[earlier].1.clone(),
+                None if known_new => None,
+                None => self.get_json(id).ok().flatten(),
+            };
+            let new = serde_json::from_slice::<JsonValue>(data).ok();
+            
+            let indexed = match &new {
+                Some(new) => self.indexes.iter().try_for_each(|index| index.check(id, new)),
+                None => Ok(()),
+            }.and_then(|_| self.update_indexes(id, old.as_ref(), new.as_ref()));
+            if let Err(e) = indexed {
+                self.unindex_batch(&docs[..i], &versions);
+                return Err(e);
+            }
+            versions.push((old, new));
+        }
+        
+        Ok(versions)
+    }
+    
+    /// Undo [`index_batch`](Self::index_batch), newest document first
+    fn unindex_batch(&mut self, docs: &[(Vec<u8>, Vec<u8>)], versions: &[(Option<JsonValue>, Option<JsonValue>)]) {
+        for ((id, _), (old, new)) in docs.iter().zip(versions).rev() {
+            // Restoring values the indexes held a moment ago cannot break a constraint
+            let _ = self.update_indexes(id, new.as_ref(), old.as_ref());
+        }
+    }
+    
+    /// Subscribe to the writes made to this collection from now on
+    pub fn subscribe(&self) -> Subscription {
+        self.changes.subscribe()
     }
     
     /// Get a list of all document IDs in the collection
@@ -48,71 +712,1364 @@ impl Collection {
         self.block_manager.scan_document_ids()
     }
     
+    /// One page of document IDs in ID order and the cursor for the next;
+    /// see [`BlockManager::scan_document_ids_paginated`]
+    pub fn scan_paginated(&self, after_cursor: Option<Vec<u8>>, limit: usize) -> Result<IdPage> {
+        self.block_manager.scan_document_ids_paginated(after_cursor, limit)
+    }
+    
+    /// Number of documents in the collection, without listing their IDs
+    pub fn count(&self) -> Result<usize> {
+        let _timer = metrics::QUERY_DURATION.with_label_values(&[&self.name, "count"]).start_timer();
+        self.block_manager.count_documents()
+    }
+    
+    /// Choose how [`find`](Self::find) gathers candidates for `query`
+    ///
+    /// The index covering the most of the query's equality conditions wins:
+    /// a compound index by the length of the leading run of its fields the
+    /// query fixes, a single-field index counting as one. Coercing queries
+    /// always scan, since indexes compare values strictly.
+    pub fn explain(&self, query: &Query, config: &QueryConfig) -> QueryPlan {
+        if config.coerce_types {
+            return QueryPlan::FullScan;
+        }
+        
+        let equalities = plan::equalities(query);
+        let value_of = |field: &str| equalities.iter().find(|(f, _)| *f == field).map(|(_, value)| (*value).clone());
+        
+        let mut best = self.indexes.iter()
+            .find_map(|index| Some(QueryPlan::Index { field: index.field().to_string(), value: value_of(index.field())? }))
+            .unwrap_or(QueryPlan::FullScan);
+        let mut best_len = usize::from(best != QueryPlan::FullScan);
+        for index in &self.compound_indexes {
+            let prefix: Vec<JsonValue> = index.fields().iter().map_while(|field| value_of(field)).collect();
+            if prefix.len() > best_len {
+                best_len = prefix.len();
+                best = QueryPlan::CompoundIndex { fields: index.fields().to_vec(), prefix };
+            }
+        }
+        
+        best
+    }
+    
+    /// JSON documents matching `query`, using an index where
+    /// [`explain`](Self::explain) finds one
+    ///
+    /// Documents found through an index come in index order rather than
+    /// write order.
+    pub fn find(&self, query: &Query, config: &QueryConfig) -> Result<Vec<(Vec<u8>, JsonValue)>> {
+        self.find_with_options(query, &FindOptions::default(), config)
+    }
+    
+    /// The page of JSON documents matching `query` selected by `options`
+    ///
+    /// Like [`find`](Self::find), but `config.max_results` caps the page
+    /// rather than the matches, so any page of a large result set can be read.
+    pub fn find_with_options(
+        &self,
+        query: &Query,
+        options: &FindOptions,
+        config: &QueryConfig,
+    ) -> Result<Vec<(Vec<u8>, JsonValue)>> {
+        let _timer = metrics::QUERY_DURATION.with_label_values(&[&self.name, "find"]).start_timer();
+        let ids = match self.explain(query, config) {
+            QueryPlan::FullScan => None,
+            QueryPlan::Index { field, value } => match self.index(&field) {
+                Some(index) => Some(index.lookup_eq(&value)?),
+                None => None,
+            },
+            QueryPlan::CompoundIndex { fields, prefix } => {
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                match self.compound_index(&fields) {
+                    Some(index) => Some(index.lookup_compound_prefix(&prefix)?),
+                    None => None,
+                }
+            }
+        };
+        let Some(ids) = ids else {
+            return self.find_by_scan(query, options, config);
+        };
+        
+        // Candidates are only narrowed by the index; the full query still decides
+        let data = self.get_many(&ids)?;
+        let documents = ids.into_iter().zip(data).filter_map(|(id, data)| {
+            let doc = serde_json::from_slice(&data?).ok()?;
+            Some((id, doc))
+        });
+        nebuladb_query::find_with_options(query, documents, options, config)
+    }
+    
+    /// Test every document against `query`, streaming them so only the
+    /// matches that can make it into the page are held in memory
+    fn find_by_scan(
+        &self,
+        query: &Query,
+        options: &FindOptions,
+        config: &QueryConfig,
+    ) -> Result<Vec<(Vec<u8>, JsonValue)>> {
+        let mut error = None;
+        let documents = self.iter()?
+            .map_while(|document| document.map_err(|e| error = Some(e)).ok())
+            .filter_map(|(id, data)| Some((id, serde_json::from_slice(&data).ok()?)));
+        let matches = nebuladb_query::find_with_options(query, documents, options, config)?;
+        
+        match error {
+            Some(e) => Err(e),
+            None => Ok(matches),
+        }
+    }
+    
+    /// Run an aggregation pipeline over every document in the collection
+    ///
+    /// Documents are streamed through the pipeline; ones that are not valid
+    /// JSON are skipped.
+    pub fn aggregate(&self, pipeline: &Pipeline) -> Result<Vec<JsonValue>> {
+        let mut error = None;
+        let documents = self.iter()?
+            .map_while(|document| document.map_err(|e| error = Some(e)).ok())
+            .filter_map(|(_, data)| serde_json::from_slice(&data).ok());
+        let results = pipeline.execute(documents)?;
+        
+        match error {
+            Some(e) => Err(e),
+            None => Ok(results),
+        }
+    }
+    
+    /// Stream the documents in write order, as `(id, data)` pairs
+    ///
+    /// Deleted and superseded versions are skipped. Blocks are read one at a
+    /// time, so iterating a large collection does not load all of it.
+    pub fn iter(&self) -> Result<DocumentIter<'_>> {
+        self.block_manager.documents()
+    }
+    
+    /// List document IDs in write order, or newest first for
+    /// [`ScanDirection::Reverse`], stopping after `limit` IDs
+    pub fn scan_in(&self, direction: ScanDirection, limit: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        let mut ids = self.block_manager.scan_document_ids_in(direction)?;
+        if let Some(limit) = limit {
+            ids.truncate(limit);
+        }
+        
+        Ok(ids)
+    }
+    
     /// Retrieve a document from the collection
     pub fn get(&self, id: &[u8]) -> Result<Option<Vec<u8>>> {
-        // Check if the document exists
-        match self.block_manager.find_document(id)? {
-            Some(data) => {
-                // Check if this document has been logically deleted
-                // by looking for a tombstone with a special ID
-                let mut tombstone_id = Vec::with_capacity(id.len() + 2);
-                tombstone_id.push(b'_');  // Prefix with underscore
-                tombstone_id.extend_from_slice(id);
-                tombstone_id.push(b'_');  // Suffix with underscore
-                
-                // If a tombstone exists, consider the document deleted
-                match self.block_manager.find_document(&tombstone_id)? {
-                    Some(_) => Ok(None), // Document was deleted
-                    None => Ok(Some(data)), // Document exists and is not deleted
-                }
-            },
-            None => Ok(None), // Document not found
+        // Skip the block scan for IDs that were never inserted
+        if !self.bloom.might_contain(id) {
+            return Ok(None);
+        }
+        
+        // A deleted document's newest entry is its deletion
+        self.block_manager.find_document(id)
+    }
+    
+    /// Retrieve a document as it was at a snapshot taken with
+    /// [`snapshot`](Self::snapshot)
+    ///
+    /// Writes made after the snapshot are not seen, flushed or not, and
+    /// compaction keeps the versions the snapshot reads.
+    pub fn get_at(&self, id: &[u8], snapshot: &Snapshot) -> Result<Option<Vec<u8>>> {
+        if !self.bloom.might_contain(id) {
+            return Ok(None);
+        }
+        
+        self.block_manager.find_document_at(id, snapshot.version())
+    }
+    
+    /// Retrieve a document along with its version and write times
+    ///
+    /// Documents stored before metadata was tracked report version 0 and
+    /// zero timestamps.
+    pub fn get_with_meta(&self, id: &[u8]) -> Result<Option<(DocumentMeta, Vec<u8>)>> {
+        if !self.bloom.might_contain(id) {
+            return Ok(None);
+        }
+        
+        Ok(self.block_manager.find_document_with_meta(id)?
+            .map(|(meta, data)| (meta.unwrap_or_default(), data)))
+    }
+    
+    /// Retrieve several documents at once, in the order of `ids`
+    ///
+    /// Equivalent to calling [`get`](Self::get) for each ID, but the blocks
+    /// are read in a single pass.
+    pub fn get_many(&self, ids: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        // Skip the block scan for IDs that were never inserted
+        let lookups: Vec<&[u8]> = ids.iter()
+            .filter(|id| self.bloom.might_contain(id))
+            .map(Vec::as_slice)
+            .collect();
+        let mut found = self.block_manager.find_documents(&lookups)?.into_iter();
+        
+        Ok(ids.iter().map(|id| {
+            if !self.bloom.might_contain(id) {
+                return None;
+            }
+            found.next().flatten()
+        }).collect())
+    }
+    
+    /// Retrieve a document and parse it as JSON
+    ///
+    /// Fails if the stored document is not valid JSON.
+    pub fn get_json(&self, id: &[u8]) -> Result<Option<JsonValue>> {
+        match self.get(id)? {
+            Some(data) => serde_json::from_slice(&data).map(Some).map_err(|e| Error::Other(format!(
+                "Document '{}' is not valid JSON: {}", String::from_utf8_lossy(id), e))),
+            None => Ok(None),
         }
     }
     
     /// Delete a document from the collection
     pub fn delete(&mut self, id: &[u8]) -> Result<bool> {
-        // In our initial implementation, we'll simply create a special
-        // document entry that marks the original document as deleted
-        
         // First, check if the document exists
-        let exists = match self.get(id)? {
-            Some(_) => true,
-            None => false,
+        let existing = match self.get(id)? {
+            Some(data) => data,
+            None => return Ok(false), // Document not found
+        };
+        let old = if !self.has_indexes() {
+            None
+        } else {
+            serde_json::from_slice::<JsonValue>(&existing).ok()
         };
         
-        if !exists {
-            return Ok(false); // Document not found
+        let position = self.log(|wal| {
+            wal.delete(&self.name, id)?;
+            wal.position(&self.name).map(Some)
+        })?;
+        
+        // The deletion entry shadows every stored version of the document
+        self.block_manager.delete(id)?;
+        self.update_indexes(id, old.as_ref(), None)?;
+        self.changes.publish(ChangeOp::Delete, id, None, position);
+        metrics::DOCUMENTS_DELETED.with_label_values(&[&self.name]).inc();
+        self.update_meta(|meta| {
+            meta.doc_count = meta.doc_count.saturating_sub(1);
+            meta.deleted_doc_count += 1;
+        })?;
+        
+        Ok(true)
+    }
+    
+    /// Delete every document that `ttl` considers expired at UNIX time `now`
+    ///
+    /// Returns the number of documents deleted.
+    pub fn expire(&mut self, ttl: &TtlIndex, now: u64) -> Result<usize> {
+        let mut expired = Vec::new();
+        for id in self.scan()? {
+            if let Ok(Some(doc)) = self.get_json(&id) {
+                if ttl.is_expired(&doc, now) {
+                    expired.push(id);
+                }
+            }
         }
         
-        // Create a tombstone document (a special marker indicating deletion)
-        // In a real implementation, you'd store this in a structured way
-        let tombstone_data = format!("{{\"_deleted\": true, \"_id\": \"{}\", \"_deleted_at\": {}}}",
-            String::from_utf8_lossy(id),
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs()
-        ).into_bytes();
+        for id in &expired {
+            self.delete(id)?;
+        }
         
-        // Insert the tombstone with a special ID format to mark deletion
-        let mut tombstone_id = Vec::with_capacity(id.len() + 2);
-        tombstone_id.push(b'_');  // Prefix with underscore
-        tombstone_id.extend_from_slice(id);
-        tombstone_id.push(b'_');  // Suffix with underscore
+        Ok(expired.len())
+    }
+    
+    /// Apply a JSON Merge Patch (RFC 7396) to a document
+    ///
+    /// The merged document is written as a new version, logged as an update.
+    /// Returns `false` if the document does not exist.
+    pub fn patch(&mut self, id: &[u8], patch: &JsonValue) -> Result<bool> {
+        let mut document = match self.get_json(id)? {
+            Some(document) => document,
+            None => return Ok(false),
+        };
         
-        // Insert the tombstone
-        self.block_manager.insert(&tombstone_id, &tombstone_data)?;
+        apply_merge_patch(&mut document, patch);
         
-        // Note: This approach doesn't actually remove the original document,
-        // it just adds a tombstone. A background job or compaction process
-        // would be responsible for actually cleaning up deleted documents.
+        let data = serde_json::to_vec(&document)
+            .map_err(|e| Error::Other(format!("Failed to serialize document: {}", e)))?;
+        self.update(id, &data)
+    }
+    
+    /// Add `delta` to the integer `field` of a document and return the new value
+    ///
+    /// A missing field counts as 0; dotted paths reach into nested objects,
+    /// which are created as needed. Fails with [`Error::NotFound`] if the
+    /// document does not exist and [`Error::TypeMismatch`] if the field, or
+    /// an object on its path, holds another type. The read and the write
+    /// happen under the same exclusive borrow, so callers sharing the
+    /// collection behind its `Mutex` never lose an increment.
+    pub fn increment(&mut self, id: &[u8], field: &str, delta: i64) -> Result<i64> {
+        let mut document = self.get_json(id)?.ok_or_else(|| Error::NotFound {
+            id: String::from_utf8_lossy(id).to_string(),
+        })?;
+        let mismatch = |expected: &str| Error::TypeMismatch {
+            field: field.to_string(),
+            expected: expected.to_string(),
+        };
         
-        Ok(true)
+        let mut target = &mut document;
+        for part in field.split('.') {
+            if target.is_null() {
+                *target = JsonValue::Object(Default::default());
+            }
+            target = target.as_object_mut().ok_or_else(|| mismatch("object"))?
+                .entry(part)
+                .or_insert(JsonValue::Null);
+        }
+        let current = match &*target {
+            JsonValue::Null => 0,
+            value => value.as_i64().ok_or_else(|| mismatch("integer"))?,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| Error::Other(format!(
+            "Incrementing '{}' by {} overflows", field, delta)))?;
+        *target = JsonValue::from(value);
+        
+        let data = serde_json::to_vec(&document)
+            .map_err(|e| Error::Other(format!("Failed to serialize document: {}", e)))?;
+        self.write(&[(id.to_vec(), data)], ChangeOp::Update)?;
+        Ok(value)
+    }
+    
+    /// Aggregate compression statistics over all on-disk blocks
+    ///
+    /// Documents still in the active block are not included until flushed.
+    pub fn compression_stats(&self) -> Result<CompressionStats> {
+        let mut stats = CompressionStats::default();
+        
+        for header in self.block_manager.block_headers()? {
+            stats.block_count += 1;
+            stats.uncompressed_bytes += header.uncompressed_size;
+            stats.compressed_bytes += header.stored_size() as u64;
+        }
+        
+        Ok(stats)
+    }
+    
+    /// Rewrite all stored blocks with the given compression
+    pub fn recompress(&mut self, target: CompressionType) -> Result<RecompressStats> {
+        let (size_before, size_after) = self.block_manager.recompress(target)?;
+        
+        Ok(RecompressStats { size_before, size_after })
+    }
+    
+    /// Reclaim the space held by deleted documents and superseded versions
+    ///
+    /// Waits for a permit from the global [`CompactionLimiter`] first. The
+    /// blocks are rewritten to a temporary file that replaces the old one
+    /// only once complete, so a crash mid-compaction leaves the data intact.
+    pub fn compact(&mut self) -> Result<CompactionStats> {
+        let _permit = CompactionLimiter::global().acquire();
+        
+        // Nothing logged before the rewrite needs replaying over its result
+        self.block_manager.flush()?;
+        self.checkpoint()?;
+        
+        let stats = self.block_manager.compact()?;
+        self.update_meta(|meta| meta.deleted_doc_count = 0)?;
+        
+        // The active block was flushed before the rewrite
+        self.checkpoint()?;
+        
+        Ok(stats)
+    }
+    
+    /// Document counts and on-disk size of the collection
+    ///
+    /// Counts come from the counters kept in `meta.json`, so no block is read
+    /// to produce them. Documents still in the active block are counted but
+    /// take no space on disk until flushed.
+    pub fn stats(&self) -> Result<CollectionStats> {
+        let mut stats = CollectionStats {
+            doc_count: self.meta.doc_count,
+            deleted_doc_count: self.meta.deleted_doc_count,
+            block_count: self.block_manager.block_headers()?.len() as u32,
+            ..CollectionStats::default()
+        };
+        
+        for entry in fs::read_dir(&self.path).map_err(Error::IoError)? {
+            let entry = entry.map_err(Error::IoError)?;
+            let metadata = entry.metadata().map_err(Error::IoError)?;
+            if !metadata.is_file() {
+                continue;
+            }
+            
+            stats.size_bytes += metadata.len();
+            if entry.path().extension().is_some_and(|ext| ext == "idx" || ext == "cidx") {
+                stats.index_size_bytes += metadata.len();
+            }
+        }
+        
+        Ok(stats)
+    }
+    
+    /// Take a snapshot to read with [`get_at`](Self::get_at)
+    ///
+    /// Reads at the snapshot see exactly the writes made before this call, in
+    /// this and every other collection. Nothing is flushed.
+    pub fn snapshot(&self) -> Snapshot {
+        mvcc::snapshot()
+    }
+    
+    /// Persist unflushed writes as a partial block once the configured
+    /// partial block interval has elapsed
+    pub fn persist_partial_if_due(&mut self) -> Result<()> {
+        self.block_manager.persist_partial_if_due()
+    }
+    
+    /// Flush the active block if the collection has been idle for `timeout`
+    pub fn flush_if_idle(&mut self, timeout: Duration) -> Result<bool> {
+        let flushed = self.block_manager.flush_if_idle(timeout)?;
+        if flushed {
+            self.checkpoint()?;
+        }
+        
+        Ok(flushed)
+    }
+    
+    /// Flush and fsync every write so far, then checkpoint the WAL
+    ///
+    /// Once this returns, the collection's data survives a crash without
+    /// relying on WAL replay.
+    pub fn sync(&mut self) -> Result<()> {
+        self.block_manager.sync()?;
+        self.checkpoint()
+    }
+    
+    /// Number of fsyncs of the collection's blocks file so far
+    pub fn block_fsync_count(&self) -> u64 {
+        self.block_manager.block_fsync_count()
+    }
+    
+    /// Rename the collection, moving its directory, index files and WAL
+    ///
+    /// The collection is closed, renamed on disk and reopened under
+    /// `new_name`. The rename is recorded in the metadata WAL before anything
+    /// moves and stays pending until the caller calls
+    /// [`WalManager::finish_metadata_operations`]; a rename a crash
+    /// interrupts is finished with [`move_files`](Self::move_files).
+    pub fn rename(&mut self, new_name: &str) -> Result<()> {
+        let base_path = self.path.parent()
+            .ok_or_else(|| Error::Other(format!("Collection '{}' has no parent directory", self.name)))?
+            .to_path_buf();
+        if base_path.join(new_name).exists() {
+            return Err(Error::AlreadyExists { name: new_name.to_string() });
+        }
+        
+        self.close()?;
+        self.log(|wal| wal.log_rename(&self.name, new_name))?;
+        self.log(|wal| wal.rename(&self.name, new_name))?;
+        Self::move_files(&base_path, &self.name, new_name)?;
+        
+        let config = self.block_manager.config().clone();
+        let changes = self.changes.clone();
+        *self = match self.wal.take() {
+            Some(wal) => Self::open_with_wal(new_name, &base_path, &config, wal)?,
+            None => Self::open(new_name, &base_path, &config)?,
+        };
+        self.changes = changes;
+        
+        Ok(())
+    }
+    
+    /// Move the directory and index files of collection `old_name` under
+    /// `base_path` to `new_name`
+    ///
+    /// Picks up where an interrupted move stopped: index files already
+    /// renamed are left alone, and nothing is left to do once the directory
+    /// has moved.
+    pub fn move_files(base_path: &Path, old_name: &str, new_name: &str) -> Result<()> {
+        let old_path = base_path.join(old_name);
+        if !old_path.exists() {
+            return Ok(());
+        }
+        
+        // Index file names start with the collection name
+        let (old_prefix, new_prefix) = (format!("{}_", old_name), format!("{}_", new_name));
+        let entries = fs::read_dir(&old_path)
+            .and_then(|dir| dir.collect::<std::io::Result<Vec<_>>>())
+            .map_err(Error::IoError)?;
+        for entry in entries {
+            let path = entry.path();
+            let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if file_name.starts_with(&new_prefix) {
+                continue;
+            }
+            if let Some(rest) = file_name.strip_prefix(&old_prefix) {
+                if path.extension().is_some_and(|ext| ext == "idx" || ext == "cidx") {
+                    fs::rename(&path, old_path.join(format!("{}{}", new_prefix, rest))).map_err(Error::IoError)?;
+                }
+            }
+        }
+        fs::rename(&old_path, base_path.join(new_name)).map_err(Error::IoError)
     }
     
     /// Close the collection, flushing any pending changes
     pub fn close(&mut self) -> Result<()> {
-        self.block_manager.flush()
+        self.block_manager.flush()?;
+        self.checkpoint()?;
+        for index in &self.indexes {
+            index.save(&self.index_path(index.field()), true)?;
+        }
+        for index in &self.text_indexes {
+            index.save(&self.text_index_path(index.field()), true)?;
+        }
+        for index in &self.compound_indexes {
+            index.save(&self.compound_index_path(index.fields()), true)?;
+        }
+        CollectionMeta { clean: true, ..self.meta }.save(&self.path.join(META_FILE))?;
+        self.bloom.save(&self.path.join("bloom.bin"))
+    }
+}
+
+/// Current UNIX time in seconds
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Apply an RFC 7396 merge patch to `target` in place
+///
+/// Object patches merge key by key, recursing into nested objects, and a
+/// `null` value removes the key. Any other patch replaces the target.
+fn apply_merge_patch(target: &mut JsonValue, patch: &JsonValue) {
+    let patch_map = match patch {
+        JsonValue::Object(map) => map,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    
+    if !target.is_object() {
+        *target = JsonValue::Object(serde_json::Map::new());
+    }
+    
+    if let JsonValue::Object(target_map) = target {
+        for (key, value) in patch_map {
+            if value.is_null() {
+                target_map.remove(key);
+            } else {
+                apply_merge_patch(target_map.entry(key.clone()).or_insert(JsonValue::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nebuladb_index::SearchMode;
+    use serde_json::json;
+
+    fn patched(collection: &mut Collection, id: &[u8], patch: JsonValue) -> JsonValue {
+        assert!(collection.patch(id, &patch).unwrap());
+        collection.get_json(id).unwrap().unwrap()
+    }
+
+    fn open_with(dir: &Path, id: &[u8], doc: JsonValue) -> Collection {
+        let mut collection = Collection::open("docs", dir, &StorageConfig::default()).unwrap();
+        collection.insert(id, doc.to_string().as_bytes()).unwrap();
+        collection
+    }
+
+    #[test]
+    fn test_replayed_update_keeps_version_and_change_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc1", json!({"v": 1}));
+        let mut subscription = collection.subscribe();
+
+        let update = WalEntry::new(EntryType::Update, 0, 0, b"doc1".to_vec(), br#"{"v":2}"#.to_vec());
+        collection.apply_wal_entry(&update).unwrap();
+
+        let (meta, data) = collection.get_with_meta(b"doc1").unwrap().unwrap();
+        assert_eq!((meta.version, data), (2, br#"{"v":2}"#.to_vec()));
+        assert_eq!(subscription.try_recv().unwrap().unwrap().op, ChangeOp::Update);
+    }
+
+    #[test]
+    fn test_scan_lists_updated_document_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc1", json!({"v": 1}));
+        collection.insert(b"doc2", br#"{"v":1}"#).unwrap();
+        collection.block_manager.flush().unwrap();
+
+        // Update once in a flushed block and once in the active block
+        collection.insert(b"doc1", br#"{"v":2}"#).unwrap();
+        collection.block_manager.flush().unwrap();
+        collection.insert(b"doc1", br#"{"v":3}"#).unwrap();
+
+        assert_eq!(collection.scan().unwrap(), vec![b"doc2".to_vec(), b"doc1".to_vec()]);
+        assert_eq!(collection.get_json(b"doc1").unwrap(), Some(json!({"v": 3})));
+    }
+
+    #[test]
+    fn test_index_follows_writes_and_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"city": "London"}));
+        collection.insert(b"b", br#"{"city":"Paris"}"#).unwrap();
+        collection.create_index("city").unwrap();
+
+        collection.insert(b"c", br#"{"city":"London"}"#).unwrap();
+        collection.insert(b"a", br#"{"city":"Rome"}"#).unwrap();
+        collection.delete(b"b").unwrap();
+
+        let lookup = |collection: &Collection, city: &str| collection.index("city").unwrap()
+            .lookup_eq(&json!(city)).unwrap();
+        assert_eq!(lookup(&collection, "London"), vec![b"c".to_vec()]);
+        assert_eq!(lookup(&collection, "Rome"), vec![b"a".to_vec()]);
+        assert!(lookup(&collection, "Paris").is_empty());
+
+        collection.close().unwrap();
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(lookup(&collection, "London"), vec![b"c".to_vec()]);
+        assert_eq!(collection.index("city").unwrap().lookup_range(&json!("A"), &json!("Z")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_index_rebuilt_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"n": 1}));
+        collection.create_index("n").unwrap();
+        collection.insert(b"b", br#"{"n":2}"#).unwrap();
+        collection.block_manager.flush().unwrap();
+
+        // Simulate a crash: no close, so the index file stays incomplete
+        drop(collection);
+
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        let index = collection.index("n").unwrap();
+        assert_eq!(index.lookup_range(&json!(1), &json!(2)).unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn test_compound_index_follows_writes_and_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"ada", json!({"last_name": "Lovelace", "first_name": "Ada"}));
+        collection.add_compound_index(&["last_name", "first_name"]).unwrap();
+        collection.insert(b"byron", br#"{"last_name":"Lovelace"}"#).unwrap();
+        collection.insert(b"alan", br#"{"last_name":"Turing","first_name":"Alan"}"#).unwrap();
+        assert!(dir.path().join("docs").join("docs_last_name+first_name.cidx").exists());
+
+        let lookup = |collection: &Collection, prefix: &[JsonValue]| collection
+            .compound_index(&["last_name", "first_name"]).unwrap()
+            .lookup_compound_prefix(prefix).unwrap();
+        assert_eq!(lookup(&collection, &[json!("Lovelace")]), vec![b"byron".to_vec(), b"ada".to_vec()]);
+        assert_eq!(lookup(&collection, &[json!("Lovelace"), JsonValue::Null]), vec![b"byron".to_vec()]);
+
+        collection.delete(b"byron").unwrap();
+        collection.close().unwrap();
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(lookup(&collection, &[json!("Lovelace")]), vec![b"ada".to_vec()]);
+        assert_eq!(lookup(&collection, &[json!("Turing"), json!("Alan")]), vec![b"alan".to_vec()]);
+    }
+
+    #[test]
+    fn test_find_explains_chosen_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"ada", json!({"last_name": "Lovelace", "first_name": "Ada", "age": 36}));
+        collection.insert(b"annie", br#"{"last_name":"Lovelace","first_name":"Annie","age":12}"#).unwrap();
+        collection.insert(b"alan", br#"{"last_name":"Turing","first_name":"Alan","age":41}"#).unwrap();
+        let config = QueryConfig::default();
+        let query = |json: JsonValue| Query::from_json(&json).unwrap();
+        let found = |collection: &Collection, q: &Query| -> Vec<Vec<u8>> {
+            collection.find(q, &config).unwrap().into_iter().map(|(id, _)| id).collect()
+        };
+
+        let by_name = query(json!({"last_name": "Lovelace", "first_name": "Annie"}));
+        assert_eq!(collection.explain(&by_name, &config), QueryPlan::FullScan);
+        assert_eq!(found(&collection, &by_name), vec![b"annie".to_vec()]);
+
+        collection.create_index("last_name").unwrap();
+        assert_eq!(collection.explain(&by_name, &config).to_string(), "index on last_name (last_name = \"Lovelace\")");
+        assert_eq!(found(&collection, &by_name), vec![b"annie".to_vec()]);
+
+        // The compound index fixes both fields, so it beats the single-field index
+        collection.add_compound_index(&["last_name", "first_name"]).unwrap();
+        assert_eq!(collection.explain(&by_name, &config), QueryPlan::CompoundIndex {
+            fields: vec!["last_name".to_string(), "first_name".to_string()],
+            prefix: vec![json!("Lovelace"), json!("Annie")],
+        });
+        assert_eq!(found(&collection, &by_name), vec![b"annie".to_vec()]);
+
+        // Other conditions still filter the index's candidates
+        let adults = query(json!({"last_name": "Lovelace", "age": {"$gte": 18}}));
+        assert_eq!(found(&collection, &adults), vec![b"ada".to_vec()]);
+
+        // A compound index without its leading field is no help
+        assert_eq!(collection.explain(&query(json!({"first_name": "Alan"})), &config), QueryPlan::FullScan);
+    }
+
+    #[test]
+    fn test_aggregate_sees_latest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("sales", dir.path(), &StorageConfig::default()).unwrap();
+        for i in 0..500 {
+            let doc = json!({"region": if i % 2 == 0 { "north" } else { "south" }, "amount": 10});
+            collection.insert(format!("s{}", i).as_bytes(), doc.to_string().as_bytes()).unwrap();
+        }
+        collection.delete(b"s0").unwrap();
+        collection.update(b"s1", br#"{"region":"south","amount":60}"#).unwrap();
+        collection.insert(b"note", b"not json").unwrap();
+
+        let pipeline = Pipeline::from_json(&json!([
+            {"$group": {"_id": "$region", "total": {"$sum": "$amount"}, "avg": {"$avg": "$amount"}}},
+            {"$sort": {"_id": 1}},
+        ])).unwrap();
+        assert_eq!(collection.aggregate(&pipeline).unwrap(), vec![
+            json!({"_id": "north", "total": 2490, "avg": 10.0}),
+            json!({"_id": "south", "total": 2550, "avg": 10.2}),
+        ]);
+    }
+
+    #[test]
+    fn test_indexed_find_matches_full_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        for i in 0..200 {
+            let doc = json!({"email": format!("user{}@example.com", i % 50), "age": i % 70, "active": i % 3 == 0});
+            collection.insert(format!("u{}", i).as_bytes(), doc.to_string().as_bytes()).unwrap();
+        }
+        collection.delete(b"u7").unwrap();
+        collection.update(b"u8", br#"{"email":"user7@example.com","age":99}"#).unwrap();
+
+        let config = QueryConfig::default();
+        let queries: Vec<Query> = [
+            json!({"email": "user7@example.com"}),
+            json!({"email": "nobody@example.com"}),
+            json!({"age": {"$gte": 30, "$lt": 40}}),
+            json!({"email": "user3@example.com", "active": true}),
+            json!({"age": {"$in": [1, 99]}}),
+        ].iter().map(|json| Query::from_json(json).unwrap()).collect();
+        let found = |collection: &Collection| -> Vec<Vec<Vec<u8>>> {
+            queries.iter().map(|query| {
+                let mut ids: Vec<Vec<u8>> = collection.find(query, &config).unwrap().into_iter().map(|(id, _)| id).collect();
+                ids.sort();
+                ids
+            }).collect()
+        };
+
+        let scanned = found(&collection);
+        assert_eq!(scanned[0].len(), 4);
+        assert!(queries.iter().all(|query| collection.explain(query, &config) == QueryPlan::FullScan));
+
+        collection.create_index("email").unwrap();
+        collection.create_index("age").unwrap();
+        assert_ne!(collection.explain(&queries[0], &config), QueryPlan::FullScan);
+        assert_ne!(collection.explain(&queries[3], &config), QueryPlan::FullScan);
+        assert_eq!(found(&collection), scanned);
+
+        assert!(collection.drop_index("email").unwrap());
+        assert!(!collection.drop_index("email").unwrap());
+        assert_eq!(collection.explain(&queries[0], &config), QueryPlan::FullScan);
+        assert_eq!(found(&collection), scanned);
+
+        // Only the remaining index comes back after a reopen
+        collection.close().unwrap();
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert!(collection.index("email").is_none());
+        assert!(collection.index("age").is_some());
+        assert_eq!(found(&collection), scanned);
+    }
+
+    #[test]
+    fn test_unique_index_rejects_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"ada", json!({"email": "ada@example.com"}));
+        collection.add_unique_index("email").unwrap();
+
+        let duplicate = collection.insert(b"eve", br#"{"email":"ada@example.com"}"#);
+        assert!(matches!(duplicate, Err(Error::DuplicateKey { ref field, .. }) if field == "email"));
+        assert_eq!(collection.get(b"eve").unwrap(), None);
+        assert_eq!(collection.get_json(b"ada").unwrap(), Some(json!({"email": "ada@example.com"})));
+
+        // Rewriting a document with its own value is not a duplicate
+        collection.insert(b"ada", br#"{"email":"ada@example.com","name":"Ada"}"#).unwrap();
+
+        collection.close().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert!(collection.index("email").unwrap().is_unique());
+        assert!(matches!(collection.insert(b"eve", br#"{"email":"ada@example.com"}"#),
+            Err(Error::DuplicateKey { .. })));
+        collection.insert(b"eve", br#"{"email":"eve@example.com"}"#).unwrap();
+    }
+
+    #[test]
+    fn test_unique_value_is_free_once_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"ada", json!({"email": "ada@example.com"}));
+        collection.insert(b"eve", br#"{"email":"eve@example.com"}"#).unwrap();
+        collection.add_unique_index("email").unwrap();
+
+        // Updates are checked like inserts
+        assert!(matches!(collection.update(b"eve", br#"{"email":"ada@example.com"}"#),
+            Err(Error::DuplicateKey { .. })));
+        assert_eq!(collection.get_json(b"eve").unwrap(), Some(json!({"email": "eve@example.com"})));
+
+        assert!(collection.delete(b"ada").unwrap());
+        collection.insert(b"ada2", br#"{"email":"ada@example.com"}"#).unwrap();
+        assert!(collection.delete(b"ada2").unwrap());
+
+        // The deletions stay deletions once flushed and reloaded
+        collection.block_manager.flush().unwrap();
+        collection.close().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        collection.update(b"eve", br#"{"email":"ada@example.com"}"#).unwrap();
+        assert_eq!(collection.index("email").unwrap().lookup_eq(&json!("ada@example.com")).unwrap(),
+            vec![b"eve".to_vec()]);
+        assert!(matches!(collection.insert(b"ada", br#"{"email":"ada@example.com"}"#),
+            Err(Error::DuplicateKey { .. })));
+    }
+
+    #[test]
+    fn test_text_index_follows_writes_and_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a1", json!({"body": "Rust async runtimes"}));
+        collection.create_text_index("body").unwrap();
+        collection.insert(b"a2", br#"{"body":"Async Rust, async everywhere"}"#).unwrap();
+        collection.insert(b"a3", br#"{"body":"Rust macros"}"#).unwrap();
+        collection.delete(b"a1").unwrap();
+
+        let search = |collection: &Collection| collection.text_index("body").unwrap()
+            .search(&["rust async"], SearchMode::All);
+        assert_eq!(search(&collection), vec![b"a2".to_vec()]);
+
+        collection.close().unwrap();
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(search(&collection), vec![b"a2".to_vec()]);
+        // The text index file is not mistaken for a field index
+        assert!(collection.index("body").is_none());
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"n": 1}));
+        collection.create_index("n").unwrap();
+
+        let batch = vec![
+            (b"b".to_vec(), br#"{"n":2}"#.to_vec()),
+            (b"a".to_vec(), br#"{"n":3}"#.to_vec()),
+            (b"c".to_vec(), b"not json".to_vec()),
+            (b"b".to_vec(), br#"{"n":4}"#.to_vec()),
+        ];
+        collection.insert_batch(&batch).unwrap();
+
+        assert_eq!(collection.count().unwrap(), 3);
+        assert_eq!(collection.get_json(b"b").unwrap(), Some(json!({"n": 4})));
+        assert_eq!(collection.get(b"c").unwrap(), Some(b"not json".to_vec()));
+        let index = collection.index("n").unwrap();
+        assert_eq!(index.lookup_range(&json!(0), &json!(10)).unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn test_insert_batch_is_all_or_nothing_on_duplicate_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"email": "a@x"}));
+        collection.add_unique_index("email").unwrap();
+
+        // The duplicate is within the batch itself
+        let batch = vec![
+            (b"b".to_vec(), br#"{"email":"b@x"}"#.to_vec()),
+            (b"c".to_vec(), br#"{"email":"b@x"}"#.to_vec()),
+        ];
+        assert!(matches!(collection.insert_batch(&batch), Err(Error::DuplicateKey { .. })));
+        assert_eq!(collection.get(b"b").unwrap(), None);
+        assert!(collection.index("email").unwrap().lookup_eq(&json!("b@x")).unwrap().is_empty());
+
+        // Freeing a value earlier in the batch lets a later document take it
+        let batch = vec![
+            (b"a".to_vec(), br#"{"email":"new@x"}"#.to_vec()),
+            (b"b".to_vec(), br#"{"email":"a@x"}"#.to_vec()),
+        ];
+        collection.insert_batch(&batch).unwrap();
+        assert_eq!(collection.index("email").unwrap().lookup_eq(&json!("a@x")).unwrap(), vec![b"b".to_vec()]);
+    }
+
+    #[test]
+    fn test_unique_index_over_existing_duplicates_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"n": 1}));
+        collection.insert(b"b", br#"{"n":1}"#).unwrap();
+
+        assert!(matches!(collection.add_unique_index("n"), Err(Error::DuplicateKey { .. })));
+        assert!(collection.index("n").is_none());
+    }
+
+    #[test]
+    fn test_scan_excludes_deleted_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"keep", json!({"n": 1}));
+        collection.insert(b"gone", br#"{"n":2}"#).unwrap();
+        assert!(collection.delete(b"gone").unwrap());
+
+        assert_eq!(collection.scan().unwrap(), vec![b"keep".to_vec()]);
+
+        // Also once the deletion is in a flushed block
+        collection.block_manager.flush().unwrap();
+        assert_eq!(collection.scan().unwrap(), vec![b"keep".to_vec()]);
+    }
+
+    #[test]
+    fn test_update_replaces_existing_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc", json!({"v": 1}));
+        collection.block_manager.flush().unwrap();
+
+        assert!(collection.update(b"doc", br#"{"v":2}"#).unwrap());
+        assert_eq!(collection.get_json(b"doc").unwrap(), Some(json!({"v": 2})));
+        assert_eq!(collection.scan().unwrap(), vec![b"doc".to_vec()]);
+    }
+
+    #[test]
+    fn test_get_with_meta_tracks_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc", json!({"v": 1}));
+        let (meta, data) = collection.get_with_meta(b"doc").unwrap().unwrap();
+        assert_eq!(data, br#"{"v":1}"#);
+        assert_eq!(meta.version, 1);
+        assert_eq!(meta.created_at, meta.updated_at);
+        assert!(meta.created_at > 0);
+        collection.block_manager.flush().unwrap();
+
+        assert!(collection.update(b"doc", br#"{"v":2}"#).unwrap());
+        assert!(collection.patch(b"doc", &json!({"w": 3})).unwrap());
+        let (updated, data) = collection.get_with_meta(b"doc").unwrap().unwrap();
+        assert_eq!(data, br#"{"v":2,"w":3}"#);
+        assert_eq!(updated.version, 3);
+        assert_eq!(updated.created_at, meta.created_at);
+        assert!(updated.updated_at >= meta.updated_at);
+
+        // `get` returns the data alone, and compaction keeps the metadata
+        assert_eq!(collection.get(b"doc").unwrap().unwrap(), data);
+        collection.compact().unwrap();
+        assert_eq!(collection.get_with_meta(b"doc").unwrap().unwrap().0, updated);
+
+        collection.delete(b"doc").unwrap();
+        assert!(collection.get_with_meta(b"doc").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_upsert_always_reads_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig { flush_threshold: 3, ..StorageConfig::default() };
+        let mut collection = Collection::open("docs", dir.path(), &config).unwrap();
+        collection.insert(b"other", b"x").unwrap();
+
+        assert_eq!(collection.upsert(b"doc", br#"{"v":0}"#).unwrap(), UpsertResult::Inserted);
+        for v in 1..10 {
+            // Versions spread over several flushed blocks and the active one
+            assert_eq!(collection.upsert(b"doc", json!({"v": v}).to_string().as_bytes()).unwrap(), UpsertResult::Updated);
+            assert_eq!(collection.get_json(b"doc").unwrap(), Some(json!({"v": v})));
+        }
+        assert_eq!(collection.count().unwrap(), 2);
+
+        collection.compact().unwrap();
+        assert_eq!(collection.get_json(b"doc").unwrap(), Some(json!({"v": 9})));
+        let stored: u32 = collection.block_manager.block_headers().unwrap().iter().map(|h| h.doc_count).sum();
+        assert_eq!(stored, 2);
+    }
+
+    #[test]
+    fn test_update_missing_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc", json!({"v": 1}));
+
+        assert!(!collection.update(b"missing", br#"{"v":2}"#).unwrap());
+        assert_eq!(collection.get(b"missing").unwrap(), None);
+
+        collection.delete(b"doc").unwrap();
+        assert!(!collection.update(b"doc", br#"{"v":2}"#).unwrap());
+        assert!(collection.scan().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_in_reverse_lists_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"log1", json!({"n": 1}));
+        collection.insert(b"log2", br#"{"n":2}"#).unwrap();
+        collection.block_manager.flush().unwrap();
+        collection.insert(b"log3", br#"{"n":3}"#).unwrap();
+
+        let forward = collection.scan_in(ScanDirection::Forward, None).unwrap();
+        assert_eq!(forward, vec![b"log1".to_vec(), b"log2".to_vec(), b"log3".to_vec()]);
+        let reverse = collection.scan_in(ScanDirection::Reverse, None).unwrap();
+        assert_eq!(reverse, vec![b"log3".to_vec(), b"log2".to_vec(), b"log1".to_vec()]);
+        let newest = collection.scan_in(ScanDirection::Reverse, Some(2)).unwrap();
+        assert_eq!(newest, vec![b"log3".to_vec(), b"log2".to_vec()]);
+    }
+
+    #[test]
+    fn test_count_matches_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc0", json!({"v": 0}));
+        for i in 1..30 {
+            collection.insert(format!("doc{}", i).as_bytes(), br#"{"v":1}"#).unwrap();
+            if i % 10 == 0 {
+                collection.block_manager.flush().unwrap();
+            }
+        }
+        collection.update(b"doc3", br#"{"v":2}"#).unwrap();
+        collection.delete(b"doc4").unwrap();
+        collection.delete(b"doc25").unwrap();
+
+        assert_eq!(collection.count().unwrap(), 28);
+        assert_eq!(collection.count().unwrap(), collection.scan().unwrap().len());
+    }
+
+    #[test]
+    fn test_stats_track_inserts_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"n": 1}));
+        collection.create_index("n").unwrap();
+        collection.insert_batch(&[
+            (b"b".to_vec(), br#"{"n":2}"#.to_vec()),
+            (b"c".to_vec(), br#"{"n":3}"#.to_vec()),
+            (b"b".to_vec(), br#"{"n":4}"#.to_vec()),
+        ]).unwrap();
+        collection.update(b"a", br#"{"n":5}"#).unwrap();
+        collection.delete(b"c").unwrap();
+        assert!(!collection.delete(b"c").unwrap());
+
+        let stats = collection.stats().unwrap();
+        assert_eq!(stats.doc_count, 2);
+        assert_eq!(stats.deleted_doc_count, 1);
+        assert_eq!(stats.doc_count, collection.count().unwrap() as u64);
+
+        // A reinserted deleted ID is live again; its deletion awaits compaction
+        collection.insert(b"c", br#"{"n":6}"#).unwrap();
+        assert_eq!(collection.stats().unwrap().doc_count, collection.count().unwrap() as u64);
+
+        collection.close().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        let stats = collection.stats().unwrap();
+        assert_eq!((stats.doc_count, stats.deleted_doc_count, stats.block_count), (3, 1, 1));
+        assert!(stats.index_size_bytes > 0);
+        assert!(stats.size_bytes > stats.index_size_bytes);
+
+        collection.compact().unwrap();
+        assert_eq!(collection.stats().unwrap().deleted_doc_count, 0);
+    }
+
+    #[test]
+    fn test_stats_recounted_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"n": 1}));
+        collection.insert(b"b", br#"{"n":2}"#).unwrap();
+        collection.delete(b"a").unwrap();
+        collection.block_manager.flush().unwrap();
+        collection.insert(b"lost", br#"{"n":3}"#).unwrap();
+
+        // Simulate a crash: the unflushed document is lost but was counted
+        drop(collection);
+
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        let stats = collection.stats().unwrap();
+        assert_eq!((stats.doc_count, stats.deleted_doc_count), (1, 1));
+    }
+
+    #[test]
+    fn test_compact_drops_deleted_and_superseded_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"keep", json!({"v": 1}));
+        for i in 0..20 {
+            collection.insert(format!("gone{}", i).as_bytes(), br#"{"padding":"xxxxxxxxxxxxxxxx"}"#).unwrap();
+        }
+        collection.block_manager.flush().unwrap();
+        for i in 0..20 {
+            collection.delete(format!("gone{}", i).as_bytes()).unwrap();
+        }
+        collection.update(b"keep", br#"{"v":2}"#).unwrap();
+
+        let stats = collection.compact().unwrap();
+        // 20 deleted documents, their 20 deletions and the old version of "keep"
+        assert_eq!(stats.documents_removed, 41);
+        assert!(stats.bytes_reclaimed > 0);
+
+        assert_eq!(collection.scan().unwrap(), vec![b"keep".to_vec()]);
+        assert_eq!(collection.get_json(b"keep").unwrap(), Some(json!({"v": 2})));
+        collection.insert(b"new", br#"{"v":3}"#).unwrap();
+        collection.close().unwrap();
+
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(collection.scan().unwrap(), vec![b"keep".to_vec(), b"new".to_vec()]);
+        assert_eq!(collection.block_manager.block_headers().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_compact_after_deleting_half() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        for i in 0..100 {
+            collection.insert(format!("doc{}", i).as_bytes(), json!({"n": i}).to_string().as_bytes()).unwrap();
+        }
+        for i in (0..100).step_by(2) {
+            assert!(collection.delete(format!("doc{}", i).as_bytes()).unwrap());
+        }
+        collection.block_manager.flush().unwrap();
+        let size_before = collection.stats().unwrap().size_bytes;
+        assert_eq!(collection.scan().unwrap().len(), 50);
+
+        collection.compact().unwrap();
+        assert!(collection.stats().unwrap().size_bytes < size_before);
+        assert_eq!(collection.stats().unwrap().deleted_doc_count, 0);
+        for i in 0..100 {
+            let found = collection.get_json(format!("doc{}", i).as_bytes()).unwrap();
+            assert_eq!(found, (i % 2 == 1).then(|| json!({"n": i})));
+        }
+        assert_eq!(collection.scan().unwrap().len(), 50);
+    }
+
+    #[test]
+    fn test_deleted_id_can_be_inserted_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc", json!({"v": 1}));
+        assert!(collection.delete(b"doc").unwrap());
+        collection.block_manager.flush().unwrap();
+
+        collection.insert(b"doc", br#"{"v":2}"#).unwrap();
+        assert_eq!(collection.get_json(b"doc").unwrap(), Some(json!({"v": 2})));
+        assert_eq!(collection.scan().unwrap(), vec![b"doc".to_vec()]);
+        assert_eq!(collection.stats().unwrap().doc_count, 1);
+    }
+
+    #[test]
+    fn test_get_many_preserves_order_and_honors_deletions() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig { flush_threshold: 2, ..StorageConfig::default() };
+        let mut collection = Collection::open("docs", dir.path(), &config).unwrap();
+        for (id, n) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+            collection.insert(id.as_bytes(), json!({"n": n}).to_string().as_bytes()).unwrap();
+        }
+        collection.delete(b"b").unwrap();
+        collection.update(b"a", br#"{"n":5}"#).unwrap();
+
+        let ids: Vec<Vec<u8>> = ["d", "missing", "b", "a", "c", "a"].iter().map(|id| id.as_bytes().to_vec()).collect();
+        let found = collection.get_many(&ids).unwrap();
+        assert_eq!(found, vec![
+            Some(br#"{"n":4}"#.to_vec()),
+            None,
+            None,
+            Some(br#"{"n":5}"#.to_vec()),
+            Some(br#"{"n":3}"#.to_vec()),
+            Some(br#"{"n":5}"#.to_vec()),
+        ]);
+        let one_by_one: Vec<Option<Vec<u8>>> = ids.iter().map(|id| collection.get(id).unwrap()).collect();
+        assert_eq!(found, one_by_one);
+        assert!(collection.get_many(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_uses_id_index_instead_of_scanning() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig { flush_threshold: 100, ..StorageConfig::default() };
+        let mut collection = Collection::open("docs", dir.path(), &config).unwrap();
+        for i in 0..10_000 {
+            collection.insert(format!("doc{}", i).as_bytes(), json!({"n": i}).to_string().as_bytes()).unwrap();
+        }
+        collection.update(b"doc42", br#"{"n":-1}"#).unwrap();
+        collection.delete(b"doc7").unwrap();
+        collection.block_manager.flush().unwrap();
+        assert!(collection.stats().unwrap().block_count >= 100);
+
+        let check = |collection: &Collection| {
+            assert_eq!(collection.get_json(b"doc9999").unwrap(), Some(json!({"n": 9999})));
+            assert_eq!(collection.get_json(b"doc42").unwrap(), Some(json!({"n": -1})));
+            assert_eq!(collection.get(b"doc7").unwrap(), None);
+            assert_eq!(collection.get(b"doc10000").unwrap(), None);
+            assert_eq!(collection.block_manager.id_scan_count(), 0);
+        };
+        check(&collection);
+
+        // The index is rebuilt on reopen and after compaction moves the blocks
+        drop(collection);
+        let mut collection = Collection::open("docs", dir.path(), &config).unwrap();
+        check(&collection);
+        collection.compact().unwrap();
+        check(&collection);
+    }
+
+    #[test]
+    fn test_iter_streams_latest_live_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig { flush_threshold: 4, ..StorageConfig::default() };
+        let mut collection = Collection::open("docs", dir.path(), &config).unwrap();
+        for i in 0..10 {
+            collection.insert(format!("doc{}", i).as_bytes(), json!({"n": i}).to_string().as_bytes()).unwrap();
+        }
+        collection.delete(b"doc3").unwrap();
+        collection.update(b"doc1", br#"{"n":100}"#).unwrap();
+        collection.insert(b"doc10", br#"{"n":10}"#).unwrap();
+        assert!(collection.block_manager.block_headers().unwrap().len() >= 3);
+
+        let documents: Vec<(Vec<u8>, Vec<u8>)> = collection.iter().unwrap().map(Result::unwrap).collect();
+        let ids: Vec<Vec<u8>> = documents.iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(ids, collection.scan().unwrap());
+        assert_eq!(ids.len(), 10);
+        for (id, data) in &documents {
+            assert_eq!(Some(data.clone()), collection.get(id).unwrap());
+        }
+
+        // find streams through the same iterator when no index applies
+        let query = Query::from_json(&json!({"n": {"$gte": 8}})).unwrap();
+        let found: Vec<Vec<u8>> = collection.find(&query, &QueryConfig::default()).unwrap()
+            .into_iter().map(|(id, _)| id).collect();
+        assert_eq!(found, vec![b"doc8".to_vec(), b"doc9".to_vec(), b"doc1".to_vec(), b"doc10".to_vec()]);
+    }
+
+    #[test]
+    fn test_get_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"name": "Ada", "tags": [1, 2]}));
+        collection.insert(b"raw", b"not json").unwrap();
+
+        assert_eq!(collection.get_json(b"a").unwrap(), Some(json!({"name": "Ada", "tags": [1, 2]})));
+        assert_eq!(collection.get_json(b"missing").unwrap(), None);
+        match collection.get_json(b"raw") {
+            Err(Error::Other(msg)) => assert!(msg.starts_with("Document 'raw' is not valid JSON"), "{}", msg),
+            other => panic!("expected a JSON error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_patch_adds_and_overwrites_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"name": "Ada", "age": 36}));
+
+        let doc = patched(&mut collection, b"a", json!({"email": "ada@example.com"}));
+        assert_eq!(doc, json!({"name": "Ada", "age": 36, "email": "ada@example.com"}));
+        let doc = patched(&mut collection, b"a", json!({"age": 37}));
+        assert_eq!(doc, json!({"name": "Ada", "age": 37, "email": "ada@example.com"}));
+        assert_eq!(collection.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_increment_from_concurrent_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        let collection = open_with(dir.path(), b"counter", json!({"hits": 0}));
+        let collection = std::sync::Arc::new(std::sync::Mutex::new(collection));
+
+        let threads: Vec<_> = (0..10).map(|_| {
+            let collection = std::sync::Arc::clone(&collection);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    collection.lock().unwrap().increment(b"counter", "hits", 1).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut collection = collection.lock().unwrap();
+        assert_eq!(collection.get_json(b"counter").unwrap(), Some(json!({"hits": 1000})));
+        assert_eq!(collection.increment(b"counter", "hits", -1000).unwrap(), 0);
+        assert_eq!(collection.increment(b"counter", "stats.views", 5).unwrap(), 5);
+        assert_eq!(collection.get_json(b"counter").unwrap(), Some(json!({"hits": 0, "stats": {"views": 5}})));
+    }
+
+    #[test]
+    fn test_increment_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"doc", json!({"name": "x", "ratio": 0.5}));
+
+        assert!(matches!(collection.increment(b"missing", "n", 1), Err(Error::NotFound { .. })));
+        assert!(matches!(collection.increment(b"doc", "name", 1), Err(Error::TypeMismatch { ref field, .. }) if field == "name"));
+        assert!(matches!(collection.increment(b"doc", "ratio", 1), Err(Error::TypeMismatch { .. })));
+        assert!(matches!(collection.increment(b"doc", "name.first", 1), Err(Error::TypeMismatch { .. })));
+        assert_eq!(collection.get_json(b"doc").unwrap(), Some(json!({"name": "x", "ratio": 0.5})));
+    }
+
+    #[test]
+    fn test_patch_null_removes_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"name": "Ada", "email": "ada@example.com"}));
+
+        let doc = patched(&mut collection, b"a", json!({"email": null}));
+        assert_eq!(doc, json!({"name": "Ada"}));
+    }
+
+    #[test]
+    fn test_patch_merges_nested_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"address": {"city": "London", "zip": "N1"}}));
+
+        let doc = patched(&mut collection, b"a", json!({"address": {"zip": "E1", "country": "UK"}}));
+        assert_eq!(doc, json!({"address": {"city": "London", "zip": "E1", "country": "UK"}}));
+    }
+
+    #[test]
+    fn test_patch_replaces_scalar_with_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = open_with(dir.path(), b"a", json!({"name": "Ada", "tags": "x"}));
+
+        let doc = patched(&mut collection, b"a", json!({"tags": {"primary": "x"}}));
+        assert_eq!(doc, json!({"name": "Ada", "tags": {"primary": "x"}}));
+    }
+
+    #[test]
+    fn test_compression_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(collection.compression_stats().unwrap().ratio(), 1.0);
+
+        for i in 0..200 {
+            let doc = json!({"_id": i, "description": "a highly repetitive description"});
+            collection.insert(format!("doc{}", i).as_bytes(), doc.to_string().as_bytes()).unwrap();
+        }
+        collection.close().unwrap();
+
+        let stats = collection.compression_stats().unwrap();
+        assert!(stats.block_count > 0);
+        assert!(stats.compressed_bytes < stats.uncompressed_bytes);
+        assert!(stats.ratio() > 1.0);
+    }
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        for i in 0..10_000 {
+            collection.insert(format!("doc{}", i).as_bytes(), b"{}").unwrap();
+        }
+        collection.close().unwrap();
+        drop(collection);
+
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        for i in 0..10_000 {
+            assert!(collection.bloom.might_contain(format!("doc{}", i).as_bytes()));
+        }
+
+        let false_positives = (0..10_000)
+            .filter(|i| collection.bloom.might_contain(format!("missing{}", i).as_bytes()))
+            .count();
+        let rate = false_positives as f64 / 10_000.0;
+        println!("Bloom filter false-positive rate: {:.4}", rate);
+        assert!(rate < 0.01, "false-positive rate {}", rate);
+    }
+
+    #[test]
+    fn test_bloom_filter_rebuilt_without_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        collection.insert(b"doc1", b"{}").unwrap();
+        collection.close().unwrap();
+        drop(collection);
+
+        std::fs::remove_file(dir.path().join("docs").join("bloom.bin")).unwrap();
+        let collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+        assert_eq!(collection.get(b"doc1").unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(collection.get(b"doc2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_patch_missing_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = Collection::open("docs", dir.path(), &StorageConfig::default()).unwrap();
+
+        assert!(!collection.patch(b"missing", &json!({"a": 1})).unwrap());
     }
 }
@@ -0,0 +1,153 @@
+//! Compaction scheduling for NebulaDB storage
+//!
+//! Compactions are IO heavy, so every compaction, whether triggered
+//! automatically or by an operator, first takes a permit from a shared
+//! limiter. Requests beyond the limit queue until a permit is released.
+
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+
+/// Default maximum number of compactions running at once
+pub const DEFAULT_MAX_CONCURRENT_COMPACTIONS: usize = 1;
+
+/// What a compaction reclaimed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// Bytes the blocks file shrank by
+    pub bytes_reclaimed: u64,
+    /// Entries dropped: deletions, deleted documents and superseded versions
+    pub documents_removed: usize,
+}
+
+/// Counting semaphore bounding the number of concurrent compactions
+#[derive(Debug, Clone)]
+pub struct CompactionLimiter {
+    inner: Arc<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    /// (running compactions, maximum allowed)
+    counts: Mutex<(usize, usize)>,
+    released: Condvar,
+}
+
+/// A held compaction permit, released on drop
+#[derive(Debug)]
+pub struct CompactionPermit {
+    inner: Arc<LimiterState>,
+}
+
+impl CompactionLimiter {
+    /// Create a limiter allowing `max` concurrent compactions (at least one)
+    pub fn new(max: usize) -> Self {
+        Self {
+            inner: Arc::new(LimiterState {
+                counts: Mutex::new((0, max.max(1))),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    /// The process-wide limiter shared by automatic and manual compactions
+    pub fn global() -> &'static CompactionLimiter {
+        static GLOBAL: OnceLock<CompactionLimiter> = OnceLock::new();
+        GLOBAL.get_or_init(|| CompactionLimiter::new(DEFAULT_MAX_CONCURRENT_COMPACTIONS))
+    }
+
+    /// Change the maximum number of concurrent compactions
+    ///
+    /// Compactions already running keep their permits; lowering the limit
+    /// only delays new ones.
+    pub fn set_limit(&self, max: usize) {
+        let mut counts = self.inner.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.1 = max.max(1);
+        self.inner.released.notify_all();
+    }
+
+    /// Current maximum number of concurrent compactions
+    pub fn limit(&self) -> usize {
+        self.inner.counts.lock().unwrap_or_else(|e| e.into_inner()).1
+    }
+
+    /// Number of compactions currently holding a permit
+    pub fn running(&self) -> usize {
+        self.inner.counts.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    /// Block until a permit is available and take it
+    pub fn acquire(&self) -> CompactionPermit {
+        let mut counts = self.inner.counts.lock().unwrap_or_else(|e| e.into_inner());
+        while counts.0 >= counts.1 {
+            counts = self.inner.released.wait(counts).unwrap_or_else(|e| e.into_inner());
+        }
+        counts.0 += 1;
+
+        CompactionPermit {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Run `f` while holding a permit
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _permit = self.acquire();
+        f()
+    }
+}
+
+impl Drop for CompactionPermit {
+    fn drop(&mut self) {
+        let mut counts = self.inner.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.0 -= 1;
+        self.inner.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_compactions_run_serially_with_limit_of_one() {
+        let limiter = CompactionLimiter::new(1);
+        let spans = Arc::new(Mutex::new(Vec::new()));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let spans = Arc::clone(&spans);
+                thread::spawn(move || {
+                    limiter.run(|| {
+                        let start = Instant::now();
+                        thread::sleep(Duration::from_millis(20));
+                        spans.lock().unwrap().push((start, Instant::now()));
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut spans = spans.lock().unwrap().clone();
+        spans.sort();
+        assert_eq!(spans.len(), 4);
+        for pair in spans.windows(2) {
+            assert!(pair[1].0 >= pair[0].1, "compactions overlapped");
+        }
+        assert_eq!(limiter.running(), 0);
+    }
+
+    #[test]
+    fn test_limit_allows_concurrent_compactions() {
+        let limiter = CompactionLimiter::new(2);
+        let first = limiter.acquire();
+        let second = limiter.acquire();
+        assert_eq!(limiter.running(), 2);
+
+        drop(first);
+        drop(second);
+        assert_eq!(limiter.running(), 0);
+    }
+}
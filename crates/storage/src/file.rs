@@ -1,8 +1,7 @@
 //! File management utilities for NebulaDB storage
 
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write, Seek, SeekFrom};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use nebuladb_core::{Error, Result};
 
 /// Interface for file operations
@@ -18,7 +17,7 @@ impl FileManager {
         
         // Create the data directory if it doesn't exist
         std::fs::create_dir_all(&data_dir)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
         
         Ok(Self { data_dir })
     }
@@ -32,7 +31,7 @@ impl FileManager {
     pub fn create_collection(&self, collection_name: &str) -> Result<()> {
         let path = self.collection_path(collection_name);
         std::fs::create_dir_all(&path)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
         
         Ok(())
     }
@@ -46,12 +45,12 @@ impl FileManager {
     /// List all collections
     pub fn list_collections(&self) -> Result<Vec<String>> {
         let entries = std::fs::read_dir(&self.data_dir)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
         
         let mut collections = Vec::new();
         
         for entry in entries {
-            let entry = entry.map_err(|e| Error::IoError(e))?;
+            let entry = entry.map_err(Error::IoError)?;
             let path = entry.path();
             
             if path.is_dir() {
@@ -73,7 +72,7 @@ impl FileManager {
         // Ensure the parent directory exists
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
-                .map_err(|e| Error::IoError(e))?;
+                .map_err(Error::IoError)?;
         }
         
         OpenOptions::new()
@@ -81,18 +80,18 @@ impl FileManager {
             .create(true)
             .truncate(true)
             .open(&path)
-            .map_err(|e| Error::IoError(e))
+            .map_err(Error::IoError)
     }
     
     /// Open an existing file
     pub fn open_file(&self, collection_name: &str, file_name: &str) -> Result<File> {
         let path = self.collection_path(collection_name).join(file_name);
-        File::open(&path).map_err(|e| Error::IoError(e))
+        File::open(&path).map_err(Error::IoError)
     }
     
     /// Delete a file
     pub fn delete_file(&self, collection_name: &str, file_name: &str) -> Result<()> {
         let path = self.collection_path(collection_name).join(file_name);
-        std::fs::remove_file(&path).map_err(|e| Error::IoError(e))
+        std::fs::remove_file(&path).map_err(Error::IoError)
     }
 }
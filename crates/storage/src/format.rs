@@ -0,0 +1,146 @@
+//! On-disk format versioning for NebulaDB data directories
+//!
+//! The root of a data directory holds a `FORMAT_VERSION` file. Opening a
+//! directory written by an older release runs the registered migrations in
+//! order to upgrade it in place; a directory written by a newer release is
+//! refused.
+
+use std::fs;
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+
+use crate::manager::BlockManager;
+use crate::StorageConfig;
+
+/// Format version written by this release
+pub const FORMAT_VERSION: u32 = 4;
+
+/// Name of the version file at the data directory root
+pub const FORMAT_VERSION_FILE: &str = "FORMAT_VERSION";
+
+/// Version assumed for directories created before the version file existed
+const UNVERSIONED: u32 = 1;
+
+/// An upgrade from `from` to `from + 1`
+struct Migration {
+    from: u32,
+    description: &'static str,
+    run: fn(&Path) -> Result<()>,
+}
+
+/// Registered migrations, in version order
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "add footer directories to legacy blocks",
+        run: add_block_directories,
+    },
+    Migration {
+        from: 2,
+        description: "replace tombstone documents with deletion entries",
+        run: convert_tombstones,
+    },
+    Migration {
+        from: 3,
+        description: "record commit versions in block headers",
+        run: keep_existing_blocks,
+    },
+];
+
+/// Read the format version of a data directory, if it records one
+pub fn read_format_version(data_dir: &Path) -> Result<Option<u32>> {
+    let path = data_dir.join(FORMAT_VERSION_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&path).map_err(Error::IoError)?;
+    contents.trim().parse().map(Some).map_err(|_| Error::Other(format!(
+        "Invalid format version '{}' in {:?}", contents.trim(), path)))
+}
+
+/// Bring a data directory to the current format version
+///
+/// A new or empty directory is stamped with [`FORMAT_VERSION`]. Returns the
+/// version the directory had before any migration ran.
+pub fn prepare_data_dir(data_dir: &Path) -> Result<u32> {
+    fs::create_dir_all(data_dir).map_err(Error::IoError)?;
+
+    let version = match read_format_version(data_dir)? {
+        Some(version) => version,
+        None => {
+            let is_empty = fs::read_dir(data_dir).map_err(Error::IoError)?.next().is_none();
+            if is_empty {
+                write_format_version(data_dir, FORMAT_VERSION)?;
+                return Ok(FORMAT_VERSION);
+            }
+            UNVERSIONED
+        }
+    };
+
+    if version > FORMAT_VERSION {
+        return Err(Error::Other(format!(
+            "Data directory {:?} has format version {}, but this release supports up to {}",
+            data_dir, version, FORMAT_VERSION)));
+    }
+
+    let mut current = version;
+    while current < FORMAT_VERSION {
+        let migration = MIGRATIONS.iter().find(|m| m.from == current).ok_or_else(|| Error::Other(
+            format!("No migration from format version {}", current)))?;
+
+        tracing::info!(version = current + 1, migration = migration.description, "migrating data directory");
+        (migration.run)(data_dir)?;
+
+        // Record each step so an interrupted upgrade resumes where it stopped
+        current += 1;
+        write_format_version(data_dir, current)?;
+    }
+
+    Ok(version)
+}
+
+/// Atomically replace the version file
+fn write_format_version(data_dir: &Path, version: u32) -> Result<()> {
+    let tmp_path = data_dir.join(format!("{}.tmp", FORMAT_VERSION_FILE));
+    fs::write(&tmp_path, format!("{}\n", version)).map_err(Error::IoError)?;
+    fs::rename(&tmp_path, data_dir.join(FORMAT_VERSION_FILE)).map_err(Error::IoError)
+}
+
+/// Migration 1 -> 2: rewrite every collection's legacy blocks with a footer
+/// directory
+fn add_block_directories(dir: &Path) -> Result<()> {
+    for_each_collection(dir, &mut |manager| manager.upgrade_legacy_blocks().map(|_| ()))
+}
+
+/// Migration 2 -> 3: replace the `_<id>_` tombstone documents of every
+/// collection with deletion entries
+fn convert_tombstones(dir: &Path) -> Result<()> {
+    for_each_collection(dir, &mut |manager| manager.convert_legacy_tombstones().map(|_| ()))
+}
+
+/// Migration 3 -> 4: nothing to rewrite; blocks written without a commit
+/// version stay readable and visible to every snapshot
+fn keep_existing_blocks(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Run `f` on the blocks of every collection under `dir`
+fn for_each_collection(dir: &Path, f: &mut dyn FnMut(&mut BlockManager) -> Result<()>) -> Result<()> {
+    for entry in fs::read_dir(dir).map_err(Error::IoError)? {
+        let path = entry.map_err(Error::IoError)?.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        if path.join("blocks.bin").exists() {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let mut manager = BlockManager::open(&name, path.clone(), StorageConfig::default())?;
+            f(&mut manager)?;
+        }
+        for_each_collection(&path, f)?;
+    }
+
+    Ok(())
+}
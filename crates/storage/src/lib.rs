@@ -4,13 +4,27 @@
 //! including block management, compression, and file operations.
 
 pub mod block;
+pub mod bloom;
+pub mod cache;
+pub mod changefeed;
 pub mod manager;
+pub mod meta;
 pub mod compression;
 pub mod file;
+pub mod format;
 pub mod wal_integration;
 pub mod collection;
+pub mod compaction;
+pub mod mvcc;
+pub mod plan;
+pub mod storage;
 
-use nebuladb_core::{Result, Config};
+use std::time::Duration;
+
+use nebuladb_core::{Result, Config, Error};
+use nebuladb_wal::SyncMode;
+
+use block::BlockOperations;
 
 /// Storage engine configuration
 #[derive(Debug, Clone)]
@@ -21,8 +35,41 @@ pub struct StorageConfig {
     pub block_size: usize,
     /// Compression algorithm to use
     pub compression: CompressionType,
+    /// Compression level (only used by algorithms that support levels)
+    pub compression_level: i32,
     /// Auto-flush threshold (in number of documents)
     pub flush_threshold: usize,
+    /// Flush a collection's active block once it has gone this long without a
+    /// write (`None` disables idle flushing)
+    pub idle_flush_timeout: Option<Duration>,
+    /// Whether new blocks store a document-offset directory in their footer
+    pub block_offset_directory: bool,
+    /// Interval at which the active block is persisted as a partial block so a
+    /// crash loses at most one interval of writes (`None` disables it,
+    /// `Some(Duration::ZERO)` persists after every write). Writes are also
+    /// persisted once traffic stops by a database's background flusher.
+    pub partial_block_interval: Option<Duration>,
+    /// Number of decompressed blocks kept in each collection's block cache
+    /// (0 disables the cache)
+    pub cache_size_blocks: usize,
+    /// Maximum number of compactions allowed to run at once, shared by
+    /// automatic and manual compactions
+    pub max_concurrent_compactions: usize,
+    /// Whether a database logs all its collections to one shared WAL
+    pub shared_wal: bool,
+    /// WAL recovery time after which a warning is logged (in milliseconds,
+    /// 0 to disable)
+    pub wal_recovery_time_budget_ms: u64,
+    /// Milliseconds between WAL group commits, which batch the writes and
+    /// fsyncs of concurrent writers (0 syncs each write on its own)
+    pub wal_group_commit_interval_ms: u64,
+    /// Pending WAL writes that trigger a group commit before its interval
+    pub wal_group_commit_max_entries: usize,
+    /// When WAL writes are synced to disk
+    pub wal_sync_mode: SyncMode,
+    /// Seconds between sweeps deleting documents expired by TTL indexes
+    /// (0 disables the sweeps)
+    pub ttl_check_interval_secs: u64,
 }
 
 impl Default for StorageConfig {
@@ -31,7 +78,19 @@ impl Default for StorageConfig {
             base: Config::default(),
             block_size: 4 * 1024 * 1024, // 4MB blocks
             compression: CompressionType::Zstd,
+            compression_level: compression::DEFAULT_COMPRESSION_LEVEL,
             flush_threshold: 1000, // Flush every 1000 documents
+            idle_flush_timeout: None,
+            block_offset_directory: true,
+            partial_block_interval: None,
+            cache_size_blocks: 64,
+            max_concurrent_compactions: compaction::DEFAULT_MAX_CONCURRENT_COMPACTIONS,
+            shared_wal: false,
+            wal_recovery_time_budget_ms: 30_000,
+            wal_group_commit_interval_ms: 0,
+            wal_group_commit_max_entries: 1024,
+            wal_sync_mode: SyncMode::Always,
+            ttl_check_interval_secs: 60,
         }
     }
 }
@@ -62,17 +121,42 @@ pub struct BlockHeader {
     pub compressed_size: u64,
     /// Timestamp when the block was created (UNIX timestamp)
     pub created_at: u64,
+    /// Commit version of the writes the block holds, 0 if written before
+    /// versions were recorded; see [`mvcc`]
+    pub commit_version: u64,
+    /// Format flags such as [`FLAG_DIRECTORY`](Self::FLAG_DIRECTORY),
+    /// recorded from [`ENTRY_KIND_VERSION`](Self::ENTRY_KIND_VERSION) on
+    pub flags: u8,
 }
 
 impl BlockHeader {
-    /// Size of the block header in bytes
+    /// Size of the header fields every block format version has, in bytes
     pub const SIZE: usize = 4 + 1 + 1 + 4 + 8 + 8 + 8;
     
+    /// Size of the largest block header in bytes
+    pub const MAX_SIZE: usize = Self::SIZE + 8 + 1;
+    
     /// Magic number for NebulaDB blocks: "NBLD"
     pub const MAGIC: [u8; 4] = [0x4E, 0x42, 0x4C, 0x44];
     
     /// Current version of the block format
-    pub const VERSION: u8 = 1;
+    pub const VERSION: u8 = 4;
+    
+    /// First block format version whose entries start their stored data with
+    /// a kind byte, and whose header records flags
+    pub const ENTRY_KIND_VERSION: u8 = 4;
+    
+    /// First block format version whose header records the commit version
+    pub const MVCC_VERSION: u8 = 3;
+    
+    /// First block format version whose footer stores a document-offset directory
+    pub const DIRECTORY_VERSION: u8 = 2;
+    
+    /// Block format version without a footer directory
+    pub const LEGACY_VERSION: u8 = 1;
+    
+    /// Flag set when the footer stores a document-offset directory
+    pub const FLAG_DIRECTORY: u8 = 1;
     
     /// Create a new block header
     pub fn new(
@@ -92,30 +176,172 @@ impl BlockHeader {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            commit_version: 0,
+            flags: Self::FLAG_DIRECTORY,
+        }
+    }
+    
+    /// Size of this header in bytes
+    pub fn size(&self) -> usize {
+        if self.version >= Self::ENTRY_KIND_VERSION {
+            Self::MAX_SIZE
+        } else if self.version >= Self::MVCC_VERSION {
+            Self::SIZE + 8
+        } else {
+            Self::SIZE
+        }
+    }
+    
+    /// Serialize the header to bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        
+        bytes.extend_from_slice(&self.magic);
+        bytes.push(self.version);
+        bytes.push(self.compression as u8);
+        bytes.extend_from_slice(&self.doc_count.to_le_bytes());
+        bytes.extend_from_slice(&self.uncompressed_size.to_le_bytes());
+        bytes.extend_from_slice(&self.compressed_size.to_le_bytes());
+        bytes.extend_from_slice(&self.created_at.to_le_bytes());
+        if self.version >= Self::MVCC_VERSION {
+            bytes.extend_from_slice(&self.commit_version.to_le_bytes());
+        }
+        if self.version >= Self::ENTRY_KIND_VERSION {
+            bytes.push(self.flags);
+        }
+
+        bytes
+    }
+
+    /// Deserialize a header from the start of `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::SIZE {
+            return Err(Error::Other("Invalid block header: too short".to_string()));
+        }
+
+        let mut magic = [0u8; 4];
+        magic.copy_from_slice(&bytes[0..4]);
+
+        if magic != Self::MAGIC {
+            return Err(Error::Other("Invalid block: wrong magic number".to_string()));
+        }
+
+        let version = bytes[4];
+        let commit_version = if version >= Self::MVCC_VERSION {
+            let field = bytes.get(Self::SIZE..Self::SIZE + 8)
+                .ok_or_else(|| Error::Other("Invalid block header: too short".to_string()))?;
+            u64::from_le_bytes(field.try_into().unwrap())
+        } else {
+            0
+        };
+        let flags = if version >= Self::ENTRY_KIND_VERSION {
+            *bytes.get(Self::SIZE + 8)
+                .ok_or_else(|| Error::Other("Invalid block header: too short".to_string()))?
+        } else {
+            0
+        };
+        let compression = match bytes[5] {
+            0 => CompressionType::None,
+            1 => CompressionType::Snappy,
+            2 => CompressionType::Zstd,
+            3 => CompressionType::Lz4,
+            _ => return Err(Error::Other("Invalid compression type".to_string())),
+        };
+
+        let doc_count = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+
+        let uncompressed_size = u64::from_le_bytes([
+            bytes[10], bytes[11], bytes[12], bytes[13],
+            bytes[14], bytes[15], bytes[16], bytes[17],
+        ]);
+
+        let compressed_size = u64::from_le_bytes([
+            bytes[18], bytes[19], bytes[20], bytes[21],
+            bytes[22], bytes[23], bytes[24], bytes[25],
+        ]);
+
+        let created_at = u64::from_le_bytes([
+            bytes[26], bytes[27], bytes[28], bytes[29],
+            bytes[30], bytes[31], bytes[32], bytes[33],
+        ]);
+
+        Ok(Self {
+            magic,
+            version,
+            compression,
+            doc_count,
+            uncompressed_size,
+            compressed_size,
+            created_at,
+            commit_version,
+            flags,
+        })
+    }
+
+    /// Whether blocks with this header store a document-offset directory in the footer
+    pub fn has_directory(&self) -> bool {
+        if self.version >= Self::ENTRY_KIND_VERSION {
+            self.flags & Self::FLAG_DIRECTORY != 0
+        } else {
+            self.version >= Self::DIRECTORY_VERSION
+        }
+    }
+    
+    /// Size of the footer that follows the payload, including any directory
+    pub fn footer_size(&self) -> usize {
+        if self.has_directory() {
+            BlockFooter::directory_size(self.doc_count as usize) + BlockFooter::SIZE
+        } else {
+            BlockFooter::SIZE
+        }
+    }
+    
+    /// Size of the data payload as stored on disk
+    ///
+    /// Blocks written before compression was applied record a compressed size
+    /// of 0 and store their data verbatim.
+    pub fn stored_size(&self) -> usize {
+        if self.compressed_size == 0 {
+            self.uncompressed_size as usize
+        } else {
+            self.compressed_size as usize
         }
     }
 }
 
 /// Footer for a data block
+///
+/// From block format version 2 the footer starts with a directory of document
+/// offsets: `[count(4)][offset(4) * count]`, followed by the fixed checksum and
+/// magic. Version 1 footers hold only the fixed part.
 #[derive(Debug, Clone)]
 pub struct BlockFooter {
-    /// CRC32 checksum of the block (header + compressed data)
+    /// Offsets of each document within the uncompressed block data
+    /// (empty for blocks written without a directory)
+    pub offsets: Vec<u32>,
+    /// CRC32 checksum of the block (header + compressed data + directory)
     pub checksum: u32,
     /// Magic number to identify NebulaDB blocks (same as header)
     pub magic: [u8; 4],
 }
 
 impl BlockFooter {
-    /// Size of the block footer in bytes
+    /// Size of the fixed part of the block footer (checksum and magic) in bytes
     pub const SIZE: usize = 4 + 4;
     
     /// Create a new block footer with the given checksum
     pub fn new(checksum: u32) -> Self {
         Self {
+            offsets: Vec::new(),
             checksum,
             magic: BlockHeader::MAGIC,
         }
     }
+    
+    /// Size in bytes of a directory holding `doc_count` offsets
+    pub fn directory_size(doc_count: usize) -> usize {
+        4 + 4 * doc_count
+    }
 }
 
 /// Represents a document storage block
@@ -123,7 +349,7 @@ impl BlockFooter {
 pub struct Block {
     /// Block header
     pub header: BlockHeader,
-    /// Compressed document data
+    /// Document data, kept uncompressed in memory and compressed on serialization
     pub data: Vec<u8>,
     /// Block footer
     pub footer: BlockFooter,
@@ -133,17 +359,32 @@ impl Block {
     /// Create a new empty block with the given compression type
     pub fn new(compression: CompressionType) -> Self {
         let header = BlockHeader::new(compression, 0, 0, 0);
-        let footer = BlockFooter::new(0); // Temporary checksum, will be updated
+        let footer = BlockFooter::new(0);
         
-        Self {
+        let mut block = Self {
             header,
             data: Vec::new(),
             footer,
-        }
+        };
+        block.footer.checksum = block.compute_checksum();
+        
+        block
     }
     
     /// Get the total size of the block in bytes
     pub fn size(&self) -> usize {
-        BlockHeader::SIZE + self.data.len() + BlockFooter::SIZE
+        self.header.size() + self.data.len() + BlockFooter::SIZE
+    }
+    
+    /// Ratio of uncompressed to compressed data size
+    ///
+    /// Only blocks read back from disk have a compressed size; blocks that
+    /// have not been serialized report 1.0.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.header.compressed_size == 0 {
+            return 1.0;
+        }
+        
+        self.header.uncompressed_size as f64 / self.header.compressed_size as f64
     }
 }
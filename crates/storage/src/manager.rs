@@ -1,15 +1,44 @@
 //! Block manager for NebulaDB storage
 
 use std::fs::{File, OpenOptions};
-use crate::{Block, BlockHeader, StorageConfig, Result};
+use crate::{Block, BlockHeader, CompressionType, StorageConfig, Result};
 use std::io::{Write, Seek, SeekFrom, Read};
 use std::path::PathBuf;
-use crate::block::{BlockOperations, DocumentEntry};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::block::{decode_stored, BlockOperations, DocumentEntry, DocumentMeta};
+use crate::cache::{BlockCache, CacheStats};
+use crate::compaction::CompactionStats;
+use crate::mvcc;
 use nebuladb_core::Error;
+use nebuladb_index::ScanDirection;
+use tracing::{debug, warn};
 
 /// Maximum size of blocks in MB
 pub const MAX_BLOCK_SIZE: usize = 4;
 
+/// Magic number for partial block files: "NBPB"
+const PARTIAL_MAGIC: [u8; 4] = [0x4E, 0x42, 0x50, 0x42];
+
+/// Size of the partial block file header: [magic(4)][block_idx(4)]
+const PARTIAL_HEADER_SIZE: usize = 4 + 4;
+
+/// Offset and total length of an on-disk block
+type BlockLocation = (u64, usize);
+
+/// A page of document IDs and the cursor for the next page, if any
+pub type IdPage = (Vec<Vec<u8>>, Option<Vec<u8>>);
+
+/// Block index and data offset of the newest flushed entry of each ID;
+/// `None` when that entry is a deletion
+type IdIndex = BTreeMap<Vec<u8>, Option<(u32, usize)>>;
+
+/// Metadata and data of a document entry, `None` when the entry is a deletion
+type StoredDocument = Option<(Option<DocumentMeta>, Vec<u8>)>;
+
 /// Block manager for a collection
 #[derive(Debug, Clone)]
 pub struct BlockManager {
@@ -25,12 +54,33 @@ pub struct BlockManager {
     current_block_idx: u32,
     /// Base file path (collection/blocks.bin)
     base_file_path: PathBuf,
+    /// Partial block file path (collection/partial.bin)
+    partial_file_path: PathBuf,
+    /// Last time the active block was persisted as a partial block
+    last_partial_persist: Option<Instant>,
+    /// Time of the last insert
+    last_write: Option<Instant>,
+    /// Cache of decompressed on-disk blocks
+    cache: Arc<Mutex<BlockCache>>,
+    /// Offset and length of each on-disk block, rebuilt after the file changes
+    locations: Arc<Mutex<Option<Vec<BlockLocation>>>>,
+    /// Where each document's newest entry is on disk; `None` when it could
+    /// not be built, in which case lookups scan the blocks
+    id_index: Option<IdIndex>,
+    /// Number of ID lookups that scanned the blocks
+    id_scans: Arc<AtomicU64>,
+    /// Number of fsyncs of the blocks file
+    block_syncs: u64,
 }
 
 impl BlockManager {
     /// Create a new block manager
-    pub fn new(name: &str, path: PathBuf, config: StorageConfig) -> Self {
+    pub fn new(name: &str, path: PathBuf, mut config: StorageConfig) -> Self {
+        config.compression_level = crate::compression::validate_level(config.compression, config.compression_level);
+        
         let base_file_path = path.join("blocks.bin");
+        let partial_file_path = path.join("partial.bin");
+        let cache = BlockCache::new(config.cache_size_blocks);
         
         Self {
             name: name.to_string(),
@@ -39,7 +89,144 @@ impl BlockManager {
             active_block: None,
             current_block_idx: 0,
             base_file_path,
+            partial_file_path,
+            last_partial_persist: None,
+            last_write: None,
+            cache: Arc::new(Mutex::new(cache)),
+            locations: Arc::new(Mutex::new(None)),
+            id_index: None,
+            id_scans: Arc::new(AtomicU64::new(0)),
+            block_syncs: 0,
+        }
+    }
+    
+    /// Open a block manager, restoring any partial block left behind by a crash
+    pub fn open(name: &str, path: PathBuf, config: StorageConfig) -> Result<Self> {
+        let mut manager = Self::new(name, path, config);
+        manager.current_block_idx = manager.find_next_block_idx()?;
+        manager.recover_partial_block()?;
+        manager.rebuild_id_index();
+        
+        // Writes from now on must be newer than those on disk
+        let on_disk = manager.block_headers()?.last().map_or(0, |header| header.commit_version);
+        let recovered = manager.active_block.as_ref().map_or(0, |block| block.header.commit_version);
+        mvcc::observe(on_disk.max(recovered));
+        Ok(manager)
+    }
+    
+    /// Restore a persisted partial block as the active block
+    fn recover_partial_block(&mut self) -> Result<()> {
+        if !self.partial_file_path.exists() {
+            return Ok(());
+        }
+        
+        let bytes = std::fs::read(&self.partial_file_path)
+            .map_err(|e| Error::Other(format!("Failed to read partial block: {}", e)))?;
+        
+        if bytes.len() < PARTIAL_HEADER_SIZE || bytes[0..4] != PARTIAL_MAGIC {
+            return Err(Error::Other(format!(
+                "Invalid partial block in collection '{}'", self.name)));
+        }
+        
+        let block_idx = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let next_block_idx = self.find_next_block_idx()?;
+        
+        // A partial block whose index was already flushed is a stale copy
+        // left behind by a crash between the flush and the cleanup
+        if block_idx < next_block_idx {
+            return self.remove_partial_block();
+        }
+        
+        let mut block = Block::from_bytes(&bytes[PARTIAL_HEADER_SIZE..])?;
+        
+        // New entries go in the current format
+        if block.header.version < BlockHeader::VERSION {
+            let directory = block.header.has_directory();
+            block = block.upgraded()?;
+            if !directory {
+                block.header.flags &= !BlockHeader::FLAG_DIRECTORY;
+            }
         }
+        
+        debug!(collection = %self.name, block = next_block_idx, docs = block.doc_count(),
+            "recovered partial block");
+        self.current_block_idx = next_block_idx;
+        self.active_block = Some(block);
+        
+        Ok(())
+    }
+    
+    /// Persist the active block as a partial block so its documents survive a crash
+    ///
+    /// The partial block lives in its own file and is replaced by the next
+    /// persist or removed by the next flush.
+    pub fn persist_partial_block(&mut self) -> Result<()> {
+        let block = match &self.active_block {
+            Some(block) if block.doc_count() > 0 => block,
+            _ => return Ok(()),
+        };
+        
+        let block_bytes = block.to_bytes()?;
+        let mut bytes = Vec::with_capacity(PARTIAL_HEADER_SIZE + block_bytes.len());
+        bytes.extend_from_slice(&PARTIAL_MAGIC);
+        bytes.extend_from_slice(&self.current_block_idx.to_le_bytes());
+        bytes.extend_from_slice(&block_bytes);
+        
+        // Write to a temporary file first so a crash mid-write never
+        // replaces a good partial block with a torn one
+        let tmp_path = self.path.join("partial.bin.tmp");
+        let mut file = File::create(&tmp_path)
+            .map_err(|e| Error::Other(format!("Failed to create partial block: {}", e)))?;
+        file.write_all(&bytes)
+            .map_err(|e| Error::Other(format!("Failed to write partial block: {}", e)))?;
+        file.sync_all()
+            .map_err(|e| Error::Other(format!("Failed to sync partial block: {}", e)))?;
+        std::fs::rename(&tmp_path, &self.partial_file_path)
+            .map_err(|e| Error::Other(format!("Failed to replace partial block: {}", e)))?;
+        
+        self.last_partial_persist = Some(Instant::now());
+        
+        Ok(())
+    }
+    
+    /// Persist the active block if it changed since the last persist and the
+    /// configured partial block interval has elapsed
+    ///
+    /// Called after each write and by the database's background flusher, so
+    /// writes are persisted even once no more arrive.
+    pub fn persist_partial_if_due(&mut self) -> Result<()> {
+        if let Some(interval) = self.config.partial_block_interval {
+            let (changed, due) = match (self.last_write, self.last_partial_persist) {
+                (None, _) => (false, false),
+                (Some(_), None) => (true, true),
+                (Some(write), Some(last)) => (write > last, last.elapsed() >= interval),
+            };
+            
+            if changed && due {
+                self.persist_partial_block()?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Remove the partial block file, if any
+    fn remove_partial_block(&mut self) -> Result<()> {
+        if self.partial_file_path.exists() {
+            std::fs::remove_file(&self.partial_file_path)
+                .map_err(|e| Error::Other(format!("Failed to remove partial block: {}", e)))?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Create an empty block in the configured format
+    fn new_block(&self) -> Block {
+        let mut block = Block::new(self.config.compression);
+        if !self.config.block_offset_directory {
+            block.header.flags &= !BlockHeader::FLAG_DIRECTORY;
+        }
+        block
     }
     
     /// Ensure the active block is initialized
@@ -52,7 +239,7 @@ impl BlockManager {
             }
             
             // Create a new block
-            let block = Block::new(self.config.compression);
+            let block = self.new_block();
             self.active_block = Some(block);
         }
         
@@ -61,10 +248,12 @@ impl BlockManager {
     
     /// Flush the current block to disk if it's past the threshold
     fn flush_if_needed(&mut self) -> Result<()> {
-        if let Some(_) = self.active_block.as_ref() {
-            // Check if we're past the threshold
-            let block_size = self.active_block.as_ref().unwrap().size();
-            if block_size >= self.config.flush_threshold as usize {
+        if let Some(block) = self.active_block.as_ref() {
+            // Flush once the block holds enough documents or reaches the
+            // configured block size, whichever comes first
+            let full = block.header.doc_count as usize >= self.config.flush_threshold
+                || block.size() >= self.config.block_size;
+            if full {
                 self.flush()?;
             }
         }
@@ -72,236 +261,716 @@ impl BlockManager {
         Ok(())
     }
     
+    /// Flush the current block and fsync the blocks file
+    ///
+    /// Unlike [`flush`](Self::flush), the file is synced even when the active
+    /// block is empty.
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        
+        if self.base_file_path.exists() {
+            File::open(&self.base_file_path)
+                .and_then(|file| file.sync_all())
+                .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+            self.block_syncs += 1;
+        }
+        
+        Ok(())
+    }
+    
     /// Flush the current block to disk
     pub fn flush(&mut self) -> Result<()> {
-        if let Some(block) = self.active_block.as_ref() {
-            // Create a copy of the block before we use it
-            let block_copy = block.clone();
+        // Where the block will sit in the file, for the ID index
+        let block_idx = match self.id_index {
+            Some(_) => self.cached_block_locations()?.len() as u32,
+            None => 0,
+        };
+        
+        if let Some(block) = self.active_block.as_mut() {
+            // Nothing to persist for an empty block
+            if block.doc_count() == 0 {
+                return Ok(());
+            }
             
-            // Create or open the file
-            let file_exists = self.base_file_path.exists();
-            let mut file = if file_exists {
-                OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .open(&self.base_file_path)
-                    .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?
-            } else {
-                OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(&self.base_file_path)
-                    .map_err(|e| Error::Other(format!("Failed to create file: {}", e)))?
-            };
+            let block_bytes = block.to_bytes_with_level(self.config.compression_level)?;
             
-            // Calculate position in the file
-            let block_size = block_copy.size() as u64;
-            let position = self.current_block_idx as u64 * block_size;
+            // Create or open the file
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&self.base_file_path)
+                .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
             
-            // Seek to the position
-            file.seek(SeekFrom::Start(position))
+            // Blocks vary in size once compressed, so new blocks are appended
+            file.seek(SeekFrom::End(0))
                 .map_err(|e| Error::Other(format!("Failed to seek in file: {}", e)))?;
             
-            // Write the block header
-            file.write_all(&block_copy.header.magic)
-                .map_err(|e| Error::Other(format!("Failed to write header magic: {}", e)))?;
-            file.write_all(&[block_copy.header.version])
-                .map_err(|e| Error::Other(format!("Failed to write header version: {}", e)))?;
-            file.write_all(&[block_copy.header.compression as u8])
-                .map_err(|e| Error::Other(format!("Failed to write header compression: {}", e)))?;
-            file.write_all(&block_copy.header.doc_count.to_le_bytes())
-                .map_err(|e| Error::Other(format!("Failed to write doc count: {}", e)))?;
-            file.write_all(&block_copy.header.uncompressed_size.to_le_bytes())
-                .map_err(|e| Error::Other(format!("Failed to write uncompressed size: {}", e)))?;
-            file.write_all(&block_copy.header.compressed_size.to_le_bytes())
-                .map_err(|e| Error::Other(format!("Failed to write compressed size: {}", e)))?;
-            file.write_all(&block_copy.header.created_at.to_le_bytes())
-                .map_err(|e| Error::Other(format!("Failed to write created at: {}", e)))?;
-            
-            // Write the block data
-            file.write_all(&block_copy.data)
-                .map_err(|e| Error::Other(format!("Failed to write block data: {}", e)))?;
-            
-            // Write the block footer
-            file.write_all(&block_copy.footer.checksum.to_le_bytes())
-                .map_err(|e| Error::Other(format!("Failed to write footer checksum: {}", e)))?;
-            file.write_all(&block_copy.footer.magic)
-                .map_err(|e| Error::Other(format!("Failed to write footer magic: {}", e)))?;
+            file.write_all(&block_bytes)
+                .map_err(|e| Error::Other(format!("Failed to write block: {}", e)))?;
+            debug!(collection = %self.name, block = self.current_block_idx, docs = block.doc_count(),
+                bytes = block_bytes.len(), "flushed block");
+            if let Some(index) = self.id_index.as_mut() {
+                index_block(index, block_idx, block);
+            }
+            self.invalidate_locations();
             
             // Increment the block index and create a new active block
             self.current_block_idx += 1;
-            self.active_block = Some(Block::new(self.config.compression));
+            self.active_block = Some(self.new_block());
             
             // Sync the file to disk
             file.sync_all()
                 .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+            self.block_syncs += 1;
+            
+            // The block is durable now, so its partial copy is no longer needed
+            self.remove_partial_block()?;
         }
         
         Ok(())
     }
     
+    /// Flush the active block if it holds documents and nothing was written for `timeout`
+    ///
+    /// Returns whether a flush happened.
+    pub fn flush_if_idle(&mut self, timeout: Duration) -> Result<bool> {
+        let has_documents = self.active_block.as_ref().is_some_and(|block| block.doc_count() > 0);
+        let idle = self.last_write.is_none_or(|last| last.elapsed() >= timeout);
+        
+        if has_documents && idle {
+            self.flush()?;
+            return Ok(true);
+        }
+        
+        Ok(false)
+    }
+    
     /// Find the next available block index
     fn find_next_block_idx(&self) -> Result<u32> {
         if !self.base_file_path.exists() {
             return Ok(0);
         }
         
-        let file = File::open(&self.base_file_path)
+        let mut file = File::open(&self.base_file_path)
             .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+        
+        Ok(self.block_locations(&mut file)?.len() as u32)
+    }
+    
+    /// Walk the block headers in the file, returning each block's offset and total length
+    fn block_locations(&self, file: &mut File) -> Result<Vec<BlockLocation>> {
         let file_size = file.metadata()
             .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
         
-        if file_size == 0 {
-            return Ok(0);
+        let mut locations = Vec::new();
+        let mut position = 0u64;
+        
+        while position + BlockHeader::SIZE as u64 <= file_size {
+            let header = match read_header_at(file, position, file_size) {
+                Ok(header) => header,
+                // A header cut short by a crash mid-write
+                Err(_) if file_size - position < BlockHeader::MAX_SIZE as u64 => break,
+                Err(e) => return Err(e),
+            };
+            let len = header.size() + header.stored_size() + header.footer_size();
+            
+            // A block cut short by a crash mid-write is not part of the file
+            if position + len as u64 > file_size {
+                break;
+            }
+            
+            locations.push((position, len));
+            position += len as u64;
         }
         
-        // For simplicity, we assume all blocks are of the same size
-        // In a real implementation, we would need to read the headers
-        // to determine the actual block sizes
-        let block = Block::new(self.config.compression);
-        let block_size = block.size() as u64;
+        Ok(locations)
+    }
+    
+    /// Read and decode the block stored at the given location
+    fn read_block_at(&self, file: &mut File, position: u64, len: usize) -> Result<Block> {
+        file.seek(SeekFrom::Start(position))
+            .map_err(|e| Error::Other(format!("Failed to seek in file: {}", e)))?;
         
-        let num_blocks = file_size / block_size;
+        let mut block_data = vec![0u8; len];
+        file.read_exact(&mut block_data)
+            .map_err(|e| Error::Other(format!("Failed to read block: {}", e)))?;
         
-        Ok(num_blocks as u32)
+        Block::from_bytes(&block_data)
     }
     
-    /// Insert a document into the block manager
-    pub fn insert(&mut self, id: &[u8], data: &[u8]) -> Result<()> {
-        // Ensure we have an active block
-        self.ensure_active_block()?;
+    /// Read the header of every on-disk block, oldest first
+    pub fn block_headers(&self) -> Result<Vec<BlockHeader>> {
+        let locations = self.cached_block_locations()?;
+        if locations.is_empty() {
+            return Ok(Vec::new());
+        }
         
-        // Create a document entry
-        let doc = DocumentEntry::new(id.to_vec(), data.to_vec());
+        let mut file = File::open(&self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+        let file_size = file.metadata()
+            .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
         
-        // Add the document to the active block
-        if let Some(block) = self.active_block.as_mut() {
-            block.add_document(doc)?;
+        let mut headers = Vec::with_capacity(locations.len());
+        for (position, _) in locations {
+            headers.push(read_header_at(&mut file, position, file_size)?);
         }
         
-        // Flush if needed
-        self.flush_if_needed()?;
+        Ok(headers)
+    }
+    
+    /// Read and decode the block at `block_idx`, reporting a checksum failure
+    /// as a corrupt block
+    fn read_indexed_block(&self, file: &mut File, block_idx: u32, position: u64, len: usize) -> Result<Block> {
+        self.read_block_at(file, position, len).map_err(|e| match e {
+            Error::ChecksumMismatch { .. } => Error::Other(format!("corrupt block at index {}", block_idx)),
+            e => e,
+        })
+    }
+    
+    /// Offset and length of each on-disk block, walking the file only when it changed
+    fn cached_block_locations(&self) -> Result<Vec<BlockLocation>> {
+        let mut locations = self.locations.lock()
+            .map_err(|_| Error::Other("Failed to lock block locations".into()))?;
         
-        Ok(())
+        if locations.is_none() {
+            let found = if self.base_file_path.exists() {
+                let mut file = File::open(&self.base_file_path)
+                    .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+                self.block_locations(&mut file)?
+            } else {
+                Vec::new()
+            };
+            *locations = Some(found);
+        }
+        
+        Ok(locations.clone().unwrap_or_default())
     }
     
-    /// Read a document from a block
-    pub fn read_document(&self, block_index: u32, offset: usize) -> Result<Vec<u8>> {
-        let path = &self.base_file_path;
+    /// Forget the block locations after the blocks file changed
+    fn invalidate_locations(&self) {
+        if let Ok(mut locations) = self.locations.lock() {
+            *locations = None;
+        }
+    }
+    
+    /// Index the newest entry of every ID in the blocks on disk
+    ///
+    /// The index is left unbuilt if a block cannot be read, so lookups scan
+    /// and report the bad block themselves.
+    fn rebuild_id_index(&mut self) {
+        self.id_index = None;
+        let locations = match self.cached_block_locations() {
+            Ok(locations) => locations,
+            Err(e) => {
+                warn!(collection = %self.name, error = ?e, "ID index unavailable, lookups will scan");
+                return;
+            }
+        };
+        
+        let mut index = IdIndex::new();
+        let mut file = None;
+        for (block_idx, (position, len)) in locations.into_iter().enumerate() {
+            match self.load_block(block_idx as u32, position, len, &mut file) {
+                Ok(block) => index_block(&mut index, block_idx as u32, &block),
+                Err(e) => {
+                    warn!(collection = %self.name, block = block_idx, error = ?e,
+                        "ID index unavailable, lookups will scan");
+                    return;
+                }
+            }
+        }
+        self.id_index = Some(index);
+    }
+    
+    /// Number of ID lookups that scanned the blocks because the ID index was
+    /// unavailable
+    pub fn id_scan_count(&self) -> u64 {
+        self.id_scans.load(Ordering::Relaxed)
+    }
+    
+    /// Number of fsyncs of the blocks file by flushes and syncs so far
+    pub fn block_fsync_count(&self) -> u64 {
+        self.block_syncs
+    }
+    
+    /// Lock the block cache
+    fn lock_cache(&self) -> Result<std::sync::MutexGuard<'_, BlockCache>> {
+        self.cache.lock()
+            .map_err(|_| Error::Other("Failed to lock block cache".into()))
+    }
+    
+    /// Get a decoded block through the cache, reading it from disk on a miss
+    ///
+    /// The file is only opened when a block actually has to be read.
+    fn load_block(&self, block_idx: u32, position: u64, len: usize, file: &mut Option<File>) -> Result<Arc<Block>> {
+        let key = (self.name.clone(), block_idx);
+        if let Some(block) = self.lock_cache()?.get(&key) {
+            return Ok(block);
+        }
+        
+        let file = match file {
+            Some(file) => file,
+            None => file.insert(File::open(&self.base_file_path)
+                .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?),
+        };
+        let block = Arc::new(self.read_indexed_block(file, block_idx, position, len)?);
+        
+        self.lock_cache()?.insert(key, Arc::clone(&block));
+        
+        Ok(block)
+    }
+    
+    /// Block cache hit and miss counters
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock()
+            .map(|cache| cache.stats())
+            .unwrap_or_default()
+    }
+    
+    /// Rewrite every stored block with the given compression
+    ///
+    /// Blocks are streamed one at a time into a temporary file which then
+    /// replaces the blocks file, so a failure leaves the original untouched.
+    /// New blocks use the target compression as well. Returns the size of the
+    /// blocks file before and after.
+    pub fn recompress(&mut self, target: CompressionType) -> Result<(u64, u64)> {
+        // Get the active block on disk so it is rewritten too
+        self.flush()?;
+        self.config.compression = target;
+        if let Some(block) = self.active_block.as_mut() {
+            block.header.compression = target;
+        }
+        
+        if !self.base_file_path.exists() {
+            return Ok((0, 0));
+        }
         
-        // Open the file
-        let mut file = File::open(path)
+        let mut file = File::open(&self.base_file_path)
             .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+        let size_before = file.metadata()
+            .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
         
-        // Calculate position in the file
-        let block = Block::new(self.config.compression);
-        let block_size = block.size() as u64;
-        let position = block_index as u64 * block_size;
+        let tmp_path = self.path.join("blocks.bin.tmp");
+        let mut tmp_file = File::create(&tmp_path)
+            .map_err(|e| Error::Other(format!("Failed to create file: {}", e)))?;
         
-        // Seek to the block
-        file.seek(SeekFrom::Start(position))
-            .map_err(|e| Error::Other(format!("Failed to seek in file: {}", e)))?;
+        for (position, len) in self.block_locations(&mut file)? {
+            let mut block = self.read_block_at(&mut file, position, len)?;
+            block.header.compression = target;
+            
+            tmp_file.write_all(&block.to_bytes_with_level(self.config.compression_level)?)
+                .map_err(|e| Error::Other(format!("Failed to write block: {}", e)))?;
+        }
         
-        // Read the block header
-        let mut header_bytes = vec![0u8; crate::BlockHeader::SIZE];
-        file.read_exact(&mut header_bytes)
-            .map_err(|e| Error::Other(format!("Failed to read header: {}", e)))?;
+        tmp_file.sync_all()
+            .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+        let size_after = tmp_file.metadata()
+            .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
+        
+        std::fs::rename(&tmp_path, &self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to replace blocks file: {}", e)))?;
         
-        // Seek to the document offset within the block
-        file.seek(SeekFrom::Start(position + crate::BlockHeader::SIZE as u64 + offset as u64))
-            .map_err(|e| Error::Other(format!("Failed to seek to document: {}", e)))?;
+        // Every block moved, so nothing cached is valid anymore
+        self.invalidate_locations();
+        self.lock_cache()?.clear();
+        self.rebuild_id_index();
         
-        // Read document ID length
-        let mut id_len_bytes = [0u8; 2];
-        file.read_exact(&mut id_len_bytes)
-            .map_err(|e| Error::Other(format!("Failed to read ID length: {}", e)))?;
-        let id_len = u16::from_le_bytes(id_len_bytes) as usize;
+        Ok((size_before, size_after))
+    }
+    
+    /// Rewrite blocks stored without a footer directory in the current block
+    /// format
+    ///
+    /// Like [`recompress`](Self::recompress), the blocks are written to a
+    /// temporary file that then replaces the blocks file. Returns the number of
+    /// blocks upgraded.
+    pub fn upgrade_legacy_blocks(&mut self) -> Result<usize> {
+        if !self.base_file_path.exists() {
+            return Ok(0);
+        }
         
-        // Skip document ID
-        file.seek(SeekFrom::Current(id_len as i64))
-            .map_err(|e| Error::Other(format!("Failed to seek past ID: {}", e)))?;
+        let mut file = File::open(&self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+        let locations = self.block_locations(&mut file)?;
         
-        // Read document data length
-        let mut data_len_bytes = [0u8; 4];
-        file.read_exact(&mut data_len_bytes)
-            .map_err(|e| Error::Other(format!("Failed to read data length: {}", e)))?;
-        let data_len = u32::from_le_bytes(data_len_bytes) as usize;
+        let mut blocks = Vec::with_capacity(locations.len());
+        for (position, len) in locations {
+            blocks.push(self.read_block_at(&mut file, position, len)?);
+        }
         
-        // Read document data
-        let mut data = vec![0u8; data_len];
-        file.read_exact(&mut data)
-            .map_err(|e| Error::Other(format!("Failed to read data: {}", e)))?;
+        let legacy = blocks.iter().filter(|block| !block.header.has_directory()).count();
+        if legacy == 0 {
+            return Ok(0);
+        }
         
-        Ok(data)
+        let tmp_path = self.path.join("blocks.bin.tmp");
+        let mut tmp_file = File::create(&tmp_path)
+            .map_err(|e| Error::Other(format!("Failed to create file: {}", e)))?;
+        
+        for block in blocks {
+            let block = block.upgraded()?;
+            tmp_file.write_all(&block.to_bytes_with_level(self.config.compression_level)?)
+                .map_err(|e| Error::Other(format!("Failed to write block: {}", e)))?;
+        }
+        
+        tmp_file.sync_all()
+            .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+        std::fs::rename(&tmp_path, &self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to replace blocks file: {}", e)))?;
+        
+        self.invalidate_locations();
+        self.lock_cache()?.clear();
+        self.rebuild_id_index();
+        
+        Ok(legacy)
     }
     
-    /// Find a document by ID
-    pub fn find_document(&self, doc_id: &[u8]) -> Result<Option<Vec<u8>>> {
+    /// Replace the tombstone documents earlier releases stored under
+    /// `_<id>_` with deletion entries for the IDs they delete
+    ///
+    /// A tombstone hid every version of its document wherever it was
+    /// written, so the deletions are appended after all other entries. Like
+    /// [`recompress`](Self::recompress), the blocks are written to a
+    /// temporary file that then replaces the blocks file. Returns the number
+    /// of tombstones replaced.
+    pub fn convert_legacy_tombstones(&mut self) -> Result<usize> {
+        self.flush()?;
+        
         if !self.base_file_path.exists() {
-            return Ok(None);
+            return Ok(0);
         }
         
-        // First, check active block if it exists
-        if let Some(block) = &self.active_block {
-            // Search the active block for the document
-            let doc_data = self.search_block_for_document(block, doc_id)?;
-            if doc_data.is_some() {
-                return Ok(doc_data);
+        let mut file = File::open(&self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
+        let mut blocks = Vec::new();
+        let mut deleted = Vec::new();
+        for (position, len) in self.block_locations(&mut file)? {
+            let block = self.read_block_at(&mut file, position, len)?;
+            let mut kept = Vec::with_capacity(block.doc_count() as usize);
+            for index in 0..block.doc_count() as usize {
+                if let Some(entry) = block.document_at(index)? {
+                    match tombstone_target(&entry.id) {
+                        Some(target) => deleted.push(DocumentEntry::deletion(target.to_vec())),
+                        None => kept.push(entry),
+                    }
+                }
+            }
+            
+            // Blocks holding only tombstones go away
+            if !kept.is_empty() {
+                let mut rewritten = Block::new(block.header.compression);
+                rewritten.header.created_at = block.header.created_at;
+                rewritten.header.commit_version = block.header.commit_version;
+                rewritten.add_documents(kept);
+                blocks.push(rewritten);
             }
         }
         
-        // Open the file
+        if deleted.is_empty() {
+            return Ok(0);
+        }
+        
+        let replaced = deleted.len();
+        let threshold = self.config.flush_threshold.max(1);
+        while !deleted.is_empty() {
+            let mut block = self.new_block();
+            block.add_documents(deleted.drain(..threshold.min(deleted.len())));
+            blocks.push(block);
+        }
+        
+        let tmp_path = self.path.join("blocks.bin.tmp");
+        let mut tmp_file = File::create(&tmp_path)
+            .map_err(|e| Error::Other(format!("Failed to create file: {}", e)))?;
+        
+        let block_count = blocks.len() as u32;
+        for block in blocks {
+            tmp_file.write_all(&block.to_bytes_with_level(self.config.compression_level)?)
+                .map_err(|e| Error::Other(format!("Failed to write block: {}", e)))?;
+        }
+        
+        tmp_file.sync_all()
+            .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+        std::fs::rename(&tmp_path, &self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to replace blocks file: {}", e)))?;
+        
+        self.invalidate_locations();
+        self.lock_cache()?.clear();
+        self.current_block_idx = block_count;
+        self.rebuild_id_index();
+        
+        Ok(replaced)
+    }
+    
+    /// Rewrite the blocks file keeping only the newest version of each live
+    /// document
+    ///
+    /// Tombstones, the documents they delete and superseded versions are
+    /// dropped, except those a live [`Snapshot`](mvcc::Snapshot) still reads.
+    /// Entries keep the order they were written in, and each rewritten block
+    /// holds writes of one commit version. Like
+    /// [`recompress`](Self::recompress), the new blocks go to a temporary file
+    /// that then replaces the blocks file.
+    pub fn compact(&mut self) -> Result<CompactionStats> {
+        self.flush()?;
+        
+        if !self.base_file_path.exists() {
+            return Ok(CompactionStats::default());
+        }
+        
         let mut file = File::open(&self.base_file_path)
             .map_err(|e| Error::Other(format!("Failed to open file: {}", e)))?;
-        
-        // Determine how many blocks are in the file
-        let file_size = file.metadata()
+        let size_before = file.metadata()
             .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
         
-        if file_size == 0 {
-            return Ok(None);
+        // Every entry, oldest first, with the commit version of its block
+        let mut entries = Vec::new();
+        for (position, len) in self.block_locations(&mut file)? {
+            let block = self.read_block_at(&mut file, position, len)?;
+            for index in 0..block.doc_count() as usize {
+                if let Some(entry) = block.document_at(index)? {
+                    entries.push((block.header.commit_version, entry));
+                }
+            }
         }
         
-        // Read each block and search for the document
-        // Start from the newest blocks (higher likelihood of finding the document)
-        let mut block_idx = self.current_block_idx;
-        while block_idx > 0 {
-            block_idx -= 1;
-            
-            // Seek to the block
-            let mock_block = Block::new(self.config.compression);
-            let block_size = mock_block.size() as u64;
-            let position = block_idx as u64 * block_size;
-            
-            if position >= file_size {
-                continue;
+        // Same rules as `scan_document_ids`: the newest entry of an ID wins,
+        // and a deletion leaves nothing to keep. An older entry stays while a
+        // live snapshot reads it: one taken from its version until the next
+        // entry of the ID.
+        let mut next_versions: HashMap<&[u8], u64> = HashMap::new();
+        let mut read = vec![false; entries.len()];
+        for (i, (commit_version, entry)) in entries.iter().enumerate().rev() {
+            read[i] = match next_versions.insert(entry.id.as_slice(), *commit_version) {
+                None => true,
+                Some(next) => mvcc::snapshot_in(*commit_version..next),
+            };
+        }
+        
+        // A deletion is kept only to hide an older document that is
+        let mut kept_ids = HashSet::new();
+        let mut live: Vec<&(u64, DocumentEntry)> = Vec::new();
+        for (entry, read) in entries.iter().zip(read) {
+            let (_, document) = entry;
+            let keep = read && (!document.deleted || kept_ids.contains(document.id.as_slice()));
+            if keep {
+                kept_ids.insert(document.id.as_slice());
+                live.push(entry);
             }
+        }
+        let documents_removed = entries.len() - live.len();
+        
+        let tmp_path = self.path.join("blocks.bin.tmp");
+        let mut tmp_file = File::create(&tmp_path)
+            .map_err(|e| Error::Other(format!("Failed to create file: {}", e)))?;
+        
+        let mut block_count = 0;
+        let mut write_block = |block: &Block| -> Result<()> {
+            tmp_file.write_all(&block.to_bytes_with_level(self.config.compression_level)?)
+                .map_err(|e| Error::Other(format!("Failed to write block: {}", e)))?;
+            block_count += 1;
+            Ok(())
+        };
+        
+        // Entries of different commit versions never share a block, so
+        // snapshot reads skip exactly the writes made after them
+        let mut block = self.new_block();
+        for (commit_version, entry) in live {
+            if block.doc_count() > 0 && block.header.commit_version != *commit_version {
+                write_block(&block)?;
+                block = self.new_block();
+            }
+            block.add_document(entry.clone())?;
+            block.header.commit_version = *commit_version;
             
-            file.seek(SeekFrom::Start(position))
-                .map_err(|e| Error::Other(format!("Failed to seek in file: {}", e)))?;
-            
-            // Read the entire block
-            let mut block_data = vec![0u8; block_size as usize];
+            let full = block.doc_count() as usize >= self.config.flush_threshold
+                || block.size() >= self.config.block_size;
+            if full {
+                write_block(&block)?;
+                block = self.new_block();
+            }
+        }
+        if block.doc_count() > 0 {
+            write_block(&block)?;
+        }
+        
+        tmp_file.sync_all()
+            .map_err(|e| Error::Other(format!("Failed to sync file: {}", e)))?;
+        let size_after = tmp_file.metadata()
+            .map_err(|e| Error::Other(format!("Failed to get metadata: {}", e)))?.len();
+        
+        std::fs::rename(&tmp_path, &self.base_file_path)
+            .map_err(|e| Error::Other(format!("Failed to replace blocks file: {}", e)))?;
+        
+        self.invalidate_locations();
+        self.lock_cache()?.clear();
+        self.current_block_idx = block_count;
+        self.active_block = Some(self.new_block());
+        self.rebuild_id_index();
+        
+        Ok(CompactionStats {
+            bytes_reclaimed: size_before.saturating_sub(size_after),
+            documents_removed,
+        })
+    }
+    
+    /// Insert a document into the block manager
+    pub fn insert(&mut self, id: &[u8], data: &[u8]) -> Result<()> {
+        self.append(std::iter::once(DocumentEntry::new(id.to_vec(), data.to_vec())))
+    }
+    
+    /// Insert several documents into the active block, checking the flush
+    /// threshold once after the last
+    ///
+    /// The whole batch lands in one block, which may therefore grow past the
+    /// flush threshold or block size.
+    pub fn insert_batch(&mut self, docs: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        
+        self.append(docs.iter().map(|(id, data)| DocumentEntry::new(id.clone(), data.clone())))
+    }
+    
+    /// Insert prepared entries, such as documents carrying metadata, as
+    /// [`insert_batch`](Self::insert_batch) does
+    pub fn insert_entries(&mut self, docs: Vec<DocumentEntry>) -> Result<()> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        
+        self.append(docs)
+    }
+    
+    /// Record the deletion of a document
+    ///
+    /// The deletion shadows earlier versions until compaction drops them
+    /// all; a later insert of the same ID is visible again.
+    pub fn delete(&mut self, id: &[u8]) -> Result<()> {
+        self.append(std::iter::once(DocumentEntry::deletion(id.to_vec())))
+    }
+    
+    /// Add documents to the active block, then flush or persist it if due
+    fn append(&mut self, docs: impl IntoIterator<Item = DocumentEntry>) -> Result<()> {
+        loop {
+            // Ensure we have an active block
+            self.ensure_active_block()?;
             
-            // Use read instead of read_exact to handle end of file gracefully
-            let bytes_read = file.read(&mut block_data)
-                .map_err(|e| Error::Other(format!("Failed to read block: {}", e)))?;
+            // Snapshots taken from now on wait until the write is applied,
+            // but not for the I/O below
+            let version = mvcc::begin_write();
             
-            if bytes_read < BlockHeader::SIZE {
+            // A block holds writes of one version, so writes made before a
+            // snapshot closed their version are committed first; their
+            // version is closed for good, so that needs no guard
+            let sealed = self.active_block.as_ref()
+                .is_some_and(|block| block.doc_count() > 0 && block.header.commit_version != *version);
+            if sealed {
+                drop(version);
+                self.flush()?;
                 continue;
             }
             
-            // Parse the block
-            let block = match Block::from_bytes(&block_data[0..bytes_read]) {
-                Ok(b) => b,
-                Err(_) => continue, // Skip invalid blocks
+            if let Some(block) = self.active_block.as_mut() {
+                block.header.commit_version = *version;
+                block.add_documents(docs);
+            }
+            break;
+        }
+        
+        self.last_write = Some(Instant::now());
+        
+        // Flush if needed
+        self.flush_if_needed()?;
+        
+        // Bound crash loss for the documents still in the active block
+        self.persist_partial_if_due()?;
+        
+        Ok(())
+    }
+    
+    /// Load the on-disk block at `block_index` through the cache
+    fn load_indexed_block(&self, block_index: u32) -> Result<Arc<Block>> {
+        let (position, len) = self.cached_block_locations()?
+            .get(block_index as usize)
+            .copied()
+            .ok_or_else(|| Error::Other(format!("Block index {} out of range", block_index)))?;
+        
+        self.load_block(block_index, position, len, &mut None)
+    }
+    
+    /// Read a document from a block
+    pub fn read_document(&self, block_index: u32, offset: usize) -> Result<Vec<u8>> {
+        // Blocks are compressed as a whole, so decode the block before
+        // reading the document at its offset
+        let block = self.load_indexed_block(block_index)?;
+        
+        if offset >= block.data.len() {
+            return Err(Error::Other(format!("Document offset {} out of range", offset)));
+        }
+        
+        let doc = DocumentEntry::decode(&block.data[offset..], offset, block.header.version)?;
+        
+        Ok(doc.data)
+    }
+    
+    /// Metadata and data of the entry at `offset` in a block, as found by the
+    /// ID index
+    fn read_stored_entry(&self, block_index: u32, offset: usize) -> Result<StoredDocument> {
+        let block = self.load_indexed_block(block_index)?;
+        let entry = block.data.get(offset..).unwrap_or_default();
+        let stored = entry.get(..2)
+            .map(|len| 2 + u16::from_le_bytes([len[0], len[1]]) as usize)
+            .and_then(|len_start| {
+                let data_len = u32::from_le_bytes(entry.get(len_start..len_start + 4)?.try_into().ok()?) as usize;
+                entry.get(len_start + 4..len_start + 4 + data_len)
+            });
+        
+        let stored = stored.ok_or_else(|| Error::Other(format!(
+            "Invalid entry at offset {} of block {}", offset, block_index)))?;
+        
+        Ok(decode_stored(stored, block.header.version)?.map(|(meta, data)| (meta, data.to_vec())))
+    }
+    
+    /// Find a document by ID
+    pub fn find_document(&self, doc_id: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.find_document_with_meta(doc_id)?.map(|(_, data)| data))
+    }
+    
+    /// Find a document by ID, along with its metadata if it was stored with any
+    ///
+    /// The newest entry of the ID decides: a deletion answers `None` without
+    /// looking at older blocks.
+    pub fn find_document_with_meta(&self, doc_id: &[u8]) -> Result<StoredDocument> {
+        // First, check active block if it exists
+        if let Some(block) = &self.active_block {
+            // Search the active block for the document
+            if let Some(doc) = self.search_block_for_document(block, doc_id)? {
+                return Ok(doc);
+            }
+        }
+        
+        // The ID index points straight at the newest flushed entry
+        if let Some(index) = &self.id_index {
+            return match index.get(doc_id) {
+                Some(&Some((block_idx, offset))) => self.read_stored_entry(block_idx, offset),
+                Some(None) | None => Ok(None),
             };
+        }
+        self.id_scans.fetch_add(1, Ordering::Relaxed);
+        
+        // Read each block and search for the document
+        // Start from the newest blocks (higher likelihood of finding the document)
+        let mut file = None;
+        for (block_idx, (position, len)) in self.cached_block_locations()?.into_iter().enumerate().rev() {
+            // A corrupt block may hold the latest version, so fail rather than
+            // fall back to an older one
+            let block = self.load_block(block_idx as u32, position, len, &mut file)?;
             
             // Search this block for the document
-            let doc_data = self.search_block_for_document(&block, doc_id)?;
-            if doc_data.is_some() {
-                return Ok(doc_data);
+            if let Some(doc) = self.search_block_for_document(&block, doc_id)? {
+                return Ok(doc);
             }
         }
         
@@ -309,8 +978,86 @@ impl BlockManager {
         Ok(None)
     }
     
-    /// Search a block for a document with the given ID
-    fn search_block_for_document(&self, block: &Block, doc_id: &[u8]) -> Result<Option<Vec<u8>>> {
+    /// Find a document by ID as it was at a snapshot
+    ///
+    /// Like [`find_document`](Self::find_document), but only blocks with a
+    /// commit version of at most `snapshot_version` count, whether flushed or
+    /// still the active block. Blocks written without a commit version are
+    /// visible at every snapshot.
+    pub fn find_document_at(&self, doc_id: &[u8], snapshot_version: u64) -> Result<Option<Vec<u8>>> {
+        let visible = |doc: StoredDocument| doc.map(|(_, data)| data);
+        
+        if let Some(block) = self.active_block.as_ref() {
+            if block.header.commit_version <= snapshot_version {
+                if let Some(doc) = self.search_block_for_document(block, doc_id)? {
+                    return Ok(visible(doc));
+                }
+            }
+        }
+        
+        let mut file = None;
+        for (block_idx, (position, len)) in self.cached_block_locations()?.into_iter().enumerate().rev() {
+            let block = self.load_block(block_idx as u32, position, len, &mut file)?;
+            if block.header.commit_version > snapshot_version {
+                continue;
+            }
+            
+            if let Some(doc) = self.search_block_for_document(&block, doc_id)? {
+                return Ok(visible(doc));
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    /// Configuration of the collection's blocks
+    pub fn config(&self) -> &StorageConfig {
+        &self.config
+    }
+    
+    /// Number of documents in the active block, not yet flushed
+    pub fn active_doc_count(&self) -> usize {
+        self.active_block.as_ref().map_or(0, |block| block.doc_count() as usize)
+    }
+    
+    /// Find several documents by ID in one pass over the blocks
+    ///
+    /// Returns the latest data of each ID, as [`find_document`](Self::find_document)
+    /// would, in the order the IDs are given. Blocks are read newest first,
+    /// through one open file, until every ID is found.
+    pub fn find_documents(&self, doc_ids: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        // `None` until the newest entry of the ID is seen, which may be a deletion
+        let mut found: HashMap<&[u8], Option<Option<Vec<u8>>>> = doc_ids.iter().map(|id| (*id, None)).collect();
+        let mut pending = found.len();
+        
+        // Within a block the latest version of a document comes last
+        let mut resolve = |block: &Block| {
+            for (id, data) in block.entries().rev() {
+                if let Some(slot @ None) = found.get_mut(id) {
+                    *slot = Some(data.map(<[u8]>::to_vec));
+                    pending -= 1;
+                }
+            }
+            pending == 0
+        };
+        
+        let done = self.active_block.as_ref().is_some_and(&mut resolve);
+        if !done {
+            let mut file = None;
+            for (block_idx, (position, len)) in self.cached_block_locations()?.into_iter().enumerate().rev() {
+                // As in `find_document`, a corrupt block may hold the latest version
+                let block = self.load_block(block_idx as u32, position, len, &mut file)?;
+                if resolve(&block) {
+                    break;
+                }
+            }
+        }
+        
+        Ok(doc_ids.iter().map(|id| found[id].clone().flatten()).collect())
+    }
+    
+    /// Search a block for the latest entry with the given ID
+    fn search_block_for_document(&self, block: &Block, doc_id: &[u8]) -> Result<Option<StoredDocument>> {
         // If the block is empty, return None
         if block.data.is_empty() {
             return Ok(None);
@@ -318,6 +1065,10 @@ impl BlockManager {
         
         let mut offset = 0;
         
+        // A document written more than once within a block has its latest
+        // version last, so keep scanning past earlier matches
+        let mut found = None;
+        
         // Iterate through document entries in the block
         while offset < block.data.len() {
             // Check if we have enough data for an ID length
@@ -363,9 +1114,7 @@ impl BlockManager {
                 }
                 
                 // Read document data
-                let data = block.data[data_len_offset + 4..data_len_offset + 4 + data_len].to_vec();
-                
-                return Ok(Some(data));
+                found = Some(&block.data[data_len_offset + 4..data_len_offset + 4 + data_len]);
             }
             
             // Move to the next document entry
@@ -383,215 +1132,589 @@ impl BlockManager {
             offset += 2 + id_len + 4 + data_len;
         }
         
-        // Document not found in this block
-        Ok(None)
+        found.map(|stored| {
+            Ok(decode_stored(stored, block.header.version)?.map(|(meta, data)| (meta, data.to_vec())))
+        }).transpose()
     }
     
     /// Scan all blocks for document IDs
+    ///
+    /// Each live document is listed once, in the order its latest version was
+    /// written. Documents whose newest entry is a deletion are left out.
     pub fn scan_document_ids(&self) -> Result<Vec<Vec<u8>>> {
-        let mut document_ids = Vec::new();
-        
-        println!("DEBUG: Scanning for document IDs in collection: {}", self.base_file_path.display());
-        
-        // First scan the active block if it exists
-        if let Some(block) = &self.active_block {
-            println!("DEBUG: Scanning active block with {} bytes of data", block.data.len());
-            let ids = self.scan_block_for_document_ids(block)?;
-            println!("DEBUG: Found {} document IDs in active block", ids.len());
-            document_ids.extend(ids);
-        } else {
-            println!("DEBUG: No active block exists");
-        }
+        self.scan_document_ids_in(ScanDirection::Forward)
+    }
+    
+    /// One page of at most `limit` live document IDs, in ID order, starting
+    /// after `after_cursor`
+    ///
+    /// Alongside the page comes the cursor for the next one, the last ID
+    /// encoded as hex, or `None` once no IDs are left. Pages follow ID order
+    /// rather than write order, so writes between pages do not shift them.
+    /// A `limit` of zero is treated as one.
+    pub fn scan_document_ids_paginated(&self, after_cursor: Option<Vec<u8>>, limit: usize) -> Result<IdPage> {
+        let limit = limit.max(1);
+        let after = after_cursor.map(|cursor| decode_cursor(&cursor)).transpose()?;
+        let start = match after.as_deref() {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
         
-        // If no block file exists, return the results from the active block
-        if !self.base_file_path.exists() {
-            println!("DEBUG: No blocks file exists at {}", self.base_file_path.display());
-            return Ok(document_ids);
+        // One more ID than asked for tells whether another page follows
+        let mut page = Vec::with_capacity(limit + 1);
+        match &self.id_index {
+            Some(index) => {
+                // Writes still in the active block override the index
+                let mut unflushed = BTreeMap::new();
+                for (id, data) in self.active_block.iter().flat_map(|block| block.entries()) {
+                    unflushed.insert(id, data.is_some());
+                }
+                
+                let mut flushed = index.range::<[u8], _>((start, Bound::Unbounded))
+                    .map(|(id, location)| (id.as_slice(), location.is_some()))
+                    .peekable();
+                let mut unflushed = unflushed.range::<[u8], _>((start, Bound::Unbounded))
+                    .map(|(id, live)| (*id, *live))
+                    .peekable();
+                while page.len() <= limit {
+                    let next = match (flushed.peek(), unflushed.peek()) {
+                        (Some(&(flushed_id, _)), Some(&(unflushed_id, _))) if flushed_id < unflushed_id => flushed.next(),
+                        (Some(&(flushed_id, _)), Some(&(unflushed_id, _))) if flushed_id == unflushed_id => {
+                            flushed.next();
+                            unflushed.next()
+                        },
+                        (_, Some(_)) => unflushed.next(),
+                        (Some(_), None) => flushed.next(),
+                        (None, None) => None,
+                    };
+                    let Some((id, live)) = next else { break };
+                    if live {
+                        page.push(id.to_vec());
+                    }
+                }
+            },
+            None => {
+                let mut ids = self.scan_document_ids()?;
+                ids.sort();
+                let past_start = |id: &Vec<u8>| after.as_ref().is_none_or(|after| id > after);
+                page.extend(ids.into_iter().filter(past_start).take(limit + 1));
+            },
         }
         
-        // Read the entire file into memory for direct inspection
-        println!("DEBUG: Reading blocks file for direct inspection");
-        let file_content = std::fs::read(&self.base_file_path)
-            .map_err(|e| Error::Other(format!("Failed to read blocks file: {}", e)))?;
-            
-        println!("DEBUG: Blocks file size: {} bytes", file_content.len());
-        
-        if file_content.is_empty() {
-            println!("DEBUG: Blocks file is empty");
-            return Ok(document_ids);
+        if page.len() > limit {
+            page.truncate(limit);
+            let cursor = page.last().map(|id| encode_cursor(id));
+            return Ok((page, cursor));
         }
-        
-        // Look for the magic number 'NBLD' that marks the beginning of a block
-        let magic = [b'N', b'B', b'L', b'D'];
-        println!("DEBUG: Searching for magic number: {:?}", magic);
-        
-        let mut position = 0;
-        while position < file_content.len() - 4 {
-            if &file_content[position..position+4] == &magic {
-                println!("DEBUG: Found block at position {}", position);
-                
-                // Extract document IDs from this block
-                self.extract_ids_from_raw_block(&file_content[position..], &mut document_ids)?;
-                
-                // Move to the next position
-                position += 4;
-            } else {
-                position += 1;
+        Ok((page, None))
+    }
+    
+    /// Like [`scan_document_ids`](Self::scan_document_ids), newest write
+    /// first when `direction` is [`ScanDirection::Reverse`]
+    pub fn scan_document_ids_in(&self, direction: ScanDirection) -> Result<Vec<Vec<u8>>> {
+        // Blocks on disk, skipping corrupt blocks so one bad block does not
+        // make the whole collection unreadable
+        let mut blocks = Vec::new();
+        let mut file = None;
+        for (block_idx, (position, len)) in self.cached_block_locations()?.into_iter().enumerate() {
+            match self.load_block(block_idx as u32, position, len, &mut file) {
+                Ok(block) => blocks.push(block),
+                Err(e) => warn!(collection = %self.name, block = block_idx, error = ?e,
+                    "skipping unreadable block in scan"),
             }
         }
         
-        println!("DEBUG: Total document IDs found: {}", document_ids.len());
-        for id in &document_ids {
-            println!("DEBUG: Document ID: {}", String::from_utf8_lossy(id));
+        // Then the active block, which holds the newest entries. Keep the
+        // newest entry of each ID unless it is a deletion; walking newest
+        // first already yields the reverse order
+        let blocks = blocks.iter().map(|block| block.as_ref()).chain(self.active_block.as_ref());
+        let entries: Vec<(&[u8], bool)> = blocks.flat_map(Block::entries)
+            .map(|(id, data)| (id, data.is_some()))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut document_ids: Vec<Vec<u8>> = entries.into_iter().rev()
+            .filter(|(id, _)| seen.insert(*id))
+            .filter(|(_, live)| *live)
+            .map(|(id, _)| id.to_vec())
+            .collect();
+        if direction == ScanDirection::Forward {
+            document_ids.reverse();
         }
         
         Ok(document_ids)
     }
+
+    /// Number of live documents, as listed by
+    /// [`scan_document_ids`](Self::scan_document_ids)
+    ///
+    /// IDs are compared in place inside the cached blocks instead of being
+    /// copied out. Corrupt blocks are skipped as in a scan.
+    pub fn count_documents(&self) -> Result<usize> {
+        self.document_counts().map(|(live, _)| live)
+    }
     
-    /// Extract document IDs from a raw block buffer starting with a magic number
-    fn extract_ids_from_raw_block(&self, block_data: &[u8], document_ids: &mut Vec<Vec<u8>>) -> Result<()> {
-        if block_data.len() < BlockHeader::SIZE {
-            println!("DEBUG: Block data too small for header");
-            return Ok(());
+    /// Number of live documents and number of distinct IDs with a deletion
+    /// entry still stored
+    pub fn document_counts(&self) -> Result<(usize, usize)> {
+        let mut blocks = Vec::new();
+        let mut file = None;
+        for (block_idx, (position, len)) in self.cached_block_locations()?.into_iter().enumerate() {
+            match self.load_block(block_idx as u32, position, len, &mut file) {
+                Ok(block) => blocks.push(block),
+                Err(e) => warn!(collection = %self.name, block = block_idx, error = ?e,
+                    "skipping unreadable block in count"),
+            }
+        }
+        
+        // Whether the newest entry of each ID stores a version
+        let blocks = blocks.iter().map(|block| block.as_ref()).chain(self.active_block.as_ref());
+        let capacity = blocks.clone().map(|block| block.header.doc_count as usize).sum();
+        let mut latest: HashMap<&[u8], bool> = HashMap::with_capacity(capacity);
+        let mut deleted: HashSet<&[u8]> = HashSet::new();
+        for (id, data) in blocks.flat_map(Block::entries) {
+            if data.is_none() {
+                deleted.insert(id);
+            }
+            latest.insert(id, data.is_some());
         }
         
-        // Skip the header and look for document entries
-        let mut offset = BlockHeader::SIZE;
+        Ok((latest.values().filter(|live| **live).count(), deleted.len()))
+    }
+    
+    /// Stream the live documents in the order their latest versions were
+    /// written, as listed by [`scan_document_ids`](Self::scan_document_ids)
+    ///
+    /// A first pass over the entry IDs finds where each live document was
+    /// last written; the documents are then read one block at a time, so
+    /// memory holds the IDs and a single block's documents rather than the
+    /// whole collection.
+    pub fn documents(&self) -> Result<DocumentIter<'_>> {
+        let mut locations: Vec<Option<BlockLocation>> = self.cached_block_locations()?.into_iter().map(Some).collect();
+        let mut latest: HashMap<Vec<u8>, (usize, usize)> = HashMap::new();
+        let mut file = None;
         
-        // Iterate through document entries in the block
-        while offset < block_data.len() - 6 {  // Minimum size for an entry (2 for ID length, 4 for data length)
-            // Read ID length
-            if offset + 2 > block_data.len() {
-                break;
+        let mut record = |block_idx: usize, block: &Block| {
+            for (entry_idx, (id, data)) in block.entries().enumerate() {
+                match data {
+                    Some(_) => { latest.insert(id.to_vec(), (block_idx, entry_idx)); },
+                    None => { latest.remove(id); },
+                }
             }
-            
-            let id_len = u16::from_le_bytes([
-                block_data[offset],
-                block_data[offset + 1],
-            ]) as usize;
-            
-            println!("DEBUG: ID length at offset {}: {}", offset, id_len);
-            
-            // Skip invalid ID lengths
-            if id_len == 0 || offset + 2 + id_len > block_data.len() {
-                offset += 1;
-                continue;
+        };
+        // Corrupt blocks are skipped as in a scan
+        for (block_idx, location) in locations.iter_mut().enumerate() {
+            let Some((position, len)) = *location else { continue };
+            match self.load_block(block_idx as u32, position, len, &mut file) {
+                Ok(block) => record(block_idx, &block),
+                Err(e) => {
+                    warn!(collection = %self.name, block = block_idx, error = ?e,
+                        "skipping unreadable block in scan");
+                    *location = None;
+                }
+            }
+        }
+        if let Some(block) = &self.active_block {
+            record(locations.len(), block);
+        }
+
+        Ok(DocumentIter {
+            manager: self,
+            locations,
+            next_block: 0,
+            latest,
+            file,
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+/// Point `index` at the entries of `block`, stored at `block_idx`; a later
+/// entry of an ID replaces an earlier one
+fn index_block(index: &mut IdIndex, block_idx: u32, block: &Block) {
+    for (id, offset, deleted) in block.entry_locations() {
+        index.insert(id.to_vec(), (!deleted).then_some((block_idx, offset)));
+    }
+}
+
+/// Pagination cursor for a page ending at `id`: the ID encoded as hex
+fn encode_cursor(id: &[u8]) -> Vec<u8> {
+    id.iter().flat_map(|byte| format!("{:02x}", byte).into_bytes()).collect()
+}
+
+/// ID a pagination cursor made by [`encode_cursor`] stands for
+fn decode_cursor(cursor: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || Error::Other(format!("Invalid cursor '{}'", String::from_utf8_lossy(cursor)));
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    cursor.chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(invalid))
+        .collect()
+}
+
+/// Iterator over the live documents of a collection, returned by
+/// [`BlockManager::documents`]
+pub struct DocumentIter<'a> {
+    manager: &'a BlockManager,
+    /// On-disk blocks, `None` for those skipped as corrupt; the active
+    /// block comes after them
+    locations: Vec<Option<BlockLocation>>,
+    /// Index of the next block to read
+    next_block: usize,
+    /// Block and entry index of the latest version of each live document not
+    /// yet returned
+    latest: HashMap<Vec<u8>, (usize, usize)>,
+    file: Option<File>,
+    /// Documents of the current block still to return
+    pending: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl DocumentIter<'_> {
+    /// Queue the latest versions held by a block
+    fn queue(&mut self, block_idx: usize, block: &Block) {
+        for (entry_idx, (id, data)) in block.entries().enumerate() {
+            if self.latest.get(id) == Some(&(block_idx, entry_idx)) {
+                self.latest.remove(id);
+                self.pending.push_back((id.to_vec(), data.unwrap_or_default().to_vec()));
+            }
+        }
+    }
+}
+
+impl Iterator for DocumentIter<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(document) = self.pending.pop_front() {
+                return Some(Ok(document));
+            }
+            if self.latest.is_empty() || self.next_block > self.locations.len() {
+                return None;
             }
             
-            // Read ID
-            let entry_id = block_data[offset + 2..offset + 2 + id_len].to_vec();
-            
-            // Validate that this looks like a document ID
-            let id_str = String::from_utf8_lossy(&entry_id);
-            println!("DEBUG: Found ID candidate: {} at offset {}", id_str, offset);
-            
-            // Add to our list if it's not a tombstone ID (doesn't start and end with underscore)
-            if !entry_id.is_empty() && !(entry_id.starts_with(b"_") && entry_id.ends_with(b"_")) {
-                // Check if the ID contains valid characters
-                let is_valid = entry_id.iter().all(|&b| 
-                    b.is_ascii_alphanumeric() || b.is_ascii_punctuation() || b.is_ascii_whitespace());
-                
-                if is_valid {
-                    println!("DEBUG: Adding valid document ID: {}", id_str);
-                    document_ids.push(entry_id);
-                    
-                    // Move past this entry
-                    if offset + 2 + id_len + 4 <= block_data.len() {
-                        let data_len = u32::from_le_bytes([
-                            block_data[offset + 2 + id_len],
-                            block_data[offset + 2 + id_len + 1],
-                            block_data[offset + 2 + id_len + 2],
-                            block_data[offset + 2 + id_len + 3],
-                        ]) as usize;
-                        
-                        offset += 2 + id_len + 4 + data_len;
-                    } else {
-                        // Can't read data length, move forward
-                        offset += 2 + id_len;
+            let block_idx = self.next_block;
+            self.next_block += 1;
+            match self.locations.get(block_idx).copied() {
+                Some(Some((position, len))) => {
+                    let block = match self.manager.load_block(block_idx as u32, position, len, &mut self.file) {
+                        Ok(block) => block,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    self.queue(block_idx, &block);
+                }
+                Some(None) => {}
+                None => {
+                    if let Some(block) = &self.manager.active_block {
+                        self.queue(block_idx, block);
                     }
-                } else {
-                    // Not a valid ID, move forward one byte
-                    offset += 1;
                 }
-            } else {
-                // Move forward one byte
-                offset += 1;
             }
         }
-        
-        Ok(())
     }
+}
 
-    /// Scan a block for all document IDs
-    fn scan_block_for_document_ids(&self, block: &Block) -> Result<Vec<Vec<u8>>> {
-        let mut document_ids = Vec::new();
-        
-        // If the block is empty, return empty list
-        if block.data.is_empty() {
-            println!("DEBUG: Block data is empty");
-            return Ok(document_ids);
+/// Read the header of the block at `position` in a blocks file of
+/// `file_size` bytes
+fn read_header_at(file: &mut File, position: u64, file_size: u64) -> Result<BlockHeader> {
+    let available = file_size.saturating_sub(position).min(BlockHeader::MAX_SIZE as u64) as usize;
+    let mut header_bytes = [0u8; BlockHeader::MAX_SIZE];
+    
+    file.seek(SeekFrom::Start(position))
+        .map_err(|e| Error::Other(format!("Failed to seek in file: {}", e)))?;
+    file.read_exact(&mut header_bytes[..available])
+        .map_err(|e| Error::Other(format!("Failed to read header: {}", e)))?;
+    
+    BlockHeader::from_bytes(&header_bytes[..available])
+}
+
+/// The document a legacy tombstone ID (`_<id>_`) deletes, or `None` for
+/// other IDs
+fn tombstone_target(id: &[u8]) -> Option<&[u8]> {
+    id.strip_prefix(b"_")?.strip_suffix(b"_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> StorageConfig {
+        StorageConfig {
+            compression: CompressionType::None,
+            ..StorageConfig::default()
         }
-        
-        println!("DEBUG: Scanning block with {} bytes of data", block.data.len());
-        
-        let mut offset = 0;
-        
-        // Iterate through document entries in the block
-        while offset < block.data.len() {
-            // Check if we have enough data for an ID length
-            if offset + 2 > block.data.len() {
-                println!("DEBUG: Not enough data for ID length at offset {}", offset);
-                break;
-            }
-            
-            // Read ID length
-            let id_len = u16::from_le_bytes([
-                block.data[offset],
-                block.data[offset + 1],
-            ]) as usize;
-            
-            println!("DEBUG: ID length at offset {}: {}", offset, id_len);
-            
-            // Check if we have enough data for the ID
-            if offset + 2 + id_len > block.data.len() {
-                println!("DEBUG: Not enough data for ID at offset {}", offset);
-                break;
+    }
+
+    #[test]
+    fn test_partial_block_survives_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.partial_block_interval = Some(Duration::ZERO);
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config.clone()).unwrap();
+        manager.insert(b"doc1", br#"{"a":1}"#).unwrap();
+        manager.insert(b"doc2", br#"{"a":2}"#).unwrap();
+
+        // Simulate a crash: the manager goes away without flushing
+        drop(manager);
+        assert!(!dir.path().join("blocks.bin").exists());
+        assert!(dir.path().join("partial.bin").exists());
+
+        let manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        assert_eq!(manager.find_document(b"doc1").unwrap(), Some(br#"{"a":1}"#.to_vec()));
+        assert_eq!(manager.find_document(b"doc2").unwrap(), Some(br#"{"a":2}"#.to_vec()));
+    }
+
+    #[test]
+    fn test_active_block_lost_without_partial_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config();
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config.clone()).unwrap();
+        manager.insert(b"doc1", br#"{"a":1}"#).unwrap();
+        drop(manager);
+
+        let manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        assert_eq!(manager.find_document(b"doc1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_flush_removes_partial_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.partial_block_interval = Some(Duration::ZERO);
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config.clone()).unwrap();
+        manager.insert(b"doc1", br#"{"a":1}"#).unwrap();
+        let partial_path = dir.path().join("partial.bin");
+        let partial_bytes = std::fs::read(&partial_path).unwrap();
+
+        manager.flush().unwrap();
+        assert!(!partial_path.exists());
+
+        // Simulate a crash between the flush and the partial block cleanup
+        std::fs::write(&partial_path, partial_bytes).unwrap();
+        let manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        assert!(manager.active_block.is_none());
+        assert!(!partial_path.exists());
+    }
+
+    #[test]
+    fn test_corrupt_block_reported_and_skipped_by_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config();
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config.clone()).unwrap();
+        manager.insert(b"doc1", br#"{"a":1}"#).unwrap();
+        manager.flush().unwrap();
+        manager.insert(b"doc2", br#"{"a":2}"#).unwrap();
+        manager.flush().unwrap();
+        drop(manager);
+
+        // Flip a byte in the first block's data
+        let path = dir.path().join("blocks.bin");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[BlockHeader::SIZE + 4] ^= 0x01;
+        std::fs::write(&path, bytes).unwrap();
+
+        let manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        match manager.find_document(b"doc1") {
+            Err(Error::Other(msg)) => assert_eq!(msg, "corrupt block at index 0"),
+            other => panic!("expected corrupt block error, got {:?}", other),
+        }
+        assert_eq!(manager.find_document(b"doc2").unwrap(), Some(br#"{"a":2}"#.to_vec()));
+        assert_eq!(manager.scan_document_ids().unwrap(), vec![b"doc2".to_vec()]);
+    }
+
+    #[test]
+    fn test_next_block_idx_counts_non_empty_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config();
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config.clone()).unwrap();
+        for block in 0..3 {
+            for i in 0..(block + 1) * 10 {
+                let doc = format!(r#"{{"block":{},"i":{}}}"#, block, i);
+                manager.insert(format!("doc{}_{}", block, i).as_bytes(), doc.as_bytes()).unwrap();
             }
-            
-            // Read ID
-            let entry_id = block.data[offset + 2..offset + 2 + id_len].to_vec();
-            let id_str = String::from_utf8_lossy(&entry_id);
-            println!("DEBUG: Found ID: {} at offset {}", id_str, offset);
-            
-            // Add to our list if it's not a tombstone ID (doesn't start and end with underscore)
-            if !(entry_id.starts_with(b"_") && entry_id.ends_with(b"_")) {
-                println!("DEBUG: Adding regular document ID: {}", id_str);
-                document_ids.push(entry_id);
-            } else {
-                println!("DEBUG: Skipping tombstone ID: {}", id_str);
+            manager.flush().unwrap();
+        }
+
+        assert_eq!(manager.find_next_block_idx().unwrap(), 3);
+        let manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        assert_eq!(manager.current_block_idx, 3);
+    }
+
+    #[test]
+    fn test_reads_document_in_fifth_variable_size_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), test_config()).unwrap();
+
+        // Blocks of 1, 3, 9, 27 and 81 documents with growing payloads
+        for block in 0..5u32 {
+            for i in 0..3usize.pow(block) {
+                let doc = format!(r#"{{"block":{},"pad":"{}"}}"#, block, "x".repeat(i * 7));
+                manager.insert(format!("doc{}_{}", block, i).as_bytes(), doc.as_bytes()).unwrap();
             }
-            
-            // Move to the next document entry
-            if offset + 2 + id_len + 4 > block.data.len() {
-                println!("DEBUG: Not enough data for data length at offset {}", offset);
-                break;
+            manager.flush().unwrap();
+        }
+
+        let expected = format!(r#"{{"block":4,"pad":"{}"}}"#, "x".repeat(40 * 7));
+        assert_eq!(manager.find_document(b"doc4_40").unwrap(), Some(expected.into_bytes()));
+        assert_eq!(manager.read_document(4, 0).unwrap(), br#"{"block":4,"pad":""}"#.to_vec());
+        assert_eq!(manager.read_document(0, 0).unwrap(), br#"{"block":0,"pad":""}"#.to_vec());
+        assert!(manager.read_document(5, 0).is_err());
+    }
+
+    #[test]
+    fn test_flush_threshold_counts_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config();
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        for i in 0..1001 {
+            let doc = format!(r#"{{"_id":"doc{}","value":{}}}"#, i, i);
+            manager.insert(format!("doc{}", i).as_bytes(), doc.as_bytes()).unwrap();
+        }
+        manager.flush().unwrap();
+
+        let headers = manager.block_headers().unwrap();
+        assert_eq!(headers.iter().map(|h| h.doc_count).collect::<Vec<_>>(), vec![1000, 1]);
+    }
+
+    #[test]
+    fn test_insert_batch_flushes_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config();
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        let docs: Vec<(Vec<u8>, Vec<u8>)> = (0..1500)
+            .map(|i| (format!("doc{}", i).into_bytes(), format!(r#"{{"value":{}}}"#, i).into_bytes()))
+            .collect();
+        manager.insert_batch(&docs[..500]).unwrap();
+        assert!(manager.block_headers().unwrap().is_empty());
+
+        // Crossing the threshold mid-batch still flushes only at the end
+        manager.insert_batch(&docs[500..]).unwrap();
+        let headers = manager.block_headers().unwrap();
+        assert_eq!(headers.iter().map(|h| h.doc_count).collect::<Vec<_>>(), vec![1500]);
+        assert_eq!(manager.find_document(b"doc1499").unwrap(), Some(br#"{"value":1499}"#.to_vec()));
+        assert_eq!(manager.count_documents().unwrap(), 1500);
+    }
+
+    #[test]
+    fn test_block_size_caps_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            block_size: 1024,
+            ..test_config()
+        };
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        for i in 0..10 {
+            manager.insert(format!("doc{}", i).as_bytes(), &[b'x'; 300]).unwrap();
+        }
+
+        // Each block is flushed as soon as it crosses 1024 bytes
+        let headers = manager.block_headers().unwrap();
+        assert_eq!(headers.iter().map(|h| h.doc_count).collect::<Vec<_>>(), vec![4, 4]);
+    }
+
+    #[test]
+    fn test_block_cache_serves_repeated_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            compression: CompressionType::Zstd,
+            ..StorageConfig::default()
+        };
+
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        for i in 0..5000 {
+            let doc = format!(r#"{{"_id":"doc{}","value":{}}}"#, i, i);
+            manager.insert(format!("doc{}", i).as_bytes(), doc.as_bytes()).unwrap();
+        }
+        manager.flush().unwrap();
+
+        for _ in 0..10 {
+            for i in 4900..5000 {
+                let doc = manager.find_document(format!("doc{}", i).as_bytes()).unwrap();
+                assert_eq!(doc, Some(format!(r#"{{"_id":"doc{}","value":{}}}"#, i, i).into_bytes()));
             }
-            
-            let data_len = u32::from_le_bytes([
-                block.data[offset + 2 + id_len],
-                block.data[offset + 2 + id_len + 1],
-                block.data[offset + 2 + id_len + 2],
-                block.data[offset + 2 + id_len + 3],
-            ]) as usize;
-            
-            println!("DEBUG: Data length: {} at offset {}", data_len, offset + 2 + id_len);
-            
-            offset += 2 + id_len + 4 + data_len;
-            println!("DEBUG: New offset: {}", offset);
         }
-        
-        println!("DEBUG: Found {} document IDs in block", document_ids.len());
-        
-        Ok(document_ids)
+
+        let stats = manager.cache_stats();
+        assert!(stats.hits > stats.misses, "{:?}", stats);
+    }
+
+    #[test]
+    fn test_paginated_scan_visits_each_document_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig { flush_threshold: 100, ..test_config() };
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), config).unwrap();
+        for i in 0..10_000 {
+            manager.insert(format!("doc{:05}", i).as_bytes(), b"{}").unwrap();
+        }
+        // Deleted on disk, deleted while unflushed, and written twice
+        manager.delete(b"doc00010").unwrap();
+        manager.flush().unwrap();
+        manager.delete(b"doc00020").unwrap();
+        manager.insert(b"doc00030", b"{}").unwrap();
+        manager.insert(b"extra", b"{}").unwrap();
+
+        let mut expected = manager.scan_document_ids().unwrap();
+        expected.sort();
+        assert_eq!(expected.len(), 9_999);
+
+        let page_through = |manager: &BlockManager| {
+            let mut seen = Vec::new();
+            let mut cursor = None;
+            loop {
+                let (page, next) = manager.scan_document_ids_paginated(cursor, 333).unwrap();
+                assert!(page.len() == 333 || next.is_none());
+                seen.extend(page);
+                match next {
+                    Some(next) => cursor = Some(next),
+                    None => return seen,
+                }
+            }
+        };
+        assert_eq!(page_through(&manager), expected);
+
+        // Without the ID index the pages come from a full scan
+        manager.id_index = None;
+        assert_eq!(page_through(&manager), expected);
+
+        let (page, next) = manager.scan_document_ids_paginated(Some(b"646f633039393938".to_vec()), 5).unwrap();
+        assert_eq!(page, vec![b"doc09999".to_vec(), b"extra".to_vec()]);
+        assert!(next.is_none());
+        assert!(manager.scan_document_ids_paginated(Some(b"xyz".to_vec()), 5).is_err());
+    }
+    #[test]
+    fn test_document_stored_as_deletion_byte_is_not_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), test_config()).unwrap();
+        manager.insert(b"raw", &[0xFE]).unwrap();
+        assert_eq!(manager.find_document(b"raw").unwrap(), Some(vec![0xFE]));
+
+        manager.flush().unwrap();
+        assert_eq!(manager.find_document(b"raw").unwrap(), Some(vec![0xFE]));
+        assert_eq!(manager.scan_document_ids().unwrap(), vec![b"raw".to_vec()]);
+
+        manager.id_index = None;
+        assert_eq!(manager.find_document(b"raw").unwrap(), Some(vec![0xFE]));
+        assert_eq!(manager.find_documents(&[b"raw"]).unwrap(), vec![Some(vec![0xFE])]);
+    }
+
+    #[test]
+    fn test_document_starting_with_meta_marker_keeps_its_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = BlockManager::open("test", dir.path().to_path_buf(), test_config()).unwrap();
+        let raw: Vec<u8> = std::iter::once(0xFF).chain(0..40).collect();
+        manager.insert(b"raw", &raw).unwrap();
+        assert_eq!(manager.find_document_with_meta(b"raw").unwrap(), Some((None, raw.clone())));
+
+        manager.flush().unwrap();
+        assert_eq!(manager.find_document_with_meta(b"raw").unwrap(), Some((None, raw.clone())));
+        assert_eq!(manager.documents().unwrap().next().unwrap().unwrap(), (b"raw".to_vec(), raw));
     }
 }
@@ -0,0 +1,52 @@
+//! Per-collection document counters kept in a `meta.json` sidecar
+
+use std::fs;
+use std::path::Path;
+
+use nebuladb_core::{Error, Result};
+use serde_json::{json, Value as JsonValue};
+
+/// File name of the sidecar inside a collection directory
+pub const META_FILE: &str = "meta.json";
+
+/// Document counters of a collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectionMeta {
+    /// Number of live documents
+    pub doc_count: u64,
+    /// Number of deleted documents whose deletion entries await compaction
+    pub deleted_doc_count: u64,
+    /// Whether the counters were saved by a clean close; writes lost in a
+    /// crash can leave the counters of an unclean file wrong
+    pub clean: bool,
+}
+
+impl CollectionMeta {
+    /// Read the sidecar at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(Error::IoError)?;
+        let value: JsonValue = serde_json::from_str(&text)
+            .map_err(|e| Error::Other(format!("Invalid collection metadata {:?}: {}", path, e)))?;
+        let counter = |name: &str| value.get(name).and_then(JsonValue::as_u64)
+            .ok_or_else(|| Error::Other(format!("Collection metadata {:?} lacks '{}'", path, name)));
+
+        Ok(Self {
+            doc_count: counter("doc_count")?,
+            deleted_doc_count: counter("deleted_doc_count")?,
+            clean: value.get("clean").and_then(JsonValue::as_bool).unwrap_or(false),
+        })
+    }
+
+    /// Replace the sidecar at `path` atomically
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let value = json!({
+            "doc_count": self.doc_count,
+            "deleted_doc_count": self.deleted_doc_count,
+            "clean": self.clean,
+        });
+
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, value.to_string()).map_err(Error::IoError)?;
+        fs::rename(&tmp_path, path).map_err(Error::IoError)
+    }
+}
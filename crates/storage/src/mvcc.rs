@@ -0,0 +1,95 @@
+//! Commit versions for snapshot reads
+//!
+//! Every write is tagged with the open version of one process-wide counter,
+//! and a block only holds writes of one version, recorded in its header.
+//! Taking a snapshot closes the open version: the reader gets it back and
+//! later writes are tagged with the next one. Reads at the snapshot skip
+//! blocks with a newer version, flushed or not, so they stay repeatable
+//! without the reader holding a lock or forcing a flush.
+//!
+//! A writer holds the open version until its write is applied, and closing
+//! the version waits for those writers. The version a snapshot returns is
+//! therefore a watermark: every write tagged with it or below is already
+//! visible.
+//!
+//! Snapshots are registered until dropped, so compaction keeps the versions
+//! of documents they still read.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+
+/// Version tagging the writes made now; 0 is left for blocks written before
+/// versions were recorded
+static OPEN_VERSION: RwLock<u64> = RwLock::new(1);
+
+/// Versions of the snapshots not dropped yet, with how many hold each
+static LIVE_SNAPSHOTS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+/// A point-in-time view of every collection, read at its [version](Self::version)
+///
+/// Compaction keeps what the snapshot reads until it is dropped.
+#[derive(Debug)]
+pub struct Snapshot {
+    version: u64,
+}
+
+impl Snapshot {
+    /// Commit version to read the snapshot at
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut live = LIVE_SNAPSHOTS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = live.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                live.remove(&self.version);
+            }
+        }
+    }
+}
+
+/// Hold the open version while applying a write tagged with it
+pub(crate) fn begin_write() -> RwLockReadGuard<'static, u64> {
+    OPEN_VERSION.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Close the open version and take a snapshot at it
+///
+/// Waits for writes still being applied with that version. Every write
+/// applied before this returns is visible at the snapshot's version, and no
+/// later one is.
+pub fn snapshot() -> Snapshot {
+    let mut open = OPEN_VERSION.write().unwrap_or_else(|e| e.into_inner());
+    let version = *open;
+    *open += 1;
+
+    // Registered before any write of the next version, so a compaction that
+    // sees such a write also sees the snapshot
+    *LIVE_SNAPSHOTS.lock().unwrap_or_else(|e| e.into_inner()).entry(version).or_default() += 1;
+    Snapshot { version }
+}
+
+/// Whether a snapshot not dropped yet was taken at a version in `versions`
+pub(crate) fn snapshot_in(versions: Range<u64>) -> bool {
+    !versions.is_empty() && LIVE_SNAPSHOTS.lock().unwrap_or_else(|e| e.into_inner()).range(versions).next().is_some()
+}
+
+/// Make sure writes from now on are tagged at least `version`, one found in
+/// an existing blocks file
+///
+/// Tagging new writes with the version of blocks already on disk is fine:
+/// those are visible at every snapshot that version can be returned by.
+pub(crate) fn observe(version: u64) {
+    if *begin_write() >= version {
+        return;
+    }
+
+    let mut open = OPEN_VERSION.write().unwrap_or_else(|e| e.into_inner());
+    *open = (*open).max(version);
+}
+
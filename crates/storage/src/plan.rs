@@ -0,0 +1,65 @@
+//! Choosing how a collection answers a query
+
+use std::fmt;
+
+use nebuladb_query::Query;
+use serde_json::Value as JsonValue;
+
+/// How [`Collection::find`](crate::collection::Collection::find) gathers the
+/// candidate documents for a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryPlan {
+    /// Every document is read and tested
+    FullScan,
+    /// Documents whose `field` equals `value`, from the index over `field`
+    Index { field: String, value: JsonValue },
+    /// Documents whose leading `fields` equal `prefix`, from a compound index
+    CompoundIndex { fields: Vec<String>, prefix: Vec<JsonValue> },
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryPlan::FullScan => write!(f, "full collection scan"),
+            QueryPlan::Index { field, value } => write!(f, "index on {} ({} = {})", field, field, value),
+            QueryPlan::CompoundIndex { fields, prefix } => {
+                let conditions: Vec<String> = fields.iter().zip(prefix)
+                    .map(|(field, value)| format!("{} = {}", field, value))
+                    .collect();
+                write!(f, "compound index on ({}) ({})", fields.join(", "), conditions.join(", "))
+            }
+        }
+    }
+}
+
+/// The field equalities every match of `query` must satisfy
+///
+/// Only top-level conditions and those nested in `$and` count; anything under
+/// `$or` may be skipped by a match.
+pub(crate) fn equalities(query: &Query) -> Vec<(&str, &JsonValue)> {
+    match query {
+        Query::Eq { field, value } => vec![(field.as_str(), value)],
+        Query::And(queries) => queries.iter().flat_map(equalities).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_equalities_skip_or() {
+        let query = Query::from_json(&json!({
+            "a": 1,
+            "b": {"$gt": 2},
+            "$and": [{"c": "x"}],
+            "$or": [{"d": 4}],
+        })).unwrap();
+
+        let mut found = equalities(&query);
+        found.sort_by_key(|(field, _)| *field);
+        assert_eq!(found, vec![("a", &json!(1)), ("c", &json!("x"))]);
+    }
+}
@@ -3,10 +3,15 @@
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use nebuladb_core::{Result, Error};
+use nebuladb_query::{FindOptions, Projection, Query, QueryConfig};
+use nebuladb_wal::manager::SharedWalManager;
+use serde_json::Value as JsonValue;
 
-use crate::{Collection, StorageConfig};
+use crate::collection::Collection;
+use crate::StorageConfig;
 
 /// Storage engine for NebulaDB
 #[derive(Debug)]
@@ -17,6 +22,8 @@ pub struct Storage {
     pub config: StorageConfig,
     /// Open collections
     collections: HashMap<String, Collection>,
+    /// Write-ahead log the collections record their writes in, if any
+    wal: Option<SharedWalManager>,
 }
 
 impl Storage {
@@ -24,7 +31,7 @@ impl Storage {
     pub fn open(path: &Path, config: Option<StorageConfig>) -> Result<Self> {
         // Create directory if it doesn't exist
         if !path.exists() {
-            fs::create_dir_all(path)?;
+            fs::create_dir_all(path).map_err(Error::IoError)?;
         }
         
         let config = config.unwrap_or_default();
@@ -33,16 +40,31 @@ impl Storage {
             path: path.to_owned(),
             config,
             collections: HashMap::new(),
+            wal: None,
         })
     }
     
+    /// Open a storage engine whose collections log their writes to `wal`
+    ///
+    /// Each collection replays the committed entries logged since its last
+    /// checkpoint when it is first opened, restoring writes a crash kept out
+    /// of its blocks.
+    pub fn open_with_wal(path: &Path, config: Option<StorageConfig>, wal: SharedWalManager) -> Result<Self> {
+        let mut storage = Self::open(path, config)?;
+        storage.wal = Some(wal);
+        Ok(storage)
+    }
+    
     /// Open or create a collection
     pub fn open_collection(&mut self, name: &str) -> Result<&mut Collection> {
         if self.collections.contains_key(name) {
             return Ok(self.collections.get_mut(name).unwrap());
         }
         
-        let collection = Collection::open(name, &self.path, &self.config)?;
+        let collection = match &self.wal {
+            Some(wal) => Collection::open_with_wal(name, &self.path, &self.config, Arc::clone(wal))?,
+            None => Collection::open(name, &self.path, &self.config)?,
+        };
         self.collections.insert(name.to_string(), collection);
         
         Ok(self.collections.get_mut(name).unwrap())
@@ -65,12 +87,43 @@ impl Storage {
         // Delete the collection directory
         let path = self.path.join(name);
         if path.exists() {
-            fs::remove_dir_all(path)?;
+            fs::remove_dir_all(path).map_err(Error::IoError)?;
         }
         
         Ok(())
     }
     
+    /// Find the documents in a collection matching `query`
+    ///
+    /// Returns each match's ID with its document, sorted and paged by
+    /// `options` if given. Documents that are not valid JSON are skipped.
+    pub fn find_documents(
+        &mut self,
+        collection: &str,
+        query: &Query,
+        options: Option<FindOptions>,
+    ) -> Result<Vec<(Vec<u8>, JsonValue)>> {
+        self.open_collection(collection)?
+            .find_with_options(query, &options.unwrap_or_default(), &QueryConfig::default())
+    }
+    
+    /// Find the documents in a collection matching `query`, keeping only the
+    /// fields selected by `projection`
+    ///
+    /// Returns each match's ID with its projected document. Documents that are
+    /// not valid JSON are skipped.
+    pub fn find_documents_projected(
+        &mut self,
+        collection: &str,
+        query: &Query,
+        projection: &Projection,
+    ) -> Result<Vec<(Vec<u8>, JsonValue)>> {
+        let matches = self.open_collection(collection)?.find(query, &QueryConfig::default())?;
+        Ok(matches.into_iter()
+            .map(|(id, doc)| (id, projection.apply(&doc)))
+            .collect())
+    }
+    
     /// Close the storage engine
     pub fn close(&mut self) -> Result<()> {
         // Close all collections
@@ -81,4 +134,83 @@ impl Storage {
         
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn storage_with_users(dir: &Path) -> Storage {
+        let mut storage = Storage::open(dir, None).unwrap();
+        let users = storage.open_collection("users").unwrap();
+        users.insert(b"u1", br#"{"_id":"u1","name":"Ada","age":36,"bio":"math"}"#).unwrap();
+        users.insert(b"u2", br#"{"_id":"u2","name":"Alan","age":41,"bio":"logic"}"#).unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_find_documents_sorted_and_paged() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = storage_with_users(dir.path());
+        storage.open_collection("users").unwrap()
+            .insert(b"u3", br#"{"_id":"u3","name":"Grace","age":9}"#).unwrap();
+        let all = Query::from_json(&json!({})).unwrap();
+
+        let options = FindOptions { sort_field: Some("age".into()), ..FindOptions::default() };
+        let found = storage.find_documents("users", &all, Some(options)).unwrap();
+        let ids: Vec<_> = found.iter().map(|(id, _)| id.as_slice()).collect();
+        assert_eq!(ids, vec![&b"u3"[..], b"u1", b"u2"]);
+
+        let options = FindOptions {
+            sort_field: Some("name".into()),
+            sort_asc: false,
+            limit: Some(1),
+            skip: 1,
+        };
+        let found = storage.find_documents("users", &all, Some(options)).unwrap();
+        assert_eq!(found[0].1["name"], json!("Alan"));
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn test_find_documents_pages_past_max_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = Storage::open(dir.path(), None).unwrap();
+        let max_results = QueryConfig::default().max_results;
+        let users = storage.open_collection("users").unwrap();
+        for i in 0..max_results + 200 {
+            users.insert(format!("u{}", i).as_bytes(), format!(r#"{{"age":{}}}"#, i).as_bytes()).unwrap();
+        }
+        let all = Query::from_json(&json!({})).unwrap();
+        assert!(storage.find_documents("users", &all, None).is_err());
+        
+        let options = FindOptions { limit: Some(10), skip: max_results + 150, ..FindOptions::default() };
+        let found = storage.find_documents("users", &all, Some(options)).unwrap();
+        assert_eq!(found.len(), 10);
+        assert_eq!(found[0].1["age"], json!(max_results + 150));
+        
+        let options = FindOptions { sort_field: Some("age".into()), sort_asc: false, limit: Some(3), skip: 0 };
+        let found = storage.find_documents("users", &all, Some(options)).unwrap();
+        let ages: Vec<_> = found.iter().map(|(_, doc)| doc["age"].clone()).collect();
+        assert_eq!(ages, vec![json!(max_results + 199), json!(max_results + 198), json!(max_results + 197)]);
+    }
+        
+    #[test]
+    fn test_find_documents_projected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = storage_with_users(dir.path());
+        let query = Query::from_json(&json!({"age": {"$gt": 40}})).unwrap();
+
+        let found = storage.find_documents_projected("users", &query, &Projection::include(["name"])).unwrap();
+        assert_eq!(found, vec![(b"u2".to_vec(), json!({"_id": "u2", "name": "Alan"}))]);
+
+        let all = Query::from_json(&json!({})).unwrap();
+        let found = storage.find_documents_projected("users", &all, &Projection::exclude(["bio"])).unwrap();
+        assert_eq!(found.len(), 2);
+        for (_, doc) in &found {
+            assert!(doc.get("bio").is_none());
+            assert!(doc.get("name").is_some());
+        }
+    }
+}
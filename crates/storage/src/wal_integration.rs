@@ -44,6 +44,11 @@ impl DatabaseStore {
         self.start_time.elapsed().as_secs()
     }
     
+    /// Get the WAL configuration
+    pub fn wal_config(&self) -> &WalConfig {
+        &self.wal_config
+    }
+    
     /// Get the number of collections
     pub fn collection_count(&self) -> usize {
         self.collections.len()
@@ -0,0 +1,37 @@
+//! Bulk loading documents into a collection
+
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::StorageConfig;
+
+fn documents(range: std::ops::Range<usize>) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> {
+    range.map(|i| (format!("user{:05}", i).into_bytes(), format!(r#"{{"n":{}}}"#, i).into_bytes()))
+}
+
+#[test]
+fn test_bulk_inserted_documents_are_retrievable() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        flush_threshold: 100,
+        ..StorageConfig::default()
+    };
+
+    let mut collection = Collection::open("users", dir.path(), &config).unwrap();
+    assert_eq!(collection.bulk_insert(documents(0..1050)).unwrap(), 1050);
+    assert_eq!(collection.bulk_insert_ordered(documents(1050..1200)).unwrap(), 150);
+    // Rewriting existing IDs through the checked path does not inflate the count
+    assert_eq!(collection.bulk_insert(documents(0..10)).unwrap(), 10);
+
+    // One block per batch of the flush threshold
+    let headers = collection.block_manager.block_headers().unwrap();
+    assert!(headers.iter().all(|header| header.doc_count <= 100));
+    assert_eq!(collection.stats().unwrap().doc_count, 1200);
+
+    collection.close().unwrap();
+    drop(collection);
+
+    let collection = Collection::open("users", dir.path(), &config).unwrap();
+    assert_eq!(collection.count().unwrap(), 1200);
+    for (id, data) in documents(0..1200) {
+        assert_eq!(collection.get(&id).unwrap(), Some(data));
+    }
+}
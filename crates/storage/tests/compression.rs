@@ -0,0 +1,69 @@
+//! Compression round-trip tests through a collection on disk
+
+use nebuladb_storage::block::BlockOperations;
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::{Block, BlockHeader, CompressionType, StorageConfig};
+
+fn document(i: usize) -> String {
+    format!(
+        r#"{{"_id":"user{}","name":"User {}","email":"user{}@example.com","active":true,"tags":["alpha","beta"]}}"#,
+        i, i, i
+    )
+}
+
+#[test]
+fn test_zstd_documents_round_trip_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        compression: CompressionType::Zstd,
+        ..StorageConfig::default()
+    };
+
+    let mut raw_bytes = 0;
+    let mut collection = Collection::open("users", dir.path(), &config).unwrap();
+    for i in 0..1000 {
+        let doc = document(i);
+        raw_bytes += doc.len();
+        collection.insert(format!("user{}", i).as_bytes(), doc.as_bytes()).unwrap();
+    }
+    collection.close().unwrap();
+    drop(collection);
+
+    let collection = Collection::open("users", dir.path(), &config).unwrap();
+    for i in 0..1000 {
+        let data = collection.get(format!("user{}", i).as_bytes()).unwrap();
+        assert_eq!(data, Some(document(i).into_bytes()));
+    }
+    assert_eq!(collection.scan().unwrap().len(), 1000);
+
+    let file_bytes = std::fs::read(dir.path().join("users").join("blocks.bin")).unwrap();
+    assert!(file_bytes.len() < raw_bytes, "{} bytes on disk for {} raw bytes", file_bytes.len(), raw_bytes);
+
+    let header = BlockHeader::from_bytes(&file_bytes).unwrap();
+    let block_len = header.size() + header.stored_size() + header.footer_size();
+    let block = Block::from_bytes(&file_bytes[..block_len]).unwrap();
+    assert_eq!(block.header.compression, CompressionType::Zstd);
+    assert_ne!(block.header.compressed_size, block.header.uncompressed_size);
+}
+
+#[test]
+fn test_uncompressed_documents_round_trip_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        compression: CompressionType::None,
+        ..StorageConfig::default()
+    };
+
+    let mut collection = Collection::open("users", dir.path(), &config).unwrap();
+    for i in 0..10 {
+        collection.insert(format!("user{}", i).as_bytes(), document(i).as_bytes()).unwrap();
+    }
+    collection.close().unwrap();
+    drop(collection);
+
+    let collection = Collection::open("users", dir.path(), &config).unwrap();
+    for i in 0..10 {
+        let data = collection.get(format!("user{}", i).as_bytes()).unwrap();
+        assert_eq!(data, Some(document(i).into_bytes()));
+    }
+}
@@ -0,0 +1,104 @@
+//! Data directory format versioning and migration
+
+use std::fs;
+use std::path::Path;
+
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::format::{prepare_data_dir, read_format_version, FORMAT_VERSION, FORMAT_VERSION_FILE};
+use nebuladb_storage::manager::BlockManager;
+use nebuladb_storage::{BlockHeader, StorageConfig};
+
+fn document(i: usize) -> String {
+    format!(r#"{{"_id":"user{}","n":{}}}"#, i, i)
+}
+
+/// Lay out a data directory the way releases before format versioning did:
+/// no version file and blocks without a footer directory
+fn write_unversioned_fixture(data_dir: &Path) {
+    let config = StorageConfig {
+        block_offset_directory: false,
+        flush_threshold: 10,
+        ..StorageConfig::default()
+    };
+
+    let mut collection = Collection::open("users", &data_dir.join("default"), &config).unwrap();
+    for i in 0..25 {
+        collection.insert(format!("user{}", i).as_bytes(), document(i).as_bytes()).unwrap();
+    }
+    collection.close().unwrap();
+}
+
+#[test]
+fn test_unversioned_directory_migrated_and_readable() {
+    let dir = tempfile::tempdir().unwrap();
+    write_unversioned_fixture(dir.path());
+
+    let collection_path = dir.path().join("default").join("users");
+    let headers = BlockManager::open("users", collection_path.clone(), StorageConfig::default())
+        .unwrap().block_headers().unwrap();
+    assert_eq!(headers.len(), 3);
+    assert!(headers.iter().all(|h| !h.has_directory()));
+
+    assert_eq!(prepare_data_dir(dir.path()).unwrap(), 1);
+    assert_eq!(read_format_version(dir.path()).unwrap(), Some(FORMAT_VERSION));
+
+    let headers = BlockManager::open("users", collection_path, StorageConfig::default())
+        .unwrap().block_headers().unwrap();
+    assert_eq!(headers.len(), 3);
+    assert!(headers.iter().all(|h| h.version == BlockHeader::VERSION && h.has_directory()));
+
+    let collection = Collection::open("users", &dir.path().join("default"), &StorageConfig::default()).unwrap();
+    for i in 0..25 {
+        let data = collection.get(format!("user{}", i).as_bytes()).unwrap();
+        assert_eq!(data, Some(document(i).into_bytes()));
+    }
+
+    // Already current: nothing left to migrate
+    assert_eq!(prepare_data_dir(dir.path()).unwrap(), FORMAT_VERSION);
+}
+
+#[test]
+fn test_tombstone_documents_become_deletions() {
+    let dir = tempfile::tempdir().unwrap();
+    let collection_path = dir.path().join("default").join("users");
+    let config = StorageConfig { flush_threshold: 10, ..StorageConfig::default() };
+
+    // Format 2 deleted a document by storing a tombstone under `_<id>_`
+    fs::create_dir_all(&collection_path).unwrap();
+    let mut manager = BlockManager::open("users", collection_path.clone(), config).unwrap();
+    for i in 0..25 {
+        manager.insert(format!("user{}", i).as_bytes(), document(i).as_bytes()).unwrap();
+    }
+    manager.insert(b"_user3_", br#"{"_deleted": true}"#).unwrap();
+    manager.insert(b"_user20_", br#"{"_deleted": true}"#).unwrap();
+    manager.flush().unwrap();
+    fs::write(dir.path().join(FORMAT_VERSION_FILE), "2\n").unwrap();
+
+    assert_eq!(prepare_data_dir(dir.path()).unwrap(), 2);
+
+    let collection = Collection::open("users", &dir.path().join("default"), &StorageConfig::default()).unwrap();
+    let ids = collection.scan().unwrap();
+    assert_eq!(ids.len(), 23);
+    assert!(!ids.contains(&b"_user3_".to_vec()));
+    assert_eq!(collection.get(b"user3").unwrap(), None);
+    assert_eq!(collection.get(b"user20").unwrap(), None);
+    assert_eq!(collection.get(b"user4").unwrap(), Some(document(4).into_bytes()));
+    assert_eq!(collection.stats().unwrap().deleted_doc_count, 2);
+}
+
+#[test]
+fn test_new_directory_stamped_with_current_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = dir.path().join("data");
+
+    assert_eq!(prepare_data_dir(&data_dir).unwrap(), FORMAT_VERSION);
+    assert_eq!(read_format_version(&data_dir).unwrap(), Some(FORMAT_VERSION));
+}
+
+#[test]
+fn test_newer_version_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join(FORMAT_VERSION_FILE), format!("{}\n", FORMAT_VERSION + 1)).unwrap();
+
+    assert!(prepare_data_dir(dir.path()).is_err());
+}
@@ -0,0 +1,181 @@
+//! Crash recovery through the write-ahead log
+
+use std::sync::{Arc, RwLock};
+
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::storage::Storage;
+use nebuladb_storage::StorageConfig;
+use nebuladb_wal::{EntryType, SyncMode, WalConfig};
+use nebuladb_wal::manager::{SharedWalManager, WalManager};
+
+fn wal_manager(dir: &std::path::Path) -> SharedWalManager {
+    open_wal_manager(dir, false)
+}
+
+fn open_wal_manager(dir: &std::path::Path, shared: bool) -> SharedWalManager {
+    let config = WalConfig {
+        dir_path: dir.join("wal").to_string_lossy().to_string(),
+        sync_mode: SyncMode::Never,
+        group_commit_interval_ms: 0,
+        checkpoint_interval: 0,
+        shared,
+        ..WalConfig::default()
+    };
+    Arc::new(RwLock::new(WalManager::new(config).unwrap()))
+}
+
+fn document(i: usize) -> String {
+    format!(r#"{{"_id":"user{}","name":"User {}"}}"#, i, i)
+}
+
+#[test]
+fn test_patch_logged_as_update_and_recovered() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        flush_threshold: usize::MAX,
+        ..StorageConfig::default()
+    };
+
+    let wal = wal_manager(dir.path());
+    let mut collection = Collection::open_with_wal("users", dir.path(), &config, Arc::clone(&wal)).unwrap();
+    collection.insert(b"user1", br#"{"name":"Ada","age":36}"#).unwrap();
+    let patch = serde_json::json!({"age": 37, "email": "ada@example.com"});
+    assert!(collection.patch(b"user1", &patch).unwrap());
+
+    let entries = wal.read().unwrap().committed_entries("users").unwrap();
+    let types: Vec<EntryType> = entries.iter().map(|entry| entry.header.entry_type).collect();
+    assert_eq!(types, vec![EntryType::Insert, EntryType::Update]);
+
+    // Crash before the patched version reaches a block
+    drop(collection);
+    drop(wal);
+
+    let collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    assert_eq!(collection.get_json(b"user1").unwrap(),
+        Some(serde_json::json!({"name": "Ada", "age": 37, "email": "ada@example.com"})));
+    // Replayed as an update, the patch continues the document's version history
+    assert_eq!(collection.get_with_meta(b"user1").unwrap().unwrap().0.version, 2);
+}
+
+#[test]
+fn test_unflushed_writes_recovered_after_crash() {
+    let dir = tempfile::tempdir().unwrap();
+    // Keep every write in the active block so only the WAL has them
+    let config = StorageConfig {
+        flush_threshold: usize::MAX,
+        ..StorageConfig::default()
+    };
+
+    let mut collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    for i in 0..200 {
+        collection.insert(format!("user{}", i).as_bytes(), document(i).as_bytes()).unwrap();
+    }
+    collection.delete(b"user7").unwrap();
+    // Crash: no close, no flush
+    drop(collection);
+    assert!(!dir.path().join("users").join("blocks.bin").exists());
+
+    let collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    for i in 0..200 {
+        let expected = (i != 7).then(|| document(i).into_bytes());
+        assert_eq!(collection.get(format!("user{}", i).as_bytes()).unwrap(), expected);
+    }
+    drop(collection);
+
+    // Replay flushed and checkpointed the documents, so a second open has nothing to redo
+    let blocks = std::fs::metadata(dir.path().join("users").join("blocks.bin")).unwrap().len();
+    let collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    assert_eq!(std::fs::metadata(dir.path().join("users").join("blocks.bin")).unwrap().len(), blocks);
+    assert_eq!(collection.get(b"user199").unwrap(), Some(document(199).into_bytes()));
+}
+
+#[test]
+fn test_shared_wal_recovers_each_collection() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        flush_threshold: usize::MAX,
+        ..StorageConfig::default()
+    };
+
+    let wal = open_wal_manager(dir.path(), true);
+    let mut users = Collection::open_with_wal("users", dir.path(), &config, Arc::clone(&wal)).unwrap();
+    let mut orders = Collection::open_with_wal("orders", dir.path(), &config, Arc::clone(&wal)).unwrap();
+    for i in 0..50 {
+        users.insert(format!("user{}", i).as_bytes(), document(i).as_bytes()).unwrap();
+        orders.insert(format!("order{}", i).as_bytes(), br#"{"total":1}"#).unwrap();
+    }
+    // Crash both collections
+    drop((users, orders, wal));
+
+    let wal_files: Vec<_> = std::fs::read_dir(dir.path().join("wal")).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(wal_files, vec!["_shared.wal".to_string()]);
+
+    let wal = open_wal_manager(dir.path(), true);
+    let users = Collection::open_with_wal("users", dir.path(), &config, Arc::clone(&wal)).unwrap();
+    let orders = Collection::open_with_wal("orders", dir.path(), &config, Arc::clone(&wal)).unwrap();
+    assert_eq!(users.scan().unwrap().len(), 50);
+    assert_eq!(orders.scan().unwrap().len(), 50);
+    for i in 0..50 {
+        assert_eq!(users.get(format!("user{}", i).as_bytes()).unwrap(), Some(document(i).into_bytes()));
+        assert_eq!(orders.get(format!("order{}", i).as_bytes()).unwrap(), Some(br#"{"total":1}"#.to_vec()));
+        assert_eq!(users.get(format!("order{}", i).as_bytes()).unwrap(), None);
+    }
+}
+
+#[test]
+fn test_storage_replays_logged_entries() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Writes that only ever reached the WAL
+    let wal = wal_manager(dir.path());
+    {
+        let mut wal = wal.write().unwrap();
+        wal.insert("users", b"user1", document(1).as_bytes()).unwrap();
+        wal.insert("users", b"user2", document(2).as_bytes()).unwrap();
+        wal.update("users", b"user1", br#"{"name":"Ada"}"#).unwrap();
+        wal.delete("users", b"user2").unwrap();
+
+        let aborted = wal.begin_transaction().unwrap();
+        wal.insert_in_transaction(aborted, "users", b"user3", document(3).as_bytes()).unwrap();
+        wal.abort_transaction(aborted).unwrap();
+        let committed = wal.begin_transaction().unwrap();
+        wal.insert_in_transaction(committed, "users", b"user4", document(4).as_bytes()).unwrap();
+        wal.commit_transaction(committed).unwrap();
+    }
+    // Crash without a checkpoint
+    drop(wal);
+
+    let wal = wal_manager(dir.path());
+    wal.write().unwrap().recover().unwrap();
+    let mut storage = Storage::open_with_wal(dir.path(), None, wal).unwrap();
+    let users = storage.open_collection("users").unwrap();
+    assert_eq!(users.get(b"user1").unwrap(), Some(br#"{"name":"Ada"}"#.to_vec()));
+    assert_eq!(users.get(b"user2").unwrap(), None);
+    assert_eq!(users.get(b"user3").unwrap(), None);
+    assert_eq!(users.get(b"user4").unwrap(), Some(document(4).into_bytes()));
+    assert_eq!(users.scan().unwrap().len(), 2);
+}
+
+#[test]
+fn test_change_sequence_continues_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig::default();
+
+    let mut collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    let mut subscription = collection.subscribe();
+    collection.insert(b"user1", document(1).as_bytes()).unwrap();
+    collection.delete(b"user1").unwrap();
+    let first = subscription.try_recv().unwrap().unwrap();
+    let last = subscription.try_recv().unwrap().unwrap();
+    assert!(last.sequence > first.sequence);
+    collection.close().unwrap();
+    drop(collection);
+
+    let mut collection = Collection::open_with_wal("users", dir.path(), &config, wal_manager(dir.path())).unwrap();
+    let mut subscription = collection.subscribe();
+    assert!(subscription.resume_token() >= last.sequence);
+    collection.insert(b"user2", document(2).as_bytes()).unwrap();
+    assert!(subscription.try_recv().unwrap().unwrap().sequence > subscription.resume_token());
+}
@@ -0,0 +1,59 @@
+//! Snapshot reads across compaction
+//!
+//! Snapshots are process-wide, so these tests live apart from the ones that
+//! count what compaction drops.
+
+use nebuladb_storage::collection::Collection;
+use nebuladb_storage::StorageConfig;
+
+#[test]
+fn test_compaction_keeps_what_live_snapshots_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut collection = Collection::open("users", dir.path(), &StorageConfig::default()).unwrap();
+    collection.insert(b"user1", br#"{"n":1}"#).unwrap();
+    collection.insert(b"user3", br#"{"n":3}"#).unwrap();
+
+    let snapshot = collection.snapshot();
+    collection.insert(b"user1", br#"{"n":2}"#).unwrap();
+    collection.insert(b"user2", br#"{"n":2}"#).unwrap();
+    collection.delete(b"user3").unwrap();
+    collection.compact().unwrap();
+
+    // Later writes went to blocks of their own version, so the snapshot
+    // still sees the documents written before it and nothing after
+    assert_eq!(collection.get_at(b"user1", &snapshot).unwrap(), Some(br#"{"n":1}"#.to_vec()));
+    assert_eq!(collection.get_at(b"user3", &snapshot).unwrap(), Some(br#"{"n":3}"#.to_vec()));
+    assert!(collection.get_at(b"user2", &snapshot).unwrap().is_none());
+    assert_eq!(collection.get(b"user1").unwrap(), Some(br#"{"n":2}"#.to_vec()));
+    assert!(collection.get(b"user3").unwrap().is_none());
+    assert_eq!(collection.count().unwrap(), 2);
+
+    // Once the snapshot is gone, so are the versions only it read
+    drop(snapshot);
+    let stats = collection.compact().unwrap();
+    assert_eq!(stats.documents_removed, 3);
+    assert_eq!(collection.get(b"user1").unwrap(), Some(br#"{"n":2}"#.to_vec()));
+    assert!(collection.get(b"user3").unwrap().is_none());
+}
+
+#[test]
+fn test_snapshots_between_flushed_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = StorageConfig {
+        flush_threshold: 1,
+        ..StorageConfig::default()
+    };
+    let mut collection = Collection::open("users", dir.path(), &config).unwrap();
+
+    // Every write flushes a block; a snapshot between any two sees exactly
+    // the writes before it
+    let mut snapshots = Vec::new();
+    for i in 0..20 {
+        collection.insert(format!("user{}", i).as_bytes(), b"{}").unwrap();
+        snapshots.push(collection.snapshot());
+    }
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        assert!(collection.get_at(format!("user{}", i).as_bytes(), snapshot).unwrap().is_some());
+        assert!(collection.get_at(format!("user{}", i + 1).as_bytes(), snapshot).unwrap().is_none());
+    }
+}
@@ -0,0 +1,75 @@
+//! WAL writes from 8 concurrent writers with an fsync per write versus group
+//! commit
+//!
+//! Run with `cargo bench -p nebuladb-wal --bench group_commit`.
+
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nebuladb_wal::manager::WalManager;
+use nebuladb_wal::{SyncMode, WalConfig};
+
+const WRITERS: usize = 8;
+const WRITES_PER_WRITER: usize = 100;
+
+fn manager(dir: &std::path::Path, group_commit_interval_ms: u64) -> Arc<RwLock<WalManager>> {
+    let config = WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        sync_mode: SyncMode::Always,
+        group_commit_interval_ms,
+        checkpoint_interval: 0,
+        ..WalConfig::default()
+    };
+    Arc::new(RwLock::new(WalManager::new(config).unwrap()))
+}
+
+/// Every writer inserts its documents, waiting for each to be durable
+fn write_concurrently(manager: Arc<RwLock<WalManager>>) {
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || {
+                for i in 0..WRITES_PER_WRITER {
+                    let id = format!("doc{}_{}", writer, i);
+                    let commit = {
+                        let mut manager = manager.write().unwrap();
+                        manager.insert("users", id.as_bytes(), br#"{"name":"someone"}"#).unwrap();
+                        manager.take_commit()
+                    };
+                    if let Some(commit) = commit {
+                        commit.wait().unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn bench_concurrent_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("8_writers");
+    group.throughput(Throughput::Elements((WRITERS * WRITES_PER_WRITER) as u64));
+    group.sample_size(10);
+
+    for (name, interval_ms) in [("fsync_per_write", 0), ("group_commit_5ms", 5)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let dir = tempfile::tempdir().unwrap();
+                    let manager = manager(dir.path(), interval_ms);
+                    (dir, manager)
+                },
+                |(_dir, manager)| write_concurrently(manager),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_writes);
+criterion_main!(benches);
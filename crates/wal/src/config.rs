@@ -1,15 +1,62 @@
 use serde::{Deserialize, Serialize};
 
+/// When WAL writes are forced to disk
+///
+/// Each mode trades durability for write latency: only `Always` guarantees
+/// that a write which returned survives a crash of the machine. A crash of
+/// just the process loses nothing in any mode, as written data sits in the
+/// OS page cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Sync after every write, or per group commit window; a write is
+    /// durable when it returns
+    #[default]
+    Always,
+    /// Writes return at once and a background thread syncs the files written
+    /// to every `interval_ms`, and on shutdown; a machine crash loses up to
+    /// one interval of writes
+    Periodic { interval_ms: u64 },
+    /// The WAL never syncs on its own, leaving it to the OS; a machine crash
+    /// may lose any write not yet flushed. Only an explicit
+    /// [`WalManager::sync_all`](crate::manager::WalManager::sync_all) syncs.
+    Never,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalConfig {
     /// Path to the WAL directory
     pub dir_path: String,
     /// Maximum size of a WAL file before rotation (in bytes)
     pub max_file_size: usize,
-    /// Sync WAL to disk after every write
-    pub sync_on_write: bool,
+    /// Number of rotated WAL segments kept per collection after a checkpoint
+    pub max_segments_to_keep: usize,
+    /// Log every collection to one shared WAL file instead of one file per
+    /// collection, so a database with many collections has a single file to sync
+    pub shared: bool,
+    /// When writes are synced to disk; configurations predating this field
+    /// get `Always`, what their `sync_on_write` defaulted to
+    #[serde(default)]
+    pub sync_mode: SyncMode,
+    /// With `SyncMode::Always`, buffer writes and let a background thread
+    /// write and sync them every this many milliseconds (0 syncs every write
+    /// on its own); see
+    /// [`WalManager::take_commit`](crate::manager::WalManager::take_commit).
+    /// Each lone writer waits up to one interval; 5 ms suits most disks.
+    #[serde(default, alias = "group_commit_window_ms")]
+    pub group_commit_interval_ms: u64,
+    /// Pending writes that make group commit sync before its interval is up
+    #[serde(default = "default_group_commit_max_entries")]
+    pub group_commit_max_entries: usize,
     /// Time interval between auto-checkpoints (in seconds, 0 to disable)
     pub checkpoint_interval: u64,
+    /// Recovery time after which a warning suggests checkpointing more often
+    /// (in milliseconds, 0 to disable); recovery always runs to completion
+    pub recovery_time_budget_ms: u64,
+}
+
+fn default_group_commit_max_entries() -> usize {
+    1024
 }
 
 impl Default for WalConfig {
@@ -17,8 +64,13 @@ impl Default for WalConfig {
         Self {
             dir_path: "wal".to_string(),
             max_file_size: 64 * 1024 * 1024, // 64MB
-            sync_on_write: true,
+            max_segments_to_keep: 4,
+            shared: false,
+            sync_mode: SyncMode::Always,
+            group_commit_interval_ms: 0,
+            group_commit_max_entries: default_group_commit_max_entries(),
             checkpoint_interval: 300, // 5 minutes
+            recovery_time_budget_ms: 30_000, // 30 seconds
         }
     }
 } 
\ No newline at end of file
@@ -0,0 +1,95 @@
+//! Deadlock detection between transactions
+
+use std::collections::{HashMap, HashSet};
+
+/// Which transactions wait for locks held by which others
+#[derive(Debug, Default)]
+pub struct WaitForGraph {
+    /// Waiting transaction -> transactions holding the locks it waits for
+    edges: HashMap<u64, HashSet<u64>>,
+}
+
+impl WaitForGraph {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `waiter` waits for `holder`, returning the transactions of
+    /// the cycle this closes, if any
+    pub fn add_wait(&mut self, waiter: u64, holder: u64) -> Option<Vec<u64>> {
+        self.edges.entry(waiter).or_default().insert(holder);
+
+        let mut path = vec![waiter];
+        let mut visited = HashSet::new();
+        self.path_to(holder, waiter, &mut path, &mut visited).then_some(path)
+    }
+
+    /// Depth-first search for `target` from `from`, extending `path` with the
+    /// transactions on the way
+    fn path_to(&self, from: u64, target: u64, path: &mut Vec<u64>, visited: &mut HashSet<u64>) -> bool {
+        if from == target {
+            return true;
+        }
+        if !visited.insert(from) {
+            return false;
+        }
+
+        path.push(from);
+        for &next in self.edges.get(&from).into_iter().flatten() {
+            if self.path_to(next, target, path, visited) {
+                return true;
+            }
+        }
+        path.pop();
+        false
+    }
+
+    /// Forget the waits of `waiter`, once it got its locks
+    pub fn stop_waiting(&mut self, waiter: u64) {
+        self.edges.remove(&waiter);
+    }
+
+    /// Forget `tx` entirely, once it committed or aborted
+    pub fn remove(&mut self, tx: u64) {
+        self.edges.remove(&tx);
+        self.edges.retain(|_, holders| {
+            holders.remove(&tx);
+            !holders.is_empty()
+        });
+    }
+
+    /// Transactions `waiter` waits for
+    pub fn holders(&self, waiter: u64) -> impl Iterator<Item = u64> + '_ {
+        self.edges.get(&waiter).into_iter().flatten().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_cycle() {
+        let mut graph = WaitForGraph::new();
+        assert_eq!(graph.add_wait(1, 2), None);
+        assert_eq!(graph.add_wait(2, 3), None);
+        assert_eq!(graph.add_wait(4, 1), None);
+
+        let mut cycle = graph.add_wait(3, 1).unwrap();
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_removed_transaction_breaks_cycle() {
+        let mut graph = WaitForGraph::new();
+        graph.add_wait(1, 2);
+        graph.remove(2);
+        assert_eq!(graph.holders(1).count(), 0);
+        assert_eq!(graph.add_wait(2, 1), None);
+
+        graph.stop_waiting(2);
+        assert_eq!(graph.add_wait(1, 2), None);
+    }
+}
@@ -24,9 +24,31 @@ pub enum EntryType {
     AbortTx = 6,
     /// Checkpoint marker
     Checkpoint = 7,
+    /// Collection about to be renamed; the entry's document ID holds the old
+    /// name and its data the new one
+    Rename = 8,
+    /// Savepoint set in a transaction; the document ID holds its name and
+    /// the data its ID
+    Savepoint = 9,
+    /// Transaction rolled back to the savepoint whose ID the data holds
+    RollbackToSavepoint = 10,
+    /// Collection about to be created; the document ID holds its name
+    CreateCollection = 11,
+    /// Collection about to be dropped; the document ID holds its name
+    DropCollection = 12,
 }
 
 impl EntryType {
+    /// Whether entries of this type record transaction state, which entries
+    /// in other WAL files may rely on
+    pub fn is_transaction_record(self) -> bool {
+        matches!(
+            self,
+            EntryType::BeginTx | EntryType::CommitTx | EntryType::AbortTx
+                | EntryType::Savepoint | EntryType::RollbackToSavepoint
+        )
+    }
+    
     /// Convert a byte to an EntryType
     pub fn from_byte(byte: u8) -> Result<Self> {
         match byte {
@@ -38,6 +60,11 @@ impl EntryType {
             5 => Ok(EntryType::CommitTx),
             6 => Ok(EntryType::AbortTx),
             7 => Ok(EntryType::Checkpoint),
+            8 => Ok(EntryType::Rename),
+            9 => Ok(EntryType::Savepoint),
+            10 => Ok(EntryType::RollbackToSavepoint),
+            11 => Ok(EntryType::CreateCollection),
+            12 => Ok(EntryType::DropCollection),
             _ => Err(Error::Other(format!("Invalid WAL entry type: {}", byte))),
         }
     }
@@ -58,7 +85,7 @@ pub struct EntryHeader {
     pub document_id: Vec<u8>,
     /// Total size of the entry data
     pub data_size: u32,
-    /// CRC32 checksum of the entry data
+    /// CRC32 checksum of the other header fields and the entry data
     pub checksum: u32,
     /// Timestamp when the entry was created (UNIX timestamp)
     pub timestamp: u64,
@@ -134,6 +161,20 @@ impl EntryHeader {
         bytes
     }
     
+    /// CRC32 over every header field but the checksum itself, then `data`,
+    /// so a damaged document ID or entry type fails the check as well
+    pub fn compute_checksum(&self, data: &[u8]) -> u32 {
+        let bytes = self.to_bytes();
+        // The checksum is followed only by the 8-byte timestamp
+        let checksum_at = bytes.len() - 12;
+        
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&bytes[..checksum_at]);
+        hasher.update(&bytes[checksum_at + 4..]);
+        hasher.update(data);
+        hasher.finalize()
+    }
+    
     /// Deserialize a header from bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize)> {
         if bytes.len() < Self::FIXED_SIZE - 2 { // -2 because doc_id_len is part of FIXED_SIZE
@@ -233,17 +274,15 @@ impl WalEntry {
         document_id: Vec<u8>,
         data: Vec<u8>,
     ) -> Self {
-        // Calculate checksum (simple implementation - use CRC32 in production)
-        let checksum = data.iter().fold(0u32, |acc, &b| acc.wrapping_add(b as u32));
-        
-        let header = EntryHeader::new(
+        let mut header = EntryHeader::new(
             entry_type,
             collection_id,
             transaction_id,
             document_id,
             data.len() as u32,
-            checksum,
+            0,
         );
+        header.checksum = header.compute_checksum(&data);
         
         Self {
             header,
@@ -295,6 +334,50 @@ impl WalEntry {
         )
     }
     
+    /// Create a savepoint entry
+    pub fn savepoint(transaction_id: u64, savepoint_id: u64, name: &str) -> Self {
+        Self::new(
+            EntryType::Savepoint,
+            0,
+            transaction_id,
+            name.as_bytes().to_vec(),
+            savepoint_id.to_le_bytes().to_vec(),
+        )
+    }
+    
+    /// Create an entry rolling a transaction back to a savepoint
+    pub fn rollback_to_savepoint(transaction_id: u64, savepoint_id: u64) -> Self {
+        Self::new(
+            EntryType::RollbackToSavepoint,
+            0,
+            transaction_id,
+            Vec::new(),
+            savepoint_id.to_le_bytes().to_vec(),
+        )
+    }
+    
+    /// Create an entry recording that a collection is about to be created
+    /// (`EntryType::CreateCollection`) or dropped (`EntryType::DropCollection`)
+    pub fn collection_metadata(entry_type: EntryType, collection_id: u64, collection_name: &str) -> Self {
+        Self::new(
+            entry_type,
+            collection_id,
+            0,
+            collection_name.as_bytes().to_vec(),
+            Vec::new(),
+        )
+    }
+    
+    /// The savepoint ID of a savepoint or rollback entry
+    pub fn savepoint_id(&self) -> Option<u64> {
+        match self.header.entry_type {
+            EntryType::Savepoint | EntryType::RollbackToSavepoint => {
+                self.data.get(..8).and_then(|bytes| bytes.try_into().ok()).map(u64::from_le_bytes)
+            }
+            _ => None,
+        }
+    }
+    
     /// Total size of the entry in bytes
     pub fn size(&self) -> usize {
         self.header.size() + self.data.len()
@@ -321,12 +404,78 @@ impl WalEntry {
         let data = bytes[offset..offset + header.data_size as usize].to_vec();
         
         // Verify checksum
-        let checksum = data.iter().fold(0u32, |acc, &b| acc.wrapping_add(b as u32));
-        if checksum != header.checksum {
-            return Err(Error::Other("Invalid WAL entry: checksum mismatch".to_string()));
+        let actual = header.compute_checksum(&data);
+        if actual != header.checksum {
+            return Err(Error::ChecksumMismatch { expected: header.checksum, actual });
         }
         
         let data_size = header.data_size;
         Ok((Self { header, data }, offset + data_size as usize))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_round_trip() {
+        let entry = WalEntry::new(EntryType::Insert, 42, 0, b"doc1".to_vec(), b"{\"a\":1}".to_vec());
+        let bytes = entry.to_bytes();
+
+        let (decoded, consumed) = WalEntry::from_bytes(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(decoded.header.entry_type, EntryType::Insert);
+        assert_eq!(decoded.header.collection_id, 42);
+        assert_eq!(decoded.header.document_id, b"doc1");
+        assert_eq!(decoded.data, b"{\"a\":1}");
+    }
+
+    #[test]
+    fn test_collection_metadata_round_trip() {
+        for entry_type in [EntryType::CreateCollection, EntryType::DropCollection] {
+            let entry = WalEntry::collection_metadata(entry_type, 7, "users");
+            let (decoded, consumed) = WalEntry::from_bytes(&entry.to_bytes()).unwrap();
+            assert_eq!(consumed, entry.size());
+            assert_eq!(decoded.header.entry_type, entry_type);
+            assert_eq!(decoded.header.collection_id, 7);
+            assert_eq!(decoded.header.document_id, b"users");
+            assert!(decoded.data.is_empty());
+        }
+
+        assert_eq!(EntryType::from_byte(EntryType::CreateCollection as u8).unwrap(), EntryType::CreateCollection);
+        assert_eq!(EntryType::from_byte(EntryType::DropCollection as u8).unwrap(), EntryType::DropCollection);
+        assert!(EntryType::from_byte(13).is_err());
+    }
+
+    #[test]
+    fn test_bit_flip_in_data_fails_checksum() {
+        let entry = WalEntry::new(EntryType::Insert, 42, 0, b"doc1".to_vec(), b"{\"a\":1}".to_vec());
+        let mut bytes = entry.to_bytes();
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+
+        match WalEntry::from_bytes(&bytes) {
+            Err(Error::ChecksumMismatch { expected, actual }) => assert_ne!(expected, actual),
+            other => panic!("expected checksum mismatch, got {:?}", other.map(|(e, _)| e)),
+        }
+    }
+
+    #[test]
+    fn test_damaged_header_fails_checksum() {
+        let entry = WalEntry::new(EntryType::Insert, 42, 7, b"doc1".to_vec(), b"{\"a\":1}".to_vec());
+        let bytes = entry.to_bytes();
+
+        // Insert turned into Delete, a different transaction, and another document
+        let type_at = 4;
+        let tx_at = 4 + 1 + 8;
+        let doc_id_at = tx_at + 8 + 2;
+        for (at, flip) in [(type_at, 0x02), (tx_at, 0x01), (doc_id_at + 3, 0x03)] {
+            let mut damaged = bytes.clone();
+            damaged[at] ^= flip;
+            assert!(matches!(WalEntry::from_bytes(&damaged), Err(Error::ChecksumMismatch { .. })),
+                "byte {} was not covered", at);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Group commit: appends made within a short interval share one fsync
+//!
+//! Appends queue their entries in the log's write buffer and register the
+//! log with the open batch. A background committer wakes every interval, or
+//! sooner once enough entries are pending: it closes the batch, writes each
+//! registered log's queued entries with one `write_all`, syncs the files
+//! once, and wakes the writers waiting on the batch.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{Result, WalError};
+use crate::log::WriteBuffer;
+use crate::WalLog;
+
+/// A log written to in the open batch
+#[derive(Debug)]
+struct PendingLog {
+    buffer: Arc<Mutex<WriteBuffer>>,
+    file: File,
+}
+
+impl PendingLog {
+    fn commit(&self) -> Result<()> {
+        self.buffer.lock()
+            .map_err(|_| WalError::Other("WAL write buffer poisoned".to_string()))?
+            .write_to(&self.file)?;
+        self.file.sync_data().map_err(WalError::Io)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BatchState {
+    /// Batch new appends join
+    open_batch: u64,
+    /// Newest batch whose appends are durable
+    durable_batch: u64,
+    /// Logs written since the open batch was last closed
+    pending: HashMap<PathBuf, PendingLog>,
+    /// Entries appended since the open batch was last closed
+    pending_entries: usize,
+    /// Set once a sync fails; no later commit is trusted to be durable
+    failure: Option<String>,
+    /// Set on shutdown; the committer commits once more and exits
+    stopped: bool,
+    /// Number of batches synced
+    syncs: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<BatchState>,
+    /// Wakes the committer early, on shutdown or a full batch
+    wake: Condvar,
+    /// Signalled whenever a batch becomes durable or its sync fails
+    synced: Condvar,
+    interval: Duration,
+    max_entries: usize,
+}
+
+impl Shared {
+    fn lock(&self) -> Result<MutexGuard<'_, BatchState>> {
+        self.state.lock().map_err(|_| WalError::Other("Group commit state poisoned".to_string()))
+    }
+}
+
+/// Batches writes and fsyncs of the WAL files of one manager
+#[derive(Debug)]
+pub struct GroupCommit {
+    shared: Arc<Shared>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl GroupCommit {
+    /// Start a committer syncing every `interval`, or as soon as
+    /// `max_entries` appends are pending
+    pub fn start(interval: Duration, max_entries: usize) -> Arc<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(BatchState { open_batch: 1, ..BatchState::default() }),
+            wake: Condvar::new(),
+            synced: Condvar::new(),
+            interval,
+            max_entries: max_entries.max(1),
+        });
+        let thread_shared = Arc::clone(&shared);
+        let handle = thread::spawn(move || run(&thread_shared));
+
+        Arc::new(Self {
+            shared,
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    /// Record an append to `log`, returning the batch it joined
+    pub(crate) fn register(&self, log: &WalLog) -> Result<u64> {
+        let mut state = self.shared.lock()?;
+        if !state.pending.contains_key(log.path()) {
+            let pending = PendingLog { buffer: log.write_buffer(), file: log.try_clone_file()? };
+            state.pending.insert(log.path().to_path_buf(), pending);
+        }
+        state.pending_entries += 1;
+        if state.pending_entries >= self.shared.max_entries {
+            self.shared.wake.notify_all();
+        }
+
+        Ok(state.open_batch)
+    }
+
+    /// Drop the pending sync of the file at `path`, once it was synced and
+    /// replaced by rotation
+    pub(crate) fn forget(&self, path: &Path) -> Result<()> {
+        self.shared.lock()?.pending.remove(path);
+        Ok(())
+    }
+
+    /// Number of fsync rounds run so far
+    pub fn sync_count(&self) -> u64 {
+        self.shared.lock().map(|state| state.syncs).unwrap_or(0)
+    }
+
+    /// Block until every append of `batch` is durable
+    fn wait(&self, batch: u64) -> Result<()> {
+        let mut state = self.shared.lock()?;
+        loop {
+            if let Some(failure) = &state.failure {
+                return Err(WalError::Other(format!("WAL group commit failed: {}", failure)));
+            }
+            if state.durable_batch >= batch {
+                return Ok(());
+            }
+            state = self.shared.synced.wait(state)
+                .map_err(|_| WalError::Other("Group commit state poisoned".to_string()))?;
+        }
+    }
+
+    /// Commit the pending appends one last time and stop the committer
+    pub fn stop(&self) -> Result<()> {
+        self.shared.lock()?.stopped = true;
+        self.shared.wake.notify_all();
+
+        let handle = self.handle.lock()
+            .map_err(|_| WalError::Other("Group commit handle poisoned".to_string()))?
+            .take();
+        if let Some(handle) = handle {
+            handle.join().map_err(|_| WalError::Other("Group commit thread panicked".to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GroupCommit {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            tracing::warn!(error = ?e, "failed to stop WAL group commit");
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    loop {
+        let (closed, logs, stopped) = {
+            let Ok(state) = shared.lock() else { return };
+            let Ok((mut state, _)) = shared.wake.wait_timeout_while(state, shared.interval, |state| {
+                !state.stopped && state.pending_entries < shared.max_entries
+            }) else {
+                return;
+            };
+
+            let closed = state.open_batch;
+            state.open_batch += 1;
+            state.pending_entries = 0;
+            let logs: Vec<PendingLog> = state.pending.drain().map(|(_, log)| log).collect();
+            (closed, logs, state.stopped)
+        };
+
+        let result = logs.iter().try_for_each(PendingLog::commit);
+
+        let Ok(mut state) = shared.lock() else { return };
+        match result {
+            Ok(()) => {
+                if !logs.is_empty() {
+                    state.syncs += 1;
+                }
+                state.durable_batch = closed;
+            }
+            Err(e) => {
+                tracing::warn!(error = ?e, "WAL group commit failed");
+                state.failure = Some(format!("{:?}", e));
+            }
+        }
+        shared.synced.notify_all();
+
+        if stopped {
+            return;
+        }
+    }
+}
+
+/// Appends waiting to become durable, returned by
+/// [`WalManager::take_commit`](crate::manager::WalManager::take_commit)
+#[must_use = "the appends are not durable until the commit is waited on"]
+#[derive(Debug)]
+pub struct PendingCommit {
+    pub(crate) group: Arc<GroupCommit>,
+    pub(crate) batch: u64,
+}
+
+impl PendingCommit {
+    /// Block until the appends are durable
+    ///
+    /// Call this without holding the WAL manager's lock, so writers on other
+    /// threads can join the same fsync.
+    pub fn wait(self) -> Result<()> {
+        self.group.wait(self.batch)
+    }
+}
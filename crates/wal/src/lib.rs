@@ -6,9 +6,13 @@
 mod entry;
 mod log;
 pub mod manager;
+pub mod group_commit;
+pub mod periodic_sync;
+pub mod deadlock;
+pub mod reader;
 pub mod config;
 pub mod error;
 
 pub use entry::{WalEntry, EntryType, EntryHeader};
-pub use log::WalLog;
-pub use config::WalConfig;
+pub use log::{WalLog, WAL_HEADER_SIZE};
+pub use config::{SyncMode, WalConfig};
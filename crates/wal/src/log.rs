@@ -2,23 +2,54 @@
 //!
 //! This module handles the low-level operations on WAL log files.
 
-use crate::entry::WalEntry;
+use crate::entry::{EntryHeader, WalEntry};
 use crate::error::{WalError, Result};
+use nebuladb_core::metrics;
+use std::collections::VecDeque;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// WAL log file format version
-const WAL_FORMAT_VERSION: u8 = 1;
+/// WAL log file format version; 2 extended entry checksums over the header
+pub(crate) const WAL_FORMAT_VERSION: u8 = 2;
 
 /// WAL log file header size in bytes
-const WAL_HEADER_SIZE: usize = 16;
+pub const WAL_HEADER_SIZE: usize = 16;
 
 /// WAL log file magic bytes: "NBWA"
-const WAL_MAGIC: [u8; 4] = [0x4E, 0x42, 0x57, 0x41];
+pub(crate) const WAL_MAGIC: [u8; 4] = [0x4E, 0x42, 0x57, 0x41];
+
+/// Entries appended with [`WalLog::append_buffered`] and not yet written
+#[derive(Debug, Default)]
+pub(crate) struct WriteBuffer {
+    /// Encoded entries, oldest first
+    entries: VecDeque<Vec<u8>>,
+    /// File position of the first queued entry
+    start: u64,
+}
+
+impl WriteBuffer {
+    /// Write every queued entry to `file` with one `write_all`, returning
+    /// how many were written
+    pub(crate) fn write_to(&mut self, mut file: &File) -> Result<usize> {
+        if self.entries.is_empty() {
+            return Ok(0);
+        }
+        
+        let count = self.entries.len();
+        let bytes: Vec<u8> = self.entries.drain(..).flatten().collect();
+        file.seek(SeekFrom::Start(self.start)).map_err(WalError::Io)?;
+        file.write_all(&bytes).map_err(WalError::Io)?;
+        self.start += bytes.len() as u64;
+        
+        Ok(count)
+    }
+}
 
 /// A Write-Ahead Log file
+#[derive(Debug)]
 pub struct WalLog {
     /// Path to the WAL file
     path: PathBuf,
@@ -28,6 +59,10 @@ pub struct WalLog {
     position: u64,
     /// Whether to sync after every write
     sync_on_write: bool,
+    /// Number of times the file was synced
+    syncs: u64,
+    /// Entries appended but not yet written, shared with a group commit
+    buffer: Arc<Mutex<WriteBuffer>>,
 }
 
 impl WalLog {
@@ -38,7 +73,7 @@ impl WalLog {
         // Create directory if it doesn't exist
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
-                .map_err(|e| WalError::Io(e))?;
+                .map_err(WalError::Io)?;
         }
         
         // Open the file
@@ -48,23 +83,23 @@ impl WalLog {
             .create(true)
             .truncate(true)
             .open(&path)
-            .map_err(|e| WalError::Io(e))?;
+            .map_err(WalError::Io)?;
         
         // Write WAL header
         // Format: [magic(4)][version(1)][reserved(3)][timestamp(8)]
-        file.write_all(&WAL_MAGIC).map_err(|e| WalError::Io(e))?;
-        file.write_all(&[WAL_FORMAT_VERSION]).map_err(|e| WalError::Io(e))?;
-        file.write_all(&[0, 0, 0]).map_err(|e| WalError::Io(e))?; // Reserved
+        file.write_all(&WAL_MAGIC).map_err(WalError::Io)?;
+        file.write_all(&[WAL_FORMAT_VERSION]).map_err(WalError::Io)?;
+        file.write_all(&[0, 0, 0]).map_err(WalError::Io)?; // Reserved
         
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
         
-        file.write_all(&timestamp.to_le_bytes()).map_err(|e| WalError::Io(e))?;
+        file.write_all(&timestamp.to_le_bytes()).map_err(WalError::Io)?;
         
         if sync_on_write {
-            file.sync_all().map_err(|e| WalError::Io(e))?;
+            file.sync_all().map_err(WalError::Io)?;
         }
         
         Ok(Self {
@@ -72,6 +107,8 @@ impl WalLog {
             file,
             position: WAL_HEADER_SIZE as u64,
             sync_on_write,
+            syncs: u64::from(sync_on_write),
+            buffer: Arc::default(),
         })
     }
     
@@ -85,110 +122,183 @@ impl WalLog {
             .write(true)
             .create(false)
             .open(&path)
-            .map_err(|e| WalError::Io(e))?;
+            .map_err(WalError::Io)?;
         
         // Read and verify WAL header
         let mut magic = [0u8; 4];
-        file.read_exact(&mut magic).map_err(|e| WalError::Io(e))?;
+        file.read_exact(&mut magic).map_err(WalError::Io)?;
         
         if magic != WAL_MAGIC {
             return Err(WalError::Other("Invalid WAL file: wrong magic number".to_string()));
         }
         
         let mut version = [0u8; 1];
-        file.read_exact(&mut version).map_err(|e| WalError::Io(e))?;
+        file.read_exact(&mut version).map_err(WalError::Io)?;
         
         if version[0] != WAL_FORMAT_VERSION {
             return Err(WalError::Other(format!("Unsupported WAL format version: {}", version[0])));
         }
         
         // Skip reserved bytes
-        file.seek(SeekFrom::Current(3)).map_err(|e| WalError::Io(e))?;
+        file.seek(SeekFrom::Current(3)).map_err(WalError::Io)?;
         
         // Skip timestamp
-        file.seek(SeekFrom::Current(8)).map_err(|e| WalError::Io(e))?;
+        file.seek(SeekFrom::Current(8)).map_err(WalError::Io)?;
         
         // Get the current file size
-        let position = file.seek(SeekFrom::End(0)).map_err(|e| WalError::Io(e))?;
+        let position = file.seek(SeekFrom::End(0)).map_err(WalError::Io)?;
         
         Ok(Self {
             path,
             file,
             position,
             sync_on_write,
+            syncs: 0,
+            buffer: Arc::default(),
         })
     }
     
     /// Append an entry to the WAL
     pub fn append(&mut self, entry: &WalEntry) -> Result<u64> {
+        self.flush_buffer()?;
+        
         // Seek to the end
         self.file.seek(SeekFrom::Start(self.position))
-            .map_err(|e| WalError::Io(e))?;
+            .map_err(WalError::Io)?;
         
         // Write the entry
         let entry_bytes = entry.to_bytes();
         let entry_pos = self.position;
         
-        self.file.write_all(&entry_bytes).map_err(|e| WalError::Io(e))?;
+        self.file.write_all(&entry_bytes).map_err(WalError::Io)?;
         
         // Update position
         self.position += entry_bytes.len() as u64;
+        metrics::WAL_BYTES_WRITTEN.inc_by(entry_bytes.len() as u64);
         
         // Sync if needed
         if self.sync_on_write {
-            self.file.sync_data().map_err(|e| WalError::Io(e))?;
+            self.sync()?;
+        }
+        
+        Ok(entry_pos)
+    }
+    
+    /// Queue an entry to be written later, along with others, returning
+    /// its position
+    ///
+    /// Queued entries are written by [`flush_buffer`](Self::flush_buffer),
+    /// which any other operation on the log runs first, or by a group commit
+    /// the log is registered with. They are never synced on their own.
+    pub fn append_buffered(&mut self, entry: &WalEntry) -> Result<u64> {
+        let mut buffer = self.buffer.lock()
+            .map_err(|_| WalError::Other("WAL write buffer poisoned".to_string()))?;
+        if buffer.entries.is_empty() {
+            buffer.start = self.position;
         }
         
+        let entry_bytes = entry.to_bytes();
+        let entry_pos = self.position;
+        self.position += entry_bytes.len() as u64;
+        metrics::WAL_BYTES_WRITTEN.inc_by(entry_bytes.len() as u64);
+        buffer.entries.push_back(entry_bytes);
+        
         Ok(entry_pos)
     }
     
+    /// Write the entries queued by [`append_buffered`](Self::append_buffered)
+    pub fn flush_buffer(&self) -> Result<()> {
+        self.lock_buffer()?.write_to(&self.file)?;
+        Ok(())
+    }
+    
+    /// Number of entries queued by [`append_buffered`](Self::append_buffered)
+    /// and not yet written
+    pub fn buffered_entries(&self) -> usize {
+        self.lock_buffer().map(|buffer| buffer.entries.len()).unwrap_or(0)
+    }
+    
+    fn lock_buffer(&self) -> Result<std::sync::MutexGuard<'_, WriteBuffer>> {
+        self.buffer.lock().map_err(|_| WalError::Other("WAL write buffer poisoned".to_string()))
+    }
+    
+    /// The queue of buffered entries, for a group commit to write
+    pub(crate) fn write_buffer(&self) -> Arc<Mutex<WriteBuffer>> {
+        Arc::clone(&self.buffer)
+    }
+    
     /// Force sync the WAL to disk
     pub fn sync(&mut self) -> Result<()> {
-        self.file.sync_data().map_err(|e| WalError::Io(e))?;
+        self.flush_buffer()?;
+        self.file.sync_data().map_err(WalError::Io)?;
+        self.syncs += 1;
         Ok(())
     }
     
+    /// Number of times this log synced its file
+    pub fn sync_count(&self) -> u64 {
+        self.syncs
+    }
+    
     /// Read an entry at the given position
     pub fn read_at(&mut self, position: u64) -> Result<WalEntry> {
         if position < WAL_HEADER_SIZE as u64 || position >= self.position {
             return Err(WalError::Other(format!("Invalid WAL position: {}", position)));
         }
+        self.flush_buffer()?;
         
-        // Seek to the position
-        self.file.seek(SeekFrom::Start(position))
-            .map_err(|e| WalError::Io(e))?;
-        
-        // Read a buffer (start with 4KB, which should be enough for most entries)
-        let mut buffer = vec![0u8; 4096];
-        let bytes_read = self.file.read(&mut buffer)
-            .map_err(|e| WalError::Io(e))?;
-        
-        if bytes_read == 0 {
-            return Err(WalError::Other("Unexpected end of WAL file".to_string()));
-        }
-        
-        buffer.truncate(bytes_read);
-        
-        // Parse the entry
-        let (entry, _) = WalEntry::from_bytes(&buffer)?;
+        let (entry, _) = read_entry(&mut self.file, position, self.position, &mut Vec::new())?;
         
         Ok(entry)
     }
     
     /// Iterate through all entries in the WAL
-    pub fn iterate(&mut self) -> Result<WalIterator> {
+    pub fn iterate(&mut self) -> Result<WalIterator<'_>> {
+        self.flush_buffer()?;
+        
         // Seek to the beginning (after header)
         self.file.seek(SeekFrom::Start(WAL_HEADER_SIZE as u64))
-            .map_err(|e| WalError::Io(e))?;
+            .map_err(WalError::Io)?;
         
         Ok(WalIterator {
             file: &mut self.file,
             position: WAL_HEADER_SIZE as u64,
             end_position: self.position,
-            buffer: vec![0u8; 4096], // Start with 4KB buffer
+            buffer: Vec::new(),
         })
     }
     
+    /// Whether a complete, valid entry starts anywhere after `position`
+    ///
+    /// Tells a torn tail, left by a crash mid-append, from damage in the
+    /// middle of the log.
+    pub fn has_valid_entry_after(&mut self, position: u64) -> Result<bool> {
+        self.flush_buffer()?;
+        
+        let mut bytes = Vec::new();
+        self.file.seek(SeekFrom::Start(position)).map_err(WalError::Io)?;
+        (&mut self.file).take(self.position.saturating_sub(position))
+            .read_to_end(&mut bytes)
+            .map_err(WalError::Io)?;
+        
+        Ok((1..bytes.len())
+            .filter(|&offset| bytes[offset..].starts_with(&EntryHeader::MAGIC))
+            .any(|offset| WalEntry::from_bytes(&bytes[offset..]).is_ok()))
+    }
+    
+    /// Cut the log off at `position`, dropping everything written after it
+    pub fn truncate(&mut self, position: u64) -> Result<()> {
+        if position < WAL_HEADER_SIZE as u64 || position > self.position {
+            return Err(WalError::Other(format!("Invalid WAL position: {}", position)));
+        }
+        self.flush_buffer()?;
+        
+        self.file.set_len(position).map_err(WalError::Io)?;
+        self.file.sync_all().map_err(WalError::Io)?;
+        self.position = position;
+        Ok(())
+    }
+    
     /// Get the current size of the WAL file
     pub fn size(&self) -> u64 {
         self.position
@@ -204,13 +314,28 @@ impl WalLog {
         &self.path
     }
     
+    /// Another handle to the WAL file, for syncing it without this log
+    pub(crate) fn try_clone_file(&self) -> Result<File> {
+        self.file.try_clone().map_err(WalError::Io)
+    }
+    
     /// Close the WAL file
     pub fn close(self) -> Result<()> {
-        self.file.sync_all().map_err(|e| WalError::Io(e))?;
+        self.flush_buffer()?;
+        self.file.sync_all().map_err(WalError::Io)?;
         Ok(())
     }
 }
 
+impl Drop for WalLog {
+    fn drop(&mut self) {
+        // Buffered entries are written, though not synced, even without a close
+        if let Err(e) = self.flush_buffer() {
+            tracing::warn!(path = %self.path.display(), error = ?e, "failed to write buffered WAL entries");
+        }
+    }
+}
+
 /// Iterator over WAL entries
 pub struct WalIterator<'a> {
     file: &'a mut File,
@@ -230,34 +355,124 @@ impl<'a> Iterator for WalIterator<'a> {
         // Remember the current position
         let entry_pos = self.position;
         
-        // Read more data if needed
-        let result = self.file.read(&mut self.buffer);
-        
-        match result {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    return None;
-                }
-                
-                self.buffer.truncate(bytes_read);
-                
-                // Parse the entry
-                match WalEntry::from_bytes(&self.buffer) {
-                    Ok((entry, bytes_consumed)) => {
-                        // Update position
-                        self.position += bytes_consumed as u64;
-                        
-                        // Seek to the next entry
-                        if let Err(e) = self.file.seek(SeekFrom::Start(self.position)) {
-                            return Some(Err(WalError::Io(e)));
-                        }
-                        
-                        Some(Ok((entry_pos, entry)))
-                    }
-                    Err(e) => Some(Err(e.into())),
-                }
+        match read_entry(self.file, entry_pos, self.end_position, &mut self.buffer) {
+            Ok((entry, bytes_consumed)) => {
+                // Advance past exactly this entry
+                self.position += bytes_consumed as u64;
+                Some(Ok((entry_pos, entry)))
+            }
+            Err(e) => {
+                // The next entry's position is unknown past an unreadable one
+                self.position = self.end_position;
+                Some(Err(e))
             }
-            Err(e) => Some(Err(WalError::Io(e))),
         }
     }
 }
+
+/// Size of the first read of an entry, enough for most entries
+const READ_CHUNK: u64 = 4096;
+
+/// Read the entry at `position` of a WAL file that ends at `end`
+///
+/// The first read covers [`READ_CHUNK`] bytes; an entry that turns out to be
+/// larger is read in full with a second read. Returns the entry and its size.
+fn read_entry(file: &mut File, position: u64, end: u64, buffer: &mut Vec<u8>) -> Result<(WalEntry, usize)> {
+    let available = end.saturating_sub(position);
+    if available == 0 {
+        return Err(WalError::Other("Unexpected end of WAL file".to_string()));
+    }
+    
+    file.seek(SeekFrom::Start(position)).map_err(WalError::Io)?;
+    buffer.resize(available.min(READ_CHUNK) as usize, 0);
+    file.read_exact(buffer).map_err(WalError::Io)?;
+    
+    // Once the header is in, the entry's full size is known
+    if let Ok((header, header_len)) = EntryHeader::from_bytes(buffer) {
+        let needed = (header_len as u64 + header.data_size as u64).min(available) as usize;
+        if needed > buffer.len() {
+            let start = buffer.len();
+            buffer.resize(needed, 0);
+            file.read_exact(&mut buffer[start..]).map_err(WalError::Io)?;
+        }
+    }
+    
+    Ok(WalEntry::from_bytes(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::EntryType;
+
+    fn entry(id: &str, size: usize) -> WalEntry {
+        WalEntry::new(EntryType::Insert, 1, 0, id.as_bytes().to_vec(), vec![b'x'; size])
+    }
+
+    #[test]
+    fn test_iterator_advances_entry_by_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = WalLog::create(dir.path().join("test.wal"), false).unwrap();
+
+        let sizes = [10, 0, 5000, 300, 9000];
+        let mut positions = Vec::new();
+        for (i, size) in sizes.iter().enumerate() {
+            positions.push(log.append(&entry(&format!("doc{}", i), *size)).unwrap());
+        }
+
+        let read: Vec<(u64, WalEntry)> = log.iterate().unwrap().map(|result| result.unwrap()).collect();
+        assert_eq!(read.len(), 5);
+        for (i, (position, entry)) in read.iter().enumerate() {
+            assert_eq!(*position, positions[i]);
+            assert_eq!(entry.header.document_id, format!("doc{}", i).into_bytes());
+            assert_eq!(entry.data.len(), sizes[i]);
+        }
+    }
+
+    #[test]
+    fn test_iterator_stops_after_unreadable_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.wal");
+        let mut log = WalLog::create(&path, false).unwrap();
+        log.append(&entry("doc0", 10)).unwrap();
+        let corrupt = log.append(&entry("doc1", 10)).unwrap();
+        log.append(&entry("doc2", 10)).unwrap();
+
+        // Break the magic number of the second entry
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[corrupt as usize] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut log = WalLog::open(&path, false).unwrap();
+        let results: Vec<_> = log.iterate().unwrap().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn test_buffered_entries_written_on_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.wal");
+        let mut log = WalLog::create(&path, false).unwrap();
+
+        let first = log.append_buffered(&entry("doc0", 10)).unwrap();
+        let second = log.append_buffered(&entry("doc1", 20)).unwrap();
+        assert_eq!(log.buffered_entries(), 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), WAL_HEADER_SIZE as u64);
+
+        // An unbuffered append writes the queue first, keeping log order
+        let third = log.append(&entry("doc2", 30)).unwrap();
+        assert_eq!(log.buffered_entries(), 0);
+        assert!(first < second && second < third);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), log.size());
+
+        log.append_buffered(&entry("doc3", 40)).unwrap();
+        drop(log);
+        let mut log = WalLog::open(&path, false).unwrap();
+        let ids: Vec<Vec<u8>> = log.iterate().unwrap()
+            .map(|result| result.unwrap().1.header.document_id)
+            .collect();
+        assert_eq!(ids, [b"doc0", b"doc1", b"doc2", b"doc3"]);
+    }
+}
@@ -3,15 +3,19 @@
 //! This module provides high-level WAL operations for collections.
 
 use crate::{
-    WalConfig,
+    SyncMode, WalConfig,
+    deadlock::WaitForGraph,
     entry::{WalEntry, EntryType},
-    log::WalLog,
+    group_commit::{GroupCommit, PendingCommit},
+    log::{WalLog, WAL_HEADER_SIZE},
+    periodic_sync::PeriodicSync,
 };
 use nebuladb_core::{Error, Result};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use std::time::{Instant, SystemTime};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
 
 /// Helper function to generate a collection ID from a collection name
 fn collection_id_from_name(name: &str) -> u64 {
@@ -24,19 +28,279 @@ fn collection_id_from_name(name: &str) -> u64 {
     hash
 }
 
+/// Name of the WAL file shared by all collections when `WalConfig::shared` is set
+pub const SHARED_WAL_NAME: &str = "_shared";
+
+/// Name of the WAL recording collection creations and drops
+pub const METADATA_WAL_NAME: &str = "_metadata";
+
+/// Number of entries read between recovery progress reports
+pub const RECOVERY_PROGRESS_INTERVAL: u64 = 1000;
+
+/// How long recovery runs before [`WalManager::recover`] starts logging progress
+const SLOW_RECOVERY_LOG_AFTER: Duration = Duration::from_secs(1);
+
+/// File opened by [`WalManager::check_writable`]; recovery ignores it
+const WRITE_PROBE_FILE: &str = ".write-probe";
+
+/// Progress of a WAL recovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    /// Entries read so far
+    pub entries_processed: u64,
+    /// Bytes of WAL read so far
+    pub bytes_processed: u64,
+    /// Total bytes of WAL to read
+    pub total_bytes: u64,
+    /// Time since recovery started
+    pub elapsed: Duration,
+}
+
+impl RecoveryProgress {
+    /// Estimated time left, extrapolated from the rate so far
+    pub fn estimated_remaining(&self) -> Option<Duration> {
+        if self.bytes_processed == 0 {
+            return None;
+        }
+        let remaining = self.total_bytes.saturating_sub(self.bytes_processed);
+        Some(self.elapsed.mul_f64(remaining as f64 / self.bytes_processed as f64))
+    }
+}
+
+/// Event reported while recovering the WAL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryEvent {
+    /// Periodic progress, and a final report once recovery completes
+    Progress(RecoveryProgress),
+    /// Recovery has run past `WalConfig::recovery_time_budget_ms`; it keeps going
+    BudgetExceeded { elapsed: Duration, budget: Duration },
+}
+
+/// Counts recovered entries and reports progress
+struct RecoveryTracker<'a> {
+    started: Instant,
+    budget: Option<Duration>,
+    warned: bool,
+    entries: u64,
+    /// Bytes in the files already read to the end
+    finished_bytes: u64,
+    total_bytes: u64,
+    on_event: &'a mut dyn FnMut(RecoveryEvent),
+}
+
+impl RecoveryTracker<'_> {
+    /// Count an entry read at `position` in the current file
+    fn entry(&mut self, position: u64) {
+        self.entries += 1;
+        if self.entries.is_multiple_of(RECOVERY_PROGRESS_INTERVAL) {
+            self.report(self.finished_bytes + position);
+        }
+    }
+    
+    /// Count a file of `size` bytes as fully read
+    fn finish_file(&mut self, size: u64) {
+        self.finished_bytes += size;
+    }
+    
+    fn report(&mut self, bytes_processed: u64) {
+        let elapsed = self.started.elapsed();
+        (self.on_event)(RecoveryEvent::Progress(RecoveryProgress {
+            entries_processed: self.entries,
+            bytes_processed,
+            total_bytes: self.total_bytes,
+            elapsed,
+        }));
+        
+        if let Some(budget) = self.budget {
+            if !self.warned && elapsed > budget {
+                self.warned = true;
+                (self.on_event)(RecoveryEvent::BudgetExceeded { elapsed, budget });
+            }
+        }
+    }
+}
+
+/// Size of a file on disk, or 0 if it cannot be read
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+        
+/// Path of the file recording the bytes rotated away from the WAL file at
+/// `wal_path`: `<collection>.lsn`
+fn rotated_bytes_path(wal_path: &Path) -> PathBuf {
+    wal_path.with_extension("lsn")
+}
+        
+/// Bytes rotated away from the WAL file at `wal_path`, 0 if none were
+fn read_rotated_bytes(wal_path: &Path) -> Result<u64> {
+    let path = rotated_bytes_path(wal_path);
+    if !path.exists() {
+        return Ok(0);
+    }
+    
+    std::fs::read_to_string(&path).map_err(Error::IoError)?
+        .trim()
+        .parse()
+        .map_err(|_| Error::Other(format!("Invalid WAL position file {:?}", path)))
+}
+        
+/// Record the bytes rotated away from the WAL file at `wal_path`, replacing
+/// the file in one step
+fn write_rotated_bytes(wal_path: &Path, bytes: u64) -> Result<()> {
+    let path = rotated_bytes_path(wal_path);
+    let tmp_path = path.with_extension("lsn.tmp");
+    std::fs::write(&tmp_path, format!("{}\n", bytes)).map_err(Error::IoError)?;
+    std::fs::rename(&tmp_path, &path).map_err(Error::IoError)
+}
+
 /// A collection's WAL state
+#[derive(Debug)]
 struct CollectionWal {
     /// The WAL log for this collection
     log: WalLog,
     /// Path to the WAL file
     path: PathBuf,
+    /// Rotated segments of this WAL, oldest first
+    segments: Vec<PathBuf>,
     /// Last checkpoint timestamp
     last_checkpoint: SystemTime,
-    /// Current transaction ID counter
-    next_tx_id: u64,
+    /// Whether the active file holds transaction records, which entries in
+    /// other collections' WALs may rely on
+    has_tx_records: bool,
+    /// Syncs of the files rotated away
+    retired_syncs: u64,
+    /// Bytes appended to the files rotated away, kept next to the active
+    /// file so positions keep growing across rotations
+    rotated_bytes: u64,
+}
+
+impl CollectionWal {
+    /// Move the active WAL file aside as `<collection>.wal.<timestamp>` and start a fresh one
+    ///
+    /// The old file is synced first unless `sync` is false.
+    fn rotate(&mut self, sync: bool, sync_on_write: bool) -> Result<()> {
+        if sync {
+            self.log.sync()?;
+        }
+        self.retired_syncs += self.log.sync_count();
+        
+        // Recorded before the file moves, so a crash in between can only
+        // make positions jump ahead
+        let rotated_bytes = self.rotated_bytes + self.log.size();
+        write_rotated_bytes(&self.path, rotated_bytes)?;
+        self.rotated_bytes = rotated_bytes;
+        
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        
+        // Rotations within the same millisecond take the next free suffix
+        let mut suffix = timestamp;
+        let mut segment = segment_path(&self.path, suffix);
+        while segment.exists() {
+            suffix += 1;
+            segment = segment_path(&self.path, suffix);
+        }
+        
+        std::fs::rename(&self.path, &segment).map_err(Error::IoError)?;
+        self.log = WalLog::create(&self.path, sync_on_write)?;
+        self.segments.push(segment);
+        self.has_tx_records = false;
+        
+        Ok(())
+    }
+    
+    /// Delete the `count` oldest segments
+    fn prune_segments(&mut self, count: usize) -> Result<()> {
+        for segment in self.segments.drain(..count.min(self.segments.len())) {
+            std::fs::remove_file(&segment).map_err(Error::IoError)?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Path of a rotated segment of the WAL file at `wal_path`
+fn segment_path(wal_path: &Path, suffix: u128) -> PathBuf {
+    let mut name = wal_path.as_os_str().to_os_string();
+    name.push(format!(".{}", suffix));
+    PathBuf::from(name)
+}
+
+/// Flags the entries of one WAL stream that a rollback to a savepoint
+/// discarded
+///
+/// A rollback discards its transaction's entries since the savepoint's marker
+/// in the same stream, or all of them if the stream has none: the savepoint
+/// is logged to every collection the transaction had written to when it was
+/// set.
+fn rolled_back_entries(entries: &[WalEntry]) -> Vec<bool> {
+    let mut rolled_back = vec![false; entries.len()];
+    // tx ID -> (savepoint ID, index of its marker), oldest first
+    let mut savepoints: HashMap<u64, Vec<(u64, usize)>> = HashMap::new();
+    
+    for (index, entry) in entries.iter().enumerate() {
+        let tx_id = entry.header.transaction_id;
+        match entry.header.entry_type {
+            EntryType::Savepoint => {
+                if let Some(savepoint_id) = entry.savepoint_id() {
+                    savepoints.entry(tx_id).or_default().push((savepoint_id, index));
+                }
+            }
+            EntryType::RollbackToSavepoint => {
+                let Some(savepoint_id) = entry.savepoint_id() else { continue };
+                let marks = savepoints.entry(tx_id).or_default();
+                let from = match marks.iter().position(|(id, _)| *id == savepoint_id) {
+                    Some(position) => {
+                        let from = marks[position].1;
+                        marks.truncate(position + 1);
+                        from
+                    }
+                    None => 0,
+                };
+                for (discarded, earlier) in rolled_back[from..index].iter_mut().zip(&entries[from..index]) {
+                    if earlier.header.transaction_id == tx_id {
+                        *discarded = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    rolled_back
+}
+
+/// State of a transaction in progress
+#[derive(Debug, Default)]
+struct ActiveTransaction {
+    /// Positions of the transaction's entries
+    positions: Vec<u64>,
+    /// Collections the transaction wrote to
+    collections: BTreeSet<String>,
+    /// Savepoints still valid, oldest first: ID and name
+    savepoints: Vec<(u64, String)>,
+}
+
+/// Drop pending background syncs of the file at `path`, which rotation
+/// synced and replaced with a new file
+fn forget_rotated(
+    group_commit: &Option<Arc<GroupCommit>>,
+    periodic_sync: &Option<Arc<PeriodicSync>>,
+    path: &Path,
+) -> Result<()> {
+    if let Some(group) = group_commit {
+        group.forget(path)?;
+    }
+    if let Some(periodic) = periodic_sync {
+        periodic.forget(path)?;
+    }
+    Ok(())
 }
 
 /// Manages WAL operations for multiple collections
+#[derive(Debug)]
 pub struct WalManager {
     /// WAL configuration
     config: WalConfig,
@@ -45,11 +309,28 @@ pub struct WalManager {
     /// Open WAL files by collection name
     collection_wals: HashMap<String, CollectionWal>,
     /// Active transactions
-    active_transactions: HashMap<u64, Vec<u64>>, // tx_id -> list of entry positions
+    active_transactions: HashMap<u64, ActiveTransaction>,
+    /// Next transaction ID handed out, above every ID already in the WAL
+    /// directory so a commit never matches an older transaction
+    next_tx_id: u64,
+    /// Next savepoint ID handed out
+    next_savepoint_id: u64,
+    /// Lock waits between active transactions
+    wait_for_graph: WaitForGraph,
+    /// Transactions aborted by a deadlock another transaction's wait found
+    deadlock_victims: HashSet<u64>,
     /// In-memory WAL cache for fast recovery
     entry_cache: HashMap<(String, Vec<u8>), u64>, // (collection, doc_id) -> position
     /// Last auto-checkpoint time
     last_auto_checkpoint: Instant,
+    /// Shared fsyncs, when a group commit interval is configured
+    group_commit: Option<Arc<GroupCommit>>,
+    /// Newest group commit batch joined since the last `take_commit`
+    pending_batch: Option<u64>,
+    /// Background syncs, in `SyncMode::Periodic`
+    periodic_sync: Option<Arc<PeriodicSync>>,
+    /// Syncs of WAL files no longer open
+    closed_syncs: u64,
 }
 
 impl WalManager {
@@ -59,40 +340,324 @@ impl WalManager {
         
         // Create the WAL directory if it doesn't exist
         std::fs::create_dir_all(&wal_dir)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
         
-        Ok(Self {
+        let group_commit = (config.sync_mode == SyncMode::Always && config.group_commit_interval_ms > 0)
+            .then(|| GroupCommit::start(
+                Duration::from_millis(config.group_commit_interval_ms),
+                config.group_commit_max_entries,
+            ));
+        let periodic_sync = match config.sync_mode {
+            SyncMode::Periodic { interval_ms: 0 } => {
+                return Err(Error::Other("Periodic WAL sync needs a non-zero interval".to_string()));
+            }
+            SyncMode::Periodic { interval_ms } => Some(PeriodicSync::start(Duration::from_millis(interval_ms))),
+            SyncMode::Always | SyncMode::Never => None,
+        };
+        
+        let mut manager = Self {
             config,
             wal_dir,
             collection_wals: HashMap::new(),
             active_transactions: HashMap::new(),
+            next_tx_id: 1,
+            next_savepoint_id: 1,
+            wait_for_graph: WaitForGraph::new(),
+            deadlock_victims: HashSet::new(),
             entry_cache: HashMap::new(),
             last_auto_checkpoint: Instant::now(),
-        })
+            group_commit,
+            pending_batch: None,
+            periodic_sync,
+            closed_syncs: 0,
+        };
+        manager.next_tx_id = manager.highest_transaction_id()? + 1;
+        
+        Ok(manager)
+    }
+    
+    /// Highest transaction ID recorded in any WAL file of the directory
+    ///
+    /// A damaged tail ends a file early, as in recovery, and files that
+    /// cannot be read at all are left for recovery to report.
+    fn highest_transaction_id(&self) -> Result<u64> {
+        let mut highest = 0;
+        for path in self.all_log_files()? {
+            let Ok(mut log) = WalLog::open(&path, false) else { continue };
+            let Ok(entries) = log.iterate() else { continue };
+            for result in entries {
+                let Ok((_, entry)) = result else { break };
+                highest = highest.max(entry.header.transaction_id);
+            }
+        }
+        
+        Ok(highest)
+    }
+    
+    /// Whether each WAL file syncs every write itself, rather than leaving
+    /// it to a group commit
+    fn sync_each_write(&self) -> bool {
+        self.config.sync_mode == SyncMode::Always && self.group_commit.is_none()
+    }
+    
+    /// Whether the WAL syncs its files at all
+    fn syncs(&self) -> bool {
+        self.config.sync_mode != SyncMode::Never
+    }
+    
+    /// Take the fsync the appends made since the last call are waiting for
+    ///
+    /// With a group commit interval configured, appends are buffered and
+    /// written and synced by a background committer; they are not known to
+    /// be durable until the returned commit is waited on (or
+    /// [`sync_all`](Self::sync_all) runs). Waiting after releasing the
+    /// manager's lock lets writers on other threads share the fsync. Returns
+    /// `None` when nothing is pending, including when every write syncs on
+    /// its own.
+    pub fn take_commit(&mut self) -> Option<PendingCommit> {
+        let batch = self.pending_batch.take()?;
+        self.group_commit.as_ref().map(|group| PendingCommit { group: Arc::clone(group), batch })
+    }
+    
+    /// Number of fsyncs group commit has run, 0 without group commit
+    pub fn group_commit_syncs(&self) -> u64 {
+        self.group_commit.as_ref().map_or(0, |group| group.sync_count())
+    }
+    
+    /// Number of fsyncs of WAL files so far, however they were triggered
+    pub fn fsync_count(&self) -> u64 {
+        let log_syncs: u64 = self.collection_wals.values()
+            .map(|wal| wal.retired_syncs + wal.log.sync_count())
+            .sum();
+        self.closed_syncs + log_syncs + self.group_commit_syncs()
+            + self.periodic_sync.as_ref().map_or(0, |periodic| periodic.sync_count())
+    }
+    
+    /// Write the entries group commit has buffered, so reads of the files
+    /// see them
+    fn flush_buffers(&self) -> Result<()> {
+        for wal in self.collection_wals.values() {
+            wal.log.flush_buffer()?;
+        }
+        Ok(())
+    }
+    
+    /// Name of the WAL a collection logs to: its own, or the shared one
+    fn log_name<'a>(&self, collection_name: &'a str) -> &'a str {
+        if self.config.shared {
+            SHARED_WAL_NAME
+        } else {
+            collection_name
+        }
+    }
+    
+    /// Get the path of the WAL file named `log_name`
+    fn wal_path(&self, log_name: &str) -> PathBuf {
+        self.wal_dir.join(format!("{}.wal", log_name))
+    }
+    
+    /// Get the path of the open WAL file for a collection, if any
+    pub fn wal_file(&self, collection_name: &str) -> Option<&Path> {
+        self.collection_wals.get(self.log_name(collection_name)).map(|wal| wal.path.as_path())
+    }
+    
+    /// List the rotated WAL segments of a collection on disk, oldest first
+    ///
+    /// With a shared WAL these are the shared segments.
+    pub fn segment_files(&self, collection_name: &str) -> Result<Vec<PathBuf>> {
+        self.log_segments(self.log_name(collection_name))
+    }
+    
+    /// List the rotated segments of the WAL named `log_name`, oldest first
+    fn log_segments(&self, log_name: &str) -> Result<Vec<PathBuf>> {
+        let prefix = format!("{}.wal.", log_name);
+        let mut segments = Vec::new();
+        
+        for entry in std::fs::read_dir(&self.wal_dir).map_err(Error::IoError)? {
+            let path = entry.map_err(Error::IoError)?.path();
+            let suffix = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|suffix| suffix.parse::<u128>().ok());
+            
+            if let Some(suffix) = suffix {
+                segments.push((suffix, path));
+            }
+        }
+        
+        segments.sort();
+        Ok(segments.into_iter().map(|(_, path)| path).collect())
+    }
+    
+    /// Read the entries of the WAL named `log_name`, one list per file, from
+    /// the oldest segment to the active file
+    fn read_log(&self, log_name: &str) -> Result<Vec<Vec<WalEntry>>> {
+        self.flush_buffers()?;
+        let mut paths = self.log_segments(log_name)?;
+        let path = self.wal_path(log_name);
+        if path.exists() {
+            paths.push(path);
+        }
+        
+        let mut files = Vec::new();
+        for path in paths {
+            let mut log = WalLog::open(&path, false)?;
+            let mut entries = Vec::new();
+            for result in log.iterate()? {
+                let (_, entry) = result?;
+                entries.push(entry);
+            }
+            files.push(entries);
+        }
+        
+        Ok(files)
+    }
+    
+    /// Read every entry logged for a collection, from the oldest segment to the active file
+    ///
+    /// With a shared WAL only the entries tagged with the collection's ID are returned.
+    pub fn read_entries(&self, collection_name: &str) -> Result<Vec<WalEntry>> {
+        let entries = self.read_log(self.log_name(collection_name))?.into_iter().flatten();
+        
+        if self.config.shared {
+            let collection_id = collection_id_from_name(collection_name);
+            Ok(entries.filter(|entry| entry.header.collection_id == collection_id).collect())
+        } else {
+            Ok(entries.collect())
+        }
+    }
+    
+    /// Number of the oldest segments of a shared WAL that no collection needs
+    /// for recovery any more
+    ///
+    /// A segment is still needed while any collection has an entry in it, or
+    /// in an older segment, that is not followed by a checkpoint of that
+    /// collection.
+    fn unneeded_shared_segments(&self) -> Result<usize> {
+        let files = self.read_log(SHARED_WAL_NAME)?;
+        
+        // collection ID -> index of the file holding its oldest uncheckpointed entry
+        let mut pending: HashMap<u64, usize> = HashMap::new();
+        for (index, entries) in files.iter().enumerate() {
+            for entry in entries {
+                match entry.header.entry_type {
+                    EntryType::Insert | EntryType::Update | EntryType::Delete => {
+                        pending.entry(entry.header.collection_id).or_insert(index);
+                    }
+                    EntryType::Checkpoint => {
+                        pending.remove(&entry.header.collection_id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        // The last file is the active one and is never pruned
+        let segment_count = files.len().saturating_sub(1);
+        Ok(pending.values().copied().min().unwrap_or(segment_count).min(segment_count))
+    }
+    
+    /// Entries that must be replayed to rebuild a collection after a crash
+    ///
+    /// Returns the insert, update and delete entries logged after the
+    /// collection's last checkpoint, in log order. Transactional entries are
+    /// only included if their transaction committed; aborted and in-flight
+    /// transactions are skipped. Commit markers may live in another
+    /// collection's WAL, so every WAL file in the directory is consulted.
+    pub fn committed_entries(&self, collection_name: &str) -> Result<Vec<WalEntry>> {
+        self.flush_buffers()?;
+        let mut committed = HashSet::new();
+        for path in self.all_log_files()? {
+            let mut log = WalLog::open(&path, false)?;
+            for result in log.iterate()? {
+                let (_, entry) = result?;
+                if entry.header.entry_type == EntryType::CommitTx {
+                    committed.insert(entry.header.transaction_id);
+                }
+            }
+        }
+        
+        let entries = self.read_entries(collection_name)?;
+        let rolled_back = rolled_back_entries(&entries);
+        let start = entries.iter()
+            .rposition(|entry| entry.header.entry_type == EntryType::Checkpoint)
+            .map_or(0, |checkpoint| checkpoint + 1);
+        
+        Ok(entries.into_iter()
+            .enumerate()
+            .skip(start)
+            .filter(|(index, _)| !rolled_back[*index])
+            .map(|(_, entry)| entry)
+            .filter(|entry| matches!(
+                entry.header.entry_type,
+                EntryType::Insert | EntryType::Update | EntryType::Delete
+            ))
+            .filter(|entry| {
+                let tx_id = entry.header.transaction_id;
+                tx_id == 0 || committed.contains(&tx_id)
+            })
+            .collect())
+    }
+    
+    /// Pass each entry [`committed_entries`](Self::committed_entries) returns
+    /// for a collection to `apply`, oldest first
+    ///
+    /// Returns the number of entries replayed, stopping at the first error
+    /// from `apply`.
+    pub fn replay(&self, collection_name: &str, mut apply: impl FnMut(&WalEntry) -> Result<()>) -> Result<usize> {
+        let entries = self.committed_entries(collection_name)?;
+        for entry in &entries {
+            apply(entry)?;
+        }
+        
+        Ok(entries.len())
     }
     
-    /// Get the WAL path for a collection
-    fn wal_path(&self, collection_name: &str) -> PathBuf {
-        self.wal_dir.join(format!("{}.wal", collection_name))
+    /// Every WAL file in the directory, active files and rotated segments alike
+    fn all_log_files(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        
+        for entry in std::fs::read_dir(&self.wal_dir).map_err(Error::IoError)? {
+            let path = entry.map_err(Error::IoError)?.path();
+            let is_log = path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.ends_with(".wal")
+                        || name.rsplit_once(".wal.").is_some_and(|(_, suffix)| suffix.parse::<u128>().is_ok())
+                });
+            
+            if is_log {
+                paths.push(path);
+            }
+        }
+        
+        Ok(paths)
     }
     
-    /// Open or create a WAL for a collection
-    fn get_or_create_wal(&mut self, collection_name: &str) -> Result<&mut CollectionWal> {
-        if !self.collection_wals.contains_key(collection_name) {
-            let path = self.wal_path(collection_name);
+    /// Open or create the WAL file named `log_name`
+    fn get_or_create_wal(&mut self, log_name: &str) -> Result<&mut CollectionWal> {
+        if !self.collection_wals.contains_key(log_name) {
+            let path = self.wal_path(log_name);
             
             // Try to open existing WAL, or create a new one
             let log = if path.exists() {
-                WalLog::open(&path, self.config.sync_on_write)?
+                WalLog::open(&path, self.sync_each_write())?
             } else {
-                WalLog::create(&path, self.config.sync_on_write)?
+                WalLog::create(&path, self.sync_each_write())?
             };
+            let segments = self.log_segments(log_name)?;
+            let rotated_bytes = read_rotated_bytes(&path)?;
             
-            self.collection_wals.insert(collection_name.to_string(), CollectionWal {
+            // Entries of a file not read by recovery are unknown
+            let has_tx_records = !log.is_empty();
+            self.collection_wals.insert(log_name.to_string(), CollectionWal {
                 log,
                 path,
+                segments,
                 last_checkpoint: SystemTime::now(),
-                next_tx_id: 1,
+                has_tx_records,
+                retired_syncs: 0,
+                rotated_bytes,
             });
         }
         
@@ -100,7 +665,41 @@ impl WalManager {
         self.check_auto_checkpoint()?;
         
         // Return a mutable reference to the collection WAL
-        Ok(self.collection_wals.get_mut(collection_name).unwrap())
+        Ok(self.collection_wals.get_mut(log_name).unwrap())
+    }
+    
+    /// Append an entry to a collection's WAL, rotating the file first if the
+    /// entry would push it past `max_file_size`
+    fn append(&mut self, collection_name: &str, entry: &WalEntry) -> Result<u64> {
+        let max_file_size = self.config.max_file_size as u64;
+        let (sync, sync_on_write) = (self.syncs(), self.sync_each_write());
+        let group_commit = self.group_commit.clone();
+        let periodic_sync = self.periodic_sync.clone();
+        let collection_wal = self.get_or_create_wal(self.log_name(collection_name))?;
+        
+        if !collection_wal.log.is_empty()
+            && collection_wal.log.size() + entry.size() as u64 > max_file_size
+        {
+            collection_wal.rotate(sync, sync_on_write)?;
+            forget_rotated(&group_commit, &periodic_sync, &collection_wal.path)?;
+        }
+        if entry.header.entry_type.is_transaction_record() {
+            collection_wal.has_tx_records = true;
+        }
+        
+        let position = if group_commit.is_some() {
+            collection_wal.log.append_buffered(entry)?
+        } else {
+            collection_wal.log.append(entry)?
+        };
+        if let Some(periodic) = periodic_sync {
+            periodic.register(&collection_wal.log)?;
+        }
+        if let Some(group) = group_commit {
+            self.pending_batch = Some(group.register(&collection_wal.log)?);
+        }
+        
+        Ok(position)
     }
     
     /// Check if we should perform an auto-checkpoint
@@ -113,12 +712,13 @@ impl WalManager {
         let elapsed = now.duration_since(self.last_auto_checkpoint).as_secs();
         
         if elapsed >= self.config.checkpoint_interval {
+            // Reset the timer first: checkpointing appends through this path again
+            self.last_auto_checkpoint = now;
+            
             // Perform checkpoint on all collections
             for name in self.collection_wals.keys().cloned().collect::<Vec<_>>() {
                 self.checkpoint(&name)?;
             }
-            
-            self.last_auto_checkpoint = now;
         }
         
         Ok(())
@@ -132,7 +732,6 @@ impl WalManager {
         document_data: &[u8],
     ) -> Result<()> {
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Insert,
@@ -142,7 +741,7 @@ impl WalManager {
             document_data.to_vec(),
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Update cache
         self.entry_cache.insert(
@@ -161,7 +760,6 @@ impl WalManager {
         document_data: &[u8],
     ) -> Result<()> {
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Update,
@@ -171,7 +769,7 @@ impl WalManager {
             document_data.to_vec(),
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Update cache
         self.entry_cache.insert(
@@ -189,7 +787,6 @@ impl WalManager {
         document_id: &[u8],
     ) -> Result<()> {
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Delete,
@@ -199,7 +796,7 @@ impl WalManager {
             Vec::new(), // No data needed for delete
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Update cache
         self.entry_cache.insert(
@@ -212,28 +809,29 @@ impl WalManager {
     
     /// Begin a transaction
     pub fn begin_transaction(&mut self) -> Result<u64> {
-        // Get the first collection to generate a tx ID
-        // In a real implementation, we would use a global tx ID generator
+        // The start is recorded in the first open WAL
         let collection_name = match self.collection_wals.keys().next() {
             Some(name) => name.clone(),
             None => {
                 // No collections yet, create a dummy one
                 let dummy_name = "_tx_manager";
-                self.get_or_create_wal(dummy_name)?;
+                self.get_or_create_wal(self.log_name(dummy_name))?;
                 dummy_name.to_string()
             }
         };
         
-        let collection_wal = self.get_or_create_wal(&collection_name)?;
-        let tx_id = collection_wal.next_tx_id;
-        collection_wal.next_tx_id += 1;
+        let tx_id = self.next_tx_id;
+        self.next_tx_id += 1;
         
         // Record the transaction start
         let entry = WalEntry::begin_tx(tx_id);
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(&collection_name, &entry)?;
         
         // Initialize transaction tracking
-        self.active_transactions.insert(tx_id, vec![position]);
+        self.active_transactions.insert(tx_id, ActiveTransaction {
+            positions: vec![position],
+            ..ActiveTransaction::default()
+        });
         
         Ok(tx_id)
     }
@@ -251,7 +849,6 @@ impl WalManager {
         }
         
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Insert,
@@ -261,11 +858,12 @@ impl WalManager {
             document_data.to_vec(),
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Track this entry in the transaction
-        if let Some(entries) = self.active_transactions.get_mut(&tx_id) {
-            entries.push(position);
+        if let Some(tx) = self.active_transactions.get_mut(&tx_id) {
+            tx.positions.push(position);
+            tx.collections.insert(collection_name.to_string());
         }
         
         Ok(())
@@ -284,7 +882,6 @@ impl WalManager {
         }
         
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Update,
@@ -294,11 +891,12 @@ impl WalManager {
             document_data.to_vec(),
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Track this entry in the transaction
-        if let Some(entries) = self.active_transactions.get_mut(&tx_id) {
-            entries.push(position);
+        if let Some(tx) = self.active_transactions.get_mut(&tx_id) {
+            tx.positions.push(position);
+            tx.collections.insert(collection_name.to_string());
         }
         
         Ok(())
@@ -316,7 +914,6 @@ impl WalManager {
         }
         
         let collection_id = collection_id_from_name(collection_name);
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         
         let entry = WalEntry::new(
             EntryType::Delete,
@@ -326,11 +923,12 @@ impl WalManager {
             Vec::new(), // No data needed for delete
         );
         
-        let position = collection_wal.log.append(&entry)?;
+        let position = self.append(collection_name, &entry)?;
         
         // Track this entry in the transaction
-        if let Some(entries) = self.active_transactions.get_mut(&tx_id) {
-            entries.push(position);
+        if let Some(tx) = self.active_transactions.get_mut(&tx_id) {
+            tx.positions.push(position);
+            tx.collections.insert(collection_name.to_string());
         }
         
         Ok(())
@@ -349,14 +947,13 @@ impl WalManager {
             None => return Err(Error::Other("No collections available".to_string())),
         };
         
-        let collection_wal = self.get_or_create_wal(&collection_name)?;
-        
         // Record the transaction commit
         let entry = WalEntry::commit_tx(tx_id);
-        collection_wal.log.append(&entry)?;
+        self.append(&collection_name, &entry)?;
         
         // Remove from active transactions
         self.active_transactions.remove(&tx_id);
+        self.wait_for_graph.remove(tx_id);
         
         Ok(())
     }
@@ -374,119 +971,498 @@ impl WalManager {
             None => return Err(Error::Other("No collections available".to_string())),
         };
         
-        let collection_wal = self.get_or_create_wal(&collection_name)?;
-        
         // Record the transaction abort
         let entry = WalEntry::abort_tx(tx_id);
-        collection_wal.log.append(&entry)?;
+        self.append(&collection_name, &entry)?;
         
         // Remove from active transactions
         self.active_transactions.remove(&tx_id);
+        self.wait_for_graph.remove(tx_id);
         
         Ok(())
     }
     
+    /// Set a savepoint named `name` in a transaction, returning its ID
+    ///
+    /// The savepoint is logged to every collection the transaction wrote to
+    /// so far; a collection it writes to later has only entries after it.
+    pub fn savepoint(&mut self, tx_id: u64, name: &str) -> Result<u64> {
+        let collections = match self.active_transactions.get(&tx_id) {
+            Some(tx) => tx.collections.clone(),
+            None => return Err(Error::Other(format!("Transaction {} not active", tx_id))),
+        };
+        
+        let savepoint_id = self.next_savepoint_id;
+        self.next_savepoint_id += 1;
+        
+        let entry = WalEntry::savepoint(tx_id, savepoint_id, name);
+        for collection_name in &collections {
+            self.append(collection_name, &entry)?;
+        }
+        
+        if let Some(tx) = self.active_transactions.get_mut(&tx_id) {
+            tx.savepoints.push((savepoint_id, name.to_string()));
+        }
+        
+        Ok(savepoint_id)
+    }
+    
+    /// Discard the writes a transaction made after a savepoint, keeping the
+    /// transaction open
+    ///
+    /// The savepoint stays valid; savepoints set after it are dropped.
+    pub fn rollback_to_savepoint(&mut self, tx_id: u64, savepoint_id: u64) -> Result<()> {
+        let tx = self.active_transactions.get(&tx_id)
+            .ok_or_else(|| Error::Other(format!("Transaction {} not active", tx_id)))?;
+        let index = tx.savepoints.iter().position(|(id, _)| *id == savepoint_id)
+            .ok_or_else(|| Error::Other(format!("Transaction {} has no savepoint {}", tx_id, savepoint_id)))?;
+        let collections = tx.collections.clone();
+        
+        let entry = WalEntry::rollback_to_savepoint(tx_id, savepoint_id);
+        for collection_name in &collections {
+            self.append(collection_name, &entry)?;
+        }
+        
+        if let Some(tx) = self.active_transactions.get_mut(&tx_id) {
+            tx.savepoints.truncate(index + 1);
+        }
+        
+        Ok(())
+    }
+    
+    /// Record that `waiter_tx` waits for a lock held by `holder_tx`
+    ///
+    /// If the wait closes a cycle, the youngest transaction in it is aborted
+    /// and [`Error::Deadlock`] names it. When that is not the waiter, the
+    /// waiter keeps waiting and the victim gets the error on its own next
+    /// call. Waiting for a finished transaction is a no-op. Once the waiter
+    /// gets the lock, call [`stop_waiting`](Self::stop_waiting).
+    pub fn wait_for(&mut self, waiter_tx: u64, holder_tx: u64) -> Result<()> {
+        if self.deadlock_victims.remove(&waiter_tx) {
+            return Err(Error::Deadlock { aborted_tx_id: waiter_tx });
+        }
+        if !self.active_transactions.contains_key(&waiter_tx) {
+            return Err(Error::Other(format!("Transaction {} not active", waiter_tx)));
+        }
+        if !self.active_transactions.contains_key(&holder_tx) {
+            return Ok(());
+        }
+        
+        if let Some(cycle) = self.wait_for_graph.add_wait(waiter_tx, holder_tx) {
+            // Transaction IDs grow, so the youngest has the largest
+            let youngest = cycle.into_iter().max().unwrap_or(waiter_tx);
+            self.abort_transaction(youngest)?;
+            if youngest != waiter_tx {
+                self.deadlock_victims.insert(youngest);
+            }
+            return Err(Error::Deadlock { aborted_tx_id: youngest });
+        }
+        
+        Ok(())
+    }
+    
+    /// Forget the lock waits of `tx_id`, once it holds the locks
+    pub fn stop_waiting(&mut self, tx_id: u64) {
+        self.wait_for_graph.stop_waiting(tx_id);
+    }
+    
     /// Perform a checkpoint for a collection
     pub fn checkpoint(&mut self, collection_name: &str) -> Result<()> {
-        let collection_wal = self.get_or_create_wal(collection_name)?;
         let collection_id = collection_id_from_name(collection_name);
         
         // Record a checkpoint entry
         let entry = WalEntry::checkpoint(collection_id);
-        collection_wal.log.append(&entry)?;
+        self.append(collection_name, &entry)?;
+        
+        // Every entry of a collection's own WAL is now checkpointed, so the
+        // active file is retired as a segment and a fresh one started. A file
+        // holding transaction records is kept active, as is a shared WAL,
+        // since other collections may still need them.
+        let log_name = self.log_name(collection_name);
+        let (sync, sync_on_write) = (self.syncs(), self.sync_each_write());
+        let (group_commit, periodic_sync) = (self.group_commit.clone(), self.periodic_sync.clone());
+        let shared = self.config.shared;
+        let collection_wal = self.get_or_create_wal(log_name)?;
+        if !shared && !collection_wal.has_tx_records && !collection_wal.log.is_empty() {
+            collection_wal.rotate(sync, sync_on_write)?;
+            forget_rotated(&group_commit, &periodic_sync, &collection_wal.path)?;
+        }
+        
+        // Rotated segments beyond the retention limit are no longer needed
+        // once a checkpoint has been recorded. A shared WAL additionally keeps
+        // any segment another collection has not checkpointed past yet.
+        let segment_count = self.log_segments(log_name)?.len();
+        let mut prunable = segment_count.saturating_sub(self.config.max_segments_to_keep);
+        if self.config.shared && prunable > 0 {
+            prunable = prunable.min(self.unneeded_shared_segments()?);
+        }
         
         // Update checkpoint time
+        let collection_wal = self.get_or_create_wal(log_name)?;
         collection_wal.last_checkpoint = SystemTime::now();
+        collection_wal.prune_segments(prunable)?;
         
-        // In a real implementation, we would:
-        // 1. Ensure all data prior to this checkpoint is persisted to storage
-        // 2. Potentially create a new WAL file and archive the old one
+        Ok(())
+    }
+    
+    /// Record that a collection is about to be created
+    /// (`EntryType::CreateCollection`) or dropped (`EntryType::DropCollection`)
+    ///
+    /// Once the filesystem changes are made, call
+    /// [`finish_metadata_operations`](Self::finish_metadata_operations);
+    /// until then the operation is returned by
+    /// [`pending_metadata_operations`](Self::pending_metadata_operations).
+    pub fn log_metadata(&mut self, entry_type: EntryType, collection_name: &str) -> Result<()> {
+        if !matches!(entry_type, EntryType::CreateCollection | EntryType::DropCollection) {
+            return Err(Error::Other(format!("{:?} is not a collection metadata operation", entry_type)));
+        }
+        
+        let entry = WalEntry::collection_metadata(
+            entry_type,
+            collection_id_from_name(METADATA_WAL_NAME),
+            collection_name,
+        );
+        self.append(METADATA_WAL_NAME, &entry)?;
+        if self.syncs() && !self.sync_each_write() {
+            self.get_or_create_wal(self.log_name(METADATA_WAL_NAME))?.log.sync()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Record that collection `old_name` is about to be renamed to `new_name`
+    /// (`EntryType::Rename`)
+    ///
+    /// Like [`log_metadata`](Self::log_metadata), the rename is returned by
+    /// [`pending_metadata_operations`](Self::pending_metadata_operations)
+    /// until it is finished. The new name is the entry's data. Fails if WAL
+    /// files for `new_name` already exist.
+    pub fn log_rename(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        if !self.config.shared
+            && (self.wal_path(new_name).exists() || !self.log_segments(new_name)?.is_empty())
+        {
+            return Err(Error::Other(format!("WAL files for '{}' already exist", new_name)));
+        }
+        
+        let entry = WalEntry::new(
+            EntryType::Rename,
+            collection_id_from_name(METADATA_WAL_NAME),
+            0,
+            old_name.as_bytes().to_vec(),
+            new_name.as_bytes().to_vec(),
+        );
+        self.append(METADATA_WAL_NAME, &entry)?;
+        if self.syncs() && !self.sync_each_write() {
+            self.get_or_create_wal(self.log_name(METADATA_WAL_NAME))?.log.sync()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Mark every logged collection creation, drop and rename as done
+    pub fn finish_metadata_operations(&mut self) -> Result<()> {
+        self.checkpoint(METADATA_WAL_NAME)
+    }
+    
+    /// Collection creations, drops and renames logged but not finished,
+    /// oldest first, which a crash interrupted
+    pub fn pending_metadata_operations(&self) -> Result<Vec<WalEntry>> {
+        let entries = self.read_entries(METADATA_WAL_NAME)?;
+        let start = entries.iter()
+            .rposition(|entry| entry.header.entry_type == EntryType::Checkpoint)
+            .map_or(0, |checkpoint| checkpoint + 1);
+        
+        Ok(entries.into_iter()
+            .skip(start)
+            .filter(|entry| matches!(
+                entry.header.entry_type,
+                EntryType::CreateCollection | EntryType::DropCollection | EntryType::Rename
+            ))
+            .collect())
+    }
+    
+    /// Position just past the last entry in a collection's WAL, counting
+    /// every byte ever appended to it
+    ///
+    /// Positions only grow, across rotations and reopening, so they order
+    /// the collection's writes. With a shared WAL they count the shared
+    /// files.
+    pub fn position(&mut self, collection_name: &str) -> Result<u64> {
+        let wal = self.get_or_create_wal(self.log_name(collection_name))?;
+        Ok(wal.rotated_bytes + wal.log.size())
+    }
+    
+    /// Bytes the WAL files of a collection take on disk, rotated segments
+    /// included
+    ///
+    /// With a shared WAL this is the size of the shared files.
+    pub fn wal_size(&self, collection_name: &str) -> u64 {
+        let log_name = self.log_name(collection_name);
+        let segments = self.log_segments(log_name).unwrap_or_default();
+        segments.iter().chain(std::iter::once(&self.wal_path(log_name)))
+            .map(|path| file_size(path))
+            .sum()
+    }
+    
+    /// Move the WAL files of a collection to a new name, once the rename is
+    /// recorded with [`log_rename`](Self::log_rename)
+    ///
+    /// The collection should be checkpointed first so no entry logged under
+    /// the old name still needs replaying. Files already moved by an
+    /// interrupted rename are left where they are. A shared WAL has no files
+    /// to move.
+    pub fn rename(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.entry_cache.retain(|(collection, _), _| collection != old_name);
+        if self.config.shared {
+            return Ok(());
+        }
+        
+        let new_path = self.wal_path(new_name);
+        
+        // Reopened under the new name on next use
+        if let Some(wal) = self.collection_wals.remove(old_name) {
+            self.closed_syncs += wal.retired_syncs + wal.log.sync_count();
+            forget_rotated(&self.group_commit, &self.periodic_sync, &wal.path)?;
+            if self.syncs() {
+                wal.log.close()?;
+            }
+        }
+        for segment in self.log_segments(old_name)? {
+            let suffix = segment.to_string_lossy().rsplit_once(".wal.")
+                .and_then(|(_, suffix)| suffix.parse::<u128>().ok())
+                .ok_or_else(|| Error::Other(format!("Invalid WAL segment name {:?}", segment)))?;
+            std::fs::rename(&segment, segment_path(&new_path, suffix)).map_err(Error::IoError)?;
+        }
+        let old_path = self.wal_path(old_name);
+        if rotated_bytes_path(&old_path).exists() {
+            std::fs::rename(rotated_bytes_path(&old_path), rotated_bytes_path(&new_path)).map_err(Error::IoError)?;
+        }
+        if old_path.exists() {
+            std::fs::rename(old_path, &new_path).map_err(Error::IoError)?;
+        }
         
         Ok(())
     }
     
+    /// Force every open WAL file to disk
+    pub fn sync_all(&mut self) -> Result<()> {
+        for wal in self.collection_wals.values_mut() {
+            wal.log.sync()?;
+        }
+        Ok(())
+    }
+    
+    /// Check that the WAL directory still accepts appends
+    ///
+    /// Opens a probe file beside the logs for appending, syncs it and removes
+    /// it, so the logs themselves are untouched.
+    pub fn check_writable(&self) -> Result<()> {
+        let probe = self.wal_dir.join(WRITE_PROBE_FILE);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&probe)
+            .map_err(Error::IoError)?;
+        file.sync_all().map_err(Error::IoError)?;
+        drop(file);
+        std::fs::remove_file(&probe).map_err(Error::IoError)
+    }
+    
     /// Close all WAL files
+    ///
+    /// Files are synced first, except with `SyncMode::Never`.
     pub fn close(mut self) -> Result<()> {
+        if let Some(periodic) = self.periodic_sync.take() {
+            periodic.stop()?;
+        }
+        if let Some(group) = self.group_commit.take() {
+            group.stop()?;
+        }
+        let sync = self.syncs();
         for (_, wal) in self.collection_wals.drain() {
-            wal.log.close()?;
+            if sync {
+                wal.log.close()?;
+            }
         }
         Ok(())
     }
     
     /// Recover from WAL files
+    ///
+    /// Progress is logged once recovery has run for a while, along with a
+    /// warning if it exceeds the configured time budget.
     pub fn recover(&mut self) -> Result<()> {
+        self.recover_with_progress(|event| match event {
+            RecoveryEvent::Progress(progress) if progress.elapsed >= SLOW_RECOVERY_LOG_AFTER => {
+                info!(entries = progress.entries_processed, bytes = progress.bytes_processed,
+                    total_bytes = progress.total_bytes,
+                    remaining = ?progress.estimated_remaining().unwrap_or_default(),
+                    "WAL recovery in progress");
+            }
+            RecoveryEvent::BudgetExceeded { elapsed, budget } => {
+                warn!(?elapsed, ?budget,
+                    "WAL recovery is over its time budget; checkpoint more often to keep recovery short");
+            }
+            _ => {}
+        })
+    }
+    
+    /// Recover WAL state, reporting progress to `on_event`
+    pub fn recover_with_progress(&mut self, mut on_event: impl FnMut(RecoveryEvent)) -> Result<()> {
         // Read WAL directory
         let entries = std::fs::read_dir(&self.wal_dir)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
         
+        // Collections are named by their active `<name>.wal` file or, if a
+        // crash interrupted a rotation, by a `<name>.wal.<timestamp>` segment
+        let mut collection_names = BTreeSet::new();
         for entry in entries {
-            let entry = entry.map_err(|e| Error::IoError(e))?;
-            let path = entry.path();
+            let entry = entry.map_err(Error::IoError)?;
+            let file_name = entry.file_name();
             
-            if path.extension() == Some(std::ffi::OsStr::new("wal")) {
-                // Extract collection name from filename
-                if let Some(filename) = path.file_stem() {
-                    if let Some(collection_name) = filename.to_str() {
-                        self.recover_collection(collection_name, &path)?;
+            if let Some(file_name) = file_name.to_str() {
+                if let Some(name) = file_name.strip_suffix(".wal") {
+                    collection_names.insert(name.to_string());
+                } else if let Some((name, suffix)) = file_name.rsplit_once(".wal.") {
+                    if suffix.parse::<u128>().is_ok() {
+                        collection_names.insert(name.to_string());
                     }
                 }
             }
         }
         
+        let mut total_bytes = 0;
+        for collection_name in &collection_names {
+            let wal_path = self.wal_path(collection_name);
+            for path in self.log_segments(collection_name)?.iter().chain(std::iter::once(&wal_path)) {
+                total_bytes += file_size(path);
+            }
+        }
+        
+        debug!(dir = %self.wal_dir.display(), collections = collection_names.len(), total_bytes,
+            "WAL recovery started");
+        
+        let budget = self.config.recovery_time_budget_ms;
+        let mut tracker = RecoveryTracker {
+            started: Instant::now(),
+            budget: (budget > 0).then(|| Duration::from_millis(budget)),
+            warned: false,
+            entries: 0,
+            finished_bytes: 0,
+            total_bytes,
+            on_event: &mut on_event,
+        };
+        
+        for collection_name in collection_names {
+            self.recover_collection(&collection_name, &mut tracker)?;
+        }
+        tracker.report(total_bytes);
+        debug!(entries = tracker.entries, elapsed = ?tracker.started.elapsed(), "WAL recovery finished");
+        
         Ok(())
     }
     
-    /// Recover a specific collection from its WAL
-    fn recover_collection(&mut self, collection_name: &str, wal_path: &Path) -> Result<()> {
-        let mut log = WalLog::open(wal_path, self.config.sync_on_write)?;
+    /// Recover a specific collection from its WAL segments and active WAL file
+    fn recover_collection(&mut self, collection_name: &str, tracker: &mut RecoveryTracker) -> Result<()> {
+        let wal_path = self.wal_path(collection_name);
+        let segments = self.log_segments(collection_name)?;
         
-        // Iterate through all entries
-        let mut valid_transactions = HashMap::new();
+        // Iterate through all entries, oldest segment first
         let mut completed_transactions = HashMap::new();
         
-        for result in log.iterate()? {
-            let (position, entry) = result?;
+        let mut paths = segments.clone();
+        if wal_path.exists() {
+            paths.push(wal_path.clone());
+        }
+        
+        let mut has_tx_records = false;
+        for path in &paths {
+            let mut log = WalLog::open(path, false)?;
+            has_tx_records = false;
             
-            match entry.header.entry_type {
-                EntryType::BeginTx => {
-                    let tx_id = entry.header.transaction_id;
-                    valid_transactions.insert(tx_id, true);
-                }
-                EntryType::CommitTx => {
-                    let tx_id = entry.header.transaction_id;
-                    completed_transactions.insert(tx_id, true);
-                }
-                EntryType::AbortTx => {
-                    let tx_id = entry.header.transaction_id;
-                    completed_transactions.insert(tx_id, false);
+            let mut valid_end = WAL_HEADER_SIZE as u64;
+            let mut unreadable = None;
+            for result in log.iterate()? {
+                let (position, entry) = match result {
+                    Ok(read) => read,
+                    Err(e) => {
+                        unreadable = Some(e);
+                        break;
+                    }
+                };
+                valid_end = position + entry.size() as u64;
+                tracker.entry(position);
+                
+                if entry.header.entry_type.is_transaction_record() {
+                    has_tx_records = true;
                 }
-                EntryType::Insert | EntryType::Update | EntryType::Delete => {
-                    let tx_id = entry.header.transaction_id;
-                    
-                    // Only process non-transactional entries or entries in committed transactions
-                    if tx_id == 0 || (completed_transactions.get(&tx_id) == Some(&true)) {
-                        // Cache this entry
-                        self.entry_cache.insert(
-                            (collection_name.to_string(), entry.header.document_id.clone()),
-                            position,
-                        );
+                match entry.header.entry_type {
+                    EntryType::CommitTx => {
+                        let tx_id = entry.header.transaction_id;
+                        completed_transactions.insert(tx_id, true);
+                    }
+                    EntryType::AbortTx => {
+                        let tx_id = entry.header.transaction_id;
+                        completed_transactions.insert(tx_id, false);
                     }
+                    EntryType::Insert | EntryType::Update | EntryType::Delete => {
+                        let tx_id = entry.header.transaction_id;
+                        
+                        // Only process non-transactional entries or entries in committed transactions
+                        if tx_id == 0 || (completed_transactions.get(&tx_id) == Some(&true)) {
+                            // Cache this entry
+                            self.entry_cache.insert(
+                                (collection_name.to_string(), entry.header.document_id.clone()),
+                                position,
+                            );
+                        }
+                    }
+                    _ => {} // Ignore other entry types
+                }
+            }
+            
+            // A crash mid-append leaves a partial entry at the end of the
+            // file, which is dropped; valid entries past a bad one mean the
+            // log itself is damaged
+            if let Some(e) = unreadable {
+                if log.has_valid_entry_after(valid_end)? {
+                    return Err(Error::Other(format!(
+                        "Corrupt WAL entry at position {} of {:?}, followed by valid entries: {:?}",
+                        valid_end, path, e)));
                 }
-                _ => {} // Ignore other entry types
+                warn!(path = %path.display(), position = valid_end, error = ?e,
+                    "truncating partially written WAL entry");
+                log.truncate(valid_end)?;
             }
+            tracker.finish_file(file_size(path));
         }
+        // Only the active file's records count, the last one read
+        let has_tx_records = has_tx_records && paths.last() == Some(&wal_path);
         
-        // In a real implementation, we would:
-        // 1. Apply valid entries to storage
-        // 2. Clean up aborted transactions
+        // Storage applies the committed entries through `replay` as each
+        // collection opens
+        
+        // Add this WAL to the collection_wals map, starting a fresh active
+        // file if a crash left only rotated segments behind
+        let log = if wal_path.exists() {
+            WalLog::open(&wal_path, self.sync_each_write())?
+        } else {
+            WalLog::create(&wal_path, self.sync_each_write())?
+        };
         
-        // Add this WAL to the collection_wals map
+        let rotated_bytes = read_rotated_bytes(&wal_path)?;
         self.collection_wals.insert(collection_name.to_string(), CollectionWal {
             log,
-            path: wal_path.to_path_buf(),
+            path: wal_path,
+            segments,
             last_checkpoint: SystemTime::now(),
-            next_tx_id: valid_transactions.keys().max().unwrap_or(&0) + 1,
+            has_tx_records,
+            retired_syncs: 0,
+            rotated_bytes,
         });
+        debug!(collection = collection_name, files = paths.len(),
+            committed_transactions = completed_transactions.values().filter(|&&committed| committed).count(),
+            "WAL recovered collection");
         
         Ok(())
     }
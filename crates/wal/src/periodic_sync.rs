@@ -0,0 +1,125 @@
+//! Background fsync of WAL files on an interval, for `SyncMode::Periodic`
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::{Result, WalError};
+use crate::WalLog;
+
+#[derive(Debug, Default)]
+struct SyncState {
+    /// Handles of the files written since the last sync
+    dirty: HashMap<PathBuf, File>,
+    /// Set on shutdown; the thread syncs once more and exits
+    stopped: bool,
+    /// Number of file syncs run
+    syncs: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<SyncState>,
+    /// Signalled on shutdown
+    wake: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> Result<MutexGuard<'_, SyncState>> {
+        self.state.lock().map_err(|_| WalError::Other("Periodic sync state poisoned".to_string()))
+    }
+}
+
+/// Thread syncing the WAL files written to since its last run
+#[derive(Debug)]
+pub struct PeriodicSync {
+    shared: Arc<Shared>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl PeriodicSync {
+    /// Start syncing every `interval`
+    pub fn start(interval: Duration) -> Arc<Self> {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+        let handle = thread::spawn(move || run(&thread_shared, interval));
+
+        Arc::new(Self {
+            shared,
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    /// Record a write to `log`, to be synced on the next run
+    pub(crate) fn register(&self, log: &WalLog) -> Result<()> {
+        let mut state = self.shared.lock()?;
+        if !state.dirty.contains_key(log.path()) {
+            let file = log.try_clone_file()?;
+            state.dirty.insert(log.path().to_path_buf(), file);
+        }
+        Ok(())
+    }
+
+    /// Drop the pending sync of the file at `path`, once it was synced and
+    /// replaced by rotation
+    pub(crate) fn forget(&self, path: &Path) -> Result<()> {
+        self.shared.lock()?.dirty.remove(path);
+        Ok(())
+    }
+
+    /// Number of file syncs run so far
+    pub fn sync_count(&self) -> u64 {
+        self.shared.lock().map(|state| state.syncs).unwrap_or(0)
+    }
+
+    /// Sync the pending files one last time and stop the thread
+    pub fn stop(&self) -> Result<()> {
+        self.shared.lock()?.stopped = true;
+        self.shared.wake.notify_all();
+
+        let handle = self.handle.lock()
+            .map_err(|_| WalError::Other("Periodic sync handle poisoned".to_string()))?
+            .take();
+        if let Some(handle) = handle {
+            handle.join().map_err(|_| WalError::Other("Periodic sync thread panicked".to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PeriodicSync {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            tracing::warn!(error = ?e, "failed to stop periodic WAL sync");
+        }
+    }
+}
+
+fn run(shared: &Shared, interval: Duration) {
+    loop {
+        let (files, stopped) = {
+            let Ok(state) = shared.lock() else { return };
+            let Ok((mut state, _)) = shared.wake.wait_timeout_while(state, interval, |state| !state.stopped) else {
+                return;
+            };
+            let files: Vec<File> = state.dirty.drain().map(|(_, file)| file).collect();
+            (files, state.stopped)
+        };
+
+        for file in &files {
+            if let Err(e) = file.sync_data() {
+                tracing::warn!(error = %e, "periodic WAL sync failed");
+            }
+        }
+        if let Ok(mut state) = shared.lock() {
+            state.syncs += files.len() as u64;
+        }
+
+        if stopped {
+            return;
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Read-only inspection of WAL files, for post-mortem debugging
+//!
+//! Unlike [`WalLog`](crate::WalLog), the reader never opens the file for
+//! writing and tells a truncated tail, as left by a crash mid-append, apart
+//! from a damaged entry.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::entry::{EntryHeader, WalEntry};
+use crate::error::{Result, WalError};
+use crate::log::{WAL_FORMAT_VERSION, WAL_HEADER_SIZE, WAL_MAGIC};
+
+/// Offset of the document ID length in an entry header
+const DOC_ID_LEN_OFFSET: usize = 4 + 1 + 8 + 8;
+
+/// Why reading stopped before the end of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// File offset of the unreadable entry
+    pub offset: u64,
+    /// Whether the entry is cut short by the end of the file, rather than
+    /// damaged
+    pub truncated: bool,
+    /// What is wrong with the entry
+    pub reason: String,
+}
+
+/// Entries to show when printing a WAL file
+#[derive(Debug, Clone, Default)]
+pub struct InspectFilter {
+    /// Only entries of this transaction
+    pub transaction_id: Option<u64>,
+    /// Only entries written after this UNIX timestamp
+    pub after: Option<u64>,
+}
+
+impl InspectFilter {
+    /// Whether an entry with `header` is shown
+    pub fn matches(&self, header: &EntryHeader) -> bool {
+        self.transaction_id.is_none_or(|tx| header.transaction_id == tx)
+            && self.after.is_none_or(|after| header.timestamp > after)
+    }
+}
+
+/// What [`WalReader::print`] printed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectSummary {
+    /// Entries read
+    pub entries: usize,
+    /// Entries that matched the filter
+    pub shown: usize,
+    /// Where reading stopped early, if it did
+    pub corruption: Option<Corruption>,
+}
+
+/// Iterates over the entries of a WAL file with their offsets, stopping at
+/// the first entry that cannot be read
+#[derive(Debug)]
+pub struct WalReader {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    position: usize,
+    corruption: Option<Corruption>,
+}
+
+impl WalReader {
+    /// Read the WAL file at `path`, checking its file header
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let bytes = fs::read(&path).map_err(WalError::Io)?;
+
+        if bytes.len() < WAL_HEADER_SIZE || bytes[..4] != WAL_MAGIC {
+            return Err(WalError::Other(format!("{:?} is not a WAL file", path)));
+        }
+        if bytes[4] != WAL_FORMAT_VERSION {
+            return Err(WalError::Other(format!("Unsupported WAL format version: {}", bytes[4])));
+        }
+
+        Ok(Self {
+            path,
+            bytes,
+            position: WAL_HEADER_SIZE,
+            corruption: None,
+        })
+    }
+
+    /// Path of the file being read
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where reading stopped early; set once the iterator has ended on an
+    /// unreadable entry
+    pub fn corruption(&self) -> Option<&Corruption> {
+        self.corruption.as_ref()
+    }
+
+    /// Print the entries matching `filter` as a table, followed by a warning
+    /// if reading stopped at an unreadable entry
+    pub fn print(mut self, out: &mut dyn Write, filter: &InspectFilter) -> Result<InspectSummary> {
+        writeln!(out, "{:>10}  {:<19}  {:<19}  {:>20}  {:>10}  {:>10}  document",
+                 "offset", "timestamp", "type", "collection", "tx", "size")?;
+
+        let mut entries = 0;
+        let mut shown = 0;
+        for (offset, entry) in self.by_ref() {
+            entries += 1;
+            if !filter.matches(&entry.header) {
+                continue;
+            }
+            shown += 1;
+
+            let header = &entry.header;
+            writeln!(out, "{:>10}  {:<19}  {:<19}  {:>20}  {:>10}  {:>10}  {}",
+                     offset,
+                     format_timestamp(header.timestamp),
+                     format!("{:?}", header.entry_type),
+                     header.collection_id,
+                     header.transaction_id,
+                     header.data_size,
+                     String::from_utf8_lossy(&header.document_id))?;
+        }
+
+        if let Some(corruption) = &self.corruption {
+            let kind = if corruption.truncated { "truncated" } else { "corrupt" };
+            writeln!(out, "WARNING: {} entry at offset {}: {}; stopped reading",
+                     kind, corruption.offset, corruption.reason)?;
+        }
+
+        Ok(InspectSummary { entries, shown, corruption: self.corruption })
+    }
+
+    fn stop(&mut self, offset: usize, truncated: bool, reason: String) -> Option<(u64, WalEntry)> {
+        self.corruption = Some(Corruption { offset: offset as u64, truncated, reason });
+        None
+    }
+}
+
+impl Iterator for WalReader {
+    type Item = (u64, WalEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.bytes.len() || self.corruption.is_some() {
+            return None;
+        }
+
+        let offset = self.position;
+        let rest = &self.bytes[offset..];
+        let (header, header_len) = match EntryHeader::from_bytes(rest) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let truncated = header_truncated(rest);
+                return self.stop(offset, truncated, format!("{:?}", e));
+            }
+        };
+
+        let entry_len = header_len + header.data_size as usize;
+        if entry_len > rest.len() {
+            let reason = format!("{} of {} bytes present", rest.len(), entry_len);
+            return self.stop(offset, true, reason);
+        }
+
+        match WalEntry::from_bytes(&rest[..entry_len]) {
+            Ok((entry, _)) => {
+                self.position += entry_len;
+                Some((offset as u64, entry))
+            }
+            Err(e) => self.stop(offset, false, format!("{:?}", e)),
+        }
+    }
+}
+
+/// Whether an entry header that failed to parse is a valid prefix cut short
+/// by the end of the file
+fn header_truncated(bytes: &[u8]) -> bool {
+    if !bytes.iter().zip(EntryHeader::MAGIC).all(|(byte, magic)| *byte == magic) {
+        return false;
+    }
+    if bytes.len() < DOC_ID_LEN_OFFSET + 2 {
+        return true;
+    }
+
+    let doc_id_len = u16::from_le_bytes([bytes[DOC_ID_LEN_OFFSET], bytes[DOC_ID_LEN_OFFSET + 1]]) as usize;
+    bytes.len() < EntryHeader::FIXED_SIZE + doc_id_len
+}
+
+/// Format a UNIX timestamp as `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Parse a UNIX timestamp given either in seconds or as
+/// `YYYY-MM-DD[ HH:MM:SS]` in UTC; a `T` may separate the date and time
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+
+    let (date, time) = match value.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    let mut seconds_of_day = 0;
+    if let Some(time) = time {
+        let mut parts = time.splitn(3, ':').map(str::parse::<u64>);
+        let (hours, minutes, seconds) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        seconds_of_day = hours * 3600 + minutes * 60 + seconds;
+    }
+
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    Some(days as u64 * 86_400 + seconds_of_day)
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Proleptic Gregorian date to days since 1970-01-01
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::EntryType;
+    use crate::WalLog;
+
+    fn write_log(path: &Path) -> Vec<u64> {
+        let mut log = WalLog::create(path, false).unwrap();
+        let positions = (0..3)
+            .map(|i| {
+                let entry = WalEntry::new(EntryType::Insert, 7, i, format!("doc{}", i).into_bytes(), vec![b'x'; 100]);
+                log.append(&entry).unwrap()
+            })
+            .collect();
+        log.close().unwrap();
+        positions
+    }
+
+    fn print(path: &Path, filter: &InspectFilter) -> (InspectSummary, String) {
+        let mut out = Vec::new();
+        let summary = WalReader::open(path).unwrap().print(&mut out, filter).unwrap();
+        (summary, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_reads_every_entry_with_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.wal");
+        let positions = write_log(&path);
+
+        let mut reader = WalReader::open(&path).unwrap();
+        let offsets: Vec<u64> = reader.by_ref().map(|(offset, _)| offset).collect();
+        assert_eq!(offsets, positions);
+        assert!(reader.corruption().is_none());
+
+        let filter = InspectFilter { transaction_id: Some(1), after: None };
+        let (summary, out) = print(&path, &filter);
+        assert_eq!((summary.entries, summary.shown), (3, 1));
+        assert!(out.contains("doc1") && !out.contains("doc2"));
+    }
+
+    #[test]
+    fn test_truncated_tail_stops_with_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.wal");
+        let positions = write_log(&path);
+
+        // Cut the last entry short, inside its data and then inside its header
+        let bytes = std::fs::read(&path).unwrap();
+        for cut in [bytes.len() - 10, positions[2] as usize + 10] {
+            std::fs::write(&path, &bytes[..cut]).unwrap();
+
+            let (summary, out) = print(&path, &InspectFilter::default());
+            assert_eq!(summary.entries, 2);
+            let corruption = summary.corruption.unwrap();
+            assert_eq!(corruption.offset, positions[2]);
+            assert!(corruption.truncated);
+            assert!(out.contains(&format!("WARNING: truncated entry at offset {}", positions[2])));
+        }
+    }
+
+    #[test]
+    fn test_damaged_entry_is_not_reported_as_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.wal");
+        let positions = write_log(&path);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[positions[1] as usize] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let (summary, _) = print(&path, &InspectFilter::default());
+        assert_eq!(summary.entries, 1);
+        let corruption = summary.corruption.unwrap();
+        assert_eq!(corruption.offset, positions[1]);
+        assert!(!corruption.truncated);
+    }
+
+    #[test]
+    fn test_timestamps_round_trip() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(parse_timestamp("1700000000"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20"), Some(1_700_000_000));
+        assert_eq!(format_timestamp(parse_timestamp("2026-10-16 08:30:05").unwrap()), "2026-10-16 08:30:05");
+        assert_eq!(parse_timestamp("2026-13-01"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
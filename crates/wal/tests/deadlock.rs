@@ -0,0 +1,125 @@
+//! Deadlock detection tests
+
+use std::collections::HashMap;
+use std::sync::{Arc, Barrier, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use nebuladb_core::{Error, Result};
+use nebuladb_wal::manager::WalManager;
+use nebuladb_wal::{SyncMode, WalConfig};
+
+/// Lock owners by key, standing in for a lock manager
+type Locks = Mutex<HashMap<&'static str, u64>>;
+
+fn config(dir: &std::path::Path) -> WalConfig {
+    WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        max_file_size: 64 * 1024 * 1024,
+        max_segments_to_keep: 2,
+        shared: false,
+        sync_mode: SyncMode::Never,
+        group_commit_interval_ms: 0,
+        group_commit_max_entries: 1024,
+        checkpoint_interval: 0,
+        recovery_time_budget_ms: 0,
+    }
+}
+
+/// Take the lock on `key` for `tx`, registering waits with the WAL manager
+fn acquire(locks: &Locks, wal: &RwLock<WalManager>, tx: u64, key: &'static str) -> Result<()> {
+    loop {
+        let holder = {
+            let mut locks = locks.lock().unwrap();
+            match locks.get(key) {
+                Some(&holder) if holder != tx => holder,
+                _ => {
+                    locks.insert(key, tx);
+                    break;
+                }
+            }
+        };
+
+        match wal.write().unwrap().wait_for(tx, holder) {
+            Err(Error::Deadlock { aborted_tx_id }) if aborted_tx_id != tx => {}
+            result => result?,
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    wal.write().unwrap().stop_waiting(tx);
+    Ok(())
+}
+
+fn release(locks: &Locks, tx: u64) {
+    locks.lock().unwrap().retain(|_, holder| *holder != tx);
+}
+
+/// Lock `first` then `second` in a transaction, retrying after a deadlock;
+/// returns the number of deadlocks hit
+fn transfer(locks: &Locks, wal: &RwLock<WalManager>, barrier: &Barrier, first: &'static str, second: &'static str) -> usize {
+    let mut deadlocks = 0;
+    loop {
+        let tx = wal.write().unwrap().begin_transaction().unwrap();
+        acquire(locks, wal, tx, first).unwrap();
+        if deadlocks == 0 {
+            // Both threads hold their first lock before either asks for its second
+            barrier.wait();
+        }
+
+        match acquire(locks, wal, tx, second) {
+            Ok(()) => {
+                wal.write().unwrap().commit_transaction(tx).unwrap();
+                release(locks, tx);
+                return deadlocks;
+            }
+            Err(Error::Deadlock { aborted_tx_id }) => {
+                assert_eq!(aborted_tx_id, tx);
+                release(locks, tx);
+                deadlocks += 1;
+                // Let the other transaction take the lock before retrying
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_opposite_lock_order_deadlock_retried() {
+    let dir = tempfile::tempdir().unwrap();
+    let wal = Arc::new(RwLock::new(WalManager::new(config(dir.path())).unwrap()));
+    let locks = Arc::new(Locks::default());
+    let barrier = Arc::new(Barrier::new(2));
+
+    let handles: Vec<_> = [("a", "b"), ("b", "a")].into_iter()
+        .map(|(first, second)| {
+            let (wal, locks, barrier) = (Arc::clone(&wal), Arc::clone(&locks), Arc::clone(&barrier));
+            thread::spawn(move || transfer(&locks, &wal, &barrier, first, second))
+        })
+        .collect();
+    let mut deadlocks: Vec<usize> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+    // Only the younger transaction was aborted, and its retry succeeded
+    deadlocks.sort_unstable();
+    assert_eq!(deadlocks[0], 0);
+    assert!(deadlocks[1] >= 1);
+    assert!(locks.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_youngest_transaction_aborted() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = WalManager::new(config(dir.path())).unwrap();
+    let older = wal.begin_transaction().unwrap();
+    let younger = wal.begin_transaction().unwrap();
+
+    // The younger transaction's wait is recorded first; the older one closes the cycle
+    wal.wait_for(younger, older).unwrap();
+    assert!(matches!(wal.wait_for(older, younger), Err(Error::Deadlock { aborted_tx_id }) if aborted_tx_id == younger));
+
+    // The victim learns of its abort on its next wait, and the older one goes on
+    assert!(matches!(wal.wait_for(younger, older), Err(Error::Deadlock { aborted_tx_id }) if aborted_tx_id == younger));
+    wal.wait_for(older, younger).unwrap();
+    wal.commit_transaction(older).unwrap();
+}
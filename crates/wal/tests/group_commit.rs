@@ -0,0 +1,96 @@
+//! Group commit tests
+
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nebuladb_wal::manager::WalManager;
+use nebuladb_wal::{SyncMode, WalConfig};
+
+const THREADS: usize = 8;
+const WRITES_PER_THREAD: usize = 50;
+
+fn config(dir: &std::path::Path, group_commit_interval_ms: u64) -> WalConfig {
+    WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        max_file_size: 64 * 1024 * 1024,
+        max_segments_to_keep: 2,
+        shared: false,
+        sync_mode: SyncMode::Always,
+        group_commit_interval_ms,
+        group_commit_max_entries: 1024,
+        checkpoint_interval: 0,
+        recovery_time_budget_ms: 0,
+    }
+}
+
+/// Insert from several threads, each waiting for its write to be durable
+fn insert_concurrently(manager: &Arc<RwLock<WalManager>>) {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let manager = Arc::clone(manager);
+            thread::spawn(move || {
+                for i in 0..WRITES_PER_THREAD {
+                    let id = format!("doc{}_{}", t, i);
+                    let commit = {
+                        let mut manager = manager.write().unwrap();
+                        manager.insert("users", id.as_bytes(), b"{}").unwrap();
+                        manager.take_commit()
+                    };
+                    if let Some(commit) = commit {
+                        commit.wait().unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_group_commit_shares_fsyncs() {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = Arc::new(RwLock::new(WalManager::new(config(dir.path(), 5)).unwrap()));
+
+    insert_concurrently(&manager);
+
+    let writes = (THREADS * WRITES_PER_THREAD) as u64;
+    let syncs = manager.read().unwrap().group_commit_syncs();
+    assert!(syncs > 0);
+    assert!(syncs < writes / 2, "{} fsyncs for {} writes", syncs, writes);
+    assert!(manager.write().unwrap().take_commit().is_none());
+
+    // Every waited-on write is in the log after a crash
+    drop(manager);
+    let mut manager = WalManager::new(config(dir.path(), 5)).unwrap();
+    manager.recover().unwrap();
+    assert_eq!(manager.read_entries("users").unwrap().len(), writes as usize);
+}
+
+#[test]
+fn test_no_group_commit_without_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = WalManager::new(config(dir.path(), 0)).unwrap();
+
+    manager.insert("users", b"doc", b"{}").unwrap();
+    assert!(manager.take_commit().is_none());
+    assert_eq!(manager.group_commit_syncs(), 0);
+}
+
+#[test]
+fn test_full_batch_commits_before_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal_config = config(dir.path(), 60_000);
+    wal_config.group_commit_max_entries = 1;
+    let mut manager = WalManager::new(wal_config).unwrap();
+
+    // The first pending entry fills the batch, so the wait is not a minute
+    let start = Instant::now();
+    manager.insert("users", b"doc", b"{}").unwrap();
+    manager.take_commit().unwrap().wait().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert_eq!(manager.group_commit_syncs(), 1);
+}
@@ -0,0 +1,246 @@
+//! Selecting WAL entries to replay after a crash
+
+use nebuladb_wal::manager::{RecoveryEvent, WalManager, RECOVERY_PROGRESS_INTERVAL};
+use nebuladb_wal::{SyncMode, WalConfig};
+use std::sync::{Arc, Mutex};
+
+fn manager(dir: &std::path::Path) -> WalManager {
+    WalManager::new(WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        sync_mode: SyncMode::Never,
+        group_commit_interval_ms: 0,
+        checkpoint_interval: 0,
+        ..WalConfig::default()
+    })
+    .unwrap()
+}
+
+fn replayed_ids(manager: &WalManager, collection: &str) -> Vec<String> {
+    manager.committed_entries(collection).unwrap()
+        .into_iter()
+        .map(|entry| String::from_utf8(entry.header.document_id).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_uncommitted_transactions_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+
+    wal.insert("users", b"plain", b"{}").unwrap();
+
+    let committed = wal.begin_transaction().unwrap();
+    wal.insert_in_transaction(committed, "users", b"committed", b"{}").unwrap();
+    wal.commit_transaction(committed).unwrap();
+
+    let aborted = wal.begin_transaction().unwrap();
+    wal.insert_in_transaction(aborted, "users", b"aborted", b"{}").unwrap();
+    wal.abort_transaction(aborted).unwrap();
+
+    let in_flight = wal.begin_transaction().unwrap();
+    wal.insert_in_transaction(in_flight, "users", b"in_flight", b"{}").unwrap();
+    drop(wal);
+
+    assert_eq!(replayed_ids(&manager(dir.path()), "users"), vec!["plain", "committed"]);
+}
+
+#[test]
+fn test_transaction_ids_are_not_reused_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+    let in_flight = wal.begin_transaction().unwrap();
+    wal.insert_in_transaction(in_flight, "users", b"in_flight", b"{}").unwrap();
+    drop(wal);
+
+    // Reopened without recovery, a new transaction must not take the ID of
+    // the one left in flight, or its commit would replay that one too
+    let mut wal = manager(dir.path());
+    let tx = wal.begin_transaction().unwrap();
+    assert!(tx > in_flight);
+    wal.insert_in_transaction(tx, "users", b"committed", b"{}").unwrap();
+    wal.commit_transaction(tx).unwrap();
+    drop(wal);
+
+    assert_eq!(replayed_ids(&manager(dir.path()), "users"), vec!["committed"]);
+}
+
+#[test]
+fn test_entries_before_checkpoint_are_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+
+    wal.insert("users", b"before", b"{}").unwrap();
+    wal.checkpoint("users").unwrap();
+    wal.insert("users", b"after", b"{}").unwrap();
+    wal.delete("users", b"before").unwrap();
+    drop(wal);
+
+    assert_eq!(replayed_ids(&manager(dir.path()), "users"), vec!["after", "before"]);
+}
+
+#[test]
+fn test_rollback_to_savepoint_discards_later_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+
+    let tx = wal.begin_transaction().unwrap();
+    wal.insert_in_transaction(tx, "users", b"before", b"{}").unwrap();
+    let savepoint = wal.savepoint(tx, "sp1").unwrap();
+    wal.insert_in_transaction(tx, "users", b"after", b"{}").unwrap();
+    // A collection first written after the savepoint loses all its writes
+    wal.insert_in_transaction(tx, "orders", b"order", b"{}").unwrap();
+    let later = wal.savepoint(tx, "sp2").unwrap();
+    wal.rollback_to_savepoint(tx, savepoint).unwrap();
+
+    // The savepoint rolled back to stays usable, later ones do not
+    assert!(wal.rollback_to_savepoint(tx, later).is_err());
+    wal.insert_in_transaction(tx, "users", b"retried", b"{}").unwrap();
+    wal.rollback_to_savepoint(tx, savepoint).unwrap();
+    wal.insert_in_transaction(tx, "users", b"kept", b"{}").unwrap();
+    wal.commit_transaction(tx).unwrap();
+    drop(wal);
+
+    let wal = manager(dir.path());
+    assert_eq!(replayed_ids(&wal, "users"), vec!["before", "kept"]);
+    assert!(replayed_ids(&wal, "orders").is_empty());
+}
+
+#[test]
+fn test_large_recovery_reports_progress_and_warns_past_budget() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+    for i in 0..20_000 {
+        wal.insert("users", format!("user{}", i).as_bytes(), br#"{"name":"someone"}"#).unwrap();
+    }
+    drop(wal);
+
+    let mut recovering = WalManager::new(WalConfig {
+        dir_path: dir.path().to_string_lossy().to_string(),
+        sync_mode: SyncMode::Never,
+        group_commit_interval_ms: 0,
+        checkpoint_interval: 0,
+        recovery_time_budget_ms: 1,
+        ..WalConfig::default()
+    })
+    .unwrap();
+
+    let mut progress = Vec::new();
+    let mut warnings = 0;
+    recovering.recover_with_progress(|event| match event {
+        RecoveryEvent::Progress(p) => progress.push(p),
+        RecoveryEvent::BudgetExceeded { .. } => warnings += 1,
+    }).unwrap();
+
+    // One report per interval plus the final one
+    assert_eq!(progress.len() as u64, 20_000 / RECOVERY_PROGRESS_INTERVAL + 1);
+    assert!(progress.windows(2).all(|w| w[0].bytes_processed <= w[1].bytes_processed));
+    let last = progress.last().unwrap();
+    assert_eq!(last.entries_processed, 20_000);
+    assert_eq!(last.bytes_processed, last.total_bytes);
+    assert_eq!(last.estimated_remaining(), Some(std::time::Duration::ZERO));
+
+    // Warned once, and recovery still ran to completion
+    assert_eq!(warnings, 1);
+    assert_eq!(recovering.committed_entries("users").unwrap().len(), 20_000);
+}
+
+/// Positions of the entries in `path`, as written by `insert`
+fn entry_positions(path: &std::path::Path) -> Vec<u64> {
+    let mut log = nebuladb_wal::WalLog::open(path, false).unwrap();
+    let positions = log.iterate().unwrap().map(|result| result.unwrap().0).collect();
+    positions
+}
+
+#[test]
+fn test_partial_entry_at_tail_is_truncated() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+    for id in ["a", "b", "c"] {
+        wal.insert("users", id.as_bytes(), br#"{"value":1}"#).unwrap();
+    }
+    drop(wal);
+
+    // Crash halfway through writing the last entry
+    let path = dir.path().join("users.wal");
+    let last = entry_positions(&path)[2];
+    let len = std::fs::metadata(&path).unwrap().len();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(last + (len - last) / 2).unwrap();
+    drop(file);
+
+    let mut wal = manager(dir.path());
+    wal.recover().unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), last);
+    assert_eq!(replayed_ids(&wal, "users"), vec!["a", "b"]);
+
+    // Appends continue from the last valid entry
+    wal.insert("users", b"d", b"{}").unwrap();
+    assert_eq!(replayed_ids(&wal, "users"), vec!["a", "b", "d"]);
+}
+
+#[test]
+fn test_corruption_before_valid_entries_fails_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+    for id in ["a", "b", "c"] {
+        wal.insert("users", id.as_bytes(), br#"{"value":1}"#).unwrap();
+    }
+    drop(wal);
+
+    // Flip a byte in the data of the middle entry, breaking its checksum
+    let path = dir.path().join("users.wal");
+    let positions = entry_positions(&path);
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[positions[2] as usize - 2] ^= 0xFF;
+    std::fs::write(&path, &bytes).unwrap();
+
+    let mut wal = manager(dir.path());
+    assert!(wal.recover().is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+}
+
+/// Log output shared with the subscriber under test
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_recovery_is_logged() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut wal = manager(dir.path());
+    for id in ["a", "b", "c"] {
+        wal.insert("users", id.as_bytes(), br#"{"value":1}"#).unwrap();
+    }
+    drop(wal);
+
+    let path = dir.path().join("users.wal");
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        manager(dir.path()).recover().unwrap();
+    });
+
+    let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(output.contains("DEBUG") && output.contains("WAL recovery started"), "{}", output);
+    assert!(output.contains("WAL recovered collection collection=\"users\""), "{}", output);
+    assert!(output.contains("WARN") && output.contains("truncating partially written WAL entry"), "{}", output);
+    assert!(output.contains("WAL recovery finished entries=2"), "{}", output);
+}
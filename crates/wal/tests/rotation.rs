@@ -0,0 +1,194 @@
+//! WAL rotation and multi-segment recovery tests
+
+use nebuladb_wal::manager::WalManager;
+use nebuladb_wal::{EntryType, SyncMode, WalConfig};
+
+fn config(dir: &std::path::Path) -> WalConfig {
+    WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        max_file_size: 4096,
+        max_segments_to_keep: 2,
+        shared: false,
+        sync_mode: SyncMode::Never,
+        group_commit_interval_ms: 0,
+        group_commit_max_entries: 1024,
+        checkpoint_interval: 0,
+        recovery_time_budget_ms: 0,
+    }
+}
+
+#[test]
+fn test_rotated_segments_recovered_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut manager = WalManager::new(config(dir.path())).unwrap();
+    for i in 0..200 {
+        let id = format!("doc{}", i);
+        let data = format!(r#"{{"_id":"{}","value":{}}}"#, id, i);
+        manager.insert("users", id.as_bytes(), data.as_bytes()).unwrap();
+    }
+    assert!(manager.segment_files("users").unwrap().len() > 1);
+
+    // Simulate a crash: drop the manager without closing it
+    drop(manager);
+
+    let mut manager = WalManager::new(config(dir.path())).unwrap();
+    manager.recover().unwrap();
+
+    let entries = manager.read_entries("users").unwrap();
+    assert_eq!(entries.len(), 200);
+    for (i, entry) in entries.iter().enumerate() {
+        assert_eq!(entry.header.entry_type, EntryType::Insert);
+        assert_eq!(entry.header.document_id, format!("doc{}", i).into_bytes());
+    }
+
+    for segment in manager.segment_files("users").unwrap() {
+        assert!(std::fs::metadata(segment).unwrap().len() <= 4096);
+    }
+}
+
+#[test]
+fn test_entry_larger_than_max_file_size_is_not_split() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut manager = WalManager::new(config(dir.path())).unwrap();
+    let large = vec![b'x'; 10_000];
+    manager.insert("users", b"small", b"{}").unwrap();
+    manager.insert("users", b"large", &large).unwrap();
+    manager.insert("users", b"after", b"{}").unwrap();
+
+    // The oversized entry fills a segment of its own
+    let segments = manager.segment_files("users").unwrap();
+    assert_eq!(segments.len(), 2);
+    assert!(std::fs::metadata(&segments[1]).unwrap().len() > 10_000);
+    drop(manager);
+
+    let mut manager = WalManager::new(config(dir.path())).unwrap();
+    manager.recover().unwrap();
+    let entries = manager.read_entries("users").unwrap();
+    let ids: Vec<&[u8]> = entries.iter().map(|entry| entry.header.document_id.as_slice()).collect();
+    assert_eq!(ids, vec![b"small".as_slice(), b"large", b"after"]);
+    assert_eq!(entries[1].data, large);
+}
+
+#[test]
+fn test_checkpoint_prunes_old_segments() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut manager = WalManager::new(config(dir.path())).unwrap();
+    for i in 0..500 {
+        let id = format!("doc{}", i);
+        manager.insert("users", id.as_bytes(), b"{}").unwrap();
+    }
+    assert!(manager.segment_files("users").unwrap().len() > 2);
+
+    manager.checkpoint("users").unwrap();
+    assert_eq!(manager.segment_files("users").unwrap().len(), 2);
+}
+
+#[test]
+fn test_shared_checkpoint_keeps_segments_other_collections_need() {
+    let dir = tempfile::tempdir().unwrap();
+    let shared = WalConfig {
+        max_segments_to_keep: 0,
+        shared: true,
+        ..config(dir.path())
+    };
+
+    let mut manager = WalManager::new(shared).unwrap();
+    manager.insert("orders", b"order0", b"{}").unwrap();
+    for i in 0..200 {
+        let id = format!("doc{}", i);
+        manager.insert("users", id.as_bytes(), b"{}").unwrap();
+    }
+    let segments = manager.segment_files("users").unwrap();
+    assert!(segments.len() > 1);
+    assert_eq!(segments, manager.segment_files("orders").unwrap());
+
+    // "orders" has not checkpointed past its entry in the oldest segment
+    manager.checkpoint("users").unwrap();
+    assert_eq!(manager.segment_files("users").unwrap(), segments);
+
+    manager.checkpoint("orders").unwrap();
+    assert!(manager.segment_files("users").unwrap().is_empty());
+    assert!(manager.committed_entries("orders").unwrap().is_empty());
+}
+
+#[test]
+fn test_checkpoint_truncates_wal() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig {
+        max_segments_to_keep: 0,
+        ..config(dir.path())
+    };
+
+    let mut manager = WalManager::new(config.clone()).unwrap();
+    for i in 0..100 {
+        let id = format!("doc{}", i);
+        manager.insert("users", id.as_bytes(), b"{}").unwrap();
+    }
+    let before = manager.wal_size("users");
+    assert!(before > 4096);
+
+    manager.checkpoint("users").unwrap();
+    assert!(manager.wal_size("users") < before);
+    assert!(manager.segment_files("users").unwrap().is_empty());
+
+    // Entries after the checkpoint are kept and replayed
+    manager.insert("users", b"after", b"{}").unwrap();
+    drop(manager);
+
+    let mut manager = WalManager::new(config).unwrap();
+    manager.recover().unwrap();
+    let entries = manager.committed_entries("users").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].header.document_id, b"after");
+}
+
+#[test]
+fn test_checkpoint_keeps_transaction_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig {
+        max_segments_to_keep: 0,
+        ..config(dir.path())
+    };
+
+    let mut manager = WalManager::new(config).unwrap();
+    manager.insert("users", b"doc0", b"{}").unwrap();
+    let tx_id = manager.begin_transaction().unwrap();
+    manager.insert_in_transaction(tx_id, "users", b"doc1", b"{}").unwrap();
+    manager.commit_transaction(tx_id).unwrap();
+
+    let before = manager.wal_size("users");
+    manager.checkpoint("users").unwrap();
+    assert!(manager.wal_size("users") > before);
+}
+
+#[test]
+fn test_position_grows_across_rotation_and_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig {
+        max_segments_to_keep: 0,
+        ..config(dir.path())
+    };
+
+    let mut manager = WalManager::new(config.clone()).unwrap();
+    let mut last = manager.position("users").unwrap();
+    for i in 0..100 {
+        let id = format!("doc{}", i);
+        manager.insert("users", id.as_bytes(), b"{}").unwrap();
+        let position = manager.position("users").unwrap();
+        assert!(position > last);
+        last = position;
+    }
+    manager.checkpoint("users").unwrap();
+    assert!(manager.position("users").unwrap() >= last);
+    last = manager.position("users").unwrap();
+    drop(manager);
+
+    let mut manager = WalManager::new(config).unwrap();
+    manager.recover().unwrap();
+    assert_eq!(manager.position("users").unwrap(), last);
+    manager.insert("users", b"after", b"{}").unwrap();
+    assert!(manager.position("users").unwrap() > last);
+}
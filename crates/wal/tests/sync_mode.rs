@@ -0,0 +1,78 @@
+//! WAL sync mode tests
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nebuladb_wal::manager::WalManager;
+use nebuladb_wal::{SyncMode, WalConfig};
+
+fn config(dir: &std::path::Path, sync_mode: SyncMode) -> WalConfig {
+    WalConfig {
+        dir_path: dir.to_string_lossy().to_string(),
+        max_file_size: 4096,
+        max_segments_to_keep: 2,
+        shared: false,
+        sync_mode,
+        group_commit_interval_ms: 0,
+        group_commit_max_entries: 1024,
+        checkpoint_interval: 0,
+        recovery_time_budget_ms: 0,
+    }
+}
+
+fn insert_docs(manager: &mut WalManager, count: usize) {
+    for i in 0..count {
+        let id = format!("doc{}", i);
+        manager.insert("users", id.as_bytes(), br#"{"value":1}"#).unwrap();
+    }
+}
+
+#[test]
+fn test_never_mode_never_fsyncs() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = WalManager::new(config(dir.path(), SyncMode::Never)).unwrap();
+
+    // Rotations, checkpoints and renames sync nothing either
+    insert_docs(&mut manager, 200);
+    assert!(manager.segment_files("users").unwrap().len() > 1);
+    manager.checkpoint("users").unwrap();
+    manager.rename("users", "people").unwrap();
+    assert_eq!(manager.fsync_count(), 0);
+
+    manager.close().unwrap();
+}
+
+#[test]
+fn test_always_mode_fsyncs_every_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = WalManager::new(config(dir.path(), SyncMode::Always)).unwrap();
+
+    insert_docs(&mut manager, 20);
+    assert!(manager.fsync_count() >= 20);
+}
+
+#[test]
+fn test_periodic_mode_syncs_in_background() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = WalManager::new(config(dir.path(), SyncMode::Periodic { interval_ms: 10 })).unwrap();
+
+    // Writes return without syncing; the background thread catches up
+    insert_docs(&mut manager, 20);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while manager.fsync_count() == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    let syncs = manager.fsync_count();
+    assert!(syncs > 0 && syncs < 20, "{} fsyncs for 20 writes", syncs);
+
+    manager.close().unwrap();
+    let mut manager = WalManager::new(config(dir.path(), SyncMode::Never)).unwrap();
+    manager.recover().unwrap();
+    assert_eq!(manager.read_entries("users").unwrap().len(), 20);
+}
+
+#[test]
+fn test_periodic_mode_rejects_zero_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(WalManager::new(config(dir.path(), SyncMode::Periodic { interval_ms: 0 })).is_err());
+}
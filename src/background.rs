@@ -0,0 +1,168 @@
+//! Background task tracking and orderly shutdown
+//!
+//! Long-running threads are spawned through [`BackgroundTasks`] so shutdown
+//! can signal every one of them, join them, and report any that did not stop
+//! in time instead of leaving detached threads behind.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Shared flag telling background tasks to stop
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownSignal {
+    /// Ask every task watching this signal to stop
+    pub fn trigger(&self) {
+        let (triggered, changed) = &*self.state;
+        *triggered.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        changed.notify_all();
+    }
+
+    /// Whether shutdown has been requested
+    pub fn is_triggered(&self) -> bool {
+        *self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sleep for up to `timeout`, waking early if shutdown is requested
+    ///
+    /// Returns whether shutdown has been requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (triggered, changed) = &*self.state;
+        let guard = triggered.lock().unwrap_or_else(|e| e.into_inner());
+        let (guard, _) = changed
+            .wait_timeout_while(guard, timeout, |triggered| !*triggered)
+            .unwrap_or_else(|e| e.into_inner());
+        *guard
+    }
+}
+
+/// Outcome of [`BackgroundTasks::shutdown`]
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// Tasks that stopped and were joined
+    pub stopped: Vec<String>,
+    /// Tasks still running when the timeout expired
+    pub timed_out: Vec<String>,
+}
+
+impl ShutdownReport {
+    /// Whether every task stopped in time
+    pub fn is_clean(&self) -> bool {
+        self.timed_out.is_empty()
+    }
+
+    /// Append the outcomes of another report
+    pub fn merge(&mut self, other: ShutdownReport) {
+        self.stopped.extend(other.stopped);
+        self.timed_out.extend(other.timed_out);
+    }
+}
+
+/// Registry of named background threads sharing one shutdown signal
+#[derive(Debug, Default)]
+pub struct BackgroundTasks {
+    signal: ShutdownSignal,
+    tasks: Mutex<Vec<(String, JoinHandle<()>)>>,
+}
+
+impl BackgroundTasks {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The signal handed to every task spawned by this registry
+    pub fn signal(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    /// Spawn a named thread running `task` with the shared shutdown signal
+    ///
+    /// The task must return once the signal is triggered.
+    pub fn spawn<F>(&self, name: &str, task: F)
+    where
+        F: FnOnce(ShutdownSignal) + Send + 'static,
+    {
+        let signal = self.signal();
+        let handle = thread::spawn(move || task(signal));
+
+        let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        // Forget tasks that already finished on their own
+        tasks.retain(|(_, handle)| !handle.is_finished());
+        tasks.push((name.to_string(), handle));
+    }
+
+    /// Number of tracked tasks that are still running
+    #[cfg(test)]
+    pub fn running(&self) -> usize {
+        let tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.iter().filter(|(_, handle)| !handle.is_finished()).count()
+    }
+
+    /// Signal every task to stop and join them, waiting at most `timeout`
+    ///
+    /// Tasks still running when the timeout expires are reported and left
+    /// detached.
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.signal.trigger();
+
+        let tasks: Vec<_> = self.tasks.lock().unwrap_or_else(|e| e.into_inner()).drain(..).collect();
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline && tasks.iter().any(|(_, handle)| !handle.is_finished()) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut report = ShutdownReport::default();
+        for (name, handle) in tasks {
+            if handle.is_finished() {
+                if handle.join().is_err() {
+                    tracing::error!(task = %name, "background task panicked");
+                }
+                report.stopped.push(name);
+            } else {
+                tracing::warn!(task = %name, ?timeout, "background task did not stop in time");
+                report.timed_out.push(name);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shutdown_stops_all_tasks() {
+        let tasks = BackgroundTasks::new();
+        for i in 0..4 {
+            tasks.spawn(&format!("worker{}", i), |signal| {
+                while !signal.wait_timeout(Duration::from_secs(60)) {}
+            });
+        }
+        assert_eq!(tasks.running(), 4);
+
+        let report = tasks.shutdown(Duration::from_secs(5));
+        assert!(report.is_clean());
+        assert_eq!(report.stopped.len(), 4);
+        assert_eq!(tasks.running(), 0);
+    }
+
+    #[test]
+    fn test_shutdown_reports_stuck_task() {
+        let tasks = BackgroundTasks::new();
+        tasks.spawn("stuck", |_| thread::sleep(Duration::from_millis(500)));
+        tasks.spawn("polite", |signal| {
+            signal.wait_timeout(Duration::from_secs(60));
+        });
+
+        let report = tasks.shutdown(Duration::from_millis(50));
+        assert_eq!(report.stopped, vec!["polite".to_string()]);
+        assert_eq!(report.timed_out, vec!["stuck".to_string()]);
+    }
+}
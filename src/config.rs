@@ -1,10 +1,13 @@
 use std::path::PathBuf;
 use std::fs::File;
 use std::io::Read;
+use std::time::Duration;
 use nebuladb_core::{Result, Error, Config as CoreConfig};
 use nebuladb_storage::StorageConfig;
-use nebuladb_wal::WalConfig;
+use nebuladb_wal::{SyncMode, WalConfig};
 use serde::{Serialize, Deserialize};
+use serde_json::Value as JsonValue;
+use crate::interfaces::auth::HttpAuthConfig;
 use crate::interfaces::http::ConnectionPoolConfig;
 use crate::interfaces::grpc::GrpcConnectionPoolConfig;
 
@@ -28,6 +31,9 @@ pub struct SystemConfig {
     
     /// Multi-threading and concurrency configuration
     pub concurrency: ConcurrencyConfig,
+    
+    /// Diagnostic logging configuration
+    pub logging: LoggingConfig,
 }
 
 /// Storage engine configuration
@@ -39,11 +45,31 @@ pub struct StorageEngineConfig {
     /// Compression algorithm
     pub compression_type: String,
     
+    /// Compression level (only used by algorithms that support levels)
+    pub compression_level: i32,
+    
     /// Auto-flush threshold (number of documents)
     pub flush_threshold: usize,
     
     /// Cache size in MB
     pub cache_size_mb: usize,
+    
+    /// Flush a collection's active block after this many milliseconds without
+    /// a write (null disables idle flushing)
+    pub idle_flush_timeout_ms: Option<u64>,
+    
+    /// Store a document-offset directory in each block footer
+    pub block_offset_directory: bool,
+    
+    /// Interval for persisting the active block as a partial block, in milliseconds
+    /// (null disables partial block persistence)
+    pub partial_block_interval_ms: Option<u64>,
+    
+    /// Maximum number of compactions running at once
+    pub max_concurrent_compactions: usize,
+    
+    /// Seconds between sweeps for documents expired by TTL indexes (0 disables them)
+    pub ttl_check_interval_secs: u64,
 }
 
 /// Interface configuration
@@ -70,6 +96,29 @@ pub struct HttpConfig {
     
     /// Connection pool configuration
     pub pool: ConnectionPoolConfig,
+    
+    /// Credentials required of requests; with none, requests are rejected
+    /// unless `allow_anonymous` is set
+    #[serde(default)]
+    pub auth: HttpAuthConfig,
+    
+    /// Requests per second each client IP may sustain (0 disables rate limiting)
+    #[serde(default)]
+    pub rate_limit_rps: u32,
+    
+    /// Requests a client IP may send at once before being limited
+    #[serde(default)]
+    pub rate_limit_burst: u32,
+    
+    /// Port serving only `GET /metrics`, without authentication; when unset
+    /// the metrics are served on `port` alongside the API
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+    
+    /// Answer `GET /health` with 200 rather than 503 while a check fails,
+    /// for load balancers that should keep a degraded server in rotation
+    #[serde(default)]
+    pub health_ok_when_degraded: bool,
 }
 
 /// gRPC interface configuration
@@ -85,6 +134,39 @@ pub struct GrpcConfig {
     pub pool: GrpcConnectionPoolConfig,
 }
 
+/// Diagnostic logging configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Minimum level logged, or a filter such as `info,nebuladb_wal=debug`
+    pub level: String,
+    
+    /// How each event is written
+    pub format: LogFormat,
+    
+    /// Where events are written
+    pub output: LogOutput,
+}
+
+/// Format of logged events
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Destination of logged events
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogOutput {
+    /// Standard error
+    Stderr,
+    /// A file, appended to
+    File(PathBuf),
+}
+
 /// Concurrency configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ConcurrencyConfig {
@@ -112,12 +194,18 @@ impl Default for SystemConfig {
             storage: StorageEngineConfig::default(),
             wal: WalConfig {
                 dir_path: "./data/wal".to_string(),
-                sync_on_write: true,
+                sync_mode: SyncMode::Always,
+                group_commit_interval_ms: 0,
+                group_commit_max_entries: 1024,
                 checkpoint_interval: 60,
                 max_file_size: 64 * 1024 * 1024, // 64MB
+                max_segments_to_keep: 4,
+                shared: false,
+                recovery_time_budget_ms: 30_000,
             },
             interfaces: InterfaceConfig::default(),
             concurrency: ConcurrencyConfig::default(),
+            logging: LoggingConfig::default(),
         }
     }
 }
@@ -127,8 +215,14 @@ impl Default for StorageEngineConfig {
         Self {
             block_size: 4 * 1024 * 1024, // 4MB
             compression_type: "zstd".to_string(),
+            compression_level: 3,
             flush_threshold: 1000,
             cache_size_mb: 128, // 128MB cache
+            idle_flush_timeout_ms: None,
+            block_offset_directory: true,
+            partial_block_interval_ms: None,
+            max_concurrent_compactions: 1,
+            ttl_check_interval_secs: 60,
         }
     }
 }
@@ -149,6 +243,11 @@ impl Default for HttpConfig {
             enabled: true,
             port: 8080,
             pool: ConnectionPoolConfig::default(),
+            auth: HttpAuthConfig::default(),
+            rate_limit_rps: 0,
+            rate_limit_burst: 0,
+            metrics_port: None,
+            health_ok_when_degraded: false,
         }
     }
 }
@@ -163,6 +262,16 @@ impl Default for GrpcConfig {
     }
 }
 
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            output: LogOutput::Stderr,
+        }
+    }
+}
+
 impl Default for ConcurrencyConfig {
     fn default() -> Self {
         Self {
@@ -179,25 +288,101 @@ impl SystemConfig {
     /// Load configuration from a file
     pub fn load_from_file(path: &str) -> Result<Self> {
         let mut file = File::open(path)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
             
         let mut contents = String::new();
         file.read_to_string(&mut contents)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
             
-        let config = serde_json::from_str(&contents)
+        Self::from_json_str(&contents)
+    }
+    
+    /// Parse configuration from a JSON string
+    ///
+    /// The parsed values are merged over the defaults, so sections or keys
+    /// missing from the input keep their default values. Keys that are not
+    /// part of the schema are rejected, as are invalid field combinations.
+    pub fn from_json_str(contents: &str) -> Result<Self> {
+        let mut overrides: JsonValue = serde_json::from_str(contents)
             .map_err(|e| Error::Other(format!("Failed to parse config: {}", e)))?;
-            
+        upgrade_legacy_keys(&mut overrides);
+        
+        let mut merged = serde_json::to_value(Self::default())
+            .map_err(|e| Error::Other(format!("Failed to serialize default config: {}", e)))?;
+        
+        let mut unknown_keys = Vec::new();
+        merge_json(&mut merged, overrides, "", &mut unknown_keys);
+        
+        if !unknown_keys.is_empty() {
+            return Err(Error::Other(format!(
+                "Unknown configuration key(s): {}", unknown_keys.join(", "))));
+        }
+        
+        let config: Self = serde_json::from_value(merged)
+            .map_err(|e| Error::Other(format!("Failed to parse config: {}", e)))?;
+        
+        config.validate()?;
+        
         Ok(config)
     }
     
+    /// Validate constraints that span multiple fields
+    pub fn validate(&self) -> Result<()> {
+        let http = &self.interfaces.http;
+        let grpc = &self.interfaces.grpc;
+        
+        if http.enabled && grpc.enabled && http.port == grpc.port {
+            return Err(Error::Other(format!(
+                "HTTP and gRPC interfaces cannot share port {}", http.port)));
+        }
+        
+        if let Some(metrics_port) = http.metrics_port {
+            if metrics_port == http.port || (grpc.enabled && metrics_port == grpc.port) {
+                return Err(Error::Other(format!(
+                    "interfaces.http.metrics_port {} is already used by another interface", metrics_port)));
+            }
+        }
+        
+        if http.rate_limit_rps > 0 && http.rate_limit_burst == 0 {
+            return Err(Error::Other(
+                "interfaces.http.rate_limit_burst must be greater than 0 when rate limiting is enabled".to_string()));
+        }
+        
+        if !matches!(self.storage.compression_type.as_str(), "none" | "snappy" | "zstd" | "lz4") {
+            return Err(Error::Other(format!(
+                "Unknown compression type '{}'", self.storage.compression_type)));
+        }
+        
+        if self.storage.block_size == 0 {
+            return Err(Error::Other("storage.block_size must be greater than 0".to_string()));
+        }
+        
+        if self.storage.max_concurrent_compactions == 0 {
+            return Err(Error::Other("storage.max_concurrent_compactions must be greater than 0".to_string()));
+        }
+        
+        if self.storage.flush_threshold == 0 {
+            return Err(Error::Other("storage.flush_threshold must be greater than 0".to_string()));
+        }
+        
+        if self.concurrency.max_databases == 0 {
+            return Err(Error::Other("concurrency.max_databases must be greater than 0".to_string()));
+        }
+        
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            return Err(Error::Other(format!("Invalid logging.level '{}': {}", self.logging.level, e)));
+        }
+        
+        Ok(())
+    }
+    
     /// Save configuration to a file
     pub fn save_to_file(&self, path: &str) -> Result<()> {
         let contents = serde_json::to_string_pretty(self)
             .map_err(|e| Error::Other(format!("Failed to serialize config: {}", e)))?;
             
         std::fs::write(path, contents)
-            .map_err(|e| Error::IoError(e))?;
+            .map_err(Error::IoError)?;
             
         Ok(())
     }
@@ -217,7 +402,188 @@ impl SystemConfig {
             base: self.core.clone(),
             block_size: self.storage.block_size,
             compression,
+            compression_level: self.storage.compression_level,
             flush_threshold: self.storage.flush_threshold,
+            idle_flush_timeout: self.storage.idle_flush_timeout_ms.map(Duration::from_millis),
+            block_offset_directory: self.storage.block_offset_directory,
+            partial_block_interval: self.storage.partial_block_interval_ms.map(Duration::from_millis),
+            // The block cache holds decompressed blocks, so size it in whole blocks
+            cache_size_blocks: self.storage.cache_size_mb * 1024 * 1024 / self.storage.block_size.max(1),
+            max_concurrent_compactions: self.storage.max_concurrent_compactions,
+            shared_wal: self.wal.shared,
+            wal_recovery_time_budget_ms: self.wal.recovery_time_budget_ms,
+            wal_group_commit_interval_ms: self.wal.group_commit_interval_ms,
+            wal_group_commit_max_entries: self.wal.group_commit_max_entries,
+            wal_sync_mode: self.wal.sync_mode,
+            ttl_check_interval_secs: self.storage.ttl_check_interval_secs,
+        }
+    }
+}
+
+/// Add a user of the HTTP interface to the config file at `path`, creating
+/// the file if needed
+///
+/// Only the user list changes; the rest of the file is kept as written. A
+/// user of the same name is replaced.
+pub fn add_http_user(path: &str, username: &str, password: &str) -> Result<()> {
+    let mut config = match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|e| Error::Other(format!("Failed to parse config: {}", e)))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => JsonValue::Object(Default::default()),
+        Err(e) => return Err(Error::IoError(e)),
+    };
+    
+    let interfaces = object_entry(&mut config, "interfaces")?;
+    let http = object_entry(interfaces, "http")?;
+    let auth = object_entry(http, "auth")?;
+    let mut auth_config: HttpAuthConfig = serde_json::from_value(auth.clone())
+        .map_err(|e| Error::Other(format!("Failed to parse interfaces.http.auth: {}", e)))?;
+    auth_config.add_user(username, password);
+    *auth = serde_json::to_value(auth_config)
+        .map_err(|e| Error::Other(format!("Failed to serialize config: {}", e)))?;
+    
+    // Refuse to write a file that would no longer load
+    let contents = serde_json::to_string_pretty(&config)
+        .map_err(|e| Error::Other(format!("Failed to serialize config: {}", e)))?;
+    SystemConfig::from_json_str(&contents)?;
+    std::fs::write(path, contents).map_err(Error::IoError)
+}
+
+/// The object under `key` of a JSON object, inserted empty if missing
+fn object_entry<'a>(value: &'a mut JsonValue, key: &str) -> Result<&'a mut JsonValue> {
+    let object = value.as_object_mut()
+        .ok_or_else(|| Error::Other(format!("Config section holding '{}' is not an object", key)))?;
+    Ok(object.entry(key).or_insert_with(|| JsonValue::Object(Default::default())))
+}
+
+/// Rewrite keys of older config files into their current form
+fn upgrade_legacy_keys(config: &mut JsonValue) {
+    // `wal.sync_on_write` became `wal.sync_mode`; a file that did not sync
+    // every write is closest to never syncing
+    if let Some(wal) = config.get_mut("wal").and_then(JsonValue::as_object_mut) {
+        if let Some(sync_on_write) = wal.remove("sync_on_write") {
+            if !wal.contains_key("sync_mode") {
+                let mode = if sync_on_write == JsonValue::Bool(false) { SyncMode::Never } else { SyncMode::Always };
+                wal.insert("sync_mode".to_string(), serde_json::to_value(mode).unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// Merge `overrides` into `base`, recording override keys that `base` does not define
+fn merge_json(base: &mut JsonValue, overrides: JsonValue, path: &str, unknown_keys: &mut Vec<String>) {
+    match (base, overrides) {
+        (JsonValue::Object(base_map), JsonValue::Object(override_map)) => {
+            for (key, value) in override_map {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                
+                match base_map.get_mut(&key) {
+                    Some(base_value) => merge_json(base_value, value, &key_path, unknown_keys),
+                    None => unknown_keys.push(key_path),
+                }
+            }
+        },
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_fills_defaults() {
+        let config = SystemConfig::from_json_str(r#"{"interfaces": {"http": {"port": 9090}}}"#).unwrap();
+        let defaults = SystemConfig::default();
+
+        assert_eq!(config.interfaces.http.port, 9090);
+        assert_eq!(config.interfaces.http.enabled, defaults.interfaces.http.enabled);
+        assert_eq!(config.interfaces.grpc.port, defaults.interfaces.grpc.port);
+        assert_eq!(config.storage.block_size, defaults.storage.block_size);
+        assert_eq!(config.wal.max_file_size, defaults.wal.max_file_size);
+    }
+
+    #[test]
+    fn test_empty_config_is_default() {
+        let config = SystemConfig::from_json_str("{}").unwrap();
+        assert_eq!(config.data_dir, SystemConfig::default().data_dir);
+    }
+
+    #[test]
+    fn test_unknown_key_is_rejected() {
+        let err = SystemConfig::from_json_str(r#"{"storage": {"compresion_type": "lz4"}}"#).unwrap_err();
+        match err {
+            Error::Other(msg) => assert!(msg.contains("storage.compresion_type"), "{}", msg),
+            other => panic!("unexpected error: {:?}", other),
         }
     }
+
+    #[test]
+    fn test_legacy_sync_on_write_is_upgraded() {
+        let config = SystemConfig::from_json_str(r#"{"wal": {"sync_on_write": false}}"#).unwrap();
+        assert_eq!(config.wal.sync_mode, SyncMode::Never);
+
+        let config = SystemConfig::from_json_str(r#"{"wal": {"sync_mode": {"periodic": {"interval_ms": 50}}}}"#).unwrap();
+        assert_eq!(config.wal.sync_mode, SyncMode::Periodic { interval_ms: 50 });
+    }
+
+    #[test]
+    fn test_add_http_user_keeps_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nebuladb.json");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, r#"{"interfaces": {"http": {"port": 9090}}}"#).unwrap();
+
+        add_http_user(path, "ada", "secret").unwrap();
+        add_http_user(path, "alan", "enigma").unwrap();
+        add_http_user(path, "ada", "changed").unwrap();
+
+        let config = SystemConfig::load_from_file(path).unwrap();
+        assert_eq!(config.interfaces.http.port, 9090);
+        let users = &config.interfaces.http.auth.users;
+        assert_eq!(users.iter().map(|user| user.username.as_str()).collect::<Vec<_>>(), ["alan", "ada"]);
+        assert_eq!(users[1].password_hash, crate::interfaces::auth::sha256_hex("changed"));
+        assert!(!std::fs::read_to_string(path).unwrap().contains("changed"));
+    }
+
+    #[test]
+    fn test_conflicting_ports_are_rejected() {
+        let json = r#"{"interfaces": {"http": {"port": 7000}, "grpc": {"port": 7000}}}"#;
+        assert!(SystemConfig::from_json_str(json).is_err());
+
+        // A disabled interface does not claim its port
+        let json = r#"{"interfaces": {"http": {"port": 7000}, "grpc": {"enabled": false, "port": 7000}}}"#;
+        assert!(SystemConfig::from_json_str(json).is_ok());
+
+        let json = r#"{"interfaces": {"http": {"port": 7000, "metrics_port": 7000}}}"#;
+        assert!(SystemConfig::from_json_str(json).is_err());
+        let json = r#"{"interfaces": {"http": {"port": 7000, "metrics_port": 9090}}}"#;
+        assert_eq!(SystemConfig::from_json_str(json).unwrap().interfaces.http.metrics_port, Some(9090));
+    }
+
+    #[test]
+    fn test_logging_section() {
+        let json = r#"{"logging": {"level": "debug", "format": "json", "output": {"file": "/var/log/nebuladb.log"}}}"#;
+        let config = SystemConfig::from_json_str(json).unwrap();
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(config.logging.output, LogOutput::File(PathBuf::from("/var/log/nebuladb.log")));
+
+        assert!(SystemConfig::from_json_str(r#"{"logging": {"level": "nebuladb=loud"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_saved_config_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nebuladb.json");
+        let path = path.to_str().unwrap();
+
+        SystemConfig::default().save_to_file(path).unwrap();
+        let config = SystemConfig::load_from_file(path).unwrap();
+        assert_eq!(config.interfaces.http.port, SystemConfig::default().interfaces.http.port);
+    }
 }
@@ -1,6 +1,6 @@
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use nebuladb_core::{Result, Error};
 use crate::database::Database;
 
@@ -19,6 +19,42 @@ pub struct Connection {
     pub in_transaction: bool,
     /// Current transaction ID if in a transaction
     pub transaction_id: Option<u64>,
+    /// IDs of every transaction this connection has open, oldest first
+    pub open_transactions: Vec<u64>,
+}
+
+impl Connection {
+    /// Record the connection's open transactions, keeping the single
+    /// transaction fields pointing at the newest one
+    fn set_open_transactions(&mut self, open: Vec<u64>) {
+        self.in_transaction = !open.is_empty();
+        self.transaction_id = open.last().copied();
+        self.open_transactions = open;
+    }
+    
+    /// Whether the database behind the connection can still be read
+    ///
+    /// A database whose lock was poisoned by a panicking writer is not.
+    pub fn is_healthy(&self) -> bool {
+        match self.database.read() {
+            Ok(db) => {
+                db.list_open_collections();
+                true
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+/// Health of the connections in a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolHealthStatus {
+    /// Connections whose database can be read
+    pub healthy: usize,
+    /// Connections whose database cannot be read
+    pub unhealthy: usize,
+    /// All connections, in use or available
+    pub total: usize,
 }
 
 /// Connection status for monitoring
@@ -51,6 +87,8 @@ pub struct ConnectionPoolConfig {
     pub idle_timeout: u64,
     /// Transaction timeout in seconds
     pub transaction_timeout: u64,
+    /// Maximum number of transactions one connection may have open at once
+    pub max_transactions_per_connection: usize,
 }
 
 impl Default for ConnectionPoolConfig {
@@ -61,6 +99,7 @@ impl Default for ConnectionPoolConfig {
             connection_timeout: 3600, // 1 hour
             idle_timeout: 600, // 10 minutes
             transaction_timeout: 30, // 30 seconds
+            max_transactions_per_connection: 8,
         }
     }
 }
@@ -114,6 +153,9 @@ impl ConnectionPool {
         
         // Create a new connection
         let conn = self.create_connection(database_name, db)?;
+        if !conn.is_healthy() {
+            return Err(Error::Other(format!("Database '{}' is unusable", database_name)));
+        }
         
         // Add to in-use connections
         if let Ok(mut in_use) = self.in_use.lock() {
@@ -146,16 +188,84 @@ impl ConnectionPool {
         Ok(())
     }
     
-    /// Clean up idle connections
+    /// Begin a transaction on a connection
+    ///
+    /// Fails once the connection has `max_transactions_per_connection`
+    /// transactions open, so one client cannot exhaust the transactions of
+    /// the whole database.
+    pub fn begin_transaction(&self, conn: &mut Connection) -> Result<u64> {
+        if conn.open_transactions.len() >= self.config.max_transactions_per_connection {
+            return Err(Error::Other(format!(
+                "Connection {} already has the maximum of {} open transactions",
+                conn.id, self.config.max_transactions_per_connection)));
+        }
+        
+        let tx_id = conn.database.write()
+            .map_err(|_| Error::Other("Failed to lock database".into()))?
+            .begin_transaction()?;
+        
+        let mut open = conn.open_transactions.clone();
+        open.push(tx_id);
+        self.update_transactions(conn, open);
+        
+        Ok(tx_id)
+    }
+    
+    /// Commit one of a connection's open transactions
+    pub fn commit_transaction(&self, conn: &mut Connection, tx_id: u64) -> Result<()> {
+        self.end_transaction(conn, tx_id, |db| db.commit_transaction(tx_id))
+    }
+    
+    /// Abort one of a connection's open transactions
+    pub fn abort_transaction(&self, conn: &mut Connection, tx_id: u64) -> Result<()> {
+        self.end_transaction(conn, tx_id, |db| db.abort_transaction(tx_id))
+    }
+    
+    /// Finish `tx_id` with `end` and stop counting it against the connection
+    fn end_transaction(
+        &self,
+        conn: &mut Connection,
+        tx_id: u64,
+        end: impl FnOnce(&mut Database) -> Result<()>,
+    ) -> Result<()> {
+        if !conn.open_transactions.contains(&tx_id) {
+            return Err(Error::Other(format!(
+                "Transaction {} is not open on connection {}", tx_id, conn.id)));
+        }
+        
+        end(&mut *conn.database.write()
+            .map_err(|_| Error::Other("Failed to lock database".into()))?)?;
+        
+        let open = conn.open_transactions.iter().copied().filter(|&id| id != tx_id).collect();
+        self.update_transactions(conn, open);
+        
+        Ok(())
+    }
+    
+    /// Set a connection's open transactions, mirroring them in the pool's
+    /// copy so timeouts see them
+    fn update_transactions(&self, conn: &mut Connection, open: Vec<u64>) {
+        conn.last_used = Instant::now();
+        conn.set_open_transactions(open);
+        
+        if let Ok(mut in_use) = self.in_use.lock() {
+            if let Some(tracked) = in_use.get_mut(&conn.id) {
+                tracked.last_used = conn.last_used;
+                tracked.set_open_transactions(conn.open_transactions.clone());
+            }
+        }
+    }
+    
+    /// Clean up idle and unhealthy connections
     pub fn cleanup_idle_connections(&self) {
         let now = Instant::now();
         
-        // Remove idle connections from the available pool
+        // Remove idle and unhealthy connections from the available pool
         if let Ok(mut available) = self.available.lock() {
             for (_, queue) in available.iter_mut() {
                 queue.retain(|conn| {
                     let idle_secs = now.duration_since(conn.last_used).as_secs();
-                    idle_secs < self.config.idle_timeout
+                    idle_secs < self.config.idle_timeout && conn.is_healthy()
                 });
             }
         }
@@ -176,8 +286,8 @@ impl ConnectionPool {
             // Abort timed out transactions
             for id in to_abort {
                 if let Some(conn) = in_use.get(&id) {
-                    if let Some(tx_id) = conn.transaction_id {
-                        if let Ok(mut db) = conn.database.write() {
+                    if let Ok(mut db) = conn.database.write() {
+                        for &tx_id in &conn.open_transactions {
                             let _ = db.abort_transaction(tx_id);
                         }
                     }
@@ -187,6 +297,28 @@ impl ConnectionPool {
         }
     }
     
+    /// Count the healthy and unhealthy connections, in use or available
+    pub fn health_check(&self) -> PoolHealthStatus {
+        let mut status = PoolHealthStatus::default();
+        let mut count = |conn: &Connection| {
+            if conn.is_healthy() {
+                status.healthy += 1;
+            } else {
+                status.unhealthy += 1;
+            }
+            status.total += 1;
+        };
+        
+        if let Ok(in_use) = self.in_use.lock() {
+            in_use.values().for_each(&mut count);
+        }
+        if let Ok(available) = self.available.lock() {
+            available.values().flatten().for_each(&mut count);
+        }
+        
+        status
+    }
+    
     /// Get connection status for monitoring
     pub fn get_connection_status(&self) -> Vec<ConnectionStatus> {
         let mut result = Vec::new();
@@ -238,7 +370,7 @@ impl ConnectionPool {
     }
     
     /// Create a new connection
-    fn create_connection(&self, database_name: &str, db: Arc<RwLock<Database>>) -> Result<Connection> {
+    fn create_connection(&self, _database_name: &str, db: Arc<RwLock<Database>>) -> Result<Connection> {
         let now = Instant::now();
         let id = self.get_next_id();
         
@@ -249,14 +381,21 @@ impl ConnectionPool {
             id,
             in_transaction: false,
             transaction_id: None,
+            open_transactions: Vec::new(),
         })
     }
     
     /// Get an available connection for the given database
+    ///
+    /// Unhealthy connections found on the way are discarded.
     fn get_available_connection(&self, database_name: &str) -> Option<Connection> {
         if let Ok(mut available) = self.available.lock() {
             if let Some(queue) = available.get_mut(database_name) {
-                if let Some(mut conn) = queue.pop_front() {
+                while let Some(mut conn) = queue.pop_front() {
+                    if !conn.is_healthy() {
+                        continue;
+                    }
+                    
                     // Update last used
                     conn.last_used = Instant::now();
                     
@@ -316,4 +455,80 @@ impl ConnectionPool {
         
         count
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nebuladb_storage::StorageConfig;
+
+    #[test]
+    fn test_open_transactions_capped_per_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new("test", dir.path(), &StorageConfig::default()).unwrap();
+        let db = Arc::new(RwLock::new(db));
+        let pool = ConnectionPool::new(ConnectionPoolConfig {
+            max_transactions_per_connection: 3,
+            ..ConnectionPoolConfig::default()
+        });
+
+        let mut conn = pool.get_connection("test", Arc::clone(&db)).unwrap();
+        let mut tx_ids = Vec::new();
+        for _ in 0..3 {
+            tx_ids.push(pool.begin_transaction(&mut conn).unwrap());
+        }
+        assert_eq!(conn.open_transactions, tx_ids);
+        assert!(pool.begin_transaction(&mut conn).is_err());
+
+        // Other connections are not affected by this one's transactions
+        let mut other = pool.get_connection("test", Arc::clone(&db)).unwrap();
+        assert!(pool.begin_transaction(&mut other).is_ok());
+
+        // Finishing a transaction frees a slot
+        pool.commit_transaction(&mut conn, tx_ids[0]).unwrap();
+        assert!(pool.begin_transaction(&mut conn).is_ok());
+        assert!(pool.abort_transaction(&mut other, tx_ids[1]).is_err());
+    }
+
+    /// Panic while holding the database's write lock, poisoning it
+    fn poison(db: &Arc<RwLock<Database>>) {
+        let db = Arc::clone(db);
+        let result = std::thread::spawn(move || {
+            let _guard = db.write().unwrap();
+            panic!("writer failed");
+        }).join();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unhealthy_connections_are_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |name: &str| Arc::new(RwLock::new(Database::new(name, dir.path(), &StorageConfig::default()).unwrap()));
+        let (good, bad) = (open("good"), open("bad"));
+        let pool = ConnectionPool::new(ConnectionPoolConfig::default());
+
+        let conn = pool.get_connection("bad", Arc::clone(&bad)).unwrap();
+        pool.release_connection(conn).unwrap();
+        let conn = pool.get_connection("good", Arc::clone(&good)).unwrap();
+        assert!(conn.is_healthy());
+        pool.release_connection(conn).unwrap();
+        assert_eq!(pool.health_check(), PoolHealthStatus { healthy: 2, unhealthy: 0, total: 2 });
+
+        poison(&bad);
+        assert_eq!(pool.health_check(), PoolHealthStatus { healthy: 1, unhealthy: 1, total: 2 });
+
+        // The recycled connection is dropped, and no new one can be made
+        assert!(pool.get_connection("bad", Arc::clone(&bad)).is_err());
+        assert_eq!(pool.health_check(), PoolHealthStatus { healthy: 1, unhealthy: 0, total: 1 });
+        let conn = pool.get_connection("good", Arc::clone(&good)).unwrap();
+        pool.release_connection(conn).unwrap();
+
+        // Cleanup drops unhealthy connections that are not idle yet
+        let other = open("other");
+        let conn = pool.get_connection("other", Arc::clone(&other)).unwrap();
+        pool.release_connection(conn).unwrap();
+        poison(&other);
+        pool.cleanup_idle_connections();
+        assert_eq!(pool.health_check(), PoolHealthStatus { healthy: 1, unhealthy: 0, total: 1 });
+    }
+}
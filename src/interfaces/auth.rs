@@ -0,0 +1,146 @@
+//! Authentication of HTTP requests by Basic credentials or API key
+
+use std::sync::Arc;
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// Header carrying an API key
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Credentials the HTTP interface accepts
+///
+/// Every request must present one of them, so with none configured all
+/// requests are rejected unless `allow_anonymous` is set. Secrets are stored
+/// as hex-encoded SHA-256 hashes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpAuthConfig {
+    /// Users allowed in with HTTP Basic auth
+    pub users: Vec<HttpUser>,
+    /// Hashes of the keys accepted in the `X-Api-Key` header
+    pub api_key_hashes: Vec<String>,
+    /// Let every request through without credentials, e.g. for local
+    /// development
+    pub allow_anonymous: bool,
+}
+
+/// A user of the HTTP interface
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpUser {
+    /// Name given in the Basic credentials
+    pub username: String,
+    /// Hash of the user's password
+    pub password_hash: String,
+}
+
+impl HttpAuthConfig {
+    /// Whether any user or API key is configured
+    pub fn has_credentials(&self) -> bool {
+        !self.users.is_empty() || !self.api_key_hashes.is_empty()
+    }
+
+    /// Add a user, replacing any user of the same name
+    pub fn add_user(&mut self, username: &str, password: &str) {
+        self.users.retain(|user| user.username != username);
+        self.users.push(HttpUser {
+            username: username.to_string(),
+            password_hash: sha256_hex(password),
+        });
+    }
+
+    /// Whether `headers` hold valid Basic credentials or a valid API key
+    pub fn authenticate(&self, headers: &HeaderMap) -> bool {
+        if let Some(key) = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+            let hash = sha256_hex(key);
+            if self.api_key_hashes.iter().any(|expected| constant_time_eq(expected.as_bytes(), hash.as_bytes())) {
+                return true;
+            }
+        }
+
+        match headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(basic_credentials) {
+            Some((username, password)) => {
+                let hash = sha256_hex(&password);
+                self.users.iter().any(|user| {
+                    user.username == username && constant_time_eq(user.password_hash.as_bytes(), hash.as_bytes())
+                })
+            },
+            None => false,
+        }
+    }
+}
+
+/// Username and password of an `Authorization: Basic ...` header value
+fn basic_credentials(value: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Hex-encoded SHA-256 hash of `value`
+pub fn sha256_hex(value: &str) -> String {
+    Sha256::digest(value.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compare without exiting at the first difference, so timing does not
+/// reveal how much of a hash matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Reject requests without valid credentials with 401
+pub async fn require_auth(State(auth): State<Arc<HttpAuthConfig>>, request: Request, next: Next) -> Response {
+    if auth.authenticate(request.headers()) {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, "Basic realm=\"NebulaDB\"")],
+        Json(json!({ "error": "Authentication required" })),
+    ).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let mut auth = HttpAuthConfig::default();
+        assert!(!auth.has_credentials());
+        assert!(!auth.allow_anonymous);
+        auth.add_user("ada", "secret");
+        auth.api_key_hashes.push(sha256_hex("key-1"));
+        assert!(auth.has_credentials());
+        assert_ne!(auth.users[0].password_hash, "secret");
+
+        let basic = |credentials: &str| headers("authorization", &format!("Basic {}", BASE64.encode(credentials)));
+        assert!(auth.authenticate(&basic("ada:secret")));
+        assert!(!auth.authenticate(&basic("ada:wrong")));
+        assert!(!auth.authenticate(&basic("bob:secret")));
+        assert!(auth.authenticate(&headers("x-api-key", "key-1")));
+        assert!(!auth.authenticate(&headers("x-api-key", "key-2")));
+        assert!(!auth.authenticate(&HeaderMap::new()));
+    }
+}
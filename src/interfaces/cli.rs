@@ -2,11 +2,37 @@ use std::path::{Path, PathBuf};
 use rustyline::{Editor, error::ReadlineError};
 use nebuladb_core::{Result, Error};
 use crate::database::Database;
-use crate::util::{is_valid_json, format_output, matches_query};
+use crate::util::{format_output, format_change_event, json_depth};
+use crate::util::format::{format_as_csv, format_as_table, OutputFormat};
+use nebuladb_query::{FindOptions, Pipeline, Projection, Query, QueryConfig};
 use serde_json::Value as JsonValue;
 use crate::interfaces::InterfaceManagerRef;
-use std::sync::{Arc, RwLock};
-use std::io::Write;
+use std::sync::{Arc, RwLock, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::cell::Cell;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use completion::NebulaCompleter;
+
+mod completion;
+
+/// Set by the Ctrl-C handler to end a running `watch`
+static WATCH_INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INSTALL_INTERRUPT_HANDLER: Once = Once::new();
+
+/// Page size of `scan --cursor` without `--limit`
+const DEFAULT_SCAN_PAGE_SIZE: usize = 100;
+
+/// Source of continuation lines for commands spanning several lines
+type ReadLine<'a> = dyn FnMut(&str) -> rustyline::Result<String> + 'a;
+
+/// Print a command's error and mark the command as failed
+macro_rules! fail {
+    ($cli:expr, $($arg:tt)*) => {{
+        println!($($arg)*);
+        $cli.failed.set(true);
+    }};
+}
 
 #[derive(Clone)]
 /// CLI interface for interacting with the database
@@ -15,6 +41,10 @@ pub struct CliInterface {
     manager: InterfaceManagerRef,
     /// Command history file path
     history_path: PathBuf,
+    /// Whether the last command failed
+    failed: Cell<bool>,
+    /// Whether scripts stop at the first failing command
+    fail_fast: bool,
 }
 
 impl CliInterface {
@@ -27,12 +57,15 @@ impl CliInterface {
         Ok(Self {
             manager,
             history_path,
+            failed: Cell::new(false),
+            fail_fast: false,
         })
     }
     
     /// Start the CLI interface
     pub fn start(&mut self) -> Result<()> {
-        let mut rl = Editor::<()>::new().expect("Failed to create editor");
+        let mut rl = Editor::<NebulaCompleter>::new().expect("Failed to create editor");
+        rl.set_helper(Some(NebulaCompleter::new(Arc::clone(&self.manager))));
         
         // Load history if it exists
         if rl.load_history(&self.history_path).is_err() {
@@ -71,39 +104,8 @@ impl CliInterface {
                     // Add to history
                     rl.add_history_entry(input);
                     
-                    let parts: Vec<&str> = input.split_whitespace().collect();
-                    let command = parts[0].to_lowercase();
-                    
-                    match command.as_str() {
-                        "help" => self.show_help(),
-                        
-                        // Database commands
-                        "createdb" => self.create_database(&parts),
-                        "usedb" => self.use_database(&parts),
-                        "listdb" => self.list_databases(),
-                        "dropdb" => self.drop_database(&parts),
-                        
-                        // Collection commands
-                        "list" => self.list_collections(),
-                        "open" => self.open_collection(&parts),
-                        "close" => self.close_collection(&parts),
-                        "create" => self.create_collection(&parts),
-                        
-                        // Document commands
-                        "insert" => self.insert_document(&parts),
-                        "json" => self.insert_json_document(&parts),
-                        "get" => self.get_document(&parts),
-                        "delete" => self.delete_document(&parts),
-                        "scan" => self.scan_collection(&parts),
-                        "find" => self.find_documents(&parts),
-                        
-                        // System commands
-                        "clear" => self.clear_screen(),
-                        "exit" | "quit" => {
-                            println!("Exiting NebulaDB. Goodbye!");
-                            break;
-                        },
-                        _ => println!("Unknown command: {}. Type 'help' for a list of commands", command),
+                    if self.execute(input, &mut |prompt| rl.readline(prompt)) {
+                        break;
                     }
                 },
                 Err(ReadlineError::Interrupted) => {
@@ -129,6 +131,107 @@ impl CliInterface {
         Ok(())
     }
     
+    /// Run one command line; `read_line` supplies any continuation lines
+    ///
+    /// Returns true when the command ends the session.
+    fn execute(&mut self, input: &str, read_line: &mut ReadLine<'_>) -> bool {
+        self.failed.set(false);
+        
+        let parts: Vec<&str> = input.split_whitespace().collect();
+        let command = parts[0].to_lowercase();
+        
+        match command.as_str() {
+            "help" => self.show_help(),
+            
+            // Database commands
+            "createdb" => self.create_database(&parts),
+            "usedb" => self.use_database(&parts),
+            "listdb" => self.list_databases(),
+            "dropdb" => self.drop_database(&parts),
+            
+            // Collection commands
+            "list" => self.list_collections(),
+            "open" => self.open_collection(&parts),
+            "close" => self.close_collection(&parts),
+            "rename" => self.rename_collection(&parts),
+            "create" => self.create_collection(&parts),
+            
+            // Document commands
+            "insert" => self.insert_document(&parts),
+            "json" => self.insert_json_document(&parts, read_line),
+            "patch" => self.patch_document(&parts),
+            "incr" => self.increment_field(&parts),
+            "get" => self.get_document(&parts),
+            "delete" => self.delete_document(&parts),
+            "scan" => self.scan_collection(&parts),
+            "find" => self.find_documents(&parts),
+            "aggregate" => self.aggregate_collection(&parts),
+            "compression" => self.show_compression_stats(&parts),
+            "stats" => self.show_collection_stats(&parts),
+            "watch" => self.watch_collection(&parts),
+            "sync" => self.sync_database(),
+            "compact" => self.compact_collection(&parts),
+            "vacuum" => self.vacuum(&parts),
+            "export" => self.export_collection(&parts),
+            "import" => self.import_collection(&parts),
+            
+            // System commands
+            "clear" => self.clear_screen(),
+            "exit" | "quit" => {
+                println!("Exiting NebulaDB. Goodbye!");
+                return true;
+            },
+            _ => fail!(self, "Unknown command: {}. Type 'help' for a list of commands", command),
+        }
+        
+        false
+    }
+    
+    /// Run the commands read from `reader`, one per line
+    ///
+    /// Blank lines and lines starting with `#` are skipped, and `exit`
+    /// stops the script. A failing command prints its error and the script
+    /// carries on, unless fail-fast is set, in which case the error is
+    /// returned.
+    pub fn execute_script(&mut self, reader: impl BufRead) -> Result<()> {
+        let mut lines = reader.lines();
+        let mut line_number = 0;
+        
+        while let Some(line) = lines.next() {
+            let line = line.map_err(Error::IoError)?;
+            line_number += 1;
+            let start_line = line_number;
+            let input = line.trim();
+            
+            if input.is_empty() || input.starts_with('#') {
+                continue;
+            }
+            
+            // Continuation lines of a command come from the script too
+            let mut read_line = |_: &str| match lines.next() {
+                Some(line) => {
+                    line_number += 1;
+                    line.map_err(ReadlineError::Io)
+                },
+                None => Err(ReadlineError::Eof),
+            };
+            if self.execute(input, &mut read_line) {
+                break;
+            }
+            
+            if self.failed.get() && self.fail_fast {
+                return Err(Error::Other(format!("Script stopped at line {}: {}", start_line, input)));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Stop scripts at the first failing command
+    pub fn set_fail_fast(&mut self, fail_fast: bool) {
+        self.fail_fast = fail_fast;
+    }
+    
     /// Show help message
     fn show_help(&self) {
         println!("Available commands:");
@@ -142,15 +245,38 @@ impl CliInterface {
         println!("  list                                - List all collections (both open and on disk)");
         println!("  open <collection_name>              - Open or create a collection");
         println!("  close <collection_name>             - Close a collection");
+        println!("  rename <old_name> <new_name>        - Rename a collection");
         println!("  create <collection_name>            - Create a new collection");
         println!();
         println!("  Document commands:");
         println!("  insert <collection> <id> <data>     - Insert a document");
-        println!("  json <collection> <id> <json>       - Insert a JSON document");
+        println!("  json <collection> <id> <json>       - Insert a JSON document; an unclosed {{ continues on");
+        println!("                                        further lines until balanced (Ctrl-C cancels)");
+        println!("  patch <collection> <id> <json>      - Merge fields into a JSON document (null removes)");
+        println!("  incr <collection> <id> <field> [n]  - Add n (default 1) to a numeric field");
         println!("  get <collection> <id>               - Get a document");
         println!("  delete <collection> <id>            - Delete a document");
-        println!("  scan <collection>                   - List all documents in a collection");
+        println!("  scan <collection> [--format <f>]    - List all documents in a collection");
+        println!("                                        (json lists IDs; table or csv shows the documents)");
+        println!("       [--limit <n>] [--cursor <hex>] - Page through the IDs in order; pass the");
+        println!("                                        next_cursor printed with a page to get the next");
         println!("  find <collection> [query]           - Find documents in a collection");
+        println!("       [--fields <field,...>]         - Only show these fields (prefix with - to hide)");
+        println!("       [--sort <field>] [--desc]      - Sort the matches by a field");
+        println!("       [--limit <n>] [--skip <n>]     - Page through the matches");
+        println!("       [--coerce]                     - Let numbers match numeric strings");
+        println!("       [--explain]                    - Show which index answers the query");
+        println!("       [--format <json|table|csv>]    - Show the matches as JSON, a table or CSV");
+        println!("  aggregate <collection> <pipeline>   - Run a JSON array of $match, $group, $sort, $skip,");
+        println!("                                        $limit and $count stages over a collection");
+        println!("  compression <collection>            - Show compression statistics for a collection");
+        println!("  stats <collection>                  - Show document counts and size on disk");
+        println!("  watch <collection>                  - Print writes to a collection until Ctrl-C");
+        println!("  sync                                - Flush and fsync all writes to disk");
+        println!("  compact <collection>                - Reclaim space from deleted and updated documents");
+        println!("  vacuum [collection]                 - Compact every collection, or just one");
+        println!("  export <collection> <file.ndjson>   - Write all documents to a newline-delimited JSON file");
+        println!("  import <collection> <file.ndjson>   - Insert the documents of a newline-delimited JSON file");
         println!();
         println!("  System commands:");
         println!("  clear                               - Clear the terminal screen");
@@ -160,7 +286,7 @@ impl CliInterface {
     /// Create a new database
     fn create_database(&mut self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: createdb <n>");
+            fail!(self, "Usage: createdb <n>");
             return;
         }
         
@@ -169,17 +295,17 @@ impl CliInterface {
         if let Ok(mut manager) = self.manager.write() {
             match manager.create_database(name) {
                 Ok(_) => println!("Database '{}' created successfully", name),
-                Err(e) => println!("Error creating database '{}': {:?}", name, e),
+                Err(e) => fail!(self, "Error creating database '{}': {:?}", name, e),
             }
         } else {
-            println!("Error: could not access database manager");
+            fail!(self, "Error: could not access database manager");
         }
     }
     
     /// Switch to a different database
     fn use_database(&mut self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: usedb <n>");
+            fail!(self, "Usage: usedb <n>");
             return;
         }
         
@@ -188,10 +314,10 @@ impl CliInterface {
         if let Ok(mut manager) = self.manager.write() {
             match manager.set_active_database(name) {
                 Ok(_) => println!("Switched to database '{}'", name),
-                Err(e) => println!("Error switching to database '{}': {:?}", name, e),
+                Err(e) => fail!(self, "Error switching to database '{}': {:?}", name, e),
             }
         } else {
-            println!("Error: could not access database manager");
+            fail!(self, "Error: could not access database manager");
         }
     }
     
@@ -217,14 +343,14 @@ impl CliInterface {
             }
             println!("Total: {} databases", databases.len());
         } else {
-            println!("Error: could not access database manager");
+            fail!(self, "Error: could not access database manager");
         }
     }
     
     /// Drop (delete) a database
     fn drop_database(&mut self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: dropdb <n>");
+            fail!(self, "Usage: dropdb <n>");
             return;
         }
         
@@ -234,7 +360,7 @@ impl CliInterface {
         println!("Are you sure you want to delete database '{}'? [y/N]", name);
         let mut input = String::new();
         if let Err(e) = std::io::stdin().read_line(&mut input) {
-            println!("Error reading input: {:?}", e);
+            fail!(self, "Error reading input: {:?}", e);
             return;
         }
         
@@ -246,10 +372,10 @@ impl CliInterface {
         if let Ok(mut manager) = self.manager.write() {
             match manager.drop_database(name) {
                 Ok(_) => println!("Database '{}' deleted successfully", name),
-                Err(e) => println!("Error deleting database '{}': {:?}", name, e),
+                Err(e) => fail!(self, "Error deleting database '{}': {:?}", name, e),
             }
         } else {
-            println!("Error: could not access database manager");
+            fail!(self, "Error: could not access database manager");
         }
     }
     
@@ -287,14 +413,89 @@ impl CliInterface {
                     open_collections.len()
                 );
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Make all writes to the active database durable
+    fn sync_database(&self) {
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let mut db = db_rwlock.write().unwrap();
+                let started = std::time::Instant::now();
+                match db.sync() {
+                    Ok(_) => println!("All writes synced to disk in {:?}", started.elapsed()),
+                    Err(e) => fail!(self, "Error syncing database: {:?}", e),
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Export a collection to a newline-delimited JSON file
+    fn export_collection(&self, parts: &[&str]) {
+        if parts.len() < 3 {
+            fail!(self, "Usage: export <collection> <file.ndjson>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        let file_path = parts[2];
+        
+        let file = match std::fs::File::create(file_path) {
+            Ok(file) => file,
+            Err(e) => {
+                fail!(self, "Error creating '{}': {}", file_path, e);
+                return;
+            }
+        };
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let mut db = db_rwlock.write().unwrap();
+                match db.export(collection_name, std::io::BufWriter::new(file)) {
+                    Ok(count) => println!("Exported {} documents from '{}' to '{}'", count, collection_name, file_path),
+                    Err(e) => fail!(self, "Error exporting collection '{}': {:?}", collection_name, e),
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Import a newline-delimited JSON file into a collection
+    fn import_collection(&self, parts: &[&str]) {
+        if parts.len() < 3 {
+            fail!(self, "Usage: import <collection> <file.ndjson>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        let file_path = parts[2];
+        
+        let file = match std::fs::File::open(file_path) {
+            Ok(file) => file,
+            Err(e) => {
+                fail!(self, "Error opening '{}': {}", file_path, e);
+                return;
+            }
+        };
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let mut db = db_rwlock.write().unwrap();
+                match db.import(collection_name, std::io::BufReader::new(file)) {
+                    Ok(count) => println!("Imported {} documents into '{}'", count, collection_name),
+                    Err(e) => fail!(self, "Error importing into collection '{}': {:?}", collection_name, e),
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Open a collection
     fn open_collection(&mut self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: open <collection_name>");
+            fail!(self, "Usage: open <collection_name>");
             return;
         }
         
@@ -312,17 +513,17 @@ impl CliInterface {
                 
                 match db.open_collection(name) {
                     Ok(_) => println!("Collection '{}' opened successfully", name),
-                    Err(e) => println!("Error opening collection '{}': {:?}", name, e),
+                    Err(e) => fail!(self, "Error opening collection '{}': {:?}", name, e),
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Close a collection
     fn close_collection(&mut self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: close <collection_name>");
+            fail!(self, "Usage: close <collection_name>");
             return;
         }
         
@@ -333,17 +534,40 @@ impl CliInterface {
                 let mut db = db_rwlock.write().unwrap();
                 match db.close_collection(name) {
                     Ok(_) => println!("Collection '{}' closed successfully", name),
-                    Err(e) => println!("Error closing collection '{}': {:?}", name, e),
+                    Err(e) => fail!(self, "Error closing collection '{}': {:?}", name, e),
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Rename a collection
+    fn rename_collection(&mut self, parts: &[&str]) {
+        if parts.len() < 3 {
+            fail!(self, "Usage: rename <old_name> <new_name>");
+            return;
+        }
+        
+        let old_name = parts[1];
+        let new_name = parts[2];
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let mut db = db_rwlock.write().unwrap();
+                match db.rename_collection(old_name, new_name) {
+                    Ok(_) => println!("Collection '{}' renamed to '{}'", old_name, new_name),
+                    Err(Error::AlreadyExists { name }) => fail!(self, "Error: collection '{}' already exists", name),
+                    Err(e) => fail!(self, "Error renaming collection '{}': {:?}", old_name, e),
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Create a new collection without opening it
     fn create_collection(&self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: create <collection_name>");
+            fail!(self, "Usage: create <collection_name>");
             return;
         }
         
@@ -355,27 +579,27 @@ impl CliInterface {
                 
                 // Check if collection already exists
                 if db.collection_exists(name) {
-                    println!("Collection '{}' already exists", name);
+                    fail!(self, "Collection '{}' already exists", name);
                     return;
                 }
                 
                 // We need a write lock to create the collection
                 drop(db);
                 
-                let mut db = db_rwlock.write().unwrap();
+                let db = db_rwlock.write().unwrap();
                 match db.create_collection(name) {
                     Ok(_) => println!("Collection '{}' created successfully", name),
-                    Err(e) => println!("Error creating collection '{}': {:?}", name, e),
+                    Err(e) => fail!(self, "Error creating collection '{}': {:?}", name, e),
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Insert a document
     fn insert_document(&mut self, parts: &[&str]) {
         if parts.len() < 4 {
-            println!("Usage: insert <collection> <id> <data>");
+            fail!(self, "Usage: insert <collection> <id> <data>");
             return;
         }
         
@@ -391,23 +615,23 @@ impl CliInterface {
                     if let Ok(mut collection) = collection_mutex.lock() {
                         match collection.insert(id, &data) {
                             Ok(_) => println!("Document inserted successfully"),
-                            Err(e) => println!("Error inserting document: {:?}", e),
+                            Err(e) => fail!(self, "Error inserting document: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Insert a JSON document
-    fn insert_json_document(&mut self, parts: &[&str]) {
+    fn insert_json_document(&mut self, parts: &[&str], read_line: &mut ReadLine<'_>) {
         if parts.len() < 4 {
-            println!("Usage: json <collection> <id> <json_data>");
+            fail!(self, "Usage: json <collection> <id> <json_data>");
             println!("Example: json users user123 {{\"name\":\"John\",\"age\":30}}");
             return;
         }
@@ -415,40 +639,154 @@ impl CliInterface {
         let collection_name = parts[1];
         let id = parts[2].as_bytes();
         
+        // Join the rest as the JSON string, reading more lines while it is open
+        let json_str = match Self::read_multiline_json(&parts[3..].join(" "), read_line) {
+            Ok(json_str) => json_str,
+            Err(e) => {
+                fail!(self, "Error: {:?}", e);
+                return;
+            }
+        };
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                if let Some(collection_mutex) = db.get_collection(collection_name) {
+                    // Lock the collection to access it
+                    if let Ok(mut collection) = collection_mutex.lock() {
+                        match collection.insert(id, json_str.as_bytes()) {
+                            Ok(_) => println!("JSON document inserted successfully"),
+                            Err(e) => fail!(self, "Error inserting document: {:?}", e),
+                        }
+                    } else {
+                        fail!(self, "Failed to lock collection");
+                    }
+                } else {
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Complete a JSON document begun with `first`, reading continuation
+    /// lines from `read_line` at a `...> ` prompt until its braces balance
+    ///
+    /// Ctrl-C or Ctrl-D while reading cancels the entry. The assembled text
+    /// is returned in compact form once it parses as a JSON object.
+    fn read_multiline_json(
+        first: &str,
+        mut read_line: impl FnMut(&str) -> std::result::Result<String, ReadlineError>,
+    ) -> Result<String> {
+        let mut json_str = first.to_string();
+        while json_depth(&json_str) > 0 {
+            match read_line("...> ") {
+                Ok(line) => {
+                    json_str.push('\n');
+                    json_str.push_str(&line);
+                },
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    return Err(Error::Other("JSON entry cancelled".to_string()));
+                },
+                Err(e) => return Err(Error::Other(format!("Error reading line: {:?}", e))),
+            }
+        }
+        
+        match serde_json::from_str::<JsonValue>(&json_str) {
+            Ok(value @ JsonValue::Object(_)) => Ok(value.to_string()),
+            Ok(_) => Err(Error::Other("JSON document must be an object".to_string())),
+            Err(e) => Err(Error::Other(format!("Invalid JSON data: {}", e))),
+        }
+    }
+    
+    /// Merge a JSON patch into a document
+    fn patch_document(&mut self, parts: &[&str]) {
+        if parts.len() < 4 {
+            fail!(self, "Usage: patch <collection> <id> <json_patch>");
+            println!("Example: patch users user123 {{\"age\":31,\"nickname\":null}}");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        let id = parts[2].as_bytes();
+        
         // Join the rest as the JSON string
-        let json_str = parts[3..].join(" ");
+        let patch = match serde_json::from_str::<JsonValue>(&parts[3..].join(" ")) {
+            Ok(patch) => patch,
+            Err(e) => {
+                fail!(self, "Invalid JSON patch: {}", e);
+                return;
+            }
+        };
         
-        // Validate JSON
-        if !is_valid_json(&json_str) {
-            println!("Error: Invalid JSON data");
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                if let Some(collection_mutex) = db.get_collection(collection_name) {
+                    // Lock the collection to access it
+                    if let Ok(mut collection) = collection_mutex.lock() {
+                        match collection.patch(id, &patch) {
+                            Ok(true) => println!("Document patched successfully"),
+                            Ok(false) => println!("Document not found"),
+                            Err(e) => fail!(self, "Error patching document: {:?}", e),
+                        }
+                    } else {
+                        fail!(self, "Failed to lock collection");
+                    }
+                } else {
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Add to a numeric field of a document
+    fn increment_field(&mut self, parts: &[&str]) {
+        if parts.len() < 4 {
+            fail!(self, "Usage: incr <collection> <id> <field> [delta]");
+            println!("Example: incr users user123 login_count");
             return;
         }
         
+        let collection_name = parts[1];
+        let id = parts[2].as_bytes();
+        let field = parts[3];
+        let delta = match parts.get(4).map(|delta| delta.parse::<i64>()) {
+            None => 1,
+            Some(Ok(delta)) => delta,
+            Some(Err(e)) => {
+                fail!(self, "Invalid delta '{}': {}", parts[4], e);
+                return;
+            }
+        };
+        
         match self.get_active_db() {
             Ok(db_rwlock) => {
                 let db = db_rwlock.read().unwrap();
                 if let Some(collection_mutex) = db.get_collection(collection_name) {
                     // Lock the collection to access it
                     if let Ok(mut collection) = collection_mutex.lock() {
-                        match collection.insert(id, json_str.as_bytes()) {
-                            Ok(_) => println!("JSON document inserted successfully"),
-                            Err(e) => println!("Error inserting document: {:?}", e),
+                        match collection.increment(id, field, delta) {
+                            Ok(value) => println!("{} = {}", field, value),
+                            Err(Error::NotFound { .. }) => println!("Document not found"),
+                            Err(e) => fail!(self, "Error incrementing field: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Get a document
     fn get_document(&self, parts: &[&str]) {
         if parts.len() < 3 {
-            println!("Usage: get <collection> <id>");
+            fail!(self, "Usage: get <collection> <id>");
             return;
         }
         
@@ -467,23 +805,23 @@ impl CliInterface {
                                 format_output(&data_str);
                             },
                             Ok(None) => println!("Document not found"),
-                            Err(e) => println!("Error retrieving document: {:?}", e),
+                            Err(e) => fail!(self, "Error retrieving document: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Delete a document
     fn delete_document(&mut self, parts: &[&str]) {
         if parts.len() < 3 {
-            println!("Usage: delete <collection> <id>");
+            fail!(self, "Usage: delete <collection> <id>");
             return;
         }
         
@@ -499,27 +837,35 @@ impl CliInterface {
                         match collection.delete(id) {
                             Ok(true) => println!("Document deleted successfully"),
                             Ok(false) => println!("Document not found"),
-                            Err(e) => println!("Error deleting document: {:?}", e),
+                            Err(e) => fail!(self, "Error deleting document: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
     
     /// Scan a collection
     fn scan_collection(&self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: scan <collection>");
+            fail!(self, "Usage: scan <collection> [--format <json|table|csv>] [--limit <n>] [--cursor <hex>]");
             return;
         }
         
         let collection_name = parts[1];
+        let args = match parse_scan_args(&parts[2..]) {
+            Ok(args) => args,
+            Err(e) => {
+                fail!(self, "Invalid scan arguments: {:?}", e);
+                return;
+            }
+        };
+        let format = args.format;
         
         match self.get_active_db() {
             Ok(db_rwlock) => {
@@ -527,10 +873,38 @@ impl CliInterface {
                 if let Some(collection_mutex) = db.get_collection(collection_name) {
                     // Lock the collection to access it
                     if let Ok(collection) = collection_mutex.lock() {
-                        match collection.scan() {
-                            Ok(ids) => {
+                        // A limit or cursor asks for one page in ID order
+                        let scanned = if args.limit.is_some() || args.cursor.is_some() {
+                            let cursor = args.cursor.map(String::into_bytes);
+                            collection.scan_paginated(cursor, args.limit.unwrap_or(DEFAULT_SCAN_PAGE_SIZE))
+                        } else {
+                            collection.scan().map(|ids| (ids, None))
+                        };
+                        match scanned {
+                            Ok((ids, next_cursor)) => {
                                 if ids.is_empty() {
                                     println!("No documents found in collection '{}'", collection_name);
+                                } else if format != OutputFormat::Json {
+                                    let mut docs = Vec::with_capacity(ids.len());
+                                    for id in &ids {
+                                        match collection.get(id) {
+                                            Ok(Some(data)) => {
+                                                let value = serde_json::from_slice(&data).unwrap_or_else(|_| {
+                                                    JsonValue::String(String::from_utf8_lossy(&data).into_owned())
+                                                });
+                                                docs.push(with_id(id, value));
+                                            },
+                                            Ok(None) => {},
+                                            Err(e) => {
+                                                fail!(self, "Error reading document: {:?}", e);
+                                                return;
+                                            }
+                                        }
+                                    }
+                                    print_formatted(format, &docs);
+                                    if format == OutputFormat::Table {
+                                        println!("Total: {} documents", docs.len());
+                                    }
                                 } else {
                                     println!("Documents in collection '{}':", collection_name);
                                     for id in &ids {
@@ -538,51 +912,190 @@ impl CliInterface {
                                     }
                                     println!("Total: {} documents", ids.len());
                                 }
+                                
+                                if let Some(cursor) = next_cursor {
+                                    let line = format!("next_cursor: {}", String::from_utf8_lossy(&cursor));
+                                    // Keep CSV output parseable
+                                    if format == OutputFormat::Csv {
+                                        eprintln!("{}", line);
+                                    } else {
+                                        println!("{}", line);
+                                    }
+                                }
                             },
-                            Err(e) => println!("Error scanning collection: {:?}", e),
+                            Err(e) => fail!(self, "Error scanning collection: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => fail!(self, "Error: {:?}", e),
         }
     }
 
+    /// Show compression statistics for a collection
+    fn show_compression_stats(&self, parts: &[&str]) {
+        if parts.len() < 2 {
+            fail!(self, "Usage: compression <collection>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                if let Some(collection_mutex) = db.get_collection(collection_name) {
+                    // Lock the collection to access it
+                    if let Ok(collection) = collection_mutex.lock() {
+                        match collection.compression_stats() {
+                            Ok(stats) => {
+                                println!("Compression statistics for '{}':", collection_name);
+                                println!("  Blocks on disk:     {}", stats.block_count);
+                                println!("  Uncompressed bytes: {}", stats.uncompressed_bytes);
+                                println!("  Stored bytes:       {}", stats.compressed_bytes);
+                                println!("  Ratio:              {:.2}", stats.ratio());
+                            },
+                            Err(e) => fail!(self, "Error reading compression statistics: {:?}", e),
+                        }
+                    } else {
+                        fail!(self, "Failed to lock collection");
+                    }
+                } else {
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+
+    /// Show document counts and size on disk for a collection
+    fn show_collection_stats(&self, parts: &[&str]) {
+        if parts.len() < 2 {
+            fail!(self, "Usage: stats <collection>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        
+        let manager = match self.manager.read() {
+            Ok(manager) => manager,
+            Err(_) => {
+                fail!(self, "Failed to lock interface manager");
+                return;
+            }
+        };
+        match manager.collection_stats(collection_name) {
+            Ok(stats) => {
+                println!("Statistics for '{}':", collection_name);
+                println!("  Documents:         {}", stats.doc_count);
+                println!("  Deleted documents: {}", stats.deleted_doc_count);
+                println!("  Blocks on disk:    {}", stats.block_count);
+                println!("  Size on disk:      {} bytes", stats.size_bytes);
+                println!("  Index size:        {} bytes", stats.index_size_bytes);
+            },
+            Err(e) => fail!(self, "Error reading collection statistics: {:?}", e),
+        }
+    }
+    
+    /// Rewrite a collection without its deleted and superseded documents
+    fn compact_collection(&self, parts: &[&str]) {
+        if parts.len() < 2 {
+            fail!(self, "Usage: compact <collection>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                if let Some(collection_mutex) = db.get_collection(collection_name) {
+                    if let Ok(mut collection) = collection_mutex.lock() {
+                        match collection.compact() {
+                            Ok(stats) => println!("Compacted '{}': removed {} entries, reclaimed {} bytes",
+                                collection_name, stats.documents_removed, stats.bytes_reclaimed),
+                            Err(e) => fail!(self, "Error compacting collection: {:?}", e),
+                        }
+                    } else {
+                        fail!(self, "Failed to lock collection");
+                    }
+                } else {
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
+    /// Compact all collections of the active database, or a single one
+    fn vacuum(&self, parts: &[&str]) {
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let mut db = db_rwlock.write().unwrap();
+                let result = match parts.get(1) {
+                    Some(collection_name) => db.vacuum_collection(collection_name),
+                    None => db.vacuum(),
+                };
+                match result {
+                    Ok(stats) => println!("Vacuum removed {} entries and reclaimed {} bytes in {:?}",
+                        stats.docs_removed, stats.bytes_reclaimed, stats.time_elapsed),
+                    Err(e) => fail!(self, "Error during vacuum: {:?}", e),
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+    
     /// Find documents in a collection
     fn find_documents(&self, parts: &[&str]) {
         if parts.len() < 2 {
-            println!("Usage: find <collection> [query]");
+            fail!(self, "Usage: find <collection> [query] [--fields <field,...>] [--sort <field>] [--desc]");
+            println!("            [--limit <n>] [--skip <n>] [--coerce] [--explain] [--format <json|table|csv>]");
             println!("Examples:");
             println!("  find users                     - Get all documents");
             println!("  find users {{\"name\":\"John\"}}    - Find documents where name = John");
+            println!("  find users {{\"age\":{{\"$gt\":30}}}} - Find documents where age > 30");
+            println!("  find articles {{\"body\":{{\"$text\":\"rust async\"}}}}");
+            println!("                                 - Find articles whose body mentions rust and async");
+            println!("  find users {{}} --fields name,age - Return only name, age and _id");
+            println!("  find users {{}} --fields -bio     - Return everything except bio");
+            println!("  find users {{}} --sort age --desc --limit 20 --skip 40");
+            println!("                                 - Third page of 20, oldest first");
+            println!("  find users {{\"age\":30}} --coerce - Also match ages stored as \"30\"");
+            println!("  find users {{}} --format csv      - Print the matches as CSV");
             return;
         }
         
         let collection_name = parts[1];
         
-        // Parse query if provided
-        let query_str = if parts.len() > 2 {
-            parts[2..].join(" ")
-        } else {
-            "{}".to_string() // Empty query matches all documents
+        // Split off the flags; whatever is left is the query
+        let args = match parse_find_args(&parts[2..]) {
+            Ok(args) => args,
+            Err(e) => {
+                fail!(self, "Invalid find arguments: {:?}", e);
+                return;
+            }
         };
         
-        println!("DEBUG: Using query string: '{}'", query_str);
-        
-        // Validate JSON
-        let query = match serde_json::from_str::<JsonValue>(&query_str) {
+        let query = match serde_json::from_str::<JsonValue>(&args.query) {
             Ok(q) => q,
             Err(e) => {
-                println!("Invalid JSON query: {}", e);
+                fail!(self, "Invalid JSON query: {}", e);
                 return;
             }
         };
         
-        println!("DEBUG: Parsed query: {:?}", query);
+        let query = match Query::from_json(&query) {
+            Ok(q) => q,
+            Err(e) => {
+                fail!(self, "Invalid query: {:?}", e);
+                return;
+            }
+        };
         
         match self.get_active_db() {
             Ok(db_rwlock) => {
@@ -590,58 +1103,175 @@ impl CliInterface {
                 if let Some(collection_mutex) = db.get_collection(collection_name) {
                     // Lock the collection to access it
                     if let Ok(collection) = collection_mutex.lock() {
-                        // Get all document IDs
-                        match collection.scan() {
-                            Ok(ids) => {
-                                if ids.is_empty() {
-                                    println!("No documents found in collection '{}'", collection_name);
-                                    return;
+                        match collection.count() {
+                            Ok(0) => {
+                                println!("No documents found in collection '{}'", collection_name);
+                                return;
+                            },
+                            Ok(_) => {},
+                            Err(e) => {
+                                fail!(self, "Error scanning collection: {:?}", e);
+                                return;
+                            },
+                        }
+                        
+                        if args.explain {
+                            println!("Plan: {}", collection.explain(&query, &args.config));
+                        }
+                        
+                        // The collection narrows the candidates with an index when it
+                        // can, and the query engine picks the matches
+                        let results = collection.find_with_options(&query, &args.options, &args.config);
+                        match results {
+                            Ok(matches) if matches.is_empty() => println!("No documents matched the query"),
+                            Ok(matches) if args.format != OutputFormat::Json => {
+                                let docs: Vec<JsonValue> = matches.iter()
+                                    .map(|(id, doc)| with_id(id, doc.clone()))
+                                    .map(|doc| match &args.projection {
+                                        Some(projection) => projection.apply(&doc),
+                                        None => doc,
+                                    })
+                                    .collect();
+                                print_formatted(args.format, &docs);
+                                if args.format == OutputFormat::Table {
+                                    println!("Found {} matching document(s)", docs.len());
                                 }
-                                
-                                println!("DEBUG: Found {} document IDs", ids.len());
-                                
-                                let mut found_count = 0;
-                                
-                                // For each ID, get the document and check if it matches the query
-                                for id in &ids {
-                                    println!("DEBUG: Checking document with ID: {}", String::from_utf8_lossy(id));
-                                    match collection.get(&id) {
-                                        Ok(Some(data)) => {
-                                            let doc_str = String::from_utf8_lossy(&data);
-                                            println!("DEBUG: Document content: {}", doc_str);
-                                            
-                                            if matches_query(&doc_str, &query) {
-                                                println!("DEBUG: Document matches query!");
-                                                found_count += 1;
-                                                println!("ID: {}", String::from_utf8_lossy(id));
-                                                format_output(&doc_str);
-                                                println!("---");
-                                            } else {
-                                                println!("DEBUG: Document does NOT match query");
-                                            }
-                                        },
-                                        Ok(None) => println!("DEBUG: Document with ID {} not found", String::from_utf8_lossy(id)),
-                                        Err(e) => println!("DEBUG: Error retrieving document: {:?}", e),
-                                    }
+                            },
+                            Ok(matches) => {
+                                for (id, doc) in &matches {
+                                    let doc = match &args.projection {
+                                        Some(projection) => projection.apply(doc),
+                                        None => doc.clone(),
+                                    };
+                                    println!("ID: {}", String::from_utf8_lossy(id));
+                                    format_output(&doc.to_string());
+                                    println!("---");
                                 }
-                                
-                                if found_count == 0 {
-                                    println!("No documents matched the query");
-                                } else {
-                                    println!("Found {} matching document(s)", found_count);
+                                println!("Found {} matching document(s)", matches.len());
+                            },
+                            Err(e) => fail!(self, "Error: {:?}", e),
+                        }
+                    } else {
+                        fail!(self, "Failed to lock collection");
+                    }
+                } else {
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+
+    /// Run an aggregation pipeline over a collection
+    fn aggregate_collection(&self, parts: &[&str]) {
+        if parts.len() < 3 {
+            fail!(self, "Usage: aggregate <collection> <pipeline>");
+            println!("Examples:");
+            println!("  aggregate sales [{{\"$group\":{{\"_id\":\"$region\",\"total\":{{\"$sum\":\"$amount\"}}}}}}]");
+            println!("                                 - Total amount per region");
+            println!("  aggregate sales [{{\"$match\":{{\"paid\":true}}}},{{\"$count\":\"paid\"}}]");
+            println!("                                 - Number of paid sales");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        let text = parts[2..].join(" ");
+        let text = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')).unwrap_or(&text);
+        
+        let pipeline = match serde_json::from_str::<JsonValue>(text) {
+            Ok(json) => json,
+            Err(e) => {
+                fail!(self, "Invalid JSON pipeline: {}", e);
+                return;
+            }
+        };
+        
+        let pipeline = match Pipeline::from_json(&pipeline) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                fail!(self, "Invalid pipeline: {:?}", e);
+                return;
+            }
+        };
+        
+        match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                if let Some(collection_mutex) = db.get_collection(collection_name) {
+                    if let Ok(collection) = collection_mutex.lock() {
+                        match collection.aggregate(&pipeline) {
+                            Ok(results) => {
+                                for result in &results {
+                                    format_output(&result.to_string());
                                 }
+                                println!("{} result(s)", results.len());
                             },
-                            Err(e) => println!("Error scanning collection: {:?}", e),
+                            Err(e) => fail!(self, "Error: {:?}", e),
                         }
                     } else {
-                        println!("Failed to lock collection");
+                        fail!(self, "Failed to lock collection");
                     }
                 } else {
-                    println!("Collection '{}' is not open", collection_name);
+                    fail!(self, "Collection '{}' is not open", collection_name);
+                }
+            },
+            Err(e) => fail!(self, "Error: {:?}", e),
+        }
+    }
+
+    /// Print every write to a collection as it happens, until Ctrl-C
+    fn watch_collection(&self, parts: &[&str]) {
+        if parts.len() < 2 {
+            fail!(self, "Usage: watch <collection>");
+            return;
+        }
+        
+        let collection_name = parts[1];
+        
+        // Subscribe, then release the locks so writers are not blocked
+        let mut subscription = match self.get_active_db() {
+            Ok(db_rwlock) => {
+                let db = db_rwlock.read().unwrap();
+                match db.get_collection(collection_name) {
+                    Some(collection_mutex) => match collection_mutex.lock() {
+                        Ok(collection) => collection.subscribe(),
+                        Err(_) => {
+                            fail!(self, "Failed to lock collection");
+                            return;
+                        }
+                    },
+                    None => {
+                        fail!(self, "Collection '{}' is not open", collection_name);
+                        return;
+                    }
                 }
             },
-            Err(e) => println!("Error: {:?}", e),
+            Err(e) => {
+                fail!(self, "Error: {:?}", e);
+                return;
+            }
+        };
+        
+        // Outside of readline Ctrl-C arrives as a signal; catch it so it
+        // only ends the watch instead of the whole process
+        INSTALL_INTERRUPT_HANDLER.call_once(|| {
+            if let Err(e) = ctrlc::set_handler(|| WATCH_INTERRUPTED.store(true, Ordering::SeqCst)) {
+                eprintln!("Failed to install Ctrl-C handler: {}", e);
+            }
+        });
+        WATCH_INTERRUPTED.store(false, Ordering::SeqCst);
+        
+        println!("Watching '{}' (press Ctrl-C to stop)", collection_name);
+        while !WATCH_INTERRUPTED.load(Ordering::SeqCst) {
+            match subscription.recv_timeout(Duration::from_millis(200)) {
+                Some(Ok(event)) => println!("{}", format_change_event(&event)),
+                Some(Err(lagged)) => println!("... {} changes missed", lagged.missed),
+                None => {},
+            }
         }
+        
+        subscription.unsubscribe();
+        println!("Stopped watching '{}'", collection_name);
     }
 
     /// Clear the terminal screen
@@ -672,3 +1302,197 @@ impl CliInterface {
         }
     }
 }
+
+/// Arguments of the `scan` command
+struct ScanArgs {
+    /// `--format` for the documents
+    format: OutputFormat,
+    /// `--limit` page size
+    limit: Option<usize>,
+    /// `--cursor` from the previous page
+    cursor: Option<String>,
+}
+
+/// Parse the flags of the `scan` command
+fn parse_scan_args(args: &[&str]) -> Result<ScanArgs> {
+    let mut parsed = ScanArgs { format: OutputFormat::Json, limit: None, cursor: None };
+    
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let value = args.next().copied()
+            .ok_or_else(|| Error::Other(format!("{} needs a value", arg)));
+        match arg {
+            "--format" => parsed.format = value?.parse()?,
+            "--limit" => parsed.limit = Some(value?.parse::<usize>().ok().filter(|n| *n > 0)
+                .ok_or_else(|| Error::Other("--limit must be a positive number".to_string()))?),
+            "--cursor" => parsed.cursor = Some(value?.to_string()),
+            _ => return Err(Error::Other(format!("Unknown flag '{}'", arg))),
+        }
+    }
+    
+    Ok(parsed)
+}
+
+/// Arguments of the `find` command
+struct FindArgs {
+    /// Query text; `{}` when none was given
+    query: String,
+    /// `--fields` projection
+    projection: Option<Projection>,
+    /// `--sort`/`--desc`/`--limit`/`--skip` options
+    options: FindOptions,
+    /// `--coerce` setting
+    config: QueryConfig,
+    /// Whether `--explain` asked for the query plan
+    explain: bool,
+    /// `--format` for the matches
+    format: OutputFormat,
+}
+
+/// Split `find` arguments into the query text and the flags
+fn parse_find_args(args: &[&str]) -> Result<FindArgs> {
+    let mut query_parts = Vec::new();
+    let mut projection = None;
+    let mut options = FindOptions::default();
+    let mut config = QueryConfig::default();
+    let mut explain = false;
+    let mut format = OutputFormat::Json;
+    
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = |flag: &str| args.next().copied()
+            .ok_or_else(|| Error::Other(format!("{} needs a value", flag)));
+        let count = |flag: &str, text: &str| text.parse::<usize>()
+            .map_err(|_| Error::Other(format!("{} must be a non-negative number", flag)));
+        
+        match arg {
+            "--fields" => projection = Some(parse_fields(value(arg)?)?),
+            "--sort" => options.sort_field = Some(value(arg)?.to_string()),
+            "--desc" => options.sort_asc = false,
+            "--limit" => options.limit = Some(count(arg, value(arg)?)?),
+            "--skip" => options.skip = count(arg, value(arg)?)?,
+            "--coerce" => config.coerce_types = true,
+            "--explain" => explain = true,
+            "--format" => format = value(arg)?.parse()?,
+            _ => query_parts.push(arg),
+        }
+    }
+    
+    let query = if query_parts.is_empty() {
+        "{}".to_string() // Empty query matches all documents
+    } else {
+        query_parts.join(" ")
+    };
+    
+    Ok(FindArgs { query, projection, options, config, explain, format })
+}
+
+/// Document `value` with its ID as `_id`, for table and CSV output; a
+/// value that is not a JSON object goes in a `value` field
+fn with_id(id: &[u8], value: JsonValue) -> JsonValue {
+    let mut doc = match value {
+        JsonValue::Object(fields) => fields,
+        value => serde_json::Map::from_iter([("value".to_string(), value)]),
+    };
+    doc.insert("_id".to_string(), JsonValue::String(String::from_utf8_lossy(id).into_owned()));
+    JsonValue::Object(doc)
+}
+
+/// Print documents as a table or CSV
+fn print_formatted(format: OutputFormat, docs: &[JsonValue]) {
+    match format {
+        OutputFormat::Table => print!("{}", format_as_table(docs)),
+        OutputFormat::Csv => print!("{}", format_as_csv(docs)),
+        OutputFormat::Json => docs.iter().for_each(|doc| format_output(&doc.to_string())),
+    }
+}
+
+/// Parse a `--fields` list such as `name,age` or `-bio,-_id` into a projection
+fn parse_fields(list: &str) -> Result<Projection> {
+    let mut spec = serde_json::Map::new();
+    for field in list.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        match field.strip_prefix('-') {
+            Some(hidden) => spec.insert(hidden.to_string(), JsonValue::from(0)),
+            None => spec.insert(field.to_string(), JsonValue::from(1)),
+        };
+    }
+    
+    if spec.is_empty() {
+        return Err(Error::Other("no fields given".to_string()));
+    }
+    
+    Projection::from_json(&JsonValue::Object(spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Feed `lines` to `read_multiline_json` as if typed at the prompt
+    fn read_lines(first: &str, lines: &[&str]) -> (Result<String>, usize) {
+        let mut lines = lines.iter();
+        let mut prompts = 0;
+        let result = CliInterface::read_multiline_json(first, |prompt| {
+            assert_eq!(prompt, "...> ");
+            prompts += 1;
+            lines.next().map(|line| line.to_string()).ok_or(ReadlineError::Interrupted)
+        });
+        (result, prompts)
+    }
+
+    #[test]
+    fn test_scan_args() {
+        let args = parse_scan_args(&["--limit", "50", "--cursor", "6162", "--format", "csv"]).unwrap();
+        assert_eq!((args.limit, args.cursor.as_deref(), args.format), (Some(50), Some("6162"), OutputFormat::Csv));
+        assert!(parse_scan_args(&[]).unwrap().limit.is_none());
+        assert!(parse_scan_args(&["--limit", "0"]).is_err());
+        assert!(parse_scan_args(&["--cursor"]).is_err());
+        assert!(parse_scan_args(&["--sort", "name"]).is_err());
+    }
+
+    #[test]
+    fn test_find_format_flag() {
+        let args = parse_find_args(&["{}", "--format", "table"]).unwrap();
+        assert_eq!(args.format, OutputFormat::Table);
+        assert_eq!(parse_find_args(&[]).unwrap().format, OutputFormat::Json);
+        assert!(parse_find_args(&["--format", "xml"]).is_err());
+
+        assert_eq!(with_id(b"ada", json!({"name": "Ada"})), json!({"_id": "ada", "name": "Ada"}));
+        assert_eq!(with_id(b"n1", json!("raw text")), json!({"_id": "n1", "value": "raw text"}));
+    }
+
+    #[test]
+    fn test_multiline_json_is_assembled() {
+        let (json, prompts) = read_lines("{", &[
+            r#"  "name": "Ada","#,
+            r#"  "address": {"city": "London", "note": "{ not a brace"},"#,
+            r#"  "tags": ["math", "poetry"]"#,
+            "}",
+            "ignored",
+        ]);
+        assert_eq!(prompts, 4);
+        let value: JsonValue = serde_json::from_str(&json.unwrap()).unwrap();
+        assert_eq!(value, serde_json::json!({
+            "name": "Ada",
+            "address": {"city": "London", "note": "{ not a brace"},
+            "tags": ["math", "poetry"],
+        }));
+
+        // A complete document on the first line needs no continuation
+        let (json, prompts) = read_lines(r#"{"name":"Ada"}"#, &[]);
+        assert_eq!((json.unwrap().as_str(), prompts), (r#"{"name":"Ada"}"#, 0));
+    }
+
+    #[test]
+    fn test_multiline_json_cancelled_or_invalid() {
+        // Ctrl-C before the braces balance
+        let (json, _) = read_lines("{", &[r#""name": "Ada""#]);
+        assert!(json.is_err());
+
+        let (json, _) = read_lines("{", &["name: Ada", "}"]);
+        assert!(json.is_err());
+        let (json, _) = read_lines("[1,", &["2]"]);
+        assert!(json.is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Tab completion for the interactive CLI
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use crate::interfaces::InterfaceManagerRef;
+
+/// Commands understood by the CLI
+const COMMANDS: &[&str] = &[
+    "help", "createdb", "usedb", "listdb", "dropdb", "list", "open", "close", "rename", "create",
+    "insert", "json", "patch", "incr", "get", "delete", "scan", "find", "aggregate",
+    "compression", "stats", "watch", "sync", "compact", "vacuum", "export", "import", "clear", "exit",
+    "quit",
+];
+
+/// Commands whose first argument is a collection of the active database
+const COLLECTION_COMMANDS: &[&str] = &[
+    "open", "close", "rename", "insert", "json", "patch", "incr", "get", "delete", "scan", "find",
+    "aggregate", "compression", "stats", "watch", "compact", "vacuum", "export", "import",
+];
+
+/// Commands whose first argument is a database
+const DATABASE_COMMANDS: &[&str] = &["usedb", "dropdb"];
+
+/// Completes command names, then the collection or database a command
+/// takes, looked up live so new collections complete straight away
+pub struct NebulaCompleter {
+    manager: InterfaceManagerRef,
+}
+
+impl NebulaCompleter {
+    /// Complete against the databases of `manager`
+    pub fn new(manager: InterfaceManagerRef) -> Self {
+        Self { manager }
+    }
+
+    /// Collections of the active database, open or on disk
+    fn collections(&self) -> Vec<String> {
+        let Ok(manager) = self.manager.read() else { return Vec::new() };
+        let Ok(db) = manager.get_active_database() else { return Vec::new() };
+        let collections = db.read().map(|db| db.list_collections()).unwrap_or_default();
+        collections
+    }
+
+    fn databases(&self) -> Vec<String> {
+        self.manager.read().map(|manager| manager.list_databases()).unwrap_or_default()
+    }
+}
+
+impl Completer for NebulaCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..];
+
+        let previous: Vec<String> = line[..start].split_whitespace().map(str::to_lowercase).collect();
+        let mut candidates = match previous.as_slice() {
+            [] => COMMANDS.iter().map(|command| command.to_string()).collect(),
+            [command] if COLLECTION_COMMANDS.contains(&command.as_str()) => self.collections(),
+            [command] if DATABASE_COMMANDS.contains(&command.as_str()) => self.databases(),
+            _ => Vec::new(),
+        };
+        candidates.retain(|candidate| candidate.starts_with(word));
+        candidates.sort();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for NebulaCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for NebulaCompleter {}
+
+impl Validator for NebulaCompleter {}
+
+impl Helper for NebulaCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::InterfaceManager;
+    use nebuladb_storage::StorageConfig;
+    use rustyline::history::History;
+    use std::sync::{Arc, RwLock};
+
+    fn complete(completer: &NebulaCompleter, line: &str) -> (usize, Vec<String>) {
+        let history = History::new();
+        completer.complete(line, line.len(), &Context::new(&history)).unwrap()
+    }
+
+    #[test]
+    fn test_completes_commands_then_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = InterfaceManager::new(dir.path(), StorageConfig::default()).unwrap();
+        manager.create_database("shop").unwrap();
+        let db = manager.get_active_database().unwrap();
+        db.read().unwrap().create_collection("users").unwrap();
+        db.read().unwrap().create_collection("orders").unwrap();
+        let completer = NebulaCompleter::new(Arc::new(RwLock::new(manager)));
+
+        assert_eq!(complete(&completer, "op"), (0, vec!["open".to_string()]));
+        assert_eq!(complete(&completer, "c").1, vec!["clear", "close", "compact", "compression", "create", "createdb"]);
+        assert_eq!(complete(&completer, "find u"), (5, vec!["users".to_string()]));
+        assert_eq!(complete(&completer, "GET ").1, vec!["orders", "users"]);
+        assert_eq!(complete(&completer, "usedb s"), (6, vec!["shop".to_string()]));
+
+        // Only the first argument is a collection or database
+        assert!(complete(&completer, "get users u").1.is_empty());
+        assert!(complete(&completer, "listdb d").1.is_empty());
+    }
+
+    #[test]
+    fn test_completes_every_dispatched_command() {
+        // The arms of the command match in `CliInterface::execute`
+        let source = include_str!("../cli.rs");
+        let dispatch = &source[source.find("match command.as_str() {").unwrap()..];
+        let dispatch = &dispatch[..dispatch.find("_ => fail!").unwrap()];
+        let dispatched: Vec<&str> = dispatch.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with('"'))
+            .filter_map(|line| line.split_once("=>"))
+            .flat_map(|(pattern, _)| pattern.split('|').map(|command| command.trim().trim_matches('"')))
+            .collect();
+
+        for command in &dispatched {
+            assert!(COMMANDS.contains(command), "'{}' is dispatched but not completed", command);
+        }
+        for command in COMMANDS {
+            assert!(dispatched.contains(command), "'{}' is completed but not dispatched", command);
+        }
+        for command in COLLECTION_COMMANDS.iter().chain(DATABASE_COMMANDS) {
+            assert!(COMMANDS.contains(command), "'{}' completes arguments but is not a command", command);
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use nebuladb_core::Result;
+use nebuladb_core::{Result, Error};
 use crate::interfaces::InterfaceManagerRef;
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
-use std::thread;
-use std::time::Duration;
+use crate::background::BackgroundTasks;
 use serde::{Serialize, Deserialize};
 
+#[cfg(feature = "grpc")]
+pub mod service;
+
 /// Configuration for the gRPC connection pool
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GrpcConnectionPoolConfig {
@@ -33,7 +36,11 @@ impl Default for GrpcConnectionPoolConfig {
 }
 
 #[derive(Clone)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
 /// gRPC interface for accessing the database
+///
+/// The server is only built with the `grpc` feature; without it, starting
+/// the interface fails.
 pub struct GrpcInterface {
     /// Reference to the interface manager
     manager: InterfaceManagerRef,
@@ -43,8 +50,8 @@ pub struct GrpcInterface {
     pool_config: GrpcConnectionPoolConfig,
     /// Whether the server is running
     running: Arc<RwLock<bool>>,
-    /// Number of active connections
-    active_connections: Arc<RwLock<usize>>,
+    /// Address the server is bound to, once started
+    local_addr: Arc<RwLock<Option<SocketAddr>>>,
 }
 
 impl GrpcInterface {
@@ -55,7 +62,7 @@ impl GrpcInterface {
             port,
             pool_config: GrpcConnectionPoolConfig::default(),
             running: Arc::new(RwLock::new(false)),
-            active_connections: Arc::new(RwLock::new(0)),
+            local_addr: Arc::new(RwLock::new(None)),
         })
     }
     
@@ -65,132 +72,139 @@ impl GrpcInterface {
     }
     
     /// Start the gRPC server
-    pub fn start(&self) -> Result<()> {
-        // Set running flag to true
+    ///
+    /// The port is bound before returning, so a port in use is reported
+    /// here. The server runs as a task of `tasks` and stops when its
+    /// shutdown signal is triggered.
+    #[cfg(feature = "grpc")]
+    pub fn start(&self, tasks: &Arc<BackgroundTasks>) -> Result<()> {
+        let listener = std::net::TcpListener::bind(("0.0.0.0", self.port)).map_err(Error::IoError)?;
+        listener.set_nonblocking(true).map_err(Error::IoError)?;
+        let addr = listener.local_addr().map_err(Error::IoError)?;
+        
+        if let Ok(mut local_addr) = self.local_addr.write() {
+            *local_addr = Some(addr);
+        }
         if let Ok(mut running) = self.running.write() {
             *running = true;
         }
         
-        println!("gRPC interface starting on port {}", self.port);
-        println!("Maximum connections: {}", self.pool_config.max_connections);
+        tracing::info!(%addr, max_connections = self.pool_config.max_connections, "gRPC interface listening");
         
-        // Create a clone of self for the connection handling thread
         let interface_clone = self.clone();
-        
-        // Spawn a thread to handle connections
-        thread::spawn(move || {
-            interface_clone.connection_acceptor();
+        tasks.spawn("grpc-server", move |signal| {
+            if let Err(e) = interface_clone.serve(listener, &signal) {
+                tracing::error!(error = ?e, "gRPC server failed");
+            }
         });
         
-        // In a real implementation, this would initialize the gRPC server
-        // with proper service definitions and connection handling
-        println!("(gRPC interface not fully implemented)");
-        
         Ok(())
     }
     
-    /// Connection accepting loop
-    fn connection_acceptor(&self) {
-        // In a real implementation, this would:
-        // 1. Create a gRPC server
-        // 2. Register service implementations
-        // 3. Accept connections up to the configured limit
+    /// Start the gRPC server
+    ///
+    /// Always fails: this build does not include the `grpc` feature.
+    #[cfg(not(feature = "grpc"))]
+    pub fn start(&self, _tasks: &Arc<BackgroundTasks>) -> Result<()> {
+        Err(Error::Other("NebulaDB was built without gRPC support; rebuild with --features grpc".to_string()))
+    }
+    
+    /// Address the server is bound to, once started
+    #[allow(dead_code, reason = "read by the tests to find the bound port")]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr.read().ok().and_then(|addr| *addr)
+    }
+    
+    /// Serve requests on `listener` until asked to stop
+    ///
+    /// At most `max_connections` requests are handled at once; the rest
+    /// wait for a slot.
+    #[cfg(feature = "grpc")]
+    fn serve(&self, listener: std::net::TcpListener, signal: &crate::background::ShutdownSignal) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::IoError)?;
         
-        // Simulation of connection handling for demonstration
-        while self.is_running() {
-            // Sleep to simulate waiting for connections
-            thread::sleep(Duration::from_secs(1));
-            
-            // Simulate accepting a connection
-            println!("gRPC: Connection received, current active: {}", self.get_active_connections());
-            
-            // Check if we can accept more connections
-            if self.get_active_connections() >= self.pool_config.max_connections {
-                println!("gRPC: Connection rejected - server at capacity");
-                continue;
-            }
-            
-            // Increment active connection count
-            self.increment_active_connections();
-            
-            // Spawn a worker thread to handle the connection
-            let interface_clone = self.clone();
-            thread::spawn(move || {
-                interface_clone.handle_connection();
-                
-                // Decrement active connection count when done
-                interface_clone.decrement_active_connections();
-            });
-        }
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(listener).map_err(Error::IoError)?;
+            let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+            tonic::transport::Server::builder()
+                .layer(tower::limit::GlobalConcurrencyLimitLayer::new(self.pool_config.max_connections))
+                .add_service(service::server(Arc::clone(&self.manager)))
+                .serve_with_incoming_shutdown(incoming, self.clone().stopped(signal.clone()))
+                .await
+                .map_err(|e| Error::Other(format!("gRPC transport error: {}", e)))
+        })?;
         
-        println!("gRPC connection acceptor stopped");
+        tracing::info!("gRPC server stopped");
+        Ok(())
     }
     
-    /// Handle a single connection
-    fn handle_connection(&self) {
-        // In a real implementation, this would:
-        // 1. Process gRPC requests
-        // 2. Execute database operations
-        // 3. Return responses
-        
-        // Simulate long-lived connection with multiple requests
-        let request_count = 5;
-        for i in 1..=request_count {
-            // Simulate processing a request
-            thread::sleep(Duration::from_secs(1));
-            println!("gRPC: Processed request {}/{}", i, request_count);
-            
-            // Check if the server is still running
-            if !self.is_running() {
-                println!("gRPC: Connection terminated early - server stopping");
-                break;
-            }
+    /// Resolves once shutdown is signalled or the server is stopped
+    #[cfg(feature = "grpc")]
+    async fn stopped(self, signal: crate::background::ShutdownSignal) {
+        while self.is_running() && !signal.is_triggered() {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         }
     }
     
     /// Check if the server is running
+    #[cfg(feature = "grpc")]
     fn is_running(&self) -> bool {
         self.running.read().map(|r| *r).unwrap_or(false)
     }
-    
-    /// Get the current number of active connections
-    fn get_active_connections(&self) -> usize {
-        self.active_connections.read().map(|c| *c).unwrap_or(0)
-    }
-    
-    /// Increment the active connection count
-    fn increment_active_connections(&self) {
-        if let Ok(mut count) = self.active_connections.write() {
-            *count += 1;
-        }
-    }
-    
-    /// Decrement the active connection count
-    fn decrement_active_connections(&self) {
-        if let Ok(mut count) = self.active_connections.write() {
-            if *count > 0 {
-                *count -= 1;
-            }
-        }
-    }
-    
-    /// Stop the gRPC server
-    pub fn stop(&self) -> Result<()> {
-        if let Ok(mut running) = self.running.write() {
-            *running = false;
-        }
-        
-        println!("gRPC server stopping...");
-        
-        // Wait for active connections to finish (with timeout)
-        let mut wait_cycles = 0;
-        while self.get_active_connections() > 0 && wait_cycles < 30 {
-            thread::sleep(Duration::from_secs(1));
-            wait_cycles += 1;
-        }
-        
-        println!("gRPC server stopped with {} connections still active", 
-                 self.get_active_connections());
-        Ok(())
+}
+
+#[cfg(all(test, feature = "grpc"))]
+mod tests {
+    use super::*;
+    use super::service::proto::nebula_db_client::NebulaDbClient;
+    use super::service::proto::{CreateDatabaseRequest, Document, DocumentRequest, FindDocumentsRequest, InsertDocumentRequest};
+    use crate::interfaces::InterfaceManager;
+    use nebuladb_storage::StorageConfig;
+    use serde_json::{json, Value as JsonValue};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_insert_and_get_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = InterfaceManager::new(dir.path(), StorageConfig::default()).unwrap();
+        let grpc = GrpcInterface::new(Arc::new(RwLock::new(manager)), 0).unwrap();
+        let tasks = Arc::new(BackgroundTasks::new());
+        grpc.start(&tasks).unwrap();
+
+        let url = format!("http://127.0.0.1:{}", grpc.local_addr().unwrap().port());
+        let mut client = NebulaDbClient::connect(url).await.unwrap();
+        client.create_database(CreateDatabaseRequest { name: "shop".into() }).await.unwrap();
+        let status = client.create_database(CreateDatabaseRequest { name: "shop".into() }).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let document = Document { id: "ada".into(), json: json!({"name": "Ada", "age": 36}).to_string() };
+        client.insert_document(InsertDocumentRequest {
+            database: "shop".into(),
+            collection: "users".into(),
+            document: Some(document),
+        }).await.unwrap();
+
+        let get = |id: &str| DocumentRequest { database: "shop".into(), collection: "users".into(), id: id.into() };
+        let found = client.get_document(get("ada")).await.unwrap().into_inner();
+        assert_eq!(found.id, "ada");
+        let json: JsonValue = serde_json::from_str(&found.json).unwrap();
+        assert_eq!(json, json!({"_id": "ada", "name": "Ada", "age": 36}));
+        assert_eq!(client.get_document(get("alan")).await.unwrap_err().code(), tonic::Code::NotFound);
+
+        let mut stream = client.find_documents(FindDocumentsRequest {
+            database: "shop".into(),
+            collection: "users".into(),
+            query: json!({"age": {"$gt": 30}}).to_string(),
+        }).await.unwrap().into_inner();
+        assert_eq!(stream.message().await.unwrap().map(|doc| doc.id), Some("ada".to_string()));
+        assert!(stream.message().await.unwrap().is_none());
+
+        client.delete_document(get("ada")).await.unwrap();
+        assert_eq!(client.get_document(get("ada")).await.unwrap_err().code(), tonic::Code::NotFound);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
     }
-} 
\ No newline at end of file
+}
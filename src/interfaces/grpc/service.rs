@@ -0,0 +1,240 @@
+//! The `NebulaDb` gRPC service defined in `proto/nebuladb.proto`
+
+use std::pin::Pin;
+
+use nebuladb_core::{Error, Result};
+use nebuladb_query::{Query, QueryConfig};
+use nebuladb_storage::changefeed::{self, ChangeOp};
+use serde_json::Value as JsonValue;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::interfaces::http::{document_json, with_id};
+use crate::interfaces::{find_database, forward_changes, open_collection, InterfaceManagerRef};
+
+use proto::nebula_db_server::{NebulaDb, NebulaDbServer};
+use proto::{
+    ChangeEvent, CreateDatabaseRequest, CreateDatabaseResponse, DeleteDocumentResponse, Document,
+    DocumentRequest, FindDocumentsRequest, InsertDocumentRequest, InsertDocumentResponse,
+    ListCollectionsRequest, ListCollectionsResponse, Operation, WatchRequest,
+};
+
+/// Code generated from the proto definitions
+pub mod proto {
+    tonic::include_proto!("nebuladb");
+}
+
+/// Response header holding the resume token of a `Watch` stream
+const RESUME_TOKEN_HEADER: &str = "resume-token";
+
+/// Build the gRPC service over the databases of `manager`
+pub fn server(manager: InterfaceManagerRef) -> NebulaDbServer<NebulaDbService> {
+    NebulaDbServer::new(NebulaDbService { manager })
+}
+
+/// Dispatches gRPC calls to the interface manager
+pub struct NebulaDbService {
+    manager: InterfaceManagerRef,
+}
+
+/// Map a database error to the closest gRPC status
+fn status(error: Error) -> Status {
+    let message = format!("{:?}", error);
+    match error {
+        Error::NotFound { .. } => Status::not_found(message),
+        Error::AlreadyExists { .. } | Error::DuplicateKey { .. } => Status::already_exists(message),
+        Error::Deadlock { .. } => Status::aborted(message),
+        Error::TypeMismatch { .. } => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Run blocking database work off the async workers
+async fn blocking<T, F>(work: F) -> std::result::Result<T, Status>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await
+        .map_err(|e| Status::internal(format!("Request handler failed: {}", e)))?
+        .map_err(status)
+}
+
+/// Parse the JSON text of a request field
+fn parse_json(field: &str, text: &str) -> std::result::Result<JsonValue, Status> {
+    serde_json::from_str(text)
+        .map_err(|e| Status::invalid_argument(format!("Invalid JSON in '{}': {}", field, e)))
+}
+
+/// A change event as sent to `Watch` streams
+fn change_event(event: changefeed::ChangeEvent) -> ChangeEvent {
+    let operation = match event.op {
+        ChangeOp::Insert => Operation::Insert,
+        ChangeOp::Update => Operation::Update,
+        ChangeOp::Delete => Operation::Delete,
+    };
+    let document_id = String::from_utf8_lossy(&event.id).to_string();
+    ChangeEvent {
+        sequence: event.sequence,
+        operation: operation.into(),
+        document: event.data.map(|data| Document {
+            id: document_id.clone(),
+            json: document_json(&event.id, &data).to_string(),
+        }),
+        document_id,
+        timestamp: event.timestamp,
+    }
+}
+
+#[tonic::async_trait]
+impl NebulaDb for NebulaDbService {
+    type FindDocumentsStream = tokio_stream::Iter<std::vec::IntoIter<std::result::Result<Document, Status>>>;
+    type WatchStream = Pin<Box<dyn Stream<Item = std::result::Result<ChangeEvent, Status>> + Send>>;
+
+    async fn create_database(
+        &self,
+        request: Request<CreateDatabaseRequest>,
+    ) -> std::result::Result<Response<CreateDatabaseResponse>, Status> {
+        let name = request.into_inner().name;
+        let manager = self.manager.clone();
+        blocking(move || {
+            let mut manager = manager.write().map_err(|_| Error::Other("Failed to lock interface manager".to_string()))?;
+            if manager.get_database(&name).is_ok() {
+                return Err(Error::AlreadyExists { name });
+            }
+            manager.create_database(&name)
+        }).await?;
+        Ok(Response::new(CreateDatabaseResponse {}))
+    }
+
+    async fn list_collections(
+        &self,
+        request: Request<ListCollectionsRequest>,
+    ) -> std::result::Result<Response<ListCollectionsResponse>, Status> {
+        let name = request.into_inner().database;
+        let manager = self.manager.clone();
+        let collections = blocking(move || {
+            let db = find_database(&manager, &name)?;
+            let db = db.read().map_err(|_| Error::Other("Failed to lock database".to_string()))?;
+            let mut collections = db.list_collections();
+            collections.sort();
+            Ok(collections)
+        }).await?;
+        Ok(Response::new(ListCollectionsResponse { collections }))
+    }
+
+    async fn insert_document(
+        &self,
+        request: Request<InsertDocumentRequest>,
+    ) -> std::result::Result<Response<InsertDocumentResponse>, Status> {
+        let request = request.into_inner();
+        let document = request.document
+            .ok_or_else(|| Status::invalid_argument("Missing document"))?;
+        if document.id.is_empty() {
+            return Err(Status::invalid_argument("Document ID must not be empty"));
+        }
+        let json = parse_json("document.json", &document.json)?;
+
+        let manager = self.manager.clone();
+        blocking(move || {
+            let db = find_database(&manager, &request.database)?;
+            let collection = open_collection(&db, &request.collection, true)?;
+            let mut collection = collection.lock().map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+            collection.insert(document.id.as_bytes(), json.to_string().as_bytes())
+        }).await?;
+        Ok(Response::new(InsertDocumentResponse {}))
+    }
+
+    async fn get_document(
+        &self,
+        request: Request<DocumentRequest>,
+    ) -> std::result::Result<Response<Document>, Status> {
+        let request = request.into_inner();
+        let manager = self.manager.clone();
+        let document = blocking(move || {
+            let db = find_database(&manager, &request.database)?;
+            let collection = open_collection(&db, &request.collection, false)?;
+            let collection = collection.lock().map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+            match collection.get(request.id.as_bytes())? {
+                Some(data) => Ok(Document {
+                    json: document_json(request.id.as_bytes(), &data).to_string(),
+                    id: request.id,
+                }),
+                None => Err(Error::NotFound { id: request.id }),
+            }
+        }).await?;
+        Ok(Response::new(document))
+    }
+
+    async fn delete_document(
+        &self,
+        request: Request<DocumentRequest>,
+    ) -> std::result::Result<Response<DeleteDocumentResponse>, Status> {
+        let request = request.into_inner();
+        let manager = self.manager.clone();
+        blocking(move || {
+            let db = find_database(&manager, &request.database)?;
+            let collection = open_collection(&db, &request.collection, false)?;
+            let mut collection = collection.lock().map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+            if !collection.delete(request.id.as_bytes())? {
+                return Err(Error::NotFound { id: request.id });
+            }
+            Ok(())
+        }).await?;
+        Ok(Response::new(DeleteDocumentResponse {}))
+    }
+
+    async fn find_documents(
+        &self,
+        request: Request<FindDocumentsRequest>,
+    ) -> std::result::Result<Response<Self::FindDocumentsStream>, Status> {
+        let request = request.into_inner();
+        let query = if request.query.trim().is_empty() {
+            Query::And(Vec::new())
+        } else {
+            Query::from_json(&parse_json("query", &request.query)?)
+                .map_err(|e| Status::invalid_argument(format!("Invalid query: {:?}", e)))?
+        };
+
+        let manager = self.manager.clone();
+        let documents = blocking(move || {
+            let db = find_database(&manager, &request.database)?;
+            let collection = open_collection(&db, &request.collection, false)?;
+            let collection = collection.lock().map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+            collection.find(&query, &QueryConfig::default())
+        }).await?;
+
+        let documents: Vec<_> = documents.into_iter()
+            .map(|(id, doc)| Ok(Document {
+                json: with_id(&id, doc).to_string(),
+                id: String::from_utf8_lossy(&id).to_string(),
+            }))
+            .collect();
+        Ok(Response::new(tokio_stream::iter(documents)))
+    }
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> std::result::Result<Response<Self::WatchStream>, Status> {
+        let request = request.into_inner();
+        let manager = self.manager.clone();
+        let subscription = blocking(move || {
+            let db = find_database(&manager, &request.database)?;
+            let collection = open_collection(&db, &request.collection, true)?;
+            let collection = collection.lock().map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+            Ok(collection.subscribe())
+        }).await?;
+
+        let resume_token = subscription.resume_token();
+        // A watcher that fell behind gets the loss as its final status and
+        // reconnects with the resume token
+        let events = ReceiverStream::new(forward_changes(subscription))
+            .map(|event| event.map(change_event).map_err(|lagged| Status::data_loss(format!(
+                "Watcher fell behind and missed {} changes", lagged.missed))));
+        let mut response = Response::new(Box::pin(events) as Self::WatchStream);
+        response.metadata_mut().insert(RESUME_TOKEN_HEADER, resume_token.into());
+        Ok(response)
+    }
+}
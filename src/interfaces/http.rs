@@ -1,9 +1,35 @@
-use nebuladb_core::Result;
-use crate::interfaces::{InterfaceManager, InterfaceManagerRef};
+use nebuladb_core::{metrics, Result, Error};
+use nebuladb_query::{FindOptions, Query, QueryConfig};
+use nebuladb_storage::changefeed::{ChangeEvent, ChangeOp, Subscription};
+use crate::interfaces::{find_database, forward_changes, open_collection, InterfaceManagerRef};
+use crate::interfaces::auth::{self, HttpAuthConfig};
+use std::net::{IpAddr, SocketAddr, TcpListener};
 use std::sync::{Arc, RwLock};
-use std::thread;
-use std::time::Duration;
+use crate::background::{BackgroundTasks, ShutdownSignal};
+use crate::database::Database;
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
+use serde_json::{json, Value as JsonValue};
+use axum::{Json, Router};
+use axum::extract::{ConnectInfo, Path, Query as UrlQuery, Request, State};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use dashmap::DashMap;
+use tracing::{error, info, warn};
+
+/// Field of a document body holding its ID
+const ID_FIELD: &str = "_id";
+
+/// How often the server checks whether it was asked to stop
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often buckets of clients that went quiet are dropped
+const RATE_LIMIT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Configuration for the connection pool
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,6 +58,57 @@ impl Default for ConnectionPoolConfig {
     }
 }
 
+/// Tokens left to a client, refilled as time passes
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token buckets limiting the request rate of each client IP
+#[derive(Clone, Debug)]
+struct RateLimiter {
+    buckets: Arc<DashMap<IpAddr, TokenBucket>>,
+    /// Tokens added per second
+    rate: f64,
+    /// Tokens a bucket holds when full
+    burst: f64,
+}
+
+impl RateLimiter {
+    fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            buckets: Arc::new(DashMap::new()),
+            rate: rate as f64,
+            burst: burst as f64,
+        }
+    }
+
+    /// Take a token for `ip`, or return how long until one is available
+    fn acquire(&self, ip: IpAddr) -> std::result::Result<(), Duration> {
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(ip).or_insert(TokenBucket { tokens: self.burst, refilled: now });
+
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Drop the buckets that have refilled completely, which behave the same
+    /// as a new bucket
+    fn sweep(&self) {
+        let full_after = self.burst / self.rate;
+        self.buckets.retain(|_, bucket| bucket.refilled.elapsed().as_secs_f64() < full_after);
+    }
+}
+
 #[derive(Clone)]
 /// HTTP interface for accessing the database via REST API
 pub struct HttpInterface {
@@ -43,8 +120,20 @@ pub struct HttpInterface {
     pool_config: ConnectionPoolConfig,
     /// Whether the server is running
     running: Arc<RwLock<bool>>,
-    /// Number of active connections
+    /// Number of requests being handled
     active_connections: Arc<RwLock<usize>>,
+    /// Address the server is bound to, once started
+    local_addr: Arc<RwLock<Option<SocketAddr>>>,
+    /// Credentials required of requests
+    auth: Arc<HttpAuthConfig>,
+    /// Per-client request rate limit, if enabled
+    rate_limiter: Option<RateLimiter>,
+    /// Separate port serving only the metrics, if any
+    metrics_port: Option<u16>,
+    /// Address the metrics server is bound to, once started on its own port
+    metrics_addr: Arc<RwLock<Option<SocketAddr>>>,
+    /// Whether `GET /health` answers 200 while degraded
+    health_ok_when_degraded: bool,
 }
 
 impl HttpInterface {
@@ -56,6 +145,12 @@ impl HttpInterface {
             pool_config: ConnectionPoolConfig::default(),
             running: Arc::new(RwLock::new(false)),
             active_connections: Arc::new(RwLock::new(0)),
+            local_addr: Arc::new(RwLock::new(None)),
+            auth: Arc::new(HttpAuthConfig::default()),
+            rate_limiter: None,
+            metrics_port: None,
+            metrics_addr: Arc::new(RwLock::new(None)),
+            health_ok_when_degraded: false,
         })
     }
     
@@ -64,79 +159,161 @@ impl HttpInterface {
         self.pool_config = config;
     }
     
+    /// Require the credentials in `auth`; requests without them get 401,
+    /// unless `auth` allows anonymous access
+    pub fn configure_auth(&mut self, auth: HttpAuthConfig) {
+        self.auth = Arc::new(auth);
+    }
+    
+    /// Limit each client IP to `rps` requests per second, in bursts of up
+    /// to `burst`; an `rps` of 0 disables the limit
+    pub fn configure_rate_limit(&mut self, rps: u32, burst: u32) {
+        self.rate_limiter = (rps > 0).then(|| RateLimiter::new(rps, burst.max(1)));
+    }
+    
+    /// Serve `GET /metrics` on its own port, without authentication, rather
+    /// than alongside the API
+    pub fn configure_metrics_port(&mut self, port: Option<u16>) {
+        self.metrics_port = port;
+    }
+    
+    /// Answer `GET /health` with 200 rather than 503 while a check fails
+    pub fn configure_health(&mut self, ok_when_degraded: bool) {
+        self.health_ok_when_degraded = ok_when_degraded;
+    }
+    
     /// Start the HTTP server
-    pub fn start(&self) -> Result<()> {
-        // Set running flag to true
+    ///
+    /// The port is bound before returning, so a port in use is reported
+    /// here. The server runs as a task of `tasks` and stops when its
+    /// shutdown signal is triggered.
+    pub fn start(&self, tasks: &Arc<BackgroundTasks>) -> Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", self.port)).map_err(Error::IoError)?;
+        listener.set_nonblocking(true).map_err(Error::IoError)?;
+        let addr = listener.local_addr().map_err(Error::IoError)?;
+        
+        if let Ok(mut local_addr) = self.local_addr.write() {
+            *local_addr = Some(addr);
+        }
         if let Ok(mut running) = self.running.write() {
             *running = true;
         }
+        if !self.auth.allow_anonymous && !self.auth.has_credentials() {
+            warn!("no HTTP users or API keys are configured; every request except the probes will get 401");
+        }
         
-        println!("HTTP interface starting on port {}", self.port);
-        println!("Maximum connections: {}", self.pool_config.max_connections);
+        info!(%addr, max_connections = self.pool_config.max_connections, "HTTP interface listening");
         
-        // Create a clone of self for the connection accepting thread
         let interface_clone = self.clone();
-        
-        // Spawn a thread to accept connections
-        thread::spawn(move || {
-            interface_clone.connection_acceptor();
+        tasks.spawn("http-server", move |signal| {
+            if let Err(e) = interface_clone.serve(listener, interface_clone.router(), &signal) {
+                error!(error = ?e, "HTTP server failed");
+            }
         });
         
-        // In a real implementation, this would create an HTTP server
-        // with a thread pool for handling connections
-        println!("(HTTP interface not fully implemented)");
-        
-        Ok(())
-    }
-    
-    /// Connection accepting loop
-    fn connection_acceptor(&self) {
-        // In a real implementation, this would:
-        // 1. Create a TCP socket and bind to the port
-        // 2. Accept connections in a loop
-        // 3. For each connection, either handle it in a thread pool or reject if at capacity
-        
-        // Simulation of connection handling for demonstration
-        while self.is_running() {
-            // Sleep to simulate waiting for connections
-            thread::sleep(Duration::from_secs(1));
-            
-            // Simulate accepting a connection
-            println!("HTTP: Connection received, current active: {}", self.get_active_connections());
-            
-            // Check if we can accept more connections
-            if self.get_active_connections() >= self.pool_config.max_connections {
-                println!("HTTP: Connection rejected - server at capacity");
-                continue;
+        if let Some(port) = self.metrics_port {
+            let listener = TcpListener::bind(("0.0.0.0", port)).map_err(Error::IoError)?;
+            listener.set_nonblocking(true).map_err(Error::IoError)?;
+            let addr = listener.local_addr().map_err(Error::IoError)?;
+            if let Ok(mut metrics_addr) = self.metrics_addr.write() {
+                *metrics_addr = Some(addr);
             }
+            info!(%addr, "HTTP metrics listening");
             
-            // Increment active connection count
-            self.increment_active_connections();
-            
-            // Spawn a worker thread to handle the connection
             let interface_clone = self.clone();
-            thread::spawn(move || {
-                interface_clone.handle_connection();
-                
-                // Decrement active connection count when done
-                interface_clone.decrement_active_connections();
+            tasks.spawn("http-metrics-server", move |signal| {
+                let app = Router::new().route("/metrics", get(render_metrics));
+                if let Err(e) = interface_clone.serve(listener, app, &signal) {
+                    error!(error = ?e, "HTTP metrics server failed");
+                }
+            });
+        }
+        
+        if let Some(limiter) = self.rate_limiter.clone() {
+            let interface_clone = self.clone();
+            tasks.spawn("http-rate-limit-sweep", move |signal| {
+                while interface_clone.is_running() && !signal.wait_timeout(RATE_LIMIT_SWEEP_INTERVAL) {
+                    limiter.sweep();
+                }
             });
         }
         
-        println!("HTTP connection acceptor stopped");
+        Ok(())
+    }
+    
+    /// Address the server is bound to, once started
+    #[allow(dead_code, reason = "read by the tests to find the bound port")]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr.read().ok().and_then(|addr| *addr)
+    }
+    
+    /// Address the metrics server is bound to, once started on its own port
+    #[allow(dead_code, reason = "read by the tests to find the bound port")]
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr.read().ok().and_then(|addr| *addr)
+    }
+    
+    /// Serve `app` on `listener` until asked to stop
+    fn serve(&self, listener: TcpListener, app: Router, signal: &ShutdownSignal) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::IoError)?;
+        
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(listener).map_err(Error::IoError)?;
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(self.clone().stopped(signal.clone()))
+                .await
+                .map_err(Error::IoError)
+        })?;
+        
+        info!("HTTP server stopped");
+        Ok(())
+    }
+    
+    /// Resolves once shutdown is signalled or the server is stopped
+    async fn stopped(self, signal: ShutdownSignal) {
+        while self.is_running() && !signal.is_triggered() {
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
     }
     
-    /// Handle a single connection
-    fn handle_connection(&self) {
-        // In a real implementation, this would:
-        // 1. Parse HTTP request headers
-        // 2. Route the request to the appropriate handler
-        // 3. Execute database operations
-        // 4. Return HTTP response
+    /// REST routes, sharing the interface manager as their state
+    fn router(&self) -> Router {
+        let mut router = Router::new()
+            .route("/databases", get(list_databases))
+            .route("/databases/{db}", post(create_database))
+            .route("/databases/{db}/collections", get(list_collections))
+            .route("/databases/{db}/collections/{coll}/documents", post(insert_document))
+            .route("/databases/{db}/collections/{coll}/documents/{id}", get(get_document).delete(delete_document))
+            .route("/databases/{db}/collections/{coll}/find", post(find_documents))
+            .route("/databases/{db}/collections/{coll}/watch", get(watch_collection));
+        if self.metrics_port.is_none() {
+            router = router.route("/metrics", get(render_metrics));
+        }
+        let router = router.layer(middleware::from_fn_with_state(self.clone(), limit_connections));
+        
+        let router = if self.auth.allow_anonymous {
+            router
+        } else {
+            router.layer(middleware::from_fn_with_state(Arc::clone(&self.auth), auth::require_auth))
+        };
         
-        // Simulation of connection handling
-        thread::sleep(Duration::from_secs(2));
-        println!("HTTP: Processed request");
+        // Limit rates first, so failed logins count against the client too
+        let router = match &self.rate_limiter {
+            Some(limiter) => router.layer(middleware::from_fn_with_state(limiter.clone(), limit_rate)),
+            None => router,
+        };
+        
+        // Probes are added past every layer so load balancers need no
+        // credentials and are never turned away
+        let probes = Router::new()
+            .route("/health", get(health))
+            .route("/ready", get(ready))
+            .with_state(self.clone());
+        router.merge(probes).with_state(Arc::clone(&self.manager))
     }
     
     /// Check if the server is running
@@ -149,10 +326,25 @@ impl HttpInterface {
         self.active_connections.read().map(|c| *c).unwrap_or(0)
     }
     
-    /// Increment the active connection count
-    fn increment_active_connections(&self) {
-        if let Ok(mut count) = self.active_connections.write() {
-            *count += 1;
+    /// Check that the server has room for another request
+    fn check_connections(&self) -> std::result::Result<(), String> {
+        let active = self.get_active_connections();
+        if active < self.pool_config.max_connections {
+            Ok(())
+        } else {
+            Err(format!("{} of {} connections in use", active, self.pool_config.max_connections))
+        }
+    }
+    
+    /// Count a new connection, unless the server is at capacity
+    fn try_acquire_connection(&self) -> bool {
+        match self.active_connections.write() {
+            Ok(mut count) if *count < self.pool_config.max_connections => {
+                *count += 1;
+                metrics::ACTIVE_CONNECTIONS.inc();
+                true
+            },
+            _ => false,
         }
     }
     
@@ -161,26 +353,737 @@ impl HttpInterface {
         if let Ok(mut count) = self.active_connections.write() {
             if *count > 0 {
                 *count -= 1;
+                metrics::ACTIVE_CONNECTIONS.dec();
             }
         }
     }
-    
-    /// Stop the HTTP server
-    pub fn stop(&self) -> Result<()> {
-        if let Ok(mut running) = self.running.write() {
-            *running = false;
+}
+
+/// Reject requests beyond the connection limit with 503
+async fn limit_connections(State(interface): State<HttpInterface>, request: Request, next: Next) -> Response {
+    if !interface.try_acquire_connection() {
+        return ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Server at capacity").into_response();
+    }
+
+    let response = next.run(request).await;
+    interface.decrement_active_connections();
+    response
+}
+
+/// Reject requests of clients over their rate limit with 429
+async fn limit_rate(
+    State(limiter): State<RateLimiter>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limiter.acquire(client.ip()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+            response.headers_mut().insert(RETRY_AFTER, retry_after.into());
+            response
         }
-        
-        println!("HTTP server stopping...");
-        
-        // Wait for active connections to finish
-        let mut wait_cycles = 0;
-        while self.get_active_connections() > 0 && wait_cycles < 30 {
-            thread::sleep(Duration::from_secs(1));
-            wait_cycles += 1;
+    }
+}
+
+/// An error sent to the client as `{"error": "..."}`
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let status = match &error {
+            Error::NotFound { .. } => StatusCode::NOT_FOUND,
+            Error::AlreadyExists { .. } | Error::DuplicateKey { .. } | Error::Deadlock { .. } => StatusCode::CONFLICT,
+            Error::TypeMismatch { .. } => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, format!("{:?}", error))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Run blocking database work off the async workers
+async fn blocking<T, F>(work: F) -> ApiResult<T>
+where
+    F: FnOnce() -> ApiResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await
+        .map_err(|e| ApiError::internal(format!("Request handler failed: {}", e)))?
+}
+
+/// Parse a stored document, falling back to a string for non-JSON data
+pub(crate) fn document_json(id: &[u8], data: &[u8]) -> JsonValue {
+    let doc = serde_json::from_slice(data)
+        .unwrap_or_else(|_| JsonValue::String(String::from_utf8_lossy(data).to_string()));
+    with_id(id, doc)
+}
+
+/// Add the `_id` field to a document object
+pub(crate) fn with_id(id: &[u8], mut doc: JsonValue) -> JsonValue {
+    if let Some(object) = doc.as_object_mut() {
+        object.insert(ID_FIELD.to_string(), JsonValue::String(String::from_utf8_lossy(id).to_string()));
+    }
+    doc
+}
+
+/// `GET /metrics`, in the Prometheus text format
+async fn render_metrics() -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render())
+}
+
+/// Run `check` on every database, reporting the first failure
+fn check_databases(
+    manager: &InterfaceManagerRef,
+    check: impl Fn(&Database) -> Result<()>,
+) -> std::result::Result<(), String> {
+    let manager = manager.read().map_err(|_| "Failed to lock interface manager".to_string())?;
+    let mut names = manager.list_databases();
+    names.sort();
+    for name in names {
+        let db = manager.get_database(&name).map_err(|e| format!("{:?}", e))?;
+        let db = db.read().map_err(|_| format!("Failed to lock database '{}'", name))?;
+        check(&db).map_err(|e| format!("database '{}': {:?}", name, e))?;
+    }
+    Ok(())
+}
+
+/// `GET /health`, without authentication
+///
+/// Each check reads "ok" or "error: ..."; any failure makes the server
+/// "degraded", answered with 503 unless configured to stay at 200.
+async fn health(State(interface): State<HttpInterface>) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let connections = interface.check_connections();
+    let manager = Arc::clone(&interface.manager);
+    let (wal, storage) = blocking(move || {
+        Ok((check_databases(&manager, Database::check_wal), check_databases(&manager, Database::check_storage)))
+    }).await?;
+
+    let checks = [("wal", wal), ("storage", storage), ("connections", connections)];
+    let degraded = checks.iter().any(|(_, result)| result.is_err());
+    let checks: serde_json::Map<String, JsonValue> = checks.into_iter()
+        .map(|(name, result)| {
+            let outcome = match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            (name.to_string(), JsonValue::String(outcome))
+        })
+        .collect();
+
+    let (status, code) = match (degraded, interface.health_ok_when_degraded) {
+        (false, _) => ("ok", StatusCode::OK),
+        (true, true) => ("degraded", StatusCode::OK),
+        (true, false) => ("degraded", StatusCode::SERVICE_UNAVAILABLE),
+    };
+    Ok((code, Json(json!({ "status": status, "checks": checks }))))
+}
+
+/// `GET /ready`, without authentication
+///
+/// 503 while any database has not completed WAL recovery, 200 after.
+async fn ready(State(interface): State<HttpInterface>) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let manager = Arc::clone(&interface.manager);
+    blocking(move || {
+        let manager = manager.read().map_err(|_| ApiError::internal("Failed to lock interface manager"))?;
+        let mut recovering = Vec::new();
+        for name in manager.list_databases() {
+            let db = manager.get_database(&name)?;
+            let db = db.read().map_err(|_| ApiError::internal("Failed to lock database"))?;
+            if !db.is_recovered() {
+                recovering.push(name);
+            }
         }
-        
-        println!("HTTP server stopped");
-        Ok(())
+        recovering.sort();
+
+        if recovering.is_empty() {
+            Ok((StatusCode::OK, Json(json!({ "status": "ready" }))))
+        } else {
+            Ok((StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "status": "recovering", "databases": recovering }))))
+        }
+    }).await
+}
+
+/// `GET /databases`
+async fn list_databases(State(manager): State<InterfaceManagerRef>) -> ApiResult<Json<JsonValue>> {
+    blocking(move || {
+        let manager = manager.read().map_err(|_| ApiError::internal("Failed to lock interface manager"))?;
+        let mut databases = manager.list_databases();
+        databases.sort();
+        Ok(Json(json!({ "databases": databases })))
+    }).await
+}
+
+/// `POST /databases/{db}`
+async fn create_database(
+    State(manager): State<InterfaceManagerRef>,
+    Path(name): Path<String>,
+) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    blocking(move || {
+        let mut manager = manager.write().map_err(|_| ApiError::internal("Failed to lock interface manager"))?;
+        if manager.get_database(&name).is_ok() {
+            return Err(Error::AlreadyExists { name }.into());
+        }
+        manager.create_database(&name)?;
+        Ok((StatusCode::CREATED, Json(json!({ "database": name }))))
+    }).await
+}
+
+/// `GET /databases/{db}/collections`
+async fn list_collections(
+    State(manager): State<InterfaceManagerRef>,
+    Path(db): Path<String>,
+) -> ApiResult<Json<JsonValue>> {
+    blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let db = db.read().map_err(|_| ApiError::internal("Failed to lock database"))?;
+        let mut collections = db.list_collections();
+        collections.sort();
+        Ok(Json(json!({ "collections": collections })))
+    }).await
+}
+
+/// `POST /databases/{db}/collections/{coll}/documents`
+///
+/// The body is a JSON object whose `_id` field, a string or number, is the
+/// document ID. The collection is created if needed.
+async fn insert_document(
+    State(manager): State<InterfaceManagerRef>,
+    Path((db, coll)): Path<(String, String)>,
+    body: std::result::Result<Json<JsonValue>, JsonRejection>,
+) -> ApiResult<(StatusCode, Json<JsonValue>)> {
+    let Json(doc) = body?;
+    let id = match doc.get(ID_FIELD) {
+        Some(JsonValue::String(id)) => id.clone(),
+        Some(id @ JsonValue::Number(_)) => id.to_string(),
+        _ => return Err(ApiError::bad_request(format!("Document has no string or number '{}' field", ID_FIELD))),
+    };
+
+    blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let collection = open_collection(&db, &coll, true)?;
+        let mut collection = collection.lock().map_err(|_| ApiError::internal("Failed to lock collection"))?;
+        collection.insert(id.as_bytes(), doc.to_string().as_bytes())?;
+        Ok((StatusCode::CREATED, Json(json!({ ID_FIELD: id }))))
+    }).await
+}
+
+/// `GET /databases/{db}/collections/{coll}/documents/{id}`
+async fn get_document(
+    State(manager): State<InterfaceManagerRef>,
+    Path((db, coll, id)): Path<(String, String, String)>,
+) -> ApiResult<Json<JsonValue>> {
+    blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let collection = open_collection(&db, &coll, false)?;
+        let collection = collection.lock().map_err(|_| ApiError::internal("Failed to lock collection"))?;
+        match collection.get(id.as_bytes())? {
+            Some(data) => Ok(Json(document_json(id.as_bytes(), &data))),
+            None => Err(Error::NotFound { id }.into()),
+        }
+    }).await
+}
+
+/// `DELETE /databases/{db}/collections/{coll}/documents/{id}`
+async fn delete_document(
+    State(manager): State<InterfaceManagerRef>,
+    Path((db, coll, id)): Path<(String, String, String)>,
+) -> ApiResult<Json<JsonValue>> {
+    blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let collection = open_collection(&db, &coll, false)?;
+        let mut collection = collection.lock().map_err(|_| ApiError::internal("Failed to lock collection"))?;
+        if !collection.delete(id.as_bytes())? {
+            return Err(Error::NotFound { id }.into());
+        }
+        Ok(Json(json!({ "deleted": id })))
+    }).await
+}
+
+/// `?limit=&skip=` paging of `find` results
+#[derive(Debug, Default, Deserialize)]
+struct PageParams {
+    limit: Option<usize>,
+    #[serde(default)]
+    skip: usize,
+}
+
+/// `POST /databases/{db}/collections/{coll}/find[?limit=n&skip=n]`
+///
+/// The body is a query such as `{"age": {"$gt": 30}}`. Skipping past the
+/// last match returns no documents.
+async fn find_documents(
+    State(manager): State<InterfaceManagerRef>,
+    Path((db, coll)): Path<(String, String)>,
+    page: std::result::Result<UrlQuery<PageParams>, QueryRejection>,
+    body: std::result::Result<Json<JsonValue>, JsonRejection>,
+) -> ApiResult<Json<JsonValue>> {
+    let UrlQuery(page) = page?;
+    let Json(query) = body?;
+    let query = Query::from_json(&query)
+        .map_err(|e| ApiError::bad_request(format!("Invalid query: {:?}", e)))?;
+
+    blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let collection = open_collection(&db, &coll, false)?;
+        let collection = collection.lock().map_err(|_| ApiError::internal("Failed to lock collection"))?;
+        let options = FindOptions { limit: page.limit, skip: page.skip, ..FindOptions::default() };
+        let documents: Vec<JsonValue> = collection.find_with_options(&query, &options, &QueryConfig::default())?
+            .into_iter()
+            .map(|(id, doc)| with_id(&id, doc))
+            .collect();
+        Ok(Json(json!({ "count": documents.len(), "documents": documents })))
+    }).await
+}
+
+/// `GET /databases/{db}/collections/{coll}/watch`
+///
+/// Upgrades to a WebSocket that first receives `{"resume_token": n}` and
+/// then one message per write to the collection. A client too slow to keep
+/// up gets `{"lagged": k}` in place of the `k` writes it lost. A reconnecting
+/// client that last saw sequence number `m` missed writes if `n > m`. The
+/// collection is created if needed.
+async fn watch_collection(
+    State(manager): State<InterfaceManagerRef>,
+    Path((db, coll)): Path<(String, String)>,
+    upgrade: WebSocketUpgrade,
+) -> ApiResult<Response> {
+    let subscription = blocking(move || {
+        let db = find_database(&manager, &db)?;
+        let collection = open_collection(&db, &coll, true)?;
+        let collection = collection.lock().map_err(|_| ApiError::internal("Failed to lock collection"))?;
+        Ok(collection.subscribe())
+    }).await?;
+
+    Ok(upgrade.on_upgrade(move |socket| stream_changes(socket, subscription)))
+}
+
+/// Send the events of `subscription` over `socket` until the client leaves
+async fn stream_changes(mut socket: WebSocket, subscription: Subscription) {
+    let handshake = json!({ "resume_token": subscription.resume_token() });
+    if socket.send(Message::Text(handshake.to_string().into())).await.is_err() {
+        return;
+    }
+
+    let mut events = forward_changes(subscription);
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let message = match event {
+                    Some(Ok(event)) => change_json(&event),
+                    Some(Err(lagged)) => json!({ "lagged": lagged.missed }),
+                    None => break,
+                };
+                let message = Message::Text(message.to_string().into());
+                if socket.send(message).await.is_err() {
+                    break;
+                }
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {},
+            },
+        }
+    }
+}
+
+/// A change event as sent to watching clients
+fn change_json(event: &ChangeEvent) -> JsonValue {
+    let operation = match event.op {
+        ChangeOp::Insert => "insert",
+        ChangeOp::Update => "update",
+        ChangeOp::Delete => "delete",
+    };
+    json!({
+        "sequence": event.sequence,
+        "operation": operation,
+        "document_id": String::from_utf8_lossy(&event.id),
+        "document": event.data.as_ref().map(|data| document_json(&event.id, data)),
+        "timestamp": event.timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::InterfaceManager;
+    use nebuladb_storage::StorageConfig;
+    use reqwest::blocking::Client;
+
+    /// Credentials that let every request through
+    fn anonymous() -> HttpAuthConfig {
+        HttpAuthConfig { allow_anonymous: true, ..HttpAuthConfig::default() }
+    }
+
+    /// Start a server on a free port, returning its base URL
+    fn start_server(dir: &std::path::Path, auth: HttpAuthConfig) -> (Arc<BackgroundTasks>, String) {
+        let manager = InterfaceManager::new(dir, StorageConfig::default()).unwrap();
+        let mut http = HttpInterface::new(Arc::new(RwLock::new(manager)), 0).unwrap();
+        http.configure_auth(auth);
+        let tasks = Arc::new(BackgroundTasks::new());
+        http.start(&tasks).unwrap();
+        let port = http.local_addr().unwrap().port();
+        (tasks, format!("http://127.0.0.1:{}", port))
+    }
+
+    #[test]
+    fn test_rest_endpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), anonymous());
+        let client = Client::new();
+
+        let response = client.post(format!("{}/databases/shop", url)).send().unwrap();
+        assert_eq!(response.status(), 201);
+        let response = client.post(format!("{}/databases/shop", url)).send().unwrap();
+        assert_eq!(response.status(), 409);
+        let databases: JsonValue = client.get(format!("{}/databases", url)).send().unwrap().json().unwrap();
+        assert_eq!(databases["databases"], json!(["default", "shop"]));
+
+        let documents = format!("{}/databases/shop/collections/users/documents", url);
+        for (id, age) in [("ada", 36), ("alan", 41), ("grace", 85)] {
+            let response = client.post(&documents).json(&json!({"_id": id, "age": age})).send().unwrap();
+            assert_eq!(response.status(), 201);
+        }
+        let response = client.post(&documents).json(&json!({"age": 1})).send().unwrap();
+        assert_eq!(response.status(), 400);
+        let error: JsonValue = response.json().unwrap();
+        assert!(error["error"].is_string());
+
+        let collections: JsonValue = client.get(format!("{}/databases/shop/collections", url)).send().unwrap().json().unwrap();
+        assert_eq!(collections["collections"], json!(["users"]));
+
+        let doc: JsonValue = client.get(format!("{}/ada", documents)).send().unwrap().json().unwrap();
+        assert_eq!(doc, json!({"_id": "ada", "age": 36}));
+
+        let found: JsonValue = client.post(format!("{}/databases/shop/collections/users/find", url))
+            .json(&json!({"age": {"$gt": 40}}))
+            .send().unwrap().json().unwrap();
+        assert_eq!(found["count"], 2);
+
+        assert_eq!(client.delete(format!("{}/ada", documents)).send().unwrap().status(), 200);
+        assert_eq!(client.delete(format!("{}/ada", documents)).send().unwrap().status(), 404);
+        assert_eq!(client.get(format!("{}/ada", documents)).send().unwrap().status(), 404);
+        assert_eq!(client.get(format!("{}/databases/missing/collections", url)).send().unwrap().status(), 404);
+        assert_eq!(client.get(format!("{}/databases/shop/collections/missing/documents/x", url)).send().unwrap().status(), 404);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_find_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), anonymous());
+        let client = Client::new();
+
+        let documents = format!("{}/databases/default/collections/users/documents", url);
+        for i in 0..50 {
+            client.post(&documents).json(&json!({"_id": i, "n": i})).send().unwrap();
+        }
+        let find = |params: &str| -> JsonValue {
+            client.post(format!("{}/databases/default/collections/users/find{}", url, params))
+                .json(&json!({}))
+                .send().unwrap().json().unwrap()
+        };
+
+        assert_eq!(find("")["count"], 50);
+        let third_page = find("?limit=20&skip=40");
+        assert_eq!(third_page["count"], 10);
+        let second_page = find("?limit=20&skip=20");
+        assert_eq!(second_page["count"], 20);
+        assert!(second_page["documents"].as_array().unwrap().iter()
+            .all(|doc| !third_page["documents"].as_array().unwrap().contains(doc)));
+        assert_eq!(find("?skip=49")["count"], 1);
+        assert_eq!(find("?skip=50")["count"], 0);
+        assert_eq!(find("?limit=20&skip=500")["documents"], json!([]));
+        assert_eq!(find("?limit=0")["count"], 0);
+
+        let response = client.post(format!("{}/databases/default/collections/users/find?limit=-1", url))
+            .json(&json!({}))
+            .send().unwrap();
+        assert_eq!(response.status(), 400);
+        assert!(response.json::<JsonValue>().unwrap()["error"].is_string());
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
     }
-} 
\ No newline at end of file
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_watch_streams_inserts() {
+        use futures_util::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), anonymous());
+        let client = reqwest::Client::new();
+        client.post(format!("{}/databases/shop", url)).send().await.unwrap();
+
+        let watch = format!("{}/databases/shop/collections/users/watch", url.replacen("http", "ws", 1));
+        let (subscribed, ready) = tokio::sync::oneshot::channel();
+        let watcher = tokio::spawn(async move {
+            let (socket, _) = tokio_tungstenite::connect_async(watch).await.unwrap();
+            let mut messages = socket.map(|message| {
+                serde_json::from_str::<JsonValue>(message.unwrap().to_text().unwrap()).unwrap()
+            });
+            let handshake = messages.next().await.unwrap();
+            subscribed.send(()).unwrap();
+
+            let events: Vec<JsonValue> = messages.take(10).collect().await;
+            (handshake, events)
+        });
+
+        let documents = format!("{}/databases/shop/collections/users/documents", url);
+        let writer = tokio::spawn(async move {
+            ready.await.unwrap();
+            for i in 0..10 {
+                let response = client.post(&documents).json(&json!({"_id": format!("user{}", i), "n": i})).send().await.unwrap();
+                assert_eq!(response.status(), 201);
+            }
+        });
+
+        writer.await.unwrap();
+        let (handshake, events) = tokio::time::timeout(Duration::from_secs(10), watcher).await.unwrap().unwrap();
+        // Sequence numbers are WAL positions, past the token and increasing
+        let mut last = handshake["resume_token"].as_u64().unwrap();
+        assert_eq!(events.len(), 10);
+        for (i, event) in events.iter().enumerate() {
+            let sequence = event["sequence"].as_u64().unwrap();
+            assert!(sequence > last);
+            last = sequence;
+            assert_eq!(event["operation"], "insert");
+            assert_eq!(event["document_id"], format!("user{}", i));
+            assert_eq!(event["document"], json!({"_id": format!("user{}", i), "n": i}));
+            assert!(event["timestamp"].as_u64().unwrap() > 0);
+        }
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_metrics_count_inserts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), anonymous());
+        let client = Client::new();
+        client.post(format!("{}/databases/shop", url)).send().unwrap();
+
+        let documents = format!("{}/databases/shop/collections/metered/documents", url);
+        for i in 0..10 {
+            let response = client.post(&documents).json(&json!({"_id": i})).send().unwrap();
+            assert_eq!(response.status(), 201);
+        }
+
+        let response = client.get(format!("{}/metrics", url)).send().unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        let body = response.text().unwrap();
+        assert!(body.contains(r#"nebuladb_documents_inserted_total{collection="metered"} 10"#), "{}", body);
+        assert!(body.contains("nebuladb_wal_bytes_written_total"));
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_metrics_on_their_own_port_skip_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = InterfaceManager::new(dir.path(), StorageConfig::default()).unwrap();
+        let mut http = HttpInterface::new(Arc::new(RwLock::new(manager)), 0).unwrap();
+        let mut auth = HttpAuthConfig::default();
+        auth.add_user("ada", "secret");
+        http.configure_auth(auth);
+        http.configure_metrics_port(Some(0));
+        let tasks = Arc::new(BackgroundTasks::new());
+        http.start(&tasks).unwrap();
+        let client = Client::new();
+
+        let metrics = format!("http://127.0.0.1:{}/metrics", http.metrics_addr().unwrap().port());
+        let response = client.get(&metrics).send().unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.text().unwrap().contains("nebuladb_active_connections"));
+
+        let api = format!("http://127.0.0.1:{}", http.local_addr().unwrap().port());
+        let response = client.get(format!("{}/metrics", api)).basic_auth("ada", Some("secret")).send().unwrap();
+        assert_eq!(response.status(), 404);
+        let response = client.get(format!("{}/databases", metrics.trim_end_matches("/metrics"))).send().unwrap();
+        assert_eq!(response.status(), 404);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_requests_need_credentials_once_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut auth = HttpAuthConfig::default();
+        auth.add_user("ada", "secret");
+        auth.api_key_hashes.push(auth::sha256_hex("key-1"));
+        let (tasks, url) = start_server(dir.path(), auth);
+        let client = Client::new();
+        let databases = format!("{}/databases", url);
+
+        let response = client.get(&databases).send().unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Basic realm=\"NebulaDB\"");
+
+        let response = client.get(&databases).basic_auth("ada", Some("wrong")).send().unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client.get(&databases).header("X-Api-Key", "key-2").send().unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = client.get(&databases).basic_auth("ada", Some("secret")).send().unwrap();
+        assert_eq!(response.status(), 200);
+        let response = client.get(&databases).header("X-Api-Key", "key-1").send().unwrap();
+        assert_eq!(response.status(), 200);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_requests_rejected_without_configured_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), HttpAuthConfig::default());
+        let client = Client::new();
+
+        let response = client.get(format!("{}/databases", url)).send().unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client.get(format!("{}/databases", url)).basic_auth("ada", Some("secret")).send().unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(client.get(format!("{}/health", url)).send().unwrap().status(), 200);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_anonymous_access_is_opt_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tasks, url) = start_server(dir.path(), anonymous());
+
+        let response = Client::new().get(format!("{}/databases", url)).send().unwrap();
+        assert_eq!(response.status(), 200);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_probes_follow_wal_state_without_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        // A log that cannot be read leaves the database unrecovered
+        let wal_dir = dir.path().join("shop").join("wal");
+        std::fs::create_dir_all(&wal_dir).unwrap();
+        std::fs::write(wal_dir.join("users.wal"), b"not a WAL file").unwrap();
+
+        let manager = Arc::new(RwLock::new(InterfaceManager::new(dir.path(), StorageConfig::default()).unwrap()));
+        let mut http = HttpInterface::new(Arc::clone(&manager), 0).unwrap();
+        let mut auth = HttpAuthConfig::default();
+        auth.add_user("ada", "secret");
+        http.configure_auth(auth);
+        let tasks = Arc::new(BackgroundTasks::new());
+        http.start(&tasks).unwrap();
+        let url = format!("http://127.0.0.1:{}", http.local_addr().unwrap().port());
+        let client = Client::new();
+
+        let response = client.get(format!("{}/ready", url)).send().unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.json::<JsonValue>().unwrap(), json!({"status": "recovering", "databases": ["shop"]}));
+
+        let response = client.get(format!("{}/health", url)).send().unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.json::<JsonValue>().unwrap(), json!({
+            "status": "ok",
+            "checks": {"wal": "ok", "storage": "ok", "connections": "ok"},
+        }));
+
+        // Once the log is repaired and recovery reruns, the server is ready
+        std::fs::remove_file(wal_dir.join("users.wal")).unwrap();
+        let db = manager.read().unwrap().get_database("shop").unwrap();
+        db.write().unwrap().recover_wal().unwrap();
+        let response = client.get(format!("{}/ready", url)).send().unwrap();
+        assert_eq!(response.status(), 200);
+
+        // Without a WAL directory appends fail
+        std::fs::remove_dir_all(&wal_dir).unwrap();
+        let response = client.get(format!("{}/health", url)).send().unwrap();
+        assert_eq!(response.status(), 503);
+        let body: JsonValue = response.json().unwrap();
+        assert_eq!(body["status"], "degraded");
+        assert!(body["checks"]["wal"].as_str().unwrap().starts_with("error: database 'shop'"), "{}", body);
+        assert_eq!(body["checks"]["storage"], "ok");
+
+        // The API itself still requires credentials
+        assert_eq!(client.get(format!("{}/databases", url)).send().unwrap().status(), 401);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+
+    #[test]
+    fn test_clients_over_rate_limit_get_429() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = InterfaceManager::new(dir.path(), StorageConfig::default()).unwrap();
+        let mut http = HttpInterface::new(Arc::new(RwLock::new(manager)), 0).unwrap();
+        http.configure_rate_limit(10, 20);
+        http.configure_auth(anonymous());
+        let tasks = Arc::new(BackgroundTasks::new());
+        http.start(&tasks).unwrap();
+        let url = format!("http://127.0.0.1:{}/databases", http.local_addr().unwrap().port());
+
+        // 10 clients send 20 requests each within about 100ms, far past a
+        // burst of 20 refilled at 10 per second
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let url = url.clone();
+                std::thread::spawn(move || {
+                    let client = Client::new();
+                    (0..20)
+                        .map(|_| {
+                            let response = client.get(&url).send().unwrap();
+                            std::thread::sleep(Duration::from_millis(5));
+                            (response.status().as_u16(), response.headers().contains_key("retry-after"))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let responses: Vec<(u16, bool)> = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+
+        let limited: Vec<_> = responses.iter().filter(|(status, _)| *status == 429).collect();
+        assert!(!limited.is_empty());
+        assert!(limited.iter().all(|(_, retry_after)| *retry_after));
+        assert!(responses.iter().filter(|(status, _)| *status == 200).count() >= 20);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).is_clean());
+    }
+}
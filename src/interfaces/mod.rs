@@ -1,3 +1,4 @@
+pub mod auth;
 pub mod cli;
 pub mod http;
 pub mod grpc;
@@ -6,7 +7,13 @@ use std::path::Path;
 use std::sync::{Arc, Mutex, RwLock};
 use std::collections::HashMap;
 use nebuladb_core::{Result, Error};
-use nebuladb_storage::StorageConfig;
+use nebuladb_storage::{format, StorageConfig};
+use nebuladb_storage::changefeed::{ChangeEvent, Lagged, Subscription};
+use nebuladb_storage::collection::{Collection, CollectionStats};
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use crate::background::{BackgroundTasks, ShutdownReport};
+use crate::config::{GrpcConfig, HttpConfig};
 use crate::database::Database;
 
 #[derive(Clone)]
@@ -30,8 +37,14 @@ pub struct InterfaceManager {
     max_connections: usize,
     /// Connection timeout in seconds
     connection_timeout: u64,
+    /// Background threads started by the interfaces
+    tasks: Arc<BackgroundTasks>,
 }
 
+/// Events held for a watching client that is slow to take them; past that
+/// its subscription falls behind and reports the events it lost
+const WATCH_BUFFER: usize = 16;
+
 // Helper type to avoid recursive type issues
 // Using RwLock instead of Mutex for better concurrency (multiple readers, single writer)
 pub type InterfaceManagerRef = Arc<RwLock<InterfaceManager>>;
@@ -39,6 +52,9 @@ pub type InterfaceManagerRef = Arc<RwLock<InterfaceManager>>;
 impl InterfaceManager {
     /// Create a new interface manager
     pub fn new(base_path: &Path, config: StorageConfig) -> Result<Self> {
+        // Upgrade data written by older releases before anything opens it
+        format::prepare_data_dir(base_path)?;
+        
         let mut manager = Self {
             base_path: base_path.into(),
             config,
@@ -49,6 +65,7 @@ impl InterfaceManager {
             grpc: None,
             max_connections: 1000, // Default value
             connection_timeout: 30, // Default value in seconds
+            tasks: Arc::new(BackgroundTasks::new()),
         };
         
         // Look for existing databases
@@ -102,6 +119,13 @@ impl InterfaceManager {
         }
     }
     
+    /// Get a database by name
+    pub fn get_database(&self, name: &str) -> Result<Arc<RwLock<Database>>> {
+        self.databases.get(name)
+            .cloned()
+            .ok_or_else(|| Error::NotFound { id: name.to_string() })
+    }
+    
     /// Create a new database
     pub fn create_database(&mut self, name: &str) -> Result<()> {
         if self.databases.contains_key(name) {
@@ -119,6 +143,23 @@ impl InterfaceManager {
         Ok(())
     }
     
+    /// Document counts and on-disk size of a collection in the active
+    /// database
+    ///
+    /// The collection is opened if needed.
+    pub fn collection_stats(&self, collection: &str) -> Result<CollectionStats> {
+        let db = self.get_active_database()?;
+        let mut db = db.write()
+            .map_err(|_| Error::Other("Failed to lock database".to_string()))?;
+        db.open_collection(collection)?;
+        
+        let collection = db.get_collection(collection)
+            .ok_or_else(|| Error::Other(format!("Collection '{}' is not open", collection)))?;
+        let collection = collection.lock()
+            .map_err(|_| Error::Other("Failed to lock collection".to_string()))?;
+        collection.stats()
+    }
+    
     /// List all available databases
     pub fn list_databases(&self) -> Vec<String> {
         self.databases.keys().cloned().collect()
@@ -160,50 +201,69 @@ impl InterfaceManager {
     }
     
     /// Enable the HTTP interface
-    pub fn enable_http(&mut self, port: u16) -> Result<()> {
+    pub fn enable_http(&mut self, config: &HttpConfig) -> Result<()> {
         let manager_ref = Arc::new(RwLock::new(self.clone()));
-        let http = http::HttpInterface::new(manager_ref, port)?;
+        let mut http = http::HttpInterface::new(manager_ref, config.port)?;
+        http.configure_pool(config.pool.clone());
+        http.configure_auth(config.auth.clone());
+        http.configure_rate_limit(config.rate_limit_rps, config.rate_limit_burst);
+        http.configure_metrics_port(config.metrics_port);
+        http.configure_health(config.health_ok_when_degraded);
         self.http = Some(Arc::new(http));
         Ok(())
     }
     
     /// Enable the gRPC interface
-    pub fn enable_grpc(&mut self, port: u16) -> Result<()> {
+    pub fn enable_grpc(&mut self, config: &GrpcConfig) -> Result<()> {
         let manager_ref = Arc::new(RwLock::new(self.clone()));
-        let grpc = grpc::GrpcInterface::new(manager_ref, port)?;
+        let mut grpc = grpc::GrpcInterface::new(manager_ref, config.port)?;
+        grpc.configure_pool(config.pool.clone());
         self.grpc = Some(Arc::new(grpc));
         Ok(())
     }
     
     /// Start all enabled interfaces
     pub fn start(&mut self) -> Result<()> {
+        if let Some(http) = &self.http {
+            if let Err(e) = http.start(&self.tasks) {
+                tracing::error!(error = ?e, "failed to start HTTP interface");
+            }
+        }
+        
+        if let Some(grpc) = &self.grpc {
+            if let Err(e) = grpc.start(&self.tasks) {
+                tracing::error!(error = ?e, "failed to start gRPC interface");
+            }
+        }
+        
+        // The CLI blocks until the user exits, so it starts last
         if let Some(cli_ref) = &self.cli {
             // Get mutable access to the CLI interface
             let mut cli = cli_ref.lock().map_err(|_| Error::Other("Failed to lock CLI interface".into()))?;
             cli.start()?;
         }
         
-        if let Some(http) = &self.http {
-            // Clone the Arc to allow for concurrent access across threads
-            let http_clone = Arc::clone(http);
-            std::thread::spawn(move || {
-                if let Err(e) = http_clone.start() {
-                    eprintln!("Error starting HTTP interface: {:?}", e);
-                }
-            });
-        }
+        Ok(())
+    }
+    
+    /// Stop all background threads and close every database
+    ///
+    /// Signals the interface threads, joins them, then shuts down each
+    /// database's own threads. Threads still running after `timeout` (per
+    /// group) are listed in the report rather than waited on.
+    pub fn shutdown(&mut self, timeout: Duration) -> ShutdownReport {
+        let mut report = self.tasks.shutdown(timeout);
         
-        if let Some(grpc) = &self.grpc {
-            // Clone the Arc to allow for concurrent access across threads
-            let grpc_clone = Arc::clone(grpc);
-            std::thread::spawn(move || {
-                if let Err(e) = grpc_clone.start() {
-                    eprintln!("Error starting gRPC interface: {:?}", e);
+        for (name, db_rwlock) in &self.databases {
+            if let Ok(mut db) = db_rwlock.write() {
+                match db.shutdown(timeout) {
+                    Ok(db_report) => report.merge(db_report),
+                    Err(e) => tracing::error!(database = %name, error = ?e, "failed to shut down database"),
                 }
-            });
+            }
         }
         
-        Ok(())
+        report
     }
     
     /// Drop (delete) a database
@@ -229,7 +289,7 @@ impl InterfaceManager {
         // Delete the directory
         let db_path = self.base_path.join(name);
         if db_path.exists() {
-            std::fs::remove_dir_all(&db_path).map_err(|e| Error::IoError(e))?;
+            std::fs::remove_dir_all(&db_path).map_err(Error::IoError)?;
         }
         
         Ok(())
@@ -242,9 +302,53 @@ impl Drop for InterfaceManager {
         for (name, db_rwlock) in &self.databases {
             if let Ok(mut db) = db_rwlock.write() {
                 if let Err(e) = db.close_all_collections() {
-                    eprintln!("Error closing collections in database '{}': {:?}", name, e);
+                    tracing::error!(database = %name, error = ?e, "failed to close collections");
                 }
             }
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Look up a database of the manager behind `manager`
+pub(crate) fn find_database(manager: &InterfaceManagerRef, name: &str) -> Result<Arc<RwLock<Database>>> {
+    let manager = manager.read().map_err(|_| Error::Other("Failed to lock interface manager".to_string()))?;
+    manager.get_database(name)
+}
+
+/// Open a collection of `db`; a missing collection is created if `create`
+/// is set and is an error otherwise
+pub(crate) fn open_collection(db: &RwLock<Database>, name: &str, create: bool) -> Result<Arc<Mutex<Collection>>> {
+    if let Some(collection) = db.read().ok().and_then(|db| db.get_collection(name)) {
+        return Ok(collection);
+    }
+
+    let mut db = db.write().map_err(|_| Error::Other("Failed to lock database".to_string()))?;
+    if !create && !db.collection_exists(name) {
+        return Err(Error::NotFound { id: name.to_string() });
+    }
+    db.open_collection(name)?;
+    db.get_collection(name)
+        .ok_or_else(|| Error::Other(format!("Collection '{}' is not open", name)))
+}
+
+/// Hand the events of a subscription to a change stream
+///
+/// A task forwards them and drops the subscription once the returned
+/// receiver is gone.
+pub(crate) fn forward_changes(mut subscription: Subscription) -> Receiver<std::result::Result<ChangeEvent, Lagged>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(WATCH_BUFFER);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                received = subscription.recv() => match received {
+                    Some(received) => if sender.send(received).await.is_err() {
+                        break;
+                    },
+                    None => break,
+                },
+                _ = sender.closed() => break,
+            }
+        }
+    });
+    receiver
+}
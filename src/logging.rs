@@ -0,0 +1,73 @@
+//! Diagnostic logging
+//!
+//! Components log through `tracing`; [`init`] installs the subscriber that
+//! filters and writes those events as configured by [`LoggingConfig`].
+
+use std::fs::OpenOptions;
+use std::sync::Arc;
+use nebuladb_core::{Error, Result};
+use tracing::Subscriber;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use crate::config::{LogFormat, LogOutput, LoggingConfig};
+
+/// Build a subscriber writing events as `config` describes
+pub fn subscriber(config: &LoggingConfig) -> Result<Box<dyn Subscriber + Send + Sync>> {
+    let filter = EnvFilter::try_new(&config.level)
+        .map_err(|e| Error::Other(format!("Invalid logging.level '{}': {}", config.level, e)))?;
+    let (writer, ansi) = match &config.output {
+        LogOutput::Stderr => (BoxMakeWriter::new(std::io::stderr), true),
+        LogOutput::File(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(Error::IoError)?;
+            (BoxMakeWriter::new(Arc::new(file)), false)
+        }
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    Ok(match config.format {
+        LogFormat::Text => Box::new(builder.with_ansi(ansi).finish()),
+        LogFormat::Json => Box::new(builder.json().finish()),
+    })
+}
+
+/// Install the process-wide subscriber; events logged before this are dropped
+pub fn init(config: &LoggingConfig) -> Result<()> {
+    tracing::subscriber::set_global_default(subscriber(config)?)
+        .map_err(|e| Error::Other(format!("Failed to initialize logging: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value as JsonValue;
+
+    #[test]
+    fn test_json_events_written_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nebuladb.log");
+        let config = LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::Json,
+            output: LogOutput::File(path.clone()),
+        };
+
+        tracing::subscriber::with_default(subscriber(&config).unwrap(), || {
+            tracing::debug!("filtered out");
+            tracing::info!(collection = "users", docs = 3, "document inserted");
+        });
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<JsonValue> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["level"], "INFO");
+        assert_eq!(events[0]["fields"]["message"], "document inserted");
+        assert_eq!(events[0]["fields"]["collection"], "users");
+        assert_eq!(events[0]["fields"]["docs"], 3);
+    }
+}
@@ -1,23 +1,31 @@
-use std::path::Path;
 use std::env;
+use std::fs::File;
+use std::io::BufReader;
 use std::process;
-use std::io;
+use std::sync::{mpsc, Arc, RwLock};
 use nebuladb_core::{Result, Error};
-use nebuladb_storage::StorageConfig;
 use crate::interfaces::InterfaceManager;
+use crate::interfaces::cli::CliInterface;
 use crate::config::SystemConfig;
-use crate::connection_pool::{ConnectionPool, ConnectionPoolConfig};
+use tracing::{info, warn};
 
+mod background;
 mod database;
 mod interfaces;
 mod util;
 mod config;
+#[allow(dead_code, reason = "connection pooling is not wired into the interfaces yet")]
 mod connection_pool;
+mod logging;
+mod wal_inspect;
 
 fn print_usage() {
     println!("NebulaDB - A distributed document database");
     println!("Usage:");
     println!("  nebuladb [options]");
+    println!("  nebuladb wal-inspect <file.wal> [--filter-tx <id>] [--after <timestamp>]");
+    println!("  nebuladb add-user <username> <password> [--config <file>]");
+    println!("  nebuladb run-script <file.ndb> [--config <file>] [--fail-fast]");
     println!();
     println!("Options:");
     println!("  --config <file>       Load configuration from file");
@@ -26,9 +34,110 @@ fn print_usage() {
     println!("  --help                Show this help message");
 }
 
+/// Print the entries of a WAL file, exiting with an error status if it
+/// ends in an unreadable entry
+fn run_wal_inspect(args: &[String]) -> Result<()> {
+    let args = wal_inspect::parse_args(args)?;
+    let summary = wal_inspect::inspect(&args, &mut std::io::stdout().lock())?;
+    println!("{} of {} entries shown", summary.shown, summary.entries);
+    if summary.corruption.is_some() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Add a user of the HTTP interface to a config file, `nebuladb.json` unless
+/// `--config` names another
+fn run_add_user(args: &[String]) -> Result<()> {
+    let (username, password) = match args {
+        [username, password, ..] => (username, password),
+        _ => {
+            eprintln!("Usage: nebuladb add-user <username> <password> [--config <file>]");
+            process::exit(1);
+        }
+    };
+    let path = match &args[2..] {
+        [] => "nebuladb.json",
+        [flag, path] if flag == "--config" => path.as_str(),
+        _ => {
+            eprintln!("Usage: nebuladb add-user <username> <password> [--config <file>]");
+            process::exit(1);
+        }
+    };
+    
+    config::add_http_user(path, username, password)?;
+    println!("Added HTTP user '{}' to {}", username, path);
+    Ok(())
+}
+
+/// Run the CLI commands of a script file against the configured data
+/// directory, stopping at the first failing command with `--fail-fast`
+fn run_script(args: &[String]) -> Result<()> {
+    let usage = || -> ! {
+        eprintln!("Usage: nebuladb run-script <file.ndb> [--config <file>] [--fail-fast]");
+        process::exit(1);
+    };
+    let Some(script_path) = args.first() else { usage() };
+    let mut config_path = None;
+    let mut fail_fast = false;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(rest.next().unwrap_or_else(|| usage())),
+            "--fail-fast" => fail_fast = true,
+            _ => usage(),
+        }
+    }
+    
+    let system_config = match config_path {
+        Some(path) => SystemConfig::load_from_file(path)?,
+        None => SystemConfig::default(),
+    };
+    logging::init(&system_config.logging)?;
+    let script = File::open(script_path).map_err(Error::IoError)?;
+    
+    std::fs::create_dir_all(&system_config.data_dir).map_err(Error::IoError)?;
+    let manager = InterfaceManager::new(&system_config.data_dir, system_config.to_storage_config())?;
+    let manager = Arc::new(RwLock::new(manager));
+    let mut cli = CliInterface::new(Arc::clone(&manager))?;
+    cli.set_fail_fast(fail_fast);
+    let result = cli.execute_script(BufReader::new(script));
+    
+    // Close the databases the script used, even if it stopped early
+    if let Ok(mut manager) = manager.write() {
+        manager.shutdown(database::SHUTDOWN_TIMEOUT);
+    }
+    result
+}
+
+/// Block until the process receives Ctrl-C
+fn wait_for_interrupt() {
+    let (sender, receiver) = mpsc::channel();
+    if let Err(e) = ctrlc::set_handler(move || {
+        let _ = sender.send(());
+    }) {
+        warn!(error = %e, "failed to install Ctrl-C handler");
+        return;
+    }
+    
+    println!("Press Ctrl-C to stop");
+    let _ = receiver.recv();
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     
+    // `wal-dump` is the command's older name
+    if matches!(args.get(1).map(String::as_str), Some("wal-inspect") | Some("wal-dump")) {
+        return run_wal_inspect(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("add-user") {
+        return run_add_user(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("run-script") {
+        return run_script(&args[2..]);
+    }
+    
     // Parse command line arguments
     let mut config_path = None;
     let mut generate_config = false;
@@ -78,23 +187,24 @@ fn main() -> Result<()> {
     }
     
     // Load configuration
-    let system_config = match config_path {
-        Some(path) => {
-            println!("Loading configuration from: {}", path);
-            SystemConfig::load_from_file(&path)?
-        },
-        None => {
-            println!("Using default configuration");
-            SystemConfig::default()
-        }
+    let system_config = match &config_path {
+        Some(path) => SystemConfig::load_from_file(path)?,
+        None => SystemConfig::default(),
     };
     
+    // Log through the configured subscriber from here on
+    logging::init(&system_config.logging)?;
+    match &config_path {
+        Some(path) => info!(path = %path, "loaded configuration"),
+        None => info!("using default configuration"),
+    }
+    
     // Create the data directory if it doesn't exist
     let data_dir = system_config.data_dir.as_path();
     if !data_dir.exists() {
         std::fs::create_dir_all(data_dir)
-            .map_err(|e| Error::IoError(e))?;
-        println!("Created data directory: {:?}", data_dir);
+            .map_err(Error::IoError)?;
+        info!(path = %data_dir.display(), "created data directory");
     }
     
     // Create storage config from system config
@@ -111,28 +221,40 @@ fn main() -> Result<()> {
     
     // Enable interfaces based on configuration
     if system_config.interfaces.enable_cli {
-        println!("Enabling CLI interface");
+        info!("enabling CLI interface");
         manager.enable_cli()?;
     }
     
     if system_config.interfaces.http.enabled || production_mode {
-        println!("Enabling HTTP interface on port {}", system_config.interfaces.http.port);
-        manager.enable_http(system_config.interfaces.http.port)?;
+        info!(port = system_config.interfaces.http.port, "enabling HTTP interface");
+        manager.enable_http(&system_config.interfaces.http)?;
     }
     
     if system_config.interfaces.grpc.enabled || production_mode {
-        println!("Enabling gRPC interface on port {}", system_config.interfaces.grpc.port);
-        manager.enable_grpc(system_config.interfaces.grpc.port)?;
+        info!(port = system_config.interfaces.grpc.port, "enabling gRPC interface");
+        manager.enable_grpc(&system_config.interfaces.grpc)?;
     }
     
-    println!("Starting NebulaDB in {} mode", 
-             if production_mode { "production" } else { "normal" });
-    println!("Data directory: {:?}", data_dir);
-    println!("Maximum databases: {}", system_config.concurrency.max_databases);
-    println!("Maximum collections per database: {}", system_config.concurrency.max_collections_per_db);
+    info!(mode = if production_mode { "production" } else { "normal" },
+        data_dir = %data_dir.display(),
+        max_databases = system_config.concurrency.max_databases,
+        max_collections_per_db = system_config.concurrency.max_collections_per_db,
+        "starting NebulaDB");
     
     // Start all enabled interfaces
     manager.start()?;
     
+    // Without the CLI holding the process open, serve until Ctrl-C
+    let serving = system_config.interfaces.http.enabled || system_config.interfaces.grpc.enabled || production_mode;
+    if serving && !system_config.interfaces.enable_cli {
+        wait_for_interrupt();
+    }
+    
+    // Stop background threads and close databases once the interfaces return
+    let report = manager.shutdown(database::SHUTDOWN_TIMEOUT);
+    if !report.is_clean() {
+        warn!(tasks = ?report.timed_out, "background tasks still running at exit");
+    }
+    
     Ok(())
 }
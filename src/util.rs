@@ -1,27 +1,32 @@
-use serde_json::Value as JsonValue;
+use nebuladb_storage::changefeed::{ChangeEvent, ChangeOp};
 
-/// Check if a string is valid JSON
-pub fn is_valid_json(json_str: &str) -> bool {
-    // Simple JSON validation - check for paired braces and at least one key-value pair
-    if !json_str.starts_with('{') || !json_str.ends_with('}') {
-        return false;
-    }
-    
-    // Count braces to ensure they're balanced
-    let mut brace_count = 0;
-    for c in json_str.chars() {
-        if c == '{' {
-            brace_count += 1;
-        } else if c == '}' {
-            brace_count -= 1;
-        }
-        
-        if brace_count < 0 {
-            return false;
+pub mod format;
+
+/// Number of `{` and `[` left open in `text`, ignoring those in strings
+///
+/// Zero once a JSON value is complete; negative if it closes too much.
+pub fn json_depth(text: &str) -> isize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => {}
+            }
         }
     }
-    
-    brace_count == 0
+    depth
 }
 
 /// Format and pretty-print document output
@@ -79,33 +84,57 @@ pub fn format_output(data: &str) {
     }
 }
 
-/// Check if a document matches a query
-pub fn matches_query(document: &str, query: &JsonValue) -> bool {
-    // Parse the document JSON
-    if let Ok(doc_value) = serde_json::from_str::<JsonValue>(document) {
-        // If query is empty, match all documents
-        if query.as_object().map_or(false, |obj| obj.is_empty()) {
-            return true;
-        }
-        
-        // If query is a simple object with key-value pairs, check each one
-        if let Some(query_obj) = query.as_object() {
-            if let Some(doc_obj) = doc_value.as_object() {
-                for (query_key, query_val) in query_obj {
-                    // Check if document has this key and the value matches
-                    match doc_obj.get(query_key) {
-                        Some(doc_val) => {
-                            if doc_val != query_val {
-                                return false;
-                            }
-                        },
-                        None => return false,
-                    }
-                }
-                return true;
-            }
-        }
+/// Shorten `text` to at most `max_chars` characters, marking the cut with "..."
+pub fn truncate_for_display(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
+
+/// One-line summary of a change feed event for the `watch` command
+pub fn format_change_event(event: &ChangeEvent) -> String {
+    let op = match event.op {
+        ChangeOp::Insert => "INSERT",
+        ChangeOp::Update => "UPDATE",
+        ChangeOp::Delete => "DELETE",
+    };
+    let id = String::from_utf8_lossy(&event.id);
+    match &event.data {
+        Some(data) => format!("[{}] {} {} {}", event.sequence, op, id,
+            truncate_for_display(&String::from_utf8_lossy(data), 60)),
+        None => format!("[{}] {} {}", event.sequence, op, id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_depth_skips_strings() {
+        assert_eq!(json_depth(r#"{"a": [1, {"b": 2}"#), 2);
+        assert_eq!(json_depth(r#"{"a": [1, {"b": 2}]}"#), 0);
+        assert_eq!(json_depth(r#"{"text": "}] \" {"#), 1);
+        assert_eq!(json_depth("}"), -1);
+    }
+
+    #[test]
+    fn test_format_change_event_truncates_value() {
+        let event = ChangeEvent {
+            sequence: 7,
+            op: ChangeOp::Insert,
+            id: b"user1".to_vec(),
+            data: Some(format!(r#"{{"bio":"{}"}}"#, "é".repeat(100)).into_bytes()),
+            timestamp: 0,
+        };
+        let line = format_change_event(&event);
+        assert!(line.starts_with("[7] INSERT user1 {\"bio\":\"é"));
+        assert!(line.ends_with("..."));
+        assert_eq!(line.chars().count(), "[7] INSERT user1 ".len() + 60);
+
+        let event = ChangeEvent { op: ChangeOp::Delete, data: None, ..event };
+        assert_eq!(format_change_event(&event), "[7] DELETE user1");
     }
-    
-    false
-} 
\ No newline at end of file
+}
@@ -0,0 +1,150 @@
+//! Table and CSV rendering of query results for the CLI
+
+use std::str::FromStr;
+use nebuladb_core::Error;
+use serde_json::Value as JsonValue;
+
+/// How the CLI shows documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Pretty-printed JSON, one document at a time
+    #[default]
+    Json,
+    /// ASCII table with a column per field
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "table" => Ok(Self::Table),
+            "csv" => Ok(Self::Csv),
+            _ => Err(Error::Other(format!("Unknown format '{}'; expected json, table or csv", name))),
+        }
+    }
+}
+
+/// Column names: the keys of the first document
+fn columns(docs: &[JsonValue]) -> Vec<String> {
+    match docs.first() {
+        Some(JsonValue::Object(fields)) => fields.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Text of one cell; strings are shown without quotes and missing fields
+/// or non-object documents give an empty cell
+fn cell(doc: &JsonValue, column: &str) -> String {
+    match doc.get(column) {
+        None => String::new(),
+        Some(JsonValue::String(text)) => text.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Render `docs` as an ASCII table, each column padded to its widest cell
+pub fn format_as_table(docs: &[JsonValue]) -> String {
+    let columns = columns(docs);
+    if columns.is_empty() {
+        return String::new();
+    }
+
+    let rows: Vec<Vec<String>> = docs.iter()
+        .map(|doc| columns.iter().map(|column| cell(doc, column).replace('\n', " ")).collect())
+        .collect();
+    let widths: Vec<usize> = columns.iter().enumerate()
+        .map(|(i, column)| rows.iter().map(|row| row[i].chars().count()).fold(column.chars().count(), usize::max))
+        .collect();
+
+    let border: String = widths.iter().map(|width| format!("+{}", "-".repeat(width + 2))).collect::<String>() + "+\n";
+    let line = |cells: &[String]| {
+        cells.iter().zip(&widths)
+            .map(|(cell, width)| format!("| {}{} ", cell, " ".repeat(width - cell.chars().count())))
+            .collect::<String>() + "|\n"
+    };
+
+    let mut table = border.clone();
+    table.push_str(&line(&columns));
+    table.push_str(&border);
+    for row in &rows {
+        table.push_str(&line(row));
+    }
+    table.push_str(&border);
+    table
+}
+
+/// Quote a CSV field when it holds a comma, quote or line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Render `docs` as RFC 4180 CSV: a header row, then one record per document
+pub fn format_as_csv(docs: &[JsonValue]) -> String {
+    let columns = columns(docs);
+    if columns.is_empty() {
+        return String::new();
+    }
+
+    let record = |cells: Vec<String>| cells.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>().join(",") + "\r\n";
+    let mut csv = record(columns.clone());
+    for doc in docs {
+        csv.push_str(&record(columns.iter().map(|column| cell(doc, column)).collect()));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_table_columns_are_aligned() {
+        let docs = vec![
+            json!({"_id": "ada", "age": 36, "name": "Ada Lovelace"}),
+            json!({"_id": "alan", "name": "Alan"}),
+            json!({"_id": "grace", "age": 85, "name": null, "rank": "admiral"}),
+        ];
+        assert_eq!(format_as_table(&docs), concat!(
+            "+-------+-----+--------------+\n",
+            "| _id   | age | name         |\n",
+            "+-------+-----+--------------+\n",
+            "| ada   | 36  | Ada Lovelace |\n",
+            "| alan  |     | Alan         |\n",
+            "| grace | 85  | null         |\n",
+            "+-------+-----+--------------+\n",
+        ));
+        assert_eq!(format_as_table(&[]), "");
+    }
+
+    #[test]
+    fn test_csv_quotes_special_characters() {
+        let docs = vec![
+            json!({"_id": "a1", "note": "plain", "tags": ["x", "y"]}),
+            json!({"_id": "a2", "note": "says \"hi\", twice"}),
+            json!({"_id": "a3", "note": "two\nlines", "tags": []}),
+        ];
+        assert_eq!(format_as_csv(&docs), concat!(
+            "_id,note,tags\r\n",
+            "a1,plain,\"[\"\"x\"\",\"\"y\"\"]\"\r\n",
+            "a2,\"says \"\"hi\"\", twice\",\r\n",
+            "a3,\"two\nlines\",[]\r\n",
+        ));
+    }
+
+    #[test]
+    fn test_parse_output_format() {
+        assert_eq!("TABLE".parse::<OutputFormat>().unwrap(), OutputFormat::Table);
+        assert_eq!("csv".parse::<OutputFormat>().unwrap(), OutputFormat::Csv);
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! `nebuladb wal-inspect`: print the entries of a WAL file without starting
+//! the server
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use nebuladb_core::{Error, Result};
+use nebuladb_wal::reader::{parse_timestamp, InspectFilter, InspectSummary, WalReader};
+
+/// Arguments of `wal-inspect`
+#[derive(Debug)]
+pub struct InspectArgs {
+    /// WAL file to read
+    pub path: PathBuf,
+    /// Entries to print
+    pub filter: InspectFilter,
+}
+
+/// Parse the arguments following `wal-inspect`:
+/// `<path> [--filter-tx <id>] [--after <timestamp>]`
+pub fn parse_args(args: &[String]) -> Result<InspectArgs> {
+    let mut path = None;
+    let mut filter = InspectFilter::default();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--filter-tx" => {
+                let value = option_value(args, i)?;
+                let tx = value.parse()
+                    .map_err(|_| Error::Other(format!("Invalid transaction ID: {}", value)))?;
+                filter.transaction_id = Some(tx);
+                i += 2;
+            },
+            "--after" => {
+                let value = option_value(args, i)?;
+                let after = parse_timestamp(value)
+                    .ok_or_else(|| Error::Other(format!("Invalid timestamp: {}", value)))?;
+                filter.after = Some(after);
+                i += 2;
+            },
+            arg if path.is_none() && !arg.starts_with("--") => {
+                path = Some(PathBuf::from(arg));
+                i += 1;
+            },
+            arg => return Err(Error::Other(format!("Unexpected argument: {}", arg))),
+        }
+    }
+
+    let path = path.ok_or_else(|| Error::Other("Missing WAL file path".to_string()))?;
+    Ok(InspectArgs { path, filter })
+}
+
+fn option_value(args: &[String], i: usize) -> Result<&str> {
+    args.get(i + 1)
+        .map(String::as_str)
+        .ok_or_else(|| Error::Other(format!("Missing argument for {}", args[i])))
+}
+
+/// Print the entries of the WAL file selected by `args` to `out`
+pub fn inspect(args: &InspectArgs, out: &mut dyn Write) -> Result<InspectSummary> {
+    let reader = WalReader::open(&args.path)?;
+    Ok(reader.print(out, &args.filter)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nebuladb_wal::{EntryType, WalEntry, WalLog};
+
+    fn args(args: &[&str]) -> Result<InspectArgs> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&["users.wal", "--filter-tx", "42", "--after", "2024-01-01"]).unwrap();
+        assert_eq!(parsed.path, PathBuf::from("users.wal"));
+        assert_eq!(parsed.filter.transaction_id, Some(42));
+        assert_eq!(parsed.filter.after, Some(1_704_067_200));
+
+        assert!(args(&[]).is_err());
+        assert!(args(&["users.wal", "--filter-tx"]).is_err());
+        assert!(args(&["users.wal", "--after", "soon"]).is_err());
+        assert!(args(&["users.wal", "other.wal"]).is_err());
+    }
+
+    #[test]
+    fn test_inspect_filters_by_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.wal");
+        let mut log = WalLog::create(&path, false).unwrap();
+        for tx in 1..=3 {
+            log.append(&WalEntry::new(EntryType::Insert, 7, tx, format!("doc{}", tx).into_bytes(), b"{}".to_vec())).unwrap();
+        }
+        log.close().unwrap();
+
+        let path = path.to_string_lossy().to_string();
+        let mut out = Vec::new();
+        let summary = inspect(&args(&[&path, "--filter-tx", "2"]).unwrap(), &mut out).unwrap();
+        assert_eq!((summary.entries, summary.shown), (3, 1));
+        assert!(summary.corruption.is_none());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("doc2") && !out.contains("doc1"));
+    }
+}
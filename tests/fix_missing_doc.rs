@@ -38,6 +38,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         compression: CompressionType::None,
         flush_threshold: 4096,
         block_size: 4096,
+        ..StorageConfig::default()
     };
     
     // Open the collection
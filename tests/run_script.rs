@@ -0,0 +1,71 @@
+//! Runs CLI scripts through the `nebuladb run-script` subcommand
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCRIPT: &str = r#"
+# Set up a shop with one collection
+createdb shop
+usedb shop
+create users
+open users
+
+json users ada {"name": "Ada",
+  "age": 36}
+json users alan {"name": "Alan"}
+bogus
+scan users
+exit
+scan users
+"#;
+
+fn run_script(dir: &Path, extra_args: &[&str]) -> Output {
+    let config = dir.join("nebuladb.json");
+    let data_dir = dir.join("data");
+    std::fs::write(&config, format!(r#"{{"data_dir": {:?}}}"#, data_dir)).unwrap();
+    let script = dir.join("setup.ndb");
+    std::fs::write(&script, SCRIPT).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_nebuladb"))
+        .arg("run-script")
+        .arg(&script)
+        .arg("--config")
+        .arg(&config)
+        .args(extra_args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_script_runs_past_failures_until_exit() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run_script(dir.path(), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    for expected in [
+        "Database 'shop' created successfully",
+        "Switched to database 'shop'",
+        "Collection 'users' created successfully",
+        "Unknown command: bogus",
+        "Documents in collection 'users':\n  - ada\n  - alan\nTotal: 2 documents",
+        "Exiting NebulaDB. Goodbye!",
+    ] {
+        assert!(stdout.contains(expected), "missing {:?} in:\n{}", expected, stdout);
+    }
+    assert_eq!(stdout.matches("JSON document inserted successfully").count(), 2);
+    // Nothing after `exit` runs
+    assert_eq!(stdout.matches("Total: 2 documents").count(), 1);
+}
+
+#[test]
+fn test_fail_fast_stops_at_first_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run_script(dir.path(), &["--fail-fast"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!output.status.success());
+    assert!(stdout.contains("Unknown command: bogus"), "{}", stdout);
+    assert!(!stdout.contains("Documents in collection"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Script stopped at line 11: bogus"));
+}